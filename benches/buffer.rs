@@ -0,0 +1,84 @@
+#![feature(test)]
+
+extern crate test;
+extern crate fiesta_net;
+
+use std::io::Cursor;
+use test::Bencher;
+
+use fiesta_net::{Buffer, BinaryReadable};
+
+/* a burst of small frames like a client moving around, 1 byte size, 2 bytes header, body */
+fn burst() -> Vec<u8> {
+	let mut data = Vec::new();
+	for i in 0..200 {
+		data.push(8);
+		data.push(0x20);
+		data.push(i as u8);
+		data.extend_from_slice(&[0; 8]);
+	}
+	data
+}
+
+/* what read_socket used to do: read into a stack array, then copy that into the ring */
+#[bench]
+fn read_through_bounce_buffer(b: &mut Bencher) {
+	let data = burst();
+	let mut buffer = Buffer::with_capacity(4096);
+	b.bytes = data.len() as u64;
+	b.iter(|| {
+		let mut reader = Cursor::new(&data[..]);
+		let mut bounce = [0; 2048];
+		loop {
+			let size = std::io::Read::read(&mut reader, &mut bounce[..]).unwrap();
+			if size == 0 {
+				break;
+			}
+			buffer.append(&bounce[0..size]).unwrap();
+			buffer.advance_read(size);
+		}
+	});
+}
+
+#[bench]
+fn read_into_ring(b: &mut Bencher) {
+	let data = burst();
+	let mut buffer = Buffer::with_capacity(4096);
+	b.bytes = data.len() as u64;
+	b.iter(|| {
+		let mut reader = Cursor::new(&data[..]);
+		loop {
+			let size = buffer.read_from(&mut reader, 2048).unwrap();
+			if size == 0 {
+				break;
+			}
+			buffer.advance_read(size);
+		}
+	});
+}
+
+/* the read position keeps wrapping, nothing is ever shifted back to the front */
+#[bench]
+fn frame_wraparound(b: &mut Bencher) {
+	let data = burst();
+	let mut buffer = Buffer::with_capacity(4096 + 7);
+	b.bytes = data.len() as u64;
+	b.iter(|| {
+		buffer.append(&data[..]).unwrap();
+		while buffer.bytes_remaining() > 0 {
+			let size = buffer.read_u8().unwrap() as usize;
+			let _header = buffer.read_u16().unwrap();
+			buffer.advance_read(size);
+		}
+	});
+}
+
+#[bench]
+fn extend_growing(b: &mut Bencher) {
+	let data = burst();
+	b.iter(|| {
+		let mut buffer = Buffer::with_capacity(64);
+		buffer.extend(&data[..]);
+		test::black_box(buffer.bytes_remaining())
+	});
+}
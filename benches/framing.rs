@@ -0,0 +1,131 @@
+#[macro_use]
+extern crate criterion;
+extern crate fiesta_net;
+extern crate mio;
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use criterion::{Criterion, Throughput};
+use mio::Token;
+
+use fiesta_net::{decode_stream, BinaryPeekable, Buffer, ClientGroup, ClientHandle, FiestaPacket, PacketProcessingInfo,
+	PacketProcessingThreadPool, PacketProcessor};
+use fiesta_net::testing::MockClient;
+
+const FRAMES: usize = 200;
+const GROUP_SIZE: usize = 64;
+
+/* a burst of small frames like a client moving around, 1 byte size, 2 bytes header, body */
+fn burst() -> Vec<u8> {
+	let mut data = Vec::new();
+	for i in 0..FRAMES {
+		data.extend_from_slice(&FiestaPacket::encode(0x2000 | i as u16, &[0; 8]).unwrap()[..]);
+	}
+	data
+}
+
+fn decode(c: &mut Criterion) {
+	let data = burst();
+	let mut group = c.benchmark_group("decode");
+	group.throughput(Throughput::Bytes(data.len() as u64));
+	group.bench_function("decode_stream", |b| b.iter(|| decode_stream(&data[..]).len()));
+
+	/* the framing the reactor runs, ring buffer and limits included */
+	let client = MockClient::new(Token(1)).unwrap();
+	group.bench_function("receive_bytes", |b| b.iter(|| client.push_bytes(&data[..]).unwrap().len()));
+	group.finish();
+}
+
+fn encode(c: &mut Criterion) {
+	let small = [0; 8];
+	let large = [0; 1024];
+	let mut group = c.benchmark_group("encode");
+	group.bench_function("small", |b| b.iter(|| FiestaPacket::encode(0x2001, &small[..]).unwrap()));
+	group.bench_function("extended size", |b| b.iter(|| FiestaPacket::encode(0x2001, &large[..]).unwrap()));
+	group.finish();
+}
+
+fn buffer(c: &mut Criterion) {
+	let data = burst();
+	let mut group = c.benchmark_group("buffer");
+	group.throughput(Throughput::Bytes(data.len() as u64));
+	group.bench_function("append", |b| {
+		let mut buffer = Buffer::with_capacity(data.len());
+		b.iter(|| {
+			buffer.append(&data[..]).unwrap();
+			buffer.clear();
+		})
+	});
+	group.bench_function("peek", |b| {
+		let mut buffer = Buffer::with_capacity(data.len());
+		buffer.append(&data[..]).unwrap();
+		b.iter(|| {
+			let mut total = 0;
+			for i in 0..FRAMES {
+				total += buffer.peek_u16(i * 11 + 1).unwrap() as usize;
+			}
+			total
+		})
+	});
+	group.finish();
+}
+
+/* one packet to every member, the sent frames are taken out so the send queues never fill */
+fn broadcast(c: &mut Criterion) {
+	let clients: Vec<MockClient> = (0..GROUP_SIZE).map(|i| MockClient::new(Token(i + 1)).unwrap()).collect();
+	let group_of_clients = ClientGroup::new();
+	for client in clients.iter() {
+		group_of_clients.join(ClientHandle::new(client.client()));
+	}
+	let packet = FiestaPacket::from_hex_str(0x2001, "00 01 02 03 04 05 06 07").unwrap();
+
+	let mut group = c.benchmark_group("broadcast");
+	group.throughput(Throughput::Elements(GROUP_SIZE as u64));
+	group.bench_function("client group", |b| b.iter(|| {
+		let sent = group_of_clients.send(&packet);
+		for client in clients.iter() {
+			client.sent_bytes().unwrap();
+		}
+		sent
+	}));
+	group.finish();
+}
+
+/* counts packets, nothing else, so the pool is what's measured */
+struct Counter {
+	processed:		Arc<AtomicUsize>,
+}
+
+impl PacketProcessor for Counter {
+	fn process_packet(&mut self, _info: PacketProcessingInfo) {
+		self.processed.fetch_add(1, Ordering::SeqCst);
+	}
+
+	fn clone(&self) -> Box<PacketProcessor> {
+		Box::new(Counter { processed: self.processed.clone() })
+	}
+}
+
+fn dispatch(c: &mut Criterion) {
+	let processed = Arc::new(AtomicUsize::new(0));
+	let mut pool = PacketProcessingThreadPool::new(4, Box::new(Counter { processed: processed.clone() })).unwrap();
+	let client = MockClient::new(Token(1)).unwrap();
+
+	let mut group = c.benchmark_group("dispatch");
+	group.throughput(Throughput::Elements(FRAMES as u64));
+	group.bench_function("thread pool", |b| b.iter(|| {
+		let target = processed.load(Ordering::SeqCst) + FRAMES;
+		for i in 0..FRAMES {
+			let packet = FiestaPacket::from_hex_str(0x2000 | i as u16, "00").unwrap();
+			pool.process_packet(PacketProcessingInfo::new(packet, client.client()));
+		}
+		while processed.load(Ordering::SeqCst) < target {
+			thread::yield_now();
+		}
+	}));
+	group.finish();
+}
+
+criterion_group!(benches, decode, encode, buffer, broadcast, dispatch);
+criterion_main!(benches);
@@ -0,0 +1,82 @@
+#[macro_use]
+extern crate criterion;
+extern crate fiesta_net;
+
+use std::io::Write;
+use std::net::{SocketAddr, TcpStream};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use criterion::{Criterion, Throughput};
+
+use fiesta_net::{FiestaServerBuilder, PacketProcessingInfo, PacketProcessor, PollStrategy};
+
+const FRAMES: usize = 200;
+
+/* counts packets, nothing else, so the reactor is what's measured */
+struct Counter {
+	received:		Arc<AtomicUsize>,
+}
+
+impl PacketProcessor for Counter {
+	fn process_packet(&mut self, _info: PacketProcessingInfo) {
+		self.received.fetch_add(1, Ordering::SeqCst);
+	}
+
+	fn clone(&self) -> Box<PacketProcessor> {
+		Box::new(Counter { received: self.received.clone() })
+	}
+}
+
+fn burst() -> Vec<u8> {
+	let mut data = Vec::new();
+	for i in 0..FRAMES {
+		data.push(8);
+		data.push(0x20);
+		data.push(i as u8);
+		data.extend_from_slice(&[0; 8]);
+	}
+	data
+}
+
+/* one client sends bursts of small frames, an iteration is over once the processor saw all of them */
+fn bench_strategy(c: &mut Criterion, name: &str, strategy: PollStrategy, port: u16) {
+	let received = Arc::new(AtomicUsize::new(0));
+	let address: SocketAddr = format!("127.0.0.1:{}", port).parse().unwrap();
+	let server = FiestaServerBuilder::new()
+		.address(address)
+		.threads(1)
+		.poll_strategy(strategy)
+		.build(Box::new(Counter { received: received.clone() }))
+		.unwrap();
+	let handle = server.handle();
+	let reactor = thread::spawn(move || server.run().unwrap());
+
+	let mut client = TcpStream::connect(address).unwrap();
+	let data = burst();
+	let mut expected = 0;
+	let mut group = c.benchmark_group("poll");
+	group.throughput(Throughput::Bytes(data.len() as u64));
+	group.bench_function(name, |b| b.iter(|| {
+		client.write_all(&data[..]).unwrap();
+		expected += FRAMES;
+		while received.load(Ordering::SeqCst) < expected {
+			thread::yield_now();
+		}
+	}));
+	group.finish();
+
+	handle.shutdown().unwrap();
+	reactor.join().unwrap();
+}
+
+fn burst_rearm(c: &mut Criterion) {
+	bench_strategy(c, "burst rearm", PollStrategy::Rearm, 19411);
+}
+
+fn burst_edge(c: &mut Criterion) {
+	bench_strategy(c, "burst edge", PollStrategy::Edge, 19412);
+}
+
+criterion_group!(benches, burst_rearm, burst_edge);
+criterion_main!(benches);
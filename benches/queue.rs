@@ -0,0 +1,48 @@
+#![feature(test)]
+
+extern crate test;
+extern crate fiesta_net;
+
+use std::collections::{LinkedList, VecDeque};
+use test::Bencher;
+
+use fiesta_net::{FiestaPacket, SharedBytes};
+
+/* a burst like the framing code queues after one read: 200 small frames, all sliced out of one chunk */
+fn burst(chunk: &SharedBytes) -> Vec<FiestaPacket> {
+	(0..200).map(|i| FiestaPacket::from_shared(0x2000 | i, chunk.slice(i as usize * 11, i as usize * 11 + 8))).collect()
+}
+
+/* what packet_queue used to be, one allocation per packet */
+#[bench]
+fn burst_through_linked_list(b: &mut Bencher) {
+	let chunk = SharedBytes::from_vec(vec![0; 200 * 11]);
+	let mut queue = LinkedList::new();
+	b.iter(|| {
+		for packet in burst(&chunk) {
+			queue.push_back(packet);
+		}
+		let mut dispatched = 0;
+		while let Some(packet) = queue.pop_front() {
+			dispatched += packet.header as usize;
+		}
+		dispatched
+	});
+}
+
+/* the ring keeps its capacity, after the first burst pushing doesn't allocate */
+#[bench]
+fn burst_through_vec_deque(b: &mut Bencher) {
+	let chunk = SharedBytes::from_vec(vec![0; 200 * 11]);
+	let mut queue = VecDeque::new();
+	b.iter(|| {
+		for packet in burst(&chunk) {
+			queue.push_back(packet);
+		}
+		let mut dispatched = 0;
+		while let Some(packet) = queue.pop_front() {
+			dispatched += packet.header as usize;
+		}
+		dispatched
+	});
+}
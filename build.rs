@@ -0,0 +1,484 @@
+/* generates src/packets.rs's structs from spec/packets.spec, see the comment at the top of that file */
+
+use std::env;
+use std::fmt::Write as FmtWrite;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+
+const SPEC: &'static str = "spec/packets.spec";
+
+#[derive(Clone, Copy, PartialEq)]
+enum Scalar {
+	U8,
+	I8,
+	U16,
+	I16,
+	U32,
+	I32,
+	U64,
+	I64,
+	F32,
+}
+
+enum Kind {
+	Scalar(Scalar),
+	/* zero padded */
+	Text(usize),
+	Bytes(usize),
+	/* whatever is left of the body, only as the last field */
+	Rest,
+	Array(Scalar, usize),
+	/* the count is an earlier field, which is left out of the struct */
+	Counted(Scalar, String),
+}
+
+/* checked after decoding, see Packet::validate */
+enum Constraint {
+	/* bytes of a string, or elements of a byte string or list */
+	MaxLen(usize),
+	/* inclusive on both ends, integers only */
+	Range(i128, i128),
+}
+
+struct Field {
+	name:			String,
+	kind:			Kind,
+	constraints:	Vec<Constraint>,
+	line:			usize,
+}
+
+/* who sends it, which of ClientPacket and ServerPacket it is in */
+#[derive(Clone, Copy, PartialEq)]
+enum Sender {
+	Unknown,
+	Client,
+	Server,
+	Both,
+}
+
+struct Packet {
+	name:			String,
+	opcode:			u16,
+	sender:			Sender,
+	fields:			Vec<Field>,
+	line:			usize,
+}
+
+impl Sender {
+	fn parse(text: &str, line: usize) -> Sender {
+		match text {
+			"client"	=> Sender::Client,
+			"server"	=> Sender::Server,
+			"both"		=> Sender::Both,
+			_			=> fail(line, &format!("expected client, server or both, got `{}`", text)),
+		}
+	}
+
+	fn client(&self) -> bool {
+		*self == Sender::Client || *self == Sender::Both
+	}
+
+	fn server(&self) -> bool {
+		*self == Sender::Server || *self == Sender::Both
+	}
+}
+
+impl Scalar {
+	fn parse(name: &str) -> Option<Scalar> {
+		Some(match name {
+			"u8"	=> Scalar::U8,
+			"i8"	=> Scalar::I8,
+			"u16"	=> Scalar::U16,
+			"i16"	=> Scalar::I16,
+			"u32"	=> Scalar::U32,
+			"i32"	=> Scalar::I32,
+			"u64"	=> Scalar::U64,
+			"i64"	=> Scalar::I64,
+			"f32"	=> Scalar::F32,
+			_		=> return None,
+		})
+	}
+
+	fn rust(&self) -> &'static str {
+		match *self {
+			Scalar::U8	=> "u8",
+			Scalar::I8	=> "i8",
+			Scalar::U16	=> "u16",
+			Scalar::I16	=> "i16",
+			Scalar::U32	=> "u32",
+			Scalar::I32	=> "i32",
+			Scalar::U64	=> "u64",
+			Scalar::I64	=> "i64",
+			Scalar::F32	=> "f32",
+		}
+	}
+
+	fn size(&self) -> usize {
+		match *self {
+			Scalar::U8 | Scalar::I8						=> 1,
+			Scalar::U16 | Scalar::I16					=> 2,
+			Scalar::U32 | Scalar::I32 | Scalar::F32		=> 4,
+			Scalar::U64 | Scalar::I64					=> 8,
+		}
+	}
+
+	fn read(&self) -> String {
+		match *self {
+			Scalar::F32	=> "f32::from_bits(try!(body.read_uint(4, Endianness::Little)) as u32)".to_string(),
+			_			=> format!("try!(body.read_uint({}, Endianness::Little)) as {}", self.size(), self.rust()),
+		}
+	}
+
+	fn write(&self, value: &str) -> String {
+		match *self {
+			Scalar::F32	=> format!("put_uint(body, {}.to_bits() as u64, 4);", value),
+			_			=> format!("put_uint(body, {} as u64, {});", value, self.size()),
+		}
+	}
+}
+
+fn fail(line: usize, message: &str) -> ! {
+	panic!("{}:{}: {}", SPEC, line, message)
+}
+
+fn parse_kind(text: &str, line: usize) -> Kind {
+	let (base, size) = match text.find('[') {
+		Some(open) => {
+			if !text.ends_with(']') {
+				fail(line, "missing `]`");
+			}
+			(&text[..open], Some(&text[open + 1..text.len() - 1]))
+		},
+		None => (text, None),
+	};
+	let number = |size: &str| size.parse::<usize>().unwrap_or_else(|_| fail(line, &format!("bad size `{}`", size)));
+	match (base, size) {
+		("string", Some(size))	=> Kind::Text(number(size)),
+		("bytes", Some(size))	=> Kind::Bytes(number(size)),
+		("bytes", None)			=> Kind::Rest,
+		(base, size) => {
+			let scalar = Scalar::parse(base).unwrap_or_else(|| fail(line, &format!("unknown type `{}`", base)));
+			match size {
+				None => Kind::Scalar(scalar),
+				Some(size) if size.chars().all(|c| c.is_digit(10)) => Kind::Array(scalar, number(size)),
+				Some(count) => Kind::Counted(scalar, count.to_string()),
+			}
+		}
+	}
+}
+
+/* max_len(16) or range(1..=9) */
+fn parse_constraint(text: &str, kind: &Kind, line: usize) -> Constraint {
+	let open = text.find('(').unwrap_or_else(|| fail(line, &format!("bad constraint `{}`", text)));
+	if !text.ends_with(')') {
+		fail(line, "missing `)`");
+	}
+	let (name, argument) = (&text[..open], &text[open + 1..text.len() - 1]);
+	let number = |text: &str| -> i128 {
+		let text = text.trim();
+		let parsed = if text.starts_with("0x") { i128::from_str_radix(&text[2..], 16) } else { text.parse() };
+		parsed.unwrap_or_else(|_| fail(line, &format!("bad number `{}`", text)))
+	};
+	match name {
+		"max_len" => match *kind {
+			Kind::Scalar(_) => fail(line, "max_len on a number, use range"),
+			_ => Constraint::MaxLen(number(argument) as usize),
+		},
+		"range" => {
+			match *kind {
+				Kind::Scalar(scalar) if scalar != Scalar::F32 => {},
+				_ => fail(line, "range only works on integer fields"),
+			}
+			let (min, max) = match argument.find("..=") {
+				Some(dots) => (number(&argument[..dots]), number(&argument[dots + 3..])),
+				None => match argument.find("..") {
+					Some(dots) => (number(&argument[..dots]), number(&argument[dots + 2..]) - 1),
+					None => fail(line, "expected `min..=max` or `min..end`"),
+				},
+			};
+			if min > max {
+				fail(line, "empty range");
+			}
+			Constraint::Range(min, max)
+		},
+		_ => fail(line, &format!("unknown constraint `{}`", name)),
+	}
+}
+
+fn parse_opcode(text: &str, line: usize) -> u16 {
+	let parsed = if text.starts_with("0x") {
+		u16::from_str_radix(&text[2..], 16)
+	} else {
+		text.parse()
+	};
+	parsed.unwrap_or_else(|_| fail(line, &format!("bad opcode `{}`", text)))
+}
+
+/*
+ * packet NC_USER_LOGIN_REQ 0x0c06 client {
+ *     user: string[256]
+ *     password: string[16]
+ * }
+ */
+fn parse(spec: &str) -> Vec<Packet> {
+	let mut packets: Vec<Packet> = Vec::new();
+	let mut current: Option<Packet> = None;
+	for (i, line) in spec.lines().enumerate() {
+		let number = i + 1;
+		let line = match line.find('#') {
+			Some(comment) => &line[..comment],
+			None => line,
+		}.trim();
+		if line.is_empty() {
+			continue;
+		}
+
+		if line == "}" {
+			match current.take() {
+				Some(packet) => packets.push(packet),
+				None => fail(number, "`}` outside of a packet"),
+			}
+			continue;
+		}
+
+		if let Some(ref mut packet) = current {
+			let colon = line.find(':').unwrap_or_else(|| fail(number, "expected `name: type`"));
+			let name = line[..colon].trim().to_string();
+			if packet.fields.iter().any(|field| field.name == name) {
+				fail(number, &format!("duplicate field `{}`", name));
+			}
+			if let Some(last) = packet.fields.last() {
+				if let Kind::Rest = last.kind {
+					fail(last.line, "`bytes` without a size has to be the last field");
+				}
+			}
+			let mut words = line[colon + 1..].split_whitespace();
+			let kind = parse_kind(words.next().unwrap_or_else(|| fail(number, "missing type")), number);
+			let constraints: Vec<Constraint> = words.map(|word| parse_constraint(word, &kind, number)).collect();
+			if let Kind::Counted(_, ref count) = kind {
+				match packet.fields.iter().find(|field| field.name == *count) {
+					Some(&Field { kind: Kind::Scalar(scalar), ref constraints, .. }) if scalar != Scalar::F32 => {
+						if !constraints.is_empty() {
+							fail(number, &format!("`{}` isn't kept in the struct, put max_len on `{}` instead", count, name));
+						}
+					},
+					_ => fail(number, &format!("`{}` is not an earlier integer field", count)),
+				}
+			}
+			packet.fields.push(Field { name: name, kind: kind, constraints: constraints, line: number });
+			continue;
+		}
+
+		let words: Vec<&str> = line.split_whitespace().collect();
+		let (name, opcode, sender) = match &words[..] {
+			["packet", name, opcode, "{"] => (name, opcode, Sender::Unknown),
+			["packet", name, opcode, sender, "{"] => (name, opcode, Sender::parse(sender, number)),
+			_ => fail(number, "expected `packet NAME OPCODE [SENDER] {`"),
+		};
+		let opcode = parse_opcode(opcode, number);
+		if let Some(other) = packets.iter().find(|packet| packet.name == *name || packet.opcode == opcode) {
+			fail(number, &format!("clashes with {} on line {}", other.name, other.line));
+		}
+		current = Some(Packet { name: name.to_string(), opcode: opcode, sender: sender, fields: Vec::new(), line: number });
+	}
+	if let Some(packet) = current {
+		fail(packet.line, &format!("{} is missing its `}}`", packet.name));
+	}
+	packets
+}
+
+/* NC_USER_LOGIN_REQ -> NcUserLoginReq */
+fn camel_case(name: &str) -> String {
+	name.split('_')
+		.filter(|word| !word.is_empty())
+		.map(|word| {
+			let lower = word.to_lowercase();
+			let mut chars = lower.chars();
+			match chars.next() {
+				Some(first) => first.to_uppercase().chain(chars).collect::<String>(),
+				None => String::new(),
+			}
+		})
+		.collect()
+}
+
+fn is_count(packet: &Packet, name: &str) -> bool {
+	packet.fields.iter().any(|field| match field.kind {
+		Kind::Counted(_, ref count) => count == name,
+		_ => false,
+	})
+}
+
+fn generate(packets: &[Packet]) -> String {
+	let mut out = String::new();
+	writeln!(out, "/* generated by build.rs from {}, edit that instead */", SPEC).unwrap();
+
+	for packet in packets {
+		let name = camel_case(&packet.name);
+		writeln!(out, "\n#[derive(Debug, Clone, PartialEq, Default)]").unwrap();
+		writeln!(out, "pub struct {} {{", name).unwrap();
+		for field in packet.fields.iter().filter(|field| !is_count(packet, &field.name)) {
+			let rust = match field.kind {
+				Kind::Scalar(scalar)		=> scalar.rust().to_string(),
+				Kind::Text(_)				=> "String".to_string(),
+				Kind::Bytes(_) | Kind::Rest	=> "Vec<u8>".to_string(),
+				Kind::Array(scalar, _) | Kind::Counted(scalar, _) => format!("Vec<{}>", scalar.rust()),
+			};
+			writeln!(out, "\tpub {}: {},", field.name, rust).unwrap();
+		}
+		writeln!(out, "}}\n").unwrap();
+
+		writeln!(out, "impl Packet for {} {{", name).unwrap();
+		writeln!(out, "\tconst OPCODE: u16 = 0x{:04x};", packet.opcode).unwrap();
+		writeln!(out, "\tconst NAME: &'static str = \"{}\";\n", packet.name).unwrap();
+
+		writeln!(out, "\tfn decode_from(body: &mut SharedBytes) -> Result<Self, BufferError> {{").unwrap();
+		for field in packet.fields.iter() {
+			let read = match field.kind {
+				Kind::Scalar(scalar)		=> scalar.read(),
+				Kind::Text(size)			=> format!("try!(read_string(body, {}))", size),
+				Kind::Bytes(size)			=> format!("try!(body.read_bytes({}))", size),
+				Kind::Rest					=> "{ let rest = body.len(); try!(body.read_bytes(rest)) }".to_string(),
+				Kind::Array(scalar, count)	=> format!("try!((0..{}).map(|_| Ok({})).collect::<Result<Vec<_>, BufferError>>())", count, scalar.read()),
+				Kind::Counted(scalar, ref count) => format!("try!((0..{} as usize).map(|_| Ok({})).collect::<Result<Vec<_>, BufferError>>())", count, scalar.read()),
+			};
+			writeln!(out, "\t\tlet {} = {};", field.name, read).unwrap();
+		}
+		write!(out, "\t\tOk({} {{", name).unwrap();
+		for field in packet.fields.iter().filter(|field| !is_count(packet, &field.name)) {
+			write!(out, " {}: {},", field.name, field.name).unwrap();
+		}
+		writeln!(out, " }})\n\t}}\n").unwrap();
+
+		writeln!(out, "\tfn encode_body(&self, body: &mut Vec<u8>) {{").unwrap();
+		for field in packet.fields.iter() {
+			let value = format!("self.{}", field.name);
+			let write = match field.kind {
+				Kind::Scalar(scalar) => {
+					let counted = packet.fields.iter().find(|other| match other.kind {
+						Kind::Counted(_, ref count) => *count == field.name,
+						_ => false,
+					});
+					match counted {
+						Some(list) => scalar.write(&format!("self.{}.len()", list.name)),
+						None => scalar.write(&value),
+					}
+				},
+				Kind::Text(size)			=> format!("put_fixed(body, {}.as_bytes(), {});", value, size),
+				Kind::Bytes(size)			=> format!("put_fixed(body, &{}[..], {});", value, size),
+				Kind::Rest					=> format!("body.extend_from_slice(&{}[..]);", value),
+				Kind::Array(scalar, count)	=> format!("for i in 0..{} {{ let value = {}.get(i).cloned().unwrap_or_default(); {} }}", count, value, scalar.write("value")),
+				Kind::Counted(scalar, _)	=> format!("for &value in {}.iter() {{ {} }}", value, scalar.write("value")),
+			};
+			writeln!(out, "\t\t{}", write).unwrap();
+		}
+		writeln!(out, "\t}}").unwrap();
+		generate_validate(&mut out, packet);
+		writeln!(out, "}}").unwrap();
+	}
+
+	writeln!(out, "\n#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]").unwrap();
+	writeln!(out, "#[repr(u16)]").unwrap();
+	writeln!(out, "pub enum Opcode {{").unwrap();
+	for packet in packets {
+		writeln!(out, "\t{} = 0x{:04x},", camel_case(&packet.name), packet.opcode).unwrap();
+	}
+	writeln!(out, "}}\n").unwrap();
+
+	writeln!(out, "impl Opcode {{").unwrap();
+	writeln!(out, "\tpub const ALL: &'static [Opcode] = &[").unwrap();
+	for packet in packets {
+		writeln!(out, "\t\tOpcode::{},", camel_case(&packet.name)).unwrap();
+	}
+	writeln!(out, "\t];\n").unwrap();
+	writeln!(out, "\tpub fn from_header(header: u16) -> Option<Opcode> {{\n\t\tmatch header {{").unwrap();
+	for packet in packets {
+		writeln!(out, "\t\t\t0x{:04x} => Some(Opcode::{}),", packet.opcode, camel_case(&packet.name)).unwrap();
+	}
+	writeln!(out, "\t\t\t_ => None,\n\t\t}}\n\t}}\n").unwrap();
+	writeln!(out, "\tpub fn name(&self) -> &'static str {{\n\t\tmatch *self {{").unwrap();
+	for packet in packets {
+		writeln!(out, "\t\t\tOpcode::{} => \"{}\",", camel_case(&packet.name), packet.name).unwrap();
+	}
+	writeln!(out, "\t\t}}\n\t}}\n}}").unwrap();
+
+	let client: Vec<&Packet> = packets.iter().filter(|packet| packet.sender.client()).collect();
+	let server: Vec<&Packet> = packets.iter().filter(|packet| packet.sender.server()).collect();
+	generate_enum(&mut out, "ClientPacket", &client);
+	generate_enum(&mut out, "ServerPacket", &server);
+	out
+}
+
+/* the default accepts everything, only packets with constraints get their own */
+fn generate_validate(out: &mut String, packet: &Packet) {
+	if packet.fields.iter().all(|field| field.constraints.is_empty()) {
+		return;
+	}
+	writeln!(out, "\n\tfn validate(&self) -> Result<(), ValidationError> {{").unwrap();
+	for field in packet.fields.iter() {
+		for constraint in field.constraints.iter() {
+			let (check, violation) = match *constraint {
+				Constraint::MaxLen(max) => (
+					format!("self.{}.len() > {}", field.name, max),
+					format!("Violation::TooLong {{ max: {}, len: self.{}.len() }}", max, field.name)),
+				Constraint::Range(min, max) => (
+					format!("(self.{} as i128) < {} || (self.{} as i128) > {}", field.name, min, field.name, max),
+					format!("Violation::OutOfRange {{ min: {}, max: {}, value: self.{} as i128 }}", min, max, field.name)),
+			};
+			writeln!(out, "\t\tif {} {{", check).unwrap();
+			writeln!(out, "\t\t\treturn Err(ValidationError {{ field: \"{}\", violation: {} }});", field.name, violation).unwrap();
+			writeln!(out, "\t\t}}").unwrap();
+		}
+	}
+	writeln!(out, "\t\tOk(())\n\t}}").unwrap();
+}
+
+/* one variant per packet, decoded by header with TryFrom<&FiestaPacket> */
+fn generate_enum(out: &mut String, name: &str, packets: &[&Packet]) {
+	writeln!(out, "\n#[derive(Debug, Clone, PartialEq)]").unwrap();
+	writeln!(out, "pub enum {} {{", name).unwrap();
+	for packet in packets {
+		let variant = camel_case(&packet.name);
+		writeln!(out, "\t{}({}),", variant, variant).unwrap();
+	}
+	writeln!(out, "}}\n").unwrap();
+
+	writeln!(out, "impl {} {{", name).unwrap();
+	writeln!(out, "\tpub fn opcode(&self) -> u16 {{\n\t\tmatch *self {{").unwrap();
+	for packet in packets {
+		let variant = camel_case(&packet.name);
+		writeln!(out, "\t\t\t{}::{}(_) => {}::OPCODE,", name, variant, variant).unwrap();
+	}
+	writeln!(out, "\t\t}}\n\t}}\n").unwrap();
+	writeln!(out, "\t/* the whole frame, ready for append_send */").unwrap();
+	writeln!(out, "\tpub fn encode(&self) -> Result<Vec<u8>, Error> {{\n\t\tmatch *self {{").unwrap();
+	for packet in packets {
+		writeln!(out, "\t\t\t{}::{}(ref packet) => packet.encode(),", name, camel_case(&packet.name)).unwrap();
+	}
+	writeln!(out, "\t\t}}\n\t}}\n}}\n").unwrap();
+
+	writeln!(out, "impl<'a> TryFrom<&'a FiestaPacket> for {} {{", name).unwrap();
+	writeln!(out, "\ttype Error = DecodeError;\n").unwrap();
+	writeln!(out, "\tfn try_from(packet: &'a FiestaPacket) -> Result<Self, DecodeError> {{").unwrap();
+	writeln!(out, "\t\tmatch packet.header {{").unwrap();
+	for packet in packets {
+		let variant = camel_case(&packet.name);
+		writeln!(out, "\t\t\t0x{:04x} => {}::from_packet(packet).map({}::{}),", packet.opcode, variant, name, variant).unwrap();
+	}
+	writeln!(out, "\t\t\theader => Err(DecodeError::UnknownOpcode(header)),\n\t\t}}\n\t}}\n}}").unwrap();
+}
+
+fn main() {
+	println!("cargo:rerun-if-changed={}", SPEC);
+	println!("cargo:rerun-if-changed=build.rs");
+
+	let mut spec = String::new();
+	File::open(SPEC).and_then(|mut file| file.read_to_string(&mut spec))
+		.unwrap_or_else(|e| panic!("could not read {}: {}", SPEC, e));
+	let packets = parse(&spec);
+
+	let out = Path::new(&env::var("OUT_DIR").unwrap()).join("packets.rs");
+	File::create(&out).and_then(|mut file| file.write_all(generate(&packets).as_bytes()))
+		.unwrap_or_else(|e| panic!("could not write {}: {}", out.display(), e));
+}
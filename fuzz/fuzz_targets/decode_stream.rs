@@ -0,0 +1,11 @@
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate fiesta_net;
+
+use fiesta_net::decode_stream;
+
+/* anything a client can send must frame without panicking */
+fuzz_target!(|data: &[u8]| {
+	let _ = decode_stream(data);
+});
@@ -0,0 +1,25 @@
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate fiesta_net;
+
+use fiesta_net::{decode_stream, FiestaPacket};
+
+/* whatever decodes has to decode the same way after encoding it again */
+fuzz_target!(|data: &[u8]| {
+	let packets: Vec<(u16, Vec<u8>)> = decode_stream(data).into_iter()
+		.filter_map(|packet| packet.ok())
+		.map(|packet| (packet.header, packet.data.to_vec()))
+		.collect();
+
+	let mut encoded = Vec::new();
+	for &(header, ref body) in packets.iter() {
+		encoded.extend_from_slice(&FiestaPacket::encode(header, &body[..]));
+	}
+
+	let again: Vec<(u16, Vec<u8>)> = decode_stream(&encoded[..]).into_iter()
+		.map(|packet| packet.expect("re-encoded frame failed to decode"))
+		.map(|packet| (packet.header, packet.data.to_vec()))
+		.collect();
+	assert_eq!(packets, again);
+});
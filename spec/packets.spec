@@ -0,0 +1,49 @@
+# packet layouts, turned into the structs in fiesta_net::packets by build.rs
+#
+#   packet NAME OPCODE [SENDER] {
+#       field: type
+#   }
+#
+# SENDER is client, server or both, it puts the packet into ClientPacket, ServerPacket
+# or both enums. packets without one are in neither
+#
+# types: u8 i8 u16 i16 u32 i32 u64 i64 f32, all little endian
+#        string[N]    N bytes, zero padded
+#        bytes[N]     N raw bytes
+#        bytes        the rest of the body, last field only
+#        u16[N]       N values
+#        u16[count]   as many values as the earlier field `count` says,
+#                     `count` itself is filled in when encoding
+#
+# constraints go after the type and are checked when decoding:
+#        max_len(N)      strings, byte strings and lists
+#        range(A..=B)    integers, A..B leaves out B
+
+packet NC_MISC_SEED_ACK 0x0807 server {
+	seed: u16
+}
+
+packet NC_MISC_HEARTBEAT_REQ 0x0804 both {
+}
+
+packet NC_MISC_HEARTBEAT_ACK 0x0805 both {
+}
+
+packet NC_USER_CLIENT_VERSION_CHECK_REQ 0x0c65 client {
+	version: string[64]
+}
+
+packet NC_USER_CLIENT_RIGHTVERSION_CHECK_ACK 0x0c67 server {
+}
+
+packet NC_USER_CLIENT_WRONGVERSION_CHECK_ACK 0x0c66 server {
+}
+
+packet NC_USER_LOGIN_REQ 0x0c06 client {
+	user: string[256]
+	password: string[16]
+}
+
+packet NC_USER_LOGINFAIL_ACK 0x0c09 server {
+	error: u16
+}
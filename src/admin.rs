@@ -0,0 +1,222 @@
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind, Read, Write};
+use std::net::{IpAddr, Shutdown};
+use std::str::FromStr;
+use mio::{Interest, Registry, Token};
+use mio::net::{TcpListener, TcpStream};
+
+use error::{FiestaResult, is_transient};
+
+/* a line this long without a newline isn't a command, the session is dropped */
+const MAX_LINE: usize = 1024;
+
+pub const ADMIN_HELP: &'static str = "\
+clients          list connected clients
+kick <token>     disconnect a client
+ban <ip>         disconnect and refuse every client from this address
+unban <ip>       accept the address again
+drain            stop accepting new clients, connected ones stay
+undrain          accept new clients again
+reload           re-read limits, bans and the trace filter from the config file
+stats            server wide counters
+shutdown         stop the server
+quit             close this session
+";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AdminCommand {
+	Clients,
+	Kick(Token),
+	Ban(IpAddr),
+	Unban(IpAddr),
+	/* true for drain, false for undrain */
+	Drain(bool),
+	Reload,
+	Stats,
+	Shutdown,
+	Help,
+	Quit,
+	/* with the reason, it's echoed back to the operator */
+	Invalid(String),
+}
+
+impl AdminCommand {
+	pub fn parse(line: &str) -> AdminCommand {
+		let words: Vec<&str> = line.split_whitespace().collect();
+		if words.len() > 2 {
+			return AdminCommand::Invalid(format!("too many arguments: {}", line.trim()));
+		}
+
+		match (words.get(0).cloned(), words.get(1).cloned()) {
+			(Some("clients"), None)		=> AdminCommand::Clients,
+			(Some("stats"), None)		=> AdminCommand::Stats,
+			(Some("drain"), None)		=> AdminCommand::Drain(true),
+			(Some("undrain"), None)		=> AdminCommand::Drain(false),
+			(Some("reload"), None)		=> AdminCommand::Reload,
+			(Some("shutdown"), None)	=> AdminCommand::Shutdown,
+			(Some("help"), None)		=> AdminCommand::Help,
+			(Some("quit"), None)		=> AdminCommand::Quit,
+			(Some("kick"), Some(token)) => match token.parse::<usize>() {
+				Ok(token)	=> AdminCommand::Kick(Token(token)),
+				Err(_)		=> AdminCommand::Invalid(format!("not a token: {}", token)),
+			},
+			(Some("ban"), Some(ip)) => match IpAddr::from_str(ip) {
+				Ok(ip)		=> AdminCommand::Ban(ip),
+				Err(_)		=> AdminCommand::Invalid(format!("not an address: {}", ip)),
+			},
+			(Some("unban"), Some(ip)) => match IpAddr::from_str(ip) {
+				Ok(ip)		=> AdminCommand::Unban(ip),
+				Err(_)		=> AdminCommand::Invalid(format!("not an address: {}", ip)),
+			},
+			(None, _)					=> AdminCommand::Invalid("empty command".to_string()),
+			_							=> AdminCommand::Invalid(format!("unknown command: {}, try help", line.trim())),
+		}
+	}
+}
+
+struct Session {
+	stream:			TcpStream,
+	/* bytes after the last complete line */
+	input:			Vec<u8>,
+	output:			Vec<u8>,
+	/* close once the output is written */
+	closing:		bool,
+}
+
+/* the admin listener and its sessions, driven by the handler on the server's own event loop */
+/* there is no authentication, bind it to a loopback or otherwise private address */
+pub struct AdminConsole {
+	listener:		TcpListener,
+	token:			Token,
+	sessions:		HashMap<Token, Session>,
+}
+
+impl AdminConsole {
+	pub fn new(listener: TcpListener, token: Token) -> Self {
+		AdminConsole {
+			listener:		listener,
+			token:			token,
+			sessions:		HashMap::new(),
+		}
+	}
+
+	pub fn register(&mut self, registry: &Registry) -> FiestaResult<()> {
+		try!(registry.register(&mut self.listener, self.token, Interest::READABLE));
+		Ok(())
+	}
+
+	pub fn owns(&self, token: Token) -> bool {
+		token == self.token || self.sessions.contains_key(&token)
+	}
+
+	pub fn is_listener(&self, token: Token) -> bool {
+		token == self.token
+	}
+
+	/* Ok(None) once the backlog is empty, the listener is edge triggered so keep going until then */
+	pub fn accept(&self) -> FiestaResult<Option<TcpStream>> {
+		match self.listener.accept() {
+			Ok((stream, _))					=> Ok(Some(stream)),
+			Err(ref e) if is_transient(e)	=> Ok(None),
+			Err(e)							=> Err(From::from(e)),
+		}
+	}
+
+	pub fn add_session(&mut self, registry: &Registry, token: Token, mut stream: TcpStream) -> FiestaResult<()> {
+		try!(registry.register(&mut stream, token, Interest::READABLE));
+		info!(target: "network", "admin session {:?} opened from {:?}", token, stream.peer_addr().ok());
+		self.sessions.insert(token, Session {
+			stream:			stream,
+			input:			Vec::new(),
+			output:			Vec::new(),
+			closing:		false,
+		});
+		self.reply(token, "fiesta admin console, try help\n");
+		Ok(())
+	}
+
+	/* every complete line read so far, None once the operator hung up */
+	pub fn read_commands(&mut self, token: Token) -> FiestaResult<Option<Vec<AdminCommand>>> {
+		let session = match self.sessions.get_mut(&token) {
+			Some(session)	=> session,
+			None			=> return Ok(None),
+		};
+
+		let mut chunk = [0; 512];
+		loop {
+			match session.stream.read(&mut chunk) {
+				Ok(0)							=> return Ok(None),
+				Ok(size)						=> session.input.extend_from_slice(&chunk[0..size]),
+				Err(ref e) if is_transient(e)	=> break,
+				Err(e)							=> return Err(From::from(e)),
+			}
+		}
+
+		let mut commands = Vec::new();
+		while let Some(end) = session.input.iter().position(|b| *b == b'\n') {
+			let line: Vec<u8> = session.input.drain(0..end + 1).collect();
+			let line = String::from_utf8_lossy(&line[..]);
+			if !line.trim().is_empty() {
+				commands.push(AdminCommand::parse(&line));
+			}
+		}
+		if session.input.len() > MAX_LINE {
+			return Err(From::from(Error::new(ErrorKind::InvalidData, "admin command line too long")));
+		}
+		Ok(Some(commands))
+	}
+
+	pub fn reply(&mut self, token: Token, text: &str) {
+		if let Some(session) = self.sessions.get_mut(&token) {
+			session.output.extend_from_slice(text.as_bytes());
+		}
+	}
+
+	/* the session is closed after the pending replies */
+	pub fn quit(&mut self, token: Token) {
+		if let Some(session) = self.sessions.get_mut(&token) {
+			session.closing = true;
+		}
+	}
+
+	/* writes what the socket takes and only asks for writable while there's more */
+	pub fn flush(&mut self, registry: &Registry, token: Token) -> FiestaResult<()> {
+		let done = match self.sessions.get_mut(&token) {
+			Some(session) => {
+				while !session.output.is_empty() {
+					match session.stream.write(&session.output[..]) {
+						Ok(0)							=> break,
+						Ok(size)						=> { session.output.drain(0..size); },
+						Err(ref e) if is_transient(e)	=> break,
+						Err(e)							=> return Err(From::from(e)),
+					}
+				}
+				if session.output.is_empty() && session.closing {
+					true
+				} else {
+					let interest = if session.output.is_empty() {
+						Interest::READABLE
+					} else {
+						Interest::READABLE | Interest::WRITABLE
+					};
+					try!(registry.reregister(&mut session.stream, token, interest));
+					false
+				}
+			},
+			None => false,
+		};
+
+		if done {
+			self.close(registry, token);
+		}
+		Ok(())
+	}
+
+	pub fn close(&mut self, registry: &Registry, token: Token) {
+		if let Some(mut session) = self.sessions.remove(&token) {
+			let _ = registry.deregister(&mut session.stream);
+			let _ = session.stream.shutdown(Shutdown::Both);
+			info!(target: "network", "admin session {:?} closed.", token);
+		}
+	}
+}
@@ -0,0 +1,129 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Error, Write};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use mio::Token;
+
+/* what happened to the connection */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuditKind {
+	Connect,
+	/* the account a login was accepted for */
+	Auth(String),
+	/* the account a refused login was for, and why */
+	AuthFailed(String, String),
+	/* the reason, a DisconnectReason, e.g. "peer_closed" when the client went away by itself */
+	Disconnect(String),
+	/* dropped by the server, the reason says who or what did it */
+	Kick(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct AuditRecord {
+	pub at:			SystemTime,
+	pub token:		Token,
+	/* honours a PROXY header */
+	pub addr:		Option<SocketAddr>,
+	pub label:		Option<String>,
+	pub kind:		AuditKind,
+}
+
+/* gets every AuditRecord of every client, from the reactors and the workers alike, so it */
+/* should hand records off rather than do anything slow with them */
+pub trait AuditSink: Send + Sync + 'static {
+	fn record(&self, record: &AuditRecord);
+}
+
+impl<F> AuditSink for F where F: Fn(&AuditRecord) + Send + Sync + 'static {
+	fn record(&self, record: &AuditRecord) {
+		self(record)
+	}
+}
+
+fn json_string(out: &mut String, value: &str) {
+	out.push('"');
+	for c in value.chars() {
+		match c {
+			'"'					=> out.push_str("\\\""),
+			'\\'				=> out.push_str("\\\\"),
+			'\n'				=> out.push_str("\\n"),
+			'\r'				=> out.push_str("\\r"),
+			'\t'				=> out.push_str("\\t"),
+			c if c < ' '		=> out.push_str(&format!("\\u{:04x}", c as u32)),
+			c					=> out.push(c),
+		}
+	}
+	out.push('"');
+}
+
+fn json_field(out: &mut String, name: &str, value: &str) {
+	out.push_str(", ");
+	json_string(out, name);
+	out.push_str(": ");
+	json_string(out, value);
+}
+
+impl AuditRecord {
+	/* one line, no trailing newline: {"time": 1571234567.123, "event": "auth", "token": 12, ...} */
+	pub fn to_json(&self) -> String {
+		let millis = self.at.duration_since(UNIX_EPOCH)
+			.map(|d| d.as_secs() * 1000 + (d.subsec_nanos() / 1000000) as u64)
+			.unwrap_or(0);
+		let event = match self.kind {
+			AuditKind::Connect			=> "connect",
+			AuditKind::Auth(_)			=> "auth",
+			AuditKind::AuthFailed(..)	=> "auth_failed",
+			AuditKind::Disconnect(_)	=> "disconnect",
+			AuditKind::Kick(_)			=> "kick",
+		};
+
+		let mut out = format!("{{\"time\": {}.{:03}, \"event\": \"{}\", \"token\": {}", millis / 1000, millis % 1000, event, self.token.0);
+		if let Some(addr) = self.addr {
+			json_field(&mut out, "addr", &addr.to_string());
+		}
+		if let Some(ref label) = self.label {
+			json_field(&mut out, "label", label);
+		}
+		match self.kind {
+			AuditKind::Connect => (),
+			AuditKind::Auth(ref account) => json_field(&mut out, "account", account),
+			AuditKind::AuthFailed(ref account, ref reason) => {
+				json_field(&mut out, "account", account);
+				json_field(&mut out, "reason", reason);
+			},
+			AuditKind::Disconnect(ref reason) | AuditKind::Kick(ref reason) => json_field(&mut out, "reason", reason),
+		}
+		out.push('}');
+		out
+	}
+}
+
+/* appends JSON lines to a file, each one is flushed right away so nothing is lost in a crash */
+pub struct JsonLinesAudit {
+	out:			Mutex<BufWriter<File>>,
+}
+
+impl JsonLinesAudit {
+	pub fn create<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+		let file = try!(OpenOptions::new().create(true).append(true).open(path.as_ref()));
+		Ok(JsonLinesAudit {
+			out:			Mutex::new(BufWriter::new(file)),
+		})
+	}
+}
+
+impl AuditSink for JsonLinesAudit {
+	fn record(&self, record: &AuditRecord) {
+		let mut line = record.to_json();
+		line.push('\n');
+		let result = match self.out.lock() {
+			Ok(mut out) => out.write_all(line.as_bytes()).and_then(|_| out.flush()),
+			Err(_) => return,
+		};
+		if let Err(e) = result {
+			warn!(target: "network", "failed to write audit log: {}", e);
+		}
+	}
+}
@@ -0,0 +1,217 @@
+/* prints the packets of a capture, either a pcapng written by PacketCapture or a plain pcap of the tcp traffic */
+/* usage: fiesta-dissect [--names opcodes.txt] [--port 9010] capture */
+extern crate fiesta_net;
+
+use std::env;
+use std::fs::File;
+use std::io::{Error, ErrorKind, Read};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::process;
+
+use fiesta_net::{decode_stream_with, Direction, FiestaPacket, FrameLimits};
+use fiesta_net::opcodes;
+use fiesta_net::replay::read_capture;
+
+const PCAPNG_MAGIC: u32 = 0x0a0d0d0a;
+const PCAP_MAGIC: u32 = 0xa1b2c3d4;
+const PCAP_MAGIC_NANOS: u32 = 0xa1b23c4d;
+
+const LINKTYPE_ETHERNET: u32 = 1;
+const LINKTYPE_RAW: u32 = 101;
+const LINKTYPE_LINUX_SLL: u32 = 113;
+
+struct Options {
+	/* only flows from or to this port, all of them if None */
+	port:			Option<u16>,
+	path:			String,
+}
+
+fn usage() -> ! {
+	println!("usage: fiesta-dissect [--names opcodes.txt] [--port port] capture");
+	println!("  opcodes.txt has one \"0x0801 NAME\" per line");
+	process::exit(2);
+}
+
+fn parse_args() -> Options {
+	let mut options = Options {
+		port:			None,
+		path:			String::new(),
+	};
+	let mut args = env::args().skip(1);
+	while let Some(arg) = args.next() {
+		match arg.as_ref() {
+			"--names" => {
+				let path = args.next().unwrap_or_else(|| usage());
+				opcodes::load_names(&path).unwrap_or_else(|e| {
+					println!("failed to read {}: {}", path, e);
+					process::exit(1);
+				});
+			},
+			"--port" => {
+				options.port = args.next().and_then(|port| port.parse().ok());
+				if options.port.is_none() {
+					usage();
+				}
+			},
+			_ if options.path.is_empty() && !arg.starts_with("--") => options.path = arg,
+			_ => usage(),
+		}
+	}
+	if options.path.is_empty() {
+		usage();
+	}
+	options
+}
+
+fn print_packet(prefix: &str, header: u16, packet: &FiestaPacket) {
+	let name = opcodes::name(header).unwrap_or_else(|| "?".to_string());
+	println!("{} {:#06x} {} ({} bytes)", prefix, header, name, packet.data.bytes_remaining());
+	print!("{}", packet.data);
+}
+
+/* the crate's own captures already hold one packet per record */
+fn dissect_pcapng(options: &Options) -> Result<(), Error> {
+	for captured in try!(read_capture(&options.path)) {
+		let arrow = if captured.direction == Direction::Inbound { "->" } else { "<-" };
+		let prefix = format!("{}.{:06} {:?} {}", captured.timestamp / 1000000, captured.timestamp % 1000000, captured.token, arrow);
+		let mut packet = FiestaPacket::new(captured.header, captured.body.len());
+		packet.data.append(&captured.body[..]);
+		print_packet(&prefix, captured.header, &packet);
+	}
+	Ok(())
+}
+
+fn u16_be(data: &[u8], offset: usize) -> u16 {
+	((data[offset] as u16) << 8) | data[offset + 1] as u16
+}
+
+/* (source, destination, tcp payload) of an ip packet, None for anything else */
+fn tcp_payload(ip: &[u8]) -> Option<(SocketAddr, SocketAddr, &[u8])> {
+	let (src, dst, tcp) = match ip.first().map(|b| b >> 4) {
+		Some(4) if ip.len() >= 20 && ip[9] == 6 => {
+			let ihl = ((ip[0] & 0x0f) as usize) * 4;
+			let total = u16_be(ip, 2) as usize;
+			if ihl < 20 || total < ihl || total > ip.len() {
+				return None;
+			}
+			(IpAddr::V4(Ipv4Addr::new(ip[12], ip[13], ip[14], ip[15])),
+			 IpAddr::V4(Ipv4Addr::new(ip[16], ip[17], ip[18], ip[19])),
+			 &ip[ihl..total])
+		},
+		/* extension headers aren't followed */
+		Some(6) if ip.len() >= 40 && ip[6] == 6 => {
+			let mut src = [0; 16];
+			let mut dst = [0; 16];
+			src.copy_from_slice(&ip[8..24]);
+			dst.copy_from_slice(&ip[24..40]);
+			let end = (40 + u16_be(ip, 4) as usize).min(ip.len());
+			(IpAddr::V6(Ipv6Addr::from(src)), IpAddr::V6(Ipv6Addr::from(dst)), &ip[40..end])
+		},
+		_ => return None,
+	};
+
+	if tcp.len() < 20 {
+		return None;
+	}
+	let offset = ((tcp[12] >> 4) as usize) * 4;
+	if offset < 20 || offset > tcp.len() {
+		return None;
+	}
+	Some((SocketAddr::new(src, u16_be(tcp, 0)), SocketAddr::new(dst, u16_be(tcp, 2)), &tcp[offset..]))
+}
+
+/* the ip packet inside a link layer frame */
+fn ip_packet(linktype: u32, frame: &[u8]) -> Option<&[u8]> {
+	let (header, ethertype) = match linktype {
+		LINKTYPE_RAW								=> return Some(frame),
+		LINKTYPE_ETHERNET if frame.len() >= 14		=> (14, u16_be(frame, 12)),
+		LINKTYPE_LINUX_SLL if frame.len() >= 16		=> (16, u16_be(frame, 14)),
+		_											=> return None,
+	};
+	match ethertype {
+		0x0800 | 0x86dd	=> Some(&frame[header..]),
+		_				=> None,
+	}
+}
+
+/* payloads are appended in capture order, retransmissions and reordering aren't undone */
+fn dissect_pcap(options: &Options, data: &[u8]) -> Result<(), Error> {
+	if data.len() < 24 {
+		return Err(Error::new(ErrorKind::InvalidData, "short pcap header"));
+	}
+	let little = data[0] == 0xd4 || data[0] == 0x4d;
+	let u32_at = |offset: usize| -> u32 {
+		let bytes = &data[offset..offset + 4];
+		if little {
+			(bytes[0] as u32) | ((bytes[1] as u32) << 8) | ((bytes[2] as u32) << 16) | ((bytes[3] as u32) << 24)
+		} else {
+			((bytes[0] as u32) << 24) | ((bytes[1] as u32) << 16) | ((bytes[2] as u32) << 8) | (bytes[3] as u32)
+		}
+	};
+	let linktype = u32_at(20);
+
+	let mut flows: Vec<((SocketAddr, SocketAddr), Vec<u8>)> = Vec::new();
+	let mut offset = 24;
+	while offset + 16 <= data.len() {
+		let captured = u32_at(offset + 8) as usize;
+		let start = offset + 16;
+		if start + captured > data.len() {
+			return Err(Error::new(ErrorKind::InvalidData, "truncated pcap record"));
+		}
+		let frame = &data[start..start + captured];
+		offset = start + captured;
+
+		if let Some((src, dst, payload)) = ip_packet(linktype, frame).and_then(tcp_payload) {
+			if payload.is_empty() {
+				continue;
+			}
+			if let Some(port) = options.port {
+				if src.port() != port && dst.port() != port {
+					continue;
+				}
+			}
+			match flows.iter().position(|&(key, _)| key == (src, dst)) {
+				Some(index)	=> flows[index].1.extend_from_slice(payload),
+				None		=> flows.push(((src, dst), payload.to_vec())),
+			}
+		}
+	}
+
+	let limits = FrameLimits::new(0xffff);
+	for &((src, dst), ref stream) in flows.iter() {
+		println!("== {} -> {}, {} bytes", src, dst, stream.len());
+		for packet in decode_stream_with(&stream[..], &limits) {
+			match packet {
+				Ok(packet)	=> print_packet("  ", packet.header, &packet),
+				Err(e)		=> println!("  framing stopped: {}", e),
+			}
+		}
+	}
+	Ok(())
+}
+
+fn dissect(options: &Options) -> Result<(), Error> {
+	let mut data = Vec::new();
+	try!(try!(File::open(&options.path)).read_to_end(&mut data));
+	if data.len() < 4 {
+		return Err(Error::new(ErrorKind::InvalidData, "not a capture"));
+	}
+
+	let magic = ((data[0] as u32) << 24) | ((data[1] as u32) << 16) | ((data[2] as u32) << 8) | (data[3] as u32);
+	let swapped = magic.swap_bytes();
+	if magic == PCAPNG_MAGIC {
+		dissect_pcapng(options)
+	} else if magic == PCAP_MAGIC || swapped == PCAP_MAGIC || magic == PCAP_MAGIC_NANOS || swapped == PCAP_MAGIC_NANOS {
+		dissect_pcap(options, &data[..])
+	} else {
+		Err(Error::new(ErrorKind::InvalidData, "neither pcap nor pcapng"))
+	}
+}
+
+fn main() {
+	let options = parse_args();
+	if let Err(e) = dissect(&options) {
+		println!("{}: {}", options.path, e);
+		process::exit(1);
+	}
+}
@@ -0,0 +1,297 @@
+use std::cmp::min;
+use std::fmt;
+use std::sync::Arc;
+
+use buffer::*;
+use hexdump;
+
+/* bodies up to this size are copied into the packet itself instead of a buffer of their own */
+pub const INLINE_BODY_SIZE: usize = 32;
+
+/* refcounted view into bytes read from a socket, cloning it doesn't copy anything */
+#[derive(Clone)]
+pub struct SharedBytes {
+	data:			Arc<Vec<u8>>,
+	start:			usize,
+	end:			usize,
+}
+
+impl SharedBytes {
+	pub fn new(data: Arc<Vec<u8>>, start: usize, end: usize) -> Self {
+		assert!(start <= end && end <= data.len());
+		SharedBytes {
+			data:			data,
+			start:			start,
+			end:			end,
+		}
+	}
+
+	pub fn from_vec(data: Vec<u8>) -> Self {
+		let end = data.len();
+		SharedBytes::new(Arc::new(data), 0, end)
+	}
+
+	pub fn len(&self) -> usize {
+		self.end - self.start
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.start == self.end
+	}
+
+	pub fn as_slice(&self) -> &[u8] {
+		&self.data[self.start..self.end]
+	}
+
+	/* offsets are relative to this view */
+	pub fn slice(&self, start: usize, end: usize) -> SharedBytes {
+		assert!(start <= end && end <= self.len());
+		SharedBytes::new(self.data.clone(), self.start + start, self.start + end)
+	}
+
+	pub fn advance(&mut self, bytes: usize) {
+		self.start += min(bytes, self.len());
+	}
+}
+
+impl BinaryReadable for SharedBytes {
+	fn read_bytes(&mut self, size: usize) -> Result<Vec<u8>, BufferError> {
+		let result = try!(self.peek_bytes(0, size));
+		self.advance(size);
+		Ok(result)
+	}
+
+	fn read_to_slice(&mut self, buf: &mut [u8]) -> Result<(), BufferError> {
+		try!(self.peek_to_slice(0, buf));
+		self.advance(buf.len());
+		Ok(())
+	}
+}
+
+impl BinaryPeekable for SharedBytes {
+	fn peek_bytes(&mut self, offset: usize, size: usize) -> Result<Vec<u8>, BufferError> {
+		Ok(try!(peek_slice(self.as_slice(), offset, size)).to_vec())
+	}
+
+	fn peek_to_slice(&mut self, offset: usize, buf: &mut [u8]) -> Result<(), BufferError> {
+		buf.copy_from_slice(try!(peek_slice(self.as_slice(), offset, buf.len())));
+		Ok(())
+	}
+}
+
+fn peek_slice(data: &[u8], offset: usize, size: usize) -> Result<&[u8], BufferError> {
+	if data.len() < offset + size {
+		Err(BufferError::Underflow { requested: offset + size, available: data.len() })
+	} else {
+		Ok(&data[offset..offset + size])
+	}
+}
+
+/* a small body kept right in the packet, decoding it doesn't allocate */
+#[derive(Clone, Copy)]
+pub struct InlineBytes {
+	data:			[u8; INLINE_BODY_SIZE],
+	start:			usize,
+	end:			usize,
+}
+
+impl InlineBytes {
+	/* `len` zeroes, to be filled through as_mut_slice() */
+	pub fn with_len(len: usize) -> Self {
+		assert!(len <= INLINE_BODY_SIZE);
+		InlineBytes {
+			data:			[0; INLINE_BODY_SIZE],
+			start:			0,
+			end:			len,
+		}
+	}
+
+	pub fn from_slice(bytes: &[u8]) -> Option<Self> {
+		if bytes.len() > INLINE_BODY_SIZE {
+			return None;
+		}
+		let mut inline = InlineBytes::with_len(bytes.len());
+		inline.as_mut_slice().copy_from_slice(bytes);
+		Some(inline)
+	}
+
+	pub fn len(&self) -> usize {
+		self.end - self.start
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.start == self.end
+	}
+
+	pub fn as_slice(&self) -> &[u8] {
+		&self.data[self.start..self.end]
+	}
+
+	pub fn as_mut_slice(&mut self) -> &mut [u8] {
+		&mut self.data[self.start..self.end]
+	}
+
+	pub fn advance(&mut self, bytes: usize) {
+		self.start += min(bytes, self.len());
+	}
+}
+
+impl BinaryReadable for InlineBytes {
+	fn read_bytes(&mut self, size: usize) -> Result<Vec<u8>, BufferError> {
+		let result = try!(self.peek_bytes(0, size));
+		self.advance(size);
+		Ok(result)
+	}
+
+	fn read_to_slice(&mut self, buf: &mut [u8]) -> Result<(), BufferError> {
+		try!(self.peek_to_slice(0, buf));
+		self.advance(buf.len());
+		Ok(())
+	}
+}
+
+impl BinaryPeekable for InlineBytes {
+	fn peek_bytes(&mut self, offset: usize, size: usize) -> Result<Vec<u8>, BufferError> {
+		Ok(try!(peek_slice(self.as_slice(), offset, size)).to_vec())
+	}
+
+	fn peek_to_slice(&mut self, offset: usize, buf: &mut [u8]) -> Result<(), BufferError> {
+		buf.copy_from_slice(try!(peek_slice(self.as_slice(), offset, buf.len())));
+		Ok(())
+	}
+}
+
+/* the body of a FiestaPacket: its own buffer, a view into the read chunk it arrived in, */
+/* or for small ones a copy inside the packet */
+pub enum PacketBody {
+	Owned(Buffer),
+	Shared(SharedBytes),
+	Inline(InlineBytes),
+}
+
+impl PacketBody {
+	pub fn bytes_remaining(&self) -> usize {
+		match *self {
+			PacketBody::Owned(ref buffer)	=> buffer.bytes_remaining(),
+			PacketBody::Shared(ref bytes)	=> bytes.len(),
+			PacketBody::Inline(ref bytes)	=> bytes.len(),
+		}
+	}
+
+	pub fn is_shared(&self) -> bool {
+		match *self {
+			PacketBody::Shared(_)	=> true,
+			_						=> false,
+		}
+	}
+
+	/* copy-on-write: a shared or inline body is copied into its own buffer first */
+	pub fn make_mut(&mut self) -> &mut Buffer {
+		let owned = {
+			let bytes = match *self {
+				PacketBody::Shared(ref bytes) => Some(bytes.as_slice()),
+				PacketBody::Inline(ref bytes) => Some(bytes.as_slice()),
+				PacketBody::Owned(_) => None,
+			};
+			bytes.map(|bytes| {
+				let mut buffer = Buffer::with_capacity(bytes.len());
+				/* can't fail, the buffer is exactly big enough */
+				let _ = buffer.append(bytes);
+				buffer
+			})
+		};
+		if let Some(buffer) = owned {
+			*self = PacketBody::Owned(buffer);
+		}
+
+		match *self {
+			PacketBody::Owned(ref mut buffer)	=> buffer,
+			_									=> unreachable!(),
+		}
+	}
+
+	pub fn append(&mut self, bytes: &[u8]) {
+		/* an owned body grows when a handler appends to it */
+		self.make_mut().extend(bytes);
+	}
+
+	pub fn advance_read(&mut self, bytes: usize) {
+		match *self {
+			PacketBody::Owned(ref mut buffer)	=> buffer.advance_read(bytes),
+			PacketBody::Shared(ref mut shared)	=> shared.advance(bytes),
+			PacketBody::Inline(ref mut inline)	=> inline.advance(bytes),
+		}
+	}
+
+	/* where the bytes are, an owned body may wrap around the end of its ring */
+	pub fn segments(&self) -> (&[u8], &[u8]) {
+		match *self {
+			PacketBody::Owned(ref buffer)	=> buffer.segments(),
+			PacketBody::Shared(ref bytes)	=> (bytes.as_slice(), &[]),
+			PacketBody::Inline(ref bytes)	=> (bytes.as_slice(), &[]),
+		}
+	}
+
+	pub fn to_vec(&self) -> Vec<u8> {
+		match *self {
+			PacketBody::Owned(ref buffer)	=> buffer.to_vec(),
+			PacketBody::Shared(ref bytes)	=> bytes.as_slice().to_vec(),
+			PacketBody::Inline(ref bytes)	=> bytes.as_slice().to_vec(),
+		}
+	}
+}
+
+impl fmt::Debug for PacketBody {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let kind = match *self {
+			PacketBody::Owned(_)	=> "Owned",
+			PacketBody::Shared(_)	=> "Shared",
+			PacketBody::Inline(_)	=> "Inline",
+		};
+		try!(write!(f, "{}([", kind));
+		try!(hexdump::write_hex(f, &self.to_vec()[..]));
+		write!(f, "])")
+	}
+}
+
+impl fmt::Display for PacketBody {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		hexdump::write_dump(f, &self.to_vec()[..])
+	}
+}
+
+impl BinaryReadable for PacketBody {
+	fn read_bytes(&mut self, size: usize) -> Result<Vec<u8>, BufferError> {
+		match *self {
+			PacketBody::Owned(ref mut buffer)	=> buffer.read_bytes(size),
+			PacketBody::Shared(ref mut shared)	=> shared.read_bytes(size),
+			PacketBody::Inline(ref mut inline)	=> inline.read_bytes(size),
+		}
+	}
+
+	fn read_to_slice(&mut self, buf: &mut [u8]) -> Result<(), BufferError> {
+		match *self {
+			PacketBody::Owned(ref mut buffer)	=> buffer.read_to_slice(buf),
+			PacketBody::Shared(ref mut shared)	=> shared.read_to_slice(buf),
+			PacketBody::Inline(ref mut inline)	=> inline.read_to_slice(buf),
+		}
+	}
+}
+
+impl BinaryPeekable for PacketBody {
+	fn peek_bytes(&mut self, offset: usize, size: usize) -> Result<Vec<u8>, BufferError> {
+		match *self {
+			PacketBody::Owned(ref mut buffer)	=> buffer.peek_bytes(offset, size),
+			PacketBody::Shared(ref mut shared)	=> shared.peek_bytes(offset, size),
+			PacketBody::Inline(ref mut inline)	=> inline.peek_bytes(offset, size),
+		}
+	}
+
+	fn peek_to_slice(&mut self, offset: usize, buf: &mut [u8]) -> Result<(), BufferError> {
+		match *self {
+			PacketBody::Owned(ref mut buffer)	=> buffer.peek_to_slice(offset, buf),
+			PacketBody::Shared(ref mut shared)	=> shared.peek_to_slice(offset, buf),
+			PacketBody::Inline(ref mut inline)	=> inline.peek_to_slice(offset, buf),
+		}
+	}
+}
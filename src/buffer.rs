@@ -1,41 +1,168 @@
-use std::io::{Error, ErrorKind, Write};
-use mio::buf::*;
+use std::cmp::{min, max};
+use std::error;
+use std::fmt;
+use std::collections::VecDeque;
+use std::io::{Error, ErrorKind, Read};
+use std::os::unix::io::AsRawFd;
+use libc;
+
+use hexdump;
 
 /* buffer of clients */
 pub const BUFFERSIZE: usize = 4 * 1024;		/* 4 KB should be plenty */
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BufferError {
+	/* a read or peek past the readable bytes */
+	Underflow { requested: usize, available: usize },
+	/* an append that doesn't fit */
+	Overflow { requested: usize, free: usize },
+	/* a read_uint, peek_uint or append_uint of more than the 8 bytes a u64 holds */
+	TooWide { size: usize },
+}
+
+impl fmt::Display for BufferError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			BufferError::Underflow { requested, available }	=> write!(f, "wanted {} bytes, only {} available", requested, available),
+			BufferError::Overflow { requested, free }		=> write!(f, "can't fit {} bytes, only {} free", requested, free),
+			BufferError::TooWide { size }					=> write!(f, "can't use {} bytes as an integer, 8 at most", size),
+		}
+	}
+}
+
+impl error::Error for BufferError {
+	fn description(&self) -> &str {
+		match *self {
+			BufferError::Underflow { .. }	=> "not enough data",
+			BufferError::Overflow { .. }	=> "buffer full",
+			BufferError::TooWide { .. }		=> "integer too wide",
+		}
+	}
+}
+
+/* so the framing code can keep using io::Error, the BufferError stays reachable through get_ref() */
+impl From<BufferError> for Error {
+	fn from(e: BufferError) -> Self {
+		let kind = match e {
+			BufferError::Underflow { .. }	=> ErrorKind::UnexpectedEof,
+			BufferError::Overflow { .. }	=> ErrorKind::Other,
+			BufferError::TooWide { .. }		=> ErrorKind::InvalidInput,
+		};
+		Error::new(kind, e)
+	}
+}
+
+fn check_underflow(requested: usize, available: usize) -> Result<(), BufferError> {
+	if requested > available {
+		Err(BufferError::Underflow { requested: requested, available: available })
+	} else {
+		Ok(())
+	}
+}
+
+fn check_width(size: usize) -> Result<(), BufferError> {
+	if size > 8 {
+		Err(BufferError::TooWide { size: size })
+	} else {
+		Ok(())
+	}
+}
+
+/* the plain read_ and peek_ methods are big endian, the _le ones are for everything else */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endianness {
+	Big,
+	Little,
+}
+
+fn decode_uint(buf: &[u8], endianness: Endianness) -> u64 {
+	match endianness {
+		Endianness::Big		=> buf.iter().fold(0, |acc, &b| (acc << 8) | b as u64),
+		Endianness::Little	=> buf.iter().rev().fold(0, |acc, &b| (acc << 8) | b as u64),
+	}
+}
+
+fn encode_uint(value: u64, size: usize, endianness: Endianness) -> Vec<u8> {
+	let mut buf: Vec<u8> = (0..size).map(|i| (value >> (8 * i)) as u8).collect();
+	if endianness == Endianness::Big {
+		buf.reverse();
+	}
+	buf
+}
+
 pub trait BinaryReadable {
-	fn read_bytes(&mut self, size: usize) -> Result<Vec<u8>, Error>;
-	fn read_u8(&mut self) -> Result<u8, Error> {
-		let buf = try!(self.read_bytes(1));
+	fn read_bytes(&mut self, size: usize) -> Result<Vec<u8>, BufferError>;
+
+	/* fills `buf`, the fixed size reads below use it with an array on the stack. */
+	/* the default goes through read_bytes, implementations override it to not allocate */
+	fn read_to_slice(&mut self, buf: &mut [u8]) -> Result<(), BufferError> {
+		let bytes = try!(self.read_bytes(buf.len()));
+		buf.copy_from_slice(&bytes[..]);
+		Ok(())
+	}
+
+	/* `size` bytes (at most 8) as an unsigned integer */
+	fn read_uint(&mut self, size: usize, endianness: Endianness) -> Result<u64, BufferError> {
+		try!(check_width(size));
+		let mut buf = [0; 8];
+		try!(self.read_to_slice(&mut buf[..size]));
+		Ok(decode_uint(&buf[..size], endianness))
+	}
+	fn read_u16_le(&mut self) -> Result<u16, BufferError> {
+		Ok(try!(self.read_uint(2, Endianness::Little)) as u16)
+	}
+	fn read_i16_le(&mut self) -> Result<i16, BufferError> {
+		Ok(try!(self.read_uint(2, Endianness::Little)) as i16)
+	}
+	fn read_u32_le(&mut self) -> Result<u32, BufferError> {
+		Ok(try!(self.read_uint(4, Endianness::Little)) as u32)
+	}
+	fn read_i32_le(&mut self) -> Result<i32, BufferError> {
+		Ok(try!(self.read_uint(4, Endianness::Little)) as i32)
+	}
+	fn read_u64_le(&mut self) -> Result<u64, BufferError> {
+		self.read_uint(8, Endianness::Little)
+	}
+	fn read_i64_le(&mut self) -> Result<i64, BufferError> {
+		Ok(try!(self.read_uint(8, Endianness::Little)) as i64)
+	}
+
+	fn read_u8(&mut self) -> Result<u8, BufferError> {
+		let mut buf = [0; 1];
+		try!(self.read_to_slice(&mut buf));
 		let result = buf[0];
 
 		Ok(result)
 	}
-	fn read_i8(&mut self) -> Result<i8, Error> {
-		let buf = try!(self.read_bytes(1));
+	fn read_i8(&mut self) -> Result<i8, BufferError> {
+		let mut buf = [0; 1];
+		try!(self.read_to_slice(&mut buf));
 		let result = buf[0] as i8;
 
 		Ok(result)
 	}
-	fn read_u16(&mut self) -> Result<u16, Error> {
-		let buf = try!(self.read_bytes(2));
+	fn read_u16(&mut self) -> Result<u16, BufferError> {
+		let mut buf = [0; 2];
+		try!(self.read_to_slice(&mut buf));
 		let result = 
 				(buf[1] as u16) 
 			|	((buf[0] as u16) << 8);
 
 		Ok(result)
 	}
-	fn read_i16(&mut self) -> Result<i16, Error> {
-		let buf = try!(self.read_bytes(2));
+	fn read_i16(&mut self) -> Result<i16, BufferError> {
+		let mut buf = [0; 2];
+		try!(self.read_to_slice(&mut buf));
 		let result = 
 				(buf[1] as i16) 
 			|	((buf[0] as i16) << 8);
 
 		Ok(result)
 	}
-	fn read_u32(&mut self) -> Result<u32, Error> {
-		let buf = try!(self.read_bytes(4));
+	fn read_u32(&mut self) -> Result<u32, BufferError> {
+		let mut buf = [0; 4];
+		try!(self.read_to_slice(&mut buf));
 		let result = 
 				(buf[3] as u32)
 			|	((buf[2] as u32) << 8)
@@ -44,8 +171,9 @@ pub trait BinaryReadable {
 
 		Ok(result)
 	}
-	fn read_i32(&mut self) -> Result<i32, Error> {
-		let buf = try!(self.read_bytes(4));
+	fn read_i32(&mut self) -> Result<i32, BufferError> {
+		let mut buf = [0; 4];
+		try!(self.read_to_slice(&mut buf));
 		let result = 
 				(buf[3] as i32)
 			|	((buf[2] as i32) << 8)
@@ -54,8 +182,9 @@ pub trait BinaryReadable {
 
 		Ok(result)
 	}
-	fn read_u64(&mut self) -> Result<u64, Error> {
-		let buf = try!(self.read_bytes(8));
+	fn read_u64(&mut self) -> Result<u64, BufferError> {
+		let mut buf = [0; 8];
+		try!(self.read_to_slice(&mut buf));
 		let result = 
 				(buf[7] as u64)
 			|	((buf[6] as u64) << 8)
@@ -68,8 +197,9 @@ pub trait BinaryReadable {
 
 		Ok(result)
 	}
-	fn read_i64(&mut self) -> Result<i64, Error> {
-		let buf = try!(self.read_bytes(8));
+	fn read_i64(&mut self) -> Result<i64, BufferError> {
+		let mut buf = [0; 8];
+		try!(self.read_to_slice(&mut buf));
 		let result = 
 				(buf[7] as i64)
 			|	((buf[6] as i64) << 8)
@@ -85,38 +215,75 @@ pub trait BinaryReadable {
 }
 
 pub trait BinaryPeekable {
-	fn peek_bytes(&mut self, offset: usize, size: usize) -> Result<Vec<u8>, Error>;
+	fn peek_bytes(&mut self, offset: usize, size: usize) -> Result<Vec<u8>, BufferError>;
+
+	/* like read_to_slice, without consuming anything */
+	fn peek_to_slice(&mut self, offset: usize, buf: &mut [u8]) -> Result<(), BufferError> {
+		let bytes = try!(self.peek_bytes(offset, buf.len()));
+		buf.copy_from_slice(&bytes[..]);
+		Ok(())
+	}
 
-	fn peek_u8(&mut self, offset: usize) -> Result<u8, Error> {
-		let buf = try!(self.peek_bytes(offset, 1));
+	fn peek_uint(&mut self, offset: usize, size: usize, endianness: Endianness) -> Result<u64, BufferError> {
+		try!(check_width(size));
+		let mut buf = [0; 8];
+		try!(self.peek_to_slice(offset, &mut buf[..size]));
+		Ok(decode_uint(&buf[..size], endianness))
+	}
+	fn peek_u16_le(&mut self, offset: usize) -> Result<u16, BufferError> {
+		Ok(try!(self.peek_uint(offset, 2, Endianness::Little)) as u16)
+	}
+	fn peek_i16_le(&mut self, offset: usize) -> Result<i16, BufferError> {
+		Ok(try!(self.peek_uint(offset, 2, Endianness::Little)) as i16)
+	}
+	fn peek_u32_le(&mut self, offset: usize) -> Result<u32, BufferError> {
+		Ok(try!(self.peek_uint(offset, 4, Endianness::Little)) as u32)
+	}
+	fn peek_i32_le(&mut self, offset: usize) -> Result<i32, BufferError> {
+		Ok(try!(self.peek_uint(offset, 4, Endianness::Little)) as i32)
+	}
+	fn peek_u64_le(&mut self, offset: usize) -> Result<u64, BufferError> {
+		self.peek_uint(offset, 8, Endianness::Little)
+	}
+	fn peek_i64_le(&mut self, offset: usize) -> Result<i64, BufferError> {
+		Ok(try!(self.peek_uint(offset, 8, Endianness::Little)) as i64)
+	}
+
+	fn peek_u8(&mut self, offset: usize) -> Result<u8, BufferError> {
+		let mut buf = [0; 1];
+		try!(self.peek_to_slice(offset, &mut buf));
 		let result = buf[0];
 
 		Ok(result)
 	}
-	fn peek_i8(&mut self, offset: usize) -> Result<i8, Error> {
-		let buf = try!(self.peek_bytes(offset, 1));
+	fn peek_i8(&mut self, offset: usize) -> Result<i8, BufferError> {
+		let mut buf = [0; 1];
+		try!(self.peek_to_slice(offset, &mut buf));
 		let result = buf[0] as i8;
 
 		Ok(result)
 	}
-	fn peek_u16(&mut self, offset: usize) -> Result<u16, Error> {
-		let buf = try!(self.peek_bytes(offset, 2));
+	fn peek_u16(&mut self, offset: usize) -> Result<u16, BufferError> {
+		let mut buf = [0; 2];
+		try!(self.peek_to_slice(offset, &mut buf));
 		let result = 
 				(buf[1] as u16) 
 			|	((buf[0] as u16) << 8);
 
 		Ok(result)
 	}
-	fn peek_i16(&mut self, offset: usize) -> Result<i16, Error> {
-		let buf = try!(self.peek_bytes(offset, 2));
+	fn peek_i16(&mut self, offset: usize) -> Result<i16, BufferError> {
+		let mut buf = [0; 2];
+		try!(self.peek_to_slice(offset, &mut buf));
 		let result = 
 				(buf[1] as i16) 
 			|	((buf[0] as i16) << 8);
 
 		Ok(result)
 	}
-	fn peek_u32(&mut self, offset: usize) -> Result<u32, Error> {
-		let buf = try!(self.peek_bytes(offset, 4));
+	fn peek_u32(&mut self, offset: usize) -> Result<u32, BufferError> {
+		let mut buf = [0; 4];
+		try!(self.peek_to_slice(offset, &mut buf));
 		let result = 
 				(buf[3] as u32)
 			|	((buf[2] as u32) << 8)
@@ -125,8 +292,9 @@ pub trait BinaryPeekable {
 
 		Ok(result)
 	}
-	fn peek_i32(&mut self, offset: usize) -> Result<i32, Error> {
-		let buf = try!(self.peek_bytes(offset, 4));
+	fn peek_i32(&mut self, offset: usize) -> Result<i32, BufferError> {
+		let mut buf = [0; 4];
+		try!(self.peek_to_slice(offset, &mut buf));
 		let result = 
 				(buf[3] as i32)
 			|	((buf[2] as i32) << 8)
@@ -135,8 +303,9 @@ pub trait BinaryPeekable {
 
 		Ok(result)
 	}
-	fn peek_u64(&mut self, offset: usize) -> Result<u64, Error> {
-		let buf = try!(self.peek_bytes(offset, 8));
+	fn peek_u64(&mut self, offset: usize) -> Result<u64, BufferError> {
+		let mut buf = [0; 8];
+		try!(self.peek_to_slice(offset, &mut buf));
 		let result = 
 				(buf[7] as u64)
 			|	((buf[6] as u64) << 8)
@@ -149,8 +318,9 @@ pub trait BinaryPeekable {
 
 		Ok(result)
 	}
-	fn peek_i64(&mut self, offset: usize) -> Result<i64, Error> {
-		let buf = try!(self.peek_bytes(offset, 8));
+	fn peek_i64(&mut self, offset: usize) -> Result<i64, BufferError> {
+		let mut buf = [0; 8];
+		try!(self.peek_to_slice(offset, &mut buf));
 		let result = 
 				(buf[7] as i64)
 			|	((buf[6] as i64) << 8)
@@ -166,87 +336,354 @@ pub trait BinaryPeekable {
 }
 
 pub struct Buffer {
-	buffer:			Box<RingBuf>,
+	data:			Vec<u8>,
+	/* read position, the readable bytes may wrap around the end of `data` */
+	head:			usize,
 	remaining:		usize,
 }
 
 impl Buffer {
 	pub fn new() -> Self {
-		Buffer {
-			buffer:		Box::new(RingBuf::new(BUFFERSIZE)),
-			remaining:	0,
-		}
+		Buffer::with_capacity(BUFFERSIZE)
 	}
 
 	pub fn with_capacity(capacity: usize) -> Self {
 		Buffer {
-			buffer:		Box::new(RingBuf::new(capacity)),
+			data:		vec![0; capacity],
+			head:		0,
 			remaining:	0,
 		}
 	}
 
+	pub fn capacity(&self) -> usize {
+		self.data.len()
+	}
+
 	pub fn bytes_remaining(&self) -> usize {
 		self.remaining
 	}
 
-	pub fn append(&mut self, bytes: &[u8]) {
-		self.buffer.write(bytes).unwrap();
+	pub fn free(&self) -> usize {
+		self.capacity() - self.remaining
+	}
+
+	fn wrap(&self, position: usize) -> usize {
+		if self.capacity() == 0 { 0 } else { position % self.capacity() }
+	}
+
+	/* all or nothing, a partially appended frame is worse than none */
+	pub fn append(&mut self, bytes: &[u8]) -> Result<(), BufferError> {
+		if bytes.len() > self.free() {
+			warn!(target: "network", "buffer full, refusing {} bytes.", bytes.len());
+			return Err(BufferError::Overflow { requested: bytes.len(), free: self.free() });
+		}
+
+		let tail = self.wrap(self.head + self.remaining);
+		let first = min(bytes.len(), self.capacity() - tail);
+		self.data[tail..tail + first].copy_from_slice(&bytes[0..first]);
+		self.data[0..bytes.len() - first].copy_from_slice(&bytes[first..]);
 		self.remaining += bytes.len();
-		if self.remaining > BUFFERSIZE {
-			// panic!("overwritten some data!!");
-			warn!(target: "networking", "overwritten some data.");
-			self.remaining = BUFFERSIZE;
+		Ok(())
+	}
+
+	/* grows the ring so `additional` more bytes fit, the only place bytes get moved around */
+	pub fn reserve(&mut self, additional: usize) {
+		if self.free() >= additional {
+			return;
+		}
+
+		let capacity = max(self.capacity() * 2, self.remaining + additional).next_power_of_two();
+		let mut data = vec![0; capacity];
+		{
+			let (first, second) = self.segments();
+			data[0..first.len()].copy_from_slice(first);
+			data[first.len()..first.len() + second.len()].copy_from_slice(second);
+		}
+		self.data = data;
+		self.head = 0;
+	}
+
+	/* the low `size` bytes of `value` */
+	pub fn append_uint(&mut self, value: u64, size: usize, endianness: Endianness) -> Result<(), BufferError> {
+		try!(check_width(size));
+		self.append(&encode_uint(value, size, endianness)[..])
+	}
+
+	pub fn append_u8(&mut self, value: u8) -> Result<(), BufferError> {
+		self.append(&[value])
+	}
+
+	pub fn append_u16(&mut self, value: u16, endianness: Endianness) -> Result<(), BufferError> {
+		self.append_uint(value as u64, 2, endianness)
+	}
+
+	pub fn append_u32(&mut self, value: u32, endianness: Endianness) -> Result<(), BufferError> {
+		self.append_uint(value as u64, 4, endianness)
+	}
+
+	pub fn append_u64(&mut self, value: u64, endianness: Endianness) -> Result<(), BufferError> {
+		self.append_uint(value, 8, endianness)
+	}
+
+	/* append that grows the buffer instead of failing */
+	pub fn extend(&mut self, bytes: &[u8]) {
+		self.reserve(bytes.len());
+		/* can't fail after the reserve */
+		let _ = self.append(bytes);
+	}
+
+	/* the free space after the readable bytes, the second slice is empty unless it wraps around */
+	pub fn free_segments_mut(&mut self) -> (&mut [u8], &mut [u8]) {
+		let free = self.free();
+		let tail = self.wrap(self.head + self.remaining);
+		let first = min(free, self.capacity() - tail);
+		let (front, back) = self.data.split_at_mut(tail);
+		(&mut back[0..first], &mut front[0..free - first])
+	}
+
+	/* `bytes` written into free_segments_mut() become readable */
+	pub fn commit(&mut self, bytes: usize) {
+		self.remaining += min(bytes, self.free());
+	}
+
+	/* reads at most `max` bytes from `reader` straight into the ring, Ok(0) on EOF or a full buffer */
+	pub fn read_from<R: Read>(&mut self, reader: &mut R, max: usize) -> Result<usize, Error> {
+		let size = {
+			let (first, _) = self.free_segments_mut();
+			let len = min(first.len(), max);
+			if len == 0 {
+				return Ok(0);
+			}
+			try!(reader.read(&mut first[0..len]))
+		};
+		self.commit(size);
+		Ok(size)
+	}
+
+	/* a buffer holding exactly the bytes in `hex`, see hexdump::parse_hex */
+	pub fn from_hex_str(hex: &str) -> Result<Buffer, Error> {
+		let bytes = try!(hexdump::parse_hex(hex));
+		let mut buffer = Buffer::with_capacity(bytes.len());
+		try!(buffer.append(&bytes[..]));
+		Ok(buffer)
+	}
+
+	/* the readable bytes, copied into one Vec */
+	pub fn to_vec(&self) -> Vec<u8> {
+		let (first, second) = self.segments();
+		let mut result = first.to_vec();
+		result.extend_from_slice(second);
+		result
+	}
+
+	pub fn clear(&mut self) {
+		self.head = 0;
+		self.remaining = 0;
+	}
+
+	/* moves `size` bytes into `target` without an intermediate Vec */
+	pub fn read_into(&mut self, target: &mut Buffer, size: usize) -> Result<(), BufferError> {
+		try!(check_underflow(size, self.remaining));
+		if target.free() < size {
+			return Err(BufferError::Overflow { requested: size, free: target.free() });
 		}
+
+		{
+			let (first, second) = self.segments();
+			let from_first = min(size, first.len());
+			try!(target.append(&first[0..from_first]));
+			try!(target.append(&second[0..size - from_first]));
+		}
+		self.advance_read(size);
+		Ok(())
 	}
 
 	pub fn advance_read(&mut self, bytes: usize) {
-		<RingBuf as Buf>::advance(&mut self.buffer, bytes);
+		let bytes = min(bytes, self.remaining);
+		self.head = self.wrap(self.head + bytes);
 		self.remaining -= bytes;
 	}
 
+	/* the readable bytes in order, the second slice is empty unless they wrap around */
+	pub fn segments(&self) -> (&[u8], &[u8]) {
+		let first = min(self.remaining, self.capacity() - self.head);
+		(&self.data[self.head..self.head + first], &self.data[0..self.remaining - first])
+	}
+
+	/* copies up to `buf.len()` bytes starting at `offset` without consuming them */
+	fn copy_out(&self, offset: usize, buf: &mut [u8]) -> usize {
+		if offset >= self.remaining {
+			return 0;
+		}
+		let size = min(buf.len(), self.remaining - offset);
+		let start = self.wrap(self.head + offset);
+		let first = min(size, self.capacity() - start);
+		buf[0..first].copy_from_slice(&self.data[start..start + first]);
+		buf[first..size].copy_from_slice(&self.data[0..size - first]);
+		size
+	}
+
 	pub fn peek_max(&mut self, offset: usize, len: usize, buf: &mut [u8]) -> Result<usize, Error> {
-		self.buffer.mark();
-		<RingBuf as Buf>::advance(&mut self.buffer, offset);
-		let size = self.buffer.read_slice(buf);
-		self.buffer.reset();
-		Ok(size)
+		let len = min(len, buf.len());
+		Ok(self.copy_out(offset, &mut buf[0..len]))
+	}
+}
+
+impl fmt::Debug for Buffer {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		try!(write!(f, "Buffer {{ remaining: {}, capacity: {}, data: [", self.remaining, self.capacity()));
+		try!(hexdump::write_hex(f, &self.to_vec()[..]));
+		write!(f, "] }}")
+	}
+}
+
+impl fmt::Display for Buffer {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		hexdump::write_dump(f, &self.to_vec()[..])
 	}
 }
 
 impl BinaryReadable for Buffer {
-	fn read_bytes(&mut self, size: usize) -> Result<Vec<u8>, Error> {
-		if self.bytes_remaining() < size {
-			Err(Error::new(ErrorKind::InvalidData, "Not enough data"))
-		} else {
-			let mut buf = vec![0; size];
-
-			if <RingBuf as Buf>::read_slice(&mut self.buffer, &mut buf[..]) != size {
-				Err(Error::new(ErrorKind::InvalidData, "Didn't read as many bytes as specified!"))
-			} else {
-				self.remaining -= size;
-				Ok(buf)
-			}
-		}
+	fn read_bytes(&mut self, size: usize) -> Result<Vec<u8>, BufferError> {
+		try!(check_underflow(size, self.bytes_remaining()));
+		let mut buf = vec![0; size];
+		self.copy_out(0, &mut buf[..]);
+		self.advance_read(size);
+		Ok(buf)
+	}
 
+	fn read_to_slice(&mut self, buf: &mut [u8]) -> Result<(), BufferError> {
+		try!(check_underflow(buf.len(), self.bytes_remaining()));
+		self.copy_out(0, buf);
+		self.advance_read(buf.len());
+		Ok(())
 	}
 }
 
 impl BinaryPeekable for Buffer {
-	fn peek_bytes(&mut self, offset: usize, size: usize) -> Result<Vec<u8>, Error> {
-		if <RingBuf as Buf>::remaining(&self.buffer) < size + offset {
-			Err(Error::new(ErrorKind::InvalidData, "Not enough data"))
-		} else {
-			self.buffer.mark();
-
-			let mut buf = vec![0; size];
-			<RingBuf as Buf>::advance(&mut self.buffer, offset);
-			if <RingBuf as Buf>::read_slice(&mut self.buffer, buf.as_mut_slice()) != size {
-				self.buffer.reset();
-				Err(Error::new(ErrorKind::InvalidData, "Didn't read as many bytes as specified!"))
+	fn peek_bytes(&mut self, offset: usize, size: usize) -> Result<Vec<u8>, BufferError> {
+		try!(check_underflow(offset + size, self.bytes_remaining()));
+		let mut buf = vec![0; size];
+		self.copy_out(offset, &mut buf[..]);
+		Ok(buf)
+	}
+
+	fn peek_to_slice(&mut self, offset: usize, buf: &mut [u8]) -> Result<(), BufferError> {
+		try!(check_underflow(offset + buf.len(), self.bytes_remaining()));
+		self.copy_out(offset, buf);
+		Ok(())
+	}
+}
+
+/* outgoing data, remembers where frames start so they are never cut in half */
+pub struct SendBuffer {
+	buffer:			Buffer,
+	frames:			VecDeque<usize>,
+	/* bytes of the front frame that are already on the wire */
+	front_sent:		usize,
+}
+
+impl SendBuffer {
+	pub fn with_capacity(capacity: usize) -> Self {
+		SendBuffer {
+			buffer:			Buffer::with_capacity(capacity),
+			frames:			VecDeque::new(),
+			front_sent:		0,
+		}
+	}
+
+	pub fn bytes_remaining(&self) -> usize {
+		self.buffer.bytes_remaining()
+	}
+
+	pub fn capacity(&self) -> usize {
+		self.buffer.capacity()
+	}
+
+	pub fn free(&self) -> usize {
+		self.capacity() - self.bytes_remaining()
+	}
+
+	pub fn frame_count(&self) -> usize {
+		self.frames.len()
+	}
+
+	pub fn push_frame(&mut self, bytes: &[u8]) -> Result<(), Error> {
+		if bytes.len() > self.free() {
+			return Err(Error::new(ErrorKind::Other, "send buffer full"));
+		}
+		try!(self.buffer.append(bytes));
+		self.frames.push_back(bytes.len());
+		Ok(())
+	}
+
+	pub fn peek_max(&mut self, offset: usize, len: usize, buf: &mut [u8]) -> Result<usize, Error> {
+		self.buffer.peek_max(offset, len, buf)
+	}
+
+	pub fn segments(&self) -> (&[u8], &[u8]) {
+		self.buffer.segments()
+	}
+
+	/* writes both halves of the ring with a single writev, without copying them first */
+	pub fn write_to<F: AsRawFd>(&mut self, fd: &F) -> Result<usize, Error> {
+		let written = {
+			let (first, second) = self.buffer.segments();
+			let iov = [
+				libc::iovec { iov_base: first.as_ptr() as *mut libc::c_void, iov_len: first.len() },
+				libc::iovec { iov_base: second.as_ptr() as *mut libc::c_void, iov_len: second.len() },
+			];
+			let count = if second.is_empty() { 1 } else { 2 };
+			unsafe { libc::writev(fd.as_raw_fd(), iov.as_ptr(), count) }
+		};
+
+		if written < 0 {
+			return Err(Error::last_os_error());
+		}
+		self.consume(written as usize);
+		Ok(written as usize)
+	}
+
+	/* `bytes` have been written to the socket */
+	pub fn consume(&mut self, bytes: usize) {
+		self.buffer.advance_read(bytes);
+
+		let mut bytes = bytes;
+		while bytes > 0 {
+			let front = match self.frames.front() {
+				Some(&front) => front,
+				None => break,
+			};
+			let left = front - self.front_sent;
+			if bytes >= left {
+				self.frames.pop_front();
+				self.front_sent = 0;
+				bytes -= left;
 			} else {
-				self.buffer.reset();
-				Ok(buf)
+				self.front_sent += bytes;
+				bytes = 0;
 			}
 		}
 	}
-}
\ No newline at end of file
+
+	/* drops whole unsent frames from the front until `needed` bytes are free */
+	/* returns the number of dropped frames, or None if that isn't possible */
+	pub fn drop_oldest(&mut self, needed: usize) -> Option<usize> {
+		if needed > self.capacity() || (self.front_sent > 0 && needed > self.free()) {
+			/* the front frame is half on the wire, dropping the rest would corrupt the stream */
+			return None;
+		}
+
+		let mut dropped = 0;
+		while self.free() < needed {
+			match self.frames.pop_front() {
+				Some(len) => {
+					self.buffer.advance_read(len);
+					dropped += 1;
+				},
+				None => return None,
+			}
+		}
+		Some(dropped)
+	}
+}
@@ -0,0 +1,191 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock, Weak};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use mio::Token;
+
+use body::SharedBytes;
+use packet::FiestaPacket;
+use handle::ClientHandle;
+
+/* subscribed to this, a subscriber sees every topic, e.g. for an audit log */
+pub const ALL_TOPICS: &'static str = "*";
+
+pub type Subscriber = Fn(&Event) + Send + Sync;
+
+pub struct Event {
+	topic:			String,
+	payload:		Arc<Any + Send + Sync>,
+}
+
+impl Event {
+	pub fn topic(&self) -> &str {
+		&self.topic
+	}
+
+	/* None if the publisher sent something else under this topic */
+	pub fn payload<T: Any>(&self) -> Option<&T> {
+		self.payload.downcast_ref::<T>()
+	}
+}
+
+/* returned by subscribe(), for unsubscribe() */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Subscription(usize);
+
+/* topic based, for handlers that shouldn't know about each other: chat publishes, moderation */
+/* and logging subscribe. subscribers run on the publisher's thread, usually a worker, so keep them short. */
+/* cheap to clone, clones share the subscribers */
+#[derive(Clone, Default)]
+pub struct Bus {
+	topics:			Arc<RwLock<HashMap<String, Vec<(Subscription, Arc<Subscriber>)>>>>,
+	next_id:		Arc<AtomicUsize>,
+}
+
+impl Bus {
+	pub fn new() -> Self {
+		Bus::default()
+	}
+
+	pub fn subscribe<F>(&self, topic: &str, subscriber: F) -> Subscription where F: Fn(&Event) + Send + Sync + 'static {
+		let subscription = Subscription(self.next_id.fetch_add(1, Ordering::SeqCst));
+		let mut topics = match self.topics.write() {
+			Ok(topics) => topics,
+			Err(poisoned) => poisoned.into_inner(),
+		};
+		topics.entry(topic.to_string()).or_insert_with(Vec::new).push((subscription, Arc::new(subscriber)));
+		subscription
+	}
+
+	/* every FiestaPacket published under `topic` goes out to the group's members */
+	pub fn subscribe_group(&self, topic: &str, group: ClientGroup) -> Subscription {
+		self.subscribe(topic, move |event| {
+			if let Some(packet) = event.payload::<FiestaPacket>() {
+				group.send(packet);
+			}
+		})
+	}
+
+	pub fn unsubscribe(&self, subscription: Subscription) {
+		let mut topics = match self.topics.write() {
+			Ok(topics) => topics,
+			Err(poisoned) => poisoned.into_inner(),
+		};
+		for subscribers in topics.values_mut() {
+			subscribers.retain(|&(id, _)| id != subscription);
+		}
+		topics.retain(|_, subscribers| !subscribers.is_empty());
+	}
+
+	/* returns how many subscribers saw it */
+	pub fn publish<T: Any + Send + Sync>(&self, topic: &str, payload: T) -> usize {
+		let event = Event {
+			topic:			topic.to_string(),
+			payload:		Arc::new(payload),
+		};
+		/* the lock isn't held while they run, a subscriber may publish or subscribe itself */
+		let subscribers: Vec<Arc<Subscriber>> = {
+			let topics = match self.topics.read() {
+				Ok(topics) => topics,
+				Err(poisoned) => poisoned.into_inner(),
+			};
+			let exact = topics.get(topic).into_iter().flat_map(|subscribers| subscribers.iter());
+			let all = topics.get(ALL_TOPICS).into_iter().flat_map(|subscribers| subscribers.iter());
+			let subscribers = exact.chain(all).map(|&(_, ref subscriber)| subscriber.clone()).collect();
+			subscribers
+		};
+		for subscriber in subscribers.iter() {
+			subscriber(&event);
+		}
+		subscribers.len()
+	}
+}
+
+/* clients that get the same packets, e.g. everyone in a map or a chat channel */
+#[derive(Clone, Default)]
+pub struct ClientGroup {
+	members:		Arc<RwLock<HashMap<Token, ClientHandle>>>,
+}
+
+impl ClientGroup {
+	pub fn new() -> Self {
+		ClientGroup::default()
+	}
+
+	/* the client remembers it too, see FiestaNetworkClient::groups() */
+	pub fn join(&self, client: ClientHandle) {
+		if let Some(member) = client.client() {
+			if let Ok(member) = member.read() {
+				member.joined(self);
+			}
+		}
+		if let Ok(mut members) = self.members.write() {
+			members.insert(client.id(), client);
+		}
+	}
+
+	pub fn leave(&self, client: Token) {
+		let member = match self.members.write() {
+			Ok(mut members) => members.remove(&client),
+			Err(_) => None,
+		};
+		if let Some(member) = member.and_then(|member| member.client()) {
+			if let Ok(member) = member.read() {
+				member.left(self);
+			}
+		}
+	}
+
+	/* doesn't keep the group alive, for the members to point back at it */
+	pub fn downgrade(&self) -> GroupRef {
+		GroupRef {
+			members:		Arc::downgrade(&self.members),
+		}
+	}
+
+	pub fn len(&self) -> usize {
+		self.members.read().map(|members| members.len()).unwrap_or(0)
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+
+	/* returns how many members it went to, disconnected ones leave the group on the way */
+	pub fn send(&self, packet: &FiestaPacket) -> usize {
+		let members: Vec<ClientHandle> = match self.members.read() {
+			Ok(members) => members.values().cloned().collect(),
+			Err(_) => return 0,
+		};
+		let body = SharedBytes::from_vec(packet.data.to_vec());
+		let mut sent = 0;
+		for member in members.iter() {
+			if !member.is_connected() {
+				self.leave(member.id());
+				continue;
+			}
+			match member.send_shared(packet.header, body.clone()) {
+				Ok(()) => sent += 1,
+				Err(e) => debug!(target: "network", "group send to {:?} failed: {}", member.id(), e),
+			}
+		}
+		sent
+	}
+}
+
+/* a group as its members see it, the group and its members would keep each other alive otherwise */
+#[derive(Clone)]
+pub struct GroupRef {
+	members:		Weak<RwLock<HashMap<Token, ClientHandle>>>,
+}
+
+impl GroupRef {
+	/* None once every clone of the group is gone */
+	pub fn upgrade(&self) -> Option<ClientGroup> {
+		self.members.upgrade().map(|members| ClientGroup { members: members })
+	}
+
+	pub fn is(&self, group: &ClientGroup) -> bool {
+		self.upgrade().map_or(false, |this| Arc::ptr_eq(&this.members, &group.members))
+	}
+}
@@ -0,0 +1,150 @@
+use std::fs::File;
+use std::io::{BufWriter, Error, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use mio::Token;
+
+/* LINKTYPE_USER0, a dissector has to be told that it's fiesta */
+pub const LINKTYPE_FIESTA: u16 = 147;
+
+const BLOCK_SECTION_HEADER: u32 = 0x0a0d0d0a;
+const BLOCK_INTERFACE: u32 = 0x00000001;
+const BLOCK_ENHANCED_PACKET: u32 = 0x00000006;
+const BYTE_ORDER_MAGIC: u32 = 0x1a2b3c4d;
+
+const OPT_END: u16 = 0;
+const OPT_COMMENT: u16 = 1;
+const OPT_EPB_FLAGS: u16 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+	Inbound,
+	Outbound,
+}
+
+/* every record is: direction (u8, 0 in / 1 out), token (u32 le), then header and body as on the wire */
+pub struct PacketCapture {
+	out:			Mutex<BufWriter<File>>,
+}
+
+fn push_u16(buf: &mut Vec<u8>, value: u16) {
+	buf.push(value as u8);
+	buf.push((value >> 8) as u8);
+}
+
+fn push_u32(buf: &mut Vec<u8>, value: u32) {
+	push_u16(buf, value as u16);
+	push_u16(buf, (value >> 16) as u16);
+}
+
+fn pad(buf: &mut Vec<u8>) {
+	while buf.len() % 4 != 0 {
+		buf.push(0);
+	}
+}
+
+fn push_option(buf: &mut Vec<u8>, code: u16, value: &[u8]) {
+	push_u16(buf, code);
+	push_u16(buf, value.len() as u16);
+	buf.extend_from_slice(value);
+	pad(buf);
+}
+
+/* type, total length, body, total length again */
+fn block(block_type: u32, body: &[u8]) -> Vec<u8> {
+	let length = 12 + body.len() as u32;
+	let mut block = Vec::with_capacity(length as usize);
+	push_u32(&mut block, block_type);
+	push_u32(&mut block, length);
+	block.extend_from_slice(body);
+	push_u32(&mut block, length);
+	block
+}
+
+/* the body of an outgoing frame, without the 1 or 3 byte size prefix */
+pub fn strip_size_prefix(frame: &[u8]) -> &[u8] {
+	match frame.first() {
+		Some(&0) if frame.len() >= 3	=> &frame[3..],
+		Some(_)							=> &frame[1..],
+		None							=> frame,
+	}
+}
+
+impl PacketCapture {
+	/* truncates `path` and writes the pcapng section and interface headers */
+	pub fn create<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+		let mut out = BufWriter::new(try!(File::create(path.as_ref())));
+
+		let mut shb = Vec::new();
+		push_u32(&mut shb, BYTE_ORDER_MAGIC);
+		push_u16(&mut shb, 1);
+		push_u16(&mut shb, 0);
+		/* unknown section length */
+		push_u32(&mut shb, 0xffffffff);
+		push_u32(&mut shb, 0xffffffff);
+		try!(out.write_all(&block(BLOCK_SECTION_HEADER, &shb[..])[..]));
+
+		let mut idb = Vec::new();
+		push_u16(&mut idb, LINKTYPE_FIESTA);
+		push_u16(&mut idb, 0);
+		/* no snap length */
+		push_u32(&mut idb, 0);
+		try!(out.write_all(&block(BLOCK_INTERFACE, &idb[..])[..]));
+		try!(out.flush());
+
+		info!(target: "network", "capturing packets to {}", path.as_ref().display());
+		Ok(PacketCapture {
+			out:			Mutex::new(out),
+		})
+	}
+
+	/* `packet` starts with the u16 header, as it is on the wire after the size prefix */
+	pub fn record(&self, direction: Direction, token: Token, packet: &[u8]) {
+		let mut data = Vec::with_capacity(5 + packet.len());
+		data.push(if direction == Direction::Inbound { 0 } else { 1 });
+		push_u32(&mut data, token.0 as u32);
+		data.extend_from_slice(packet);
+
+		let micros = SystemTime::now().duration_since(UNIX_EPOCH)
+			.map(|d| d.as_secs() * 1000000 + (d.subsec_nanos() / 1000) as u64)
+			.unwrap_or(0);
+
+		let mut epb = Vec::with_capacity(32 + data.len());
+		/* interface 0 */
+		push_u32(&mut epb, 0);
+		push_u32(&mut epb, (micros >> 32) as u32);
+		push_u32(&mut epb, micros as u32);
+		push_u32(&mut epb, data.len() as u32);
+		push_u32(&mut epb, data.len() as u32);
+		epb.extend_from_slice(&data[..]);
+		pad(&mut epb);
+
+		let mut flags = Vec::new();
+		push_u32(&mut flags, if direction == Direction::Inbound { 1 } else { 2 });
+		push_option(&mut epb, OPT_EPB_FLAGS, &flags[..]);
+		push_option(&mut epb, OPT_COMMENT, format!("token={}", token.0).as_bytes());
+		push_option(&mut epb, OPT_END, &[]);
+
+		let result = match self.out.lock() {
+			Ok(mut out) => out.write_all(&block(BLOCK_ENHANCED_PACKET, &epb[..])[..]),
+			Err(_) => return,
+		};
+		if let Err(e) = result {
+			warn!(target: "network", "failed to write packet capture: {}", e);
+		}
+	}
+
+	pub fn flush(&self) -> Result<(), Error> {
+		match self.out.lock() {
+			Ok(mut out) => out.flush(),
+			Err(_) => Ok(()),
+		}
+	}
+}
+
+impl Drop for PacketCapture {
+	fn drop(&mut self) {
+		let _ = self.flush();
+	}
+}
@@ -1,364 +1,2807 @@
-use std::collections::{HashMap, LinkedList};
-use std::io::{Error, ErrorKind, Read, Write};
-use std::sync::{Mutex, Arc, RwLock, MutexGuard};
-use std::mem::drop;
-use mio::*;
-use mio::tcp::*;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::{Error, ErrorKind};
+use std::sync::{Mutex, Arc, RwLock, MutexGuard, LockResult, RwLockReadGuard, RwLockWriteGuard};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::cmp::min;
+use std::fmt;
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
+use std::net::{IpAddr, Shutdown, SocketAddr};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{Receiver, Sender};
+use std::time::{Duration, Instant, SystemTime};
+use mio::{Events, Interest, Poll, Registry, Token};
+use mio::event::Event;
+use mio::net::{TcpListener, TcpStream};
 
+#[cfg(feature = "admin")]
+use admin::{AdminCommand, AdminConsole, ADMIN_HELP};
+use audit::{AuditKind, AuditRecord, AuditSink};
+use body::SharedBytes;
 use buffer::*;
+use bus::{ClientGroup, GroupRef};
+use codec::{Codec, LengthPrefix};
+use hexdump::HexDump;
+use trace::TraceFilter;
+use transport::{Transport, TransportIo};
+use capture::{Direction, PacketCapture, strip_size_prefix};
+use config;
+use config::{RuntimeConfig, FRAME_OVERHEAD};
+use error::{FiestaNetError, FiestaResult, is_transient};
+use framing;
+use handle::ClientHandle;
+use pool::BufferPool;
+use metrics::Metrics;
+use opcodes::OpcodeName;
+use outbound::{FrameQueue, OutFrame, SendQueue};
+use packet::FiestaPacket;
+use stats::{ClientCounters, ClientStats};
+use limits::{FrameLimits, SlowConsumerPolicy, ReadBackpressure, ByteRateLimit, FloodAction};
+use listener::normalize_addr;
+use protocol::{Keepalive, ProtocolState, SeedHandshake, StateRules, ViolationAction};
+use packets::{NcMiscSeedAck, Packet};
+use version::ProtocolVersion;
+use proxy;
+use proxy::ProxyHeader;
+use reactor::{Notifier, PollStrategy, Task, Timers, WAKER_TOKEN};
+use session::Extensions;
+use sockopt::SocketOptions;
+#[cfg(feature = "tls")]
+use tls::{TlsConfig, TlsSession};
+#[cfg(feature = "websocket")]
+use websocket::WebSocketTransport;
+#[cfg(feature = "compression")]
+use compression;
+#[cfg(feature = "compression")]
+use compression::{Compression, COMPRESSED_FLAG};
+#[cfg(feature = "crypto")]
+use keystream::{Decryption, Keystream};
+#[cfg(feature = "tokio")]
+use futures_util::task::AtomicWaker;
+#[cfg(feature = "spans")]
+use spans;
+#[cfg(feature = "spans")]
+use tracing::Span;
 use super::processing::*;
 
 pub const SERVER_TOKEN: Token = Token(0);
+/* how much is taken off the socket per read to begin with, doubled while reads fill it up */
+const READ_CHUNK_SIZE: usize = 2048;
+const MAX_READ_CHUNK_SIZE: usize = 64 * 1024;
+/* bytes and reads from one client per event before the others get their turn */
+const MAX_READ_PER_EVENT: usize = 256 * 1024;
+const MAX_READS_PER_EVENT: usize = 64;
+/* connections taken off a listener per tick unless the builder says otherwise */
+pub const DEFAULT_ACCEPTS_PER_TICK: usize = 64;
+/* writes per event with PollStrategy::Edge before the socket is re-armed to let other clients in */
+const MAX_IO_PER_EVENT: usize = 16;
+/* how long close_after_flush() waits for a client that doesn't read */
+const CLOSE_FLUSH_TIMEOUT: Duration = Duration::from_secs(10);
 
 pub struct FiestaHandler {
-	listener:		TcpListener,
+	listeners:		HashMap<Token, TcpListener>,
+	max_accepts:	usize,
+	/* listeners that hit max_accepts, the poll won't report them again so the next tick carries on */
+	pending_accepts:	Vec<Token>,
+	/* the listeners are deregistered, connections wait in the backlog */
+	draining:		bool,
+	/* set by a graceful shutdown, the clients are closed once flushed or at the latest then */
+	closing_deadline:	Option<Instant>,
+	/* drained whenever the waker fires */
+	messages:		Receiver<ServerMessage>,
+	notifier:		Notifier,
+	timers:			Timers,
+	scheduled:		HashMap<usize, Scheduled>,
+	next_scheduled:	usize,
+	/* only on the first reactor */
+	tick_clock:		Option<TickClock>,
+	/* cleared by ServerMessage::Shutdown */
+	running:		bool,
 	clients:		HashMap<Token, Arc<RwLock<Box<FiestaNetworkClient>>>>,
 	token_count:	usize,
+	/* reactors share one token space, each one takes every `token_stride`th token */
+	token_stride:	usize,
+	/* the other reactors, accepted clients are handed out to them in turn */
+	peers:			Vec<Notifier>,
+	next_peer:		usize,
+	/* every other reactor, the admin console's ban, drain and reload are passed on to them */
+	siblings:		Vec<Notifier>,
 	processor:		Box<PacketProcessor>,
+	proxy_protocol:	bool,
+	socket_options:	SocketOptions,
+	buffer_size:	usize,
+	write_buffer_size:	usize,
+	send_policy:	SlowConsumerPolicy,
+	poll_strategy:	PollStrategy,
+	max_clients:	Option<usize>,
+	frame_limits:	Arc<FrameLimits>,
+	state_rules:	Option<Arc<StateRules>>,
+	keepalive:		Option<Keepalive>,
+	/* sent to every new client, see SeedHandshake */
+	seed_handshake:	Option<Arc<SeedHandshake>>,
+	pool:			BufferPool,
+	backpressure:	Option<ReadBackpressure>,
+	/* shared with the clients so a reload reaches them */
+	byte_rate_limit:	Arc<RwLock<Option<ByteRateLimit>>>,
+	handshake_timeout:	Option<Duration>,
+	metrics:		Arc<Metrics>,
+	capture:		Option<Arc<PacketCapture>>,
+	audit:			Option<Arc<AuditSink>>,
+	codec:			Option<Arc<Codec>>,
+	trace:			Arc<RwLock<TraceFilter>>,
+	#[cfg(feature = "admin")]
+	admin:			Option<AdminConsole>,
+	/* connections from these are refused, shared with the other reactors and the clients that wait */
+	/* for a PROXY header */
+	banned:			Arc<RwLock<HashSet<IpAddr>>>,
+	/* the part of `banned` that came from the config file, a reload only replaces these */
+	config_bans:	HashSet<IpAddr>,
+	/* re-read by the admin console's reload */
+	config_path:	Option<PathBuf>,
+	#[cfg(feature = "tls")]
+	tls_config:		Option<Arc<TlsConfig>>,
+	#[cfg(feature = "compression")]
+	compression:	Option<Compression>,
+	#[cfg(feature = "websocket")]
+	websocket:		bool,
+}
+
+/* what only the reactor touches, behind a single lock nobody else takes */
+struct ClientIo {
+	read_buffer:	Buffer,
+	/* packet bodies may point into this, it's only reused once they're all gone */
+	read_chunk:		Arc<Vec<u8>>,
+	/* how much the next read asks for, grows for clients that keep the socket full */
+	read_size:		usize,
+	/* everybody else goes through `outbound` */
+	write_buffer:	FrameQueue,
+	/* what the socket is registered with right now */
+	registered:		Interest,
+	proxy_pending:	bool,
 }
 
 pub struct FiestaNetworkClient {
-	client:			Mutex<TcpStream>,
-	read_buffer:	Mutex<Buffer>,
-	write_buffer:	Mutex<Buffer>,
-	packet_queue:	Mutex<LinkedList<FiestaPacket>>,
+	/* reads and writes go through &self, shutting it down works from any thread */
+	transport:		Box<Transport>,
+	io:				Mutex<ClientIo>,
+	outbound:		SendQueue,
+	send_policy:	SlowConsumerPolicy,
+	packet_queue:	Mutex<VecDeque<FiestaPacket>>,
 	is_alive:		Mutex<bool>,
-	interest:		Mutex<EventSet>,
+	interest:		Mutex<Interest>,
 	id:				Token,
+	peer_addr:		Option<SocketAddr>,
+	local_addr:		Option<SocketAddr>,
+	proxied_addr:	Mutex<Option<SocketAddr>>,
+	/* e.g. "acct:melissa char:Ranger" once the handlers know who this is */
+	label:			RwLock<Option<String>>,
+	/* what the handlers keep on it, and the groups it is in, both move along on a SessionStore resume */
+	extensions:		Mutex<Extensions>,
+	groups:			Mutex<Vec<GroupRef>>,
+	limits:			Arc<FrameLimits>,
+	state_rules:	Option<Arc<StateRules>>,
+	protocol_state:	Mutex<ProtocolState>,
+	/* picked during the version check, translates opcodes from then on */
+	protocol_version:	RwLock<Option<Arc<ProtocolVersion>>>,
+	/* heartbeats sent since the last answer */
+	keepalive_missed:	AtomicUsize,
+	pool:			BufferPool,
+	/* packets handed to the processor that haven't been dropped yet */
+	in_flight:		AtomicUsize,
+	read_paused:	AtomicBool,
+	backpressure:	Option<ReadBackpressure>,
+	notify:			Mutex<Option<Notifier>>,
+	byte_rate_limit:	Arc<RwLock<Option<ByteRateLimit>>>,
+	/* the handler's, checked again once a PROXY header says where the client really is */
+	banned:			Arc<RwLock<HashSet<IpAddr>>>,
+	/* start of the current one second window and the bytes read in it */
+	byte_window:	Mutex<(Instant, usize)>,
+	throttled:		AtomicBool,
+	/* set once the first complete packet came in */
+	handshake_done:	AtomicBool,
+	/* why it was closed from our side, for the reactor to pass on once it notices */
+	closing:		Mutex<Option<DisconnectReason>>,
+	/* close_after_flush() was called, nothing is read and the socket goes once the buffer is empty */
+	flush_then_close:	AtomicBool,
+	/* one of the locks above was poisoned, see recover() */
+	poisoned:		AtomicBool,
+	metrics:		Arc<Metrics>,
+	counters:		ClientCounters,
+	capture:		Option<Arc<PacketCapture>>,
+	audit:			Option<Arc<AuditSink>>,
+	/* None is the game's LengthPrefix, with the zero copy reads only it gets */
+	codec:			Option<Arc<Codec>>,
+	trace:			Arc<RwLock<TraceFilter>>,
+	#[cfg(feature = "spans")]
+	span:			Span,
+	#[cfg(feature = "tls")]
+	tls:			Option<Mutex<TlsSession>>,
+	#[cfg(feature = "compression")]
+	compression:	Option<Compression>,
+	/* set by the first compressed frame, only then are ours compressed too */
+	#[cfg(feature = "compression")]
+	peer_compresses:	AtomicBool,
+	/* for what the client sends, set once it has its seed */
+	#[cfg(feature = "crypto")]
+	decryption:		Mutex<Option<Decryption>>,
+	/* `limits` without the opcode table, the opcode is still encrypted while the frame is cut */
+	#[cfg(feature = "crypto")]
+	encrypted_limits:	FrameLimits,
+	/* woken by append_send, the tokio frontend has no reactor watching the interest */
+	#[cfg(feature = "tokio")]
+	send_waker:		Option<Arc<AtomicWaker>>,
+}
+
+/* sent to the event loop through a `Notifier` */
+#[derive(Debug)]
+pub enum ServerMessage {
+	Shutdown,
+	/* drain, give the clients up to this long to get their pending sends out, then close them and stop */
+	GracefulShutdown(Duration),
+	/* a paused client's backlog has drained, start reading again */
+	ResumeRead(Token),
+	/* a client accepted by another reactor, this one serves it from now on */
+	Adopt(TcpStream),
+	/* true stops accepting, connected clients are served until they leave; false accepts again */
+	Drain(bool),
+	/* replaces the packet trace filter of all clients */
+	SetTrace(TraceFilter),
+	/* another reactor banned the address, drop the clients it covers here too */
+	Ban(IpAddr),
+	/* limits, bans and the trace filter from a freshly loaded config file */
+	Reload(RuntimeConfig),
+	/* a frame for the client, appended to its send queue at the deadline if it's still there */
+	SendAt(Token, Instant, OutFrame),
+	Schedule(Instant, Task),
+	/* the reactor answers with a snapshot of its clients, see ServerHandle::clients() */
+	Clients(Sender<Vec<ClientStats>>),
+	/* the client called close_after_flush(), look at its interest and start the clock */
+	CloseAfterFlush(Token),
+	/* FiestaNetworkClient::kick(), e.g. from game logic holding a ClientHandle */
+	Kick(Token),
+}
+
+/* scheduled with `Timers::schedule()` */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClientTimeout {
+	/* the flood throttle's second is over */
+	Unthrottle(Token),
+	/* drop the client if it still hasn't sent its first packet */
+	Handshake(Token),
+	/* send the next heartbeat, or drop the client if too many went unanswered */
+	Keepalive(Token),
+	/* one of the handler's `scheduled` */
+	Scheduled(usize),
+	/* the next world update is due */
+	Tick,
+	/* close_after_flush() is taking too long */
+	FlushDeadline(Token),
+}
+
+/* why a client is gone, for PacketProcessor::on_disconnect, the audit log and the metrics */
+#[derive(Debug)]
+pub enum DisconnectReason {
+	/* EOF, the other side hung up */
+	PeerClosed,
+	/* the socket failed or what came in couldn't be framed */
+	ReadError(Error),
+	/* a write failed or the send buffer overflowed */
+	WriteStall,
+	/* the server's doing: kick(), a ban, ClientHandle::disconnect() or a shutdown */
+	Kicked,
+	/* a packet it wasn't allowed to send, a byte flood or a broken PROXY header */
+	ProtocolViolation,
+	/* no handshake or heartbeat answer in time */
+	IdleTimeout,
+	/* a panic while one of its locks was held, what they guard can't be trusted anymore */
+	Poisoned,
+}
+
+impl DisconnectReason {
+	/* one word, for metrics and log fields */
+	pub fn name(&self) -> &'static str {
+		match *self {
+			DisconnectReason::PeerClosed		=> "peer_closed",
+			DisconnectReason::ReadError(_)		=> "read_error",
+			DisconnectReason::WriteStall		=> "write_stall",
+			DisconnectReason::Kicked			=> "kicked",
+			DisconnectReason::ProtocolViolation	=> "protocol_violation",
+			DisconnectReason::IdleTimeout		=> "idle_timeout",
+			DisconnectReason::Poisoned			=> "poisoned",
+		}
+	}
+}
+
+/* io::Error can't be cloned, the copy keeps its kind and message */
+impl Clone for DisconnectReason {
+	fn clone(&self) -> Self {
+		match *self {
+			DisconnectReason::PeerClosed		=> DisconnectReason::PeerClosed,
+			DisconnectReason::ReadError(ref e)	=> DisconnectReason::ReadError(Error::new(e.kind(), e.to_string())),
+			DisconnectReason::WriteStall		=> DisconnectReason::WriteStall,
+			DisconnectReason::Kicked			=> DisconnectReason::Kicked,
+			DisconnectReason::ProtocolViolation	=> DisconnectReason::ProtocolViolation,
+			DisconnectReason::IdleTimeout		=> DisconnectReason::IdleTimeout,
+			DisconnectReason::Poisoned			=> DisconnectReason::Poisoned,
+		}
+	}
 }
 
-pub struct FiestaPacket {
-	pub header:			u16,
-	pub data:			Buffer,
+impl fmt::Display for DisconnectReason {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			DisconnectReason::ReadError(ref e)	=> write!(f, "{}: {}", self.name(), e),
+			_									=> write!(f, "{}", self.name()),
+		}
+	}
+}
+
+/* registering and the like failing, it's the socket either way */
+fn socket_error<E: fmt::Display>(e: &E) -> DisconnectReason {
+	DisconnectReason::ReadError(Error::new(ErrorKind::Other, e.to_string()))
+}
+
+/* waiting in the timers for ServerMessage::SendAt and Schedule */
+enum Scheduled {
+	Send(Token, OutFrame),
+	Run(Task),
+}
+
+/* mio can't register an empty interest, a client that wants nothing waits for writable, */
+/* which is edge triggered and fires at most once */
+fn without(interest: Interest, remove: Interest) -> Interest {
+	interest.remove(remove).unwrap_or(Interest::WRITABLE)
 }
 
 impl FiestaNetworkClient {
-	pub fn new(inner_client: TcpStream, id: Token) -> Self {
+	/* a TcpStream usually, anything else that implements Transport works the same */
+	pub fn new<T: Transport>(transport: T, id: Token) -> Self {
+		let peer_addr = transport.peer_addr().map(normalize_addr);
+		let local_addr = transport.local_addr().map(normalize_addr);
 		FiestaNetworkClient {
-			client:			Mutex::new(inner_client),
-			read_buffer:	Mutex::new(Buffer::new()),
-			write_buffer:	Mutex::new(Buffer::new()),
-			packet_queue:	Mutex::new(LinkedList::new()),
+			transport:		Box::new(transport),
+			io:				Mutex::new(ClientIo {
+				read_buffer:	Buffer::new(),
+				read_chunk:		Arc::new(vec![0; READ_CHUNK_SIZE]),
+				read_size:		READ_CHUNK_SIZE,
+				write_buffer:	FrameQueue::with_capacity(BUFFERSIZE),
+				registered:		Interest::READABLE | Interest::WRITABLE,
+				proxy_pending:	false,
+			}),
+			outbound:		SendQueue::new(BUFFERSIZE),
+			send_policy:	SlowConsumerPolicy::Disconnect,
+			packet_queue:	Mutex::new(VecDeque::new()),
 			is_alive:		Mutex::new(true),
-			interest:		Mutex::new(EventSet::all()),
-			id:				id
+			interest:		Mutex::new(Interest::READABLE | Interest::WRITABLE),
+			id:				id,
+			peer_addr:		peer_addr,
+			local_addr:		local_addr,
+			proxied_addr:	Mutex::new(None),
+			label:			RwLock::new(None),
+			extensions:		Mutex::new(Extensions::new()),
+			groups:			Mutex::new(Vec::new()),
+			limits:			Arc::new(FrameLimits::default()),
+			state_rules:	None,
+			protocol_state:	Mutex::new(ProtocolState::Connected),
+			protocol_version:	RwLock::new(None),
+			keepalive_missed:	AtomicUsize::new(0),
+			pool:			BufferPool::default(),
+			in_flight:		AtomicUsize::new(0),
+			read_paused:	AtomicBool::new(false),
+			backpressure:	None,
+			notify:			Mutex::new(None),
+			byte_rate_limit:	Arc::new(RwLock::new(None)),
+			banned:			Arc::new(RwLock::new(HashSet::new())),
+			byte_window:	Mutex::new((Instant::now(), 0)),
+			throttled:		AtomicBool::new(false),
+			handshake_done:	AtomicBool::new(false),
+			closing:		Mutex::new(None),
+			flush_then_close:	AtomicBool::new(false),
+			poisoned:		AtomicBool::new(false),
+			metrics:		Arc::new(Metrics::new()),
+			counters:		ClientCounters::new(),
+			capture:		None,
+			audit:			None,
+			codec:			None,
+			trace:			Arc::new(RwLock::new(TraceFilter::Off)),
+			#[cfg(feature = "spans")]
+			span:			spans::connection_span(id, peer_addr),
+			#[cfg(feature = "tls")]
+			tls:			None,
+			#[cfg(feature = "compression")]
+			compression:	None,
+			#[cfg(feature = "compression")]
+			peer_compresses:	AtomicBool::new(false),
+			#[cfg(feature = "crypto")]
+			decryption:		Mutex::new(None),
+			#[cfg(feature = "crypto")]
+			encrypted_limits:	FrameLimits::new(FrameLimits::default().max_frame_size()),
+			#[cfg(feature = "tokio")]
+			send_waker:		None,
 		}
 	}
 
-	pub fn can_read_next_packet(&self) -> bool {
-		match self.get_next_size() {
-			Ok(s) => {
-				let guard = self.read_buffer.lock().unwrap();
-				guard.bytes_remaining() >= (s as usize) + 2 + if s > 255 { 2 } else { 1 }
-				/* for the size of the header/size info */
-			},
-			Err(_) => false
+	pub fn with_buffer_size(mut self, size: usize) -> Self {
+		self.io_mut().read_buffer = Buffer::with_capacity(size);
+		self
+	}
+
+	/* caps how much unsent data a client may pile up */
+	pub fn with_write_buffer(mut self, size: usize, policy: SlowConsumerPolicy) -> Self {
+		self.io_mut().write_buffer = FrameQueue::with_capacity(size);
+		self.outbound = SendQueue::new(size);
+		self.send_policy = policy;
+		self
+	}
+
+	pub fn with_frame_limits(mut self, limits: Arc<FrameLimits>) -> Self {
+		#[cfg(feature = "crypto")]
+		{
+			self.encrypted_limits = FrameLimits::new(limits.max_frame_size());
 		}
+		self.limits = limits;
+		self
 	}
 
-	fn can_read_next_packet_inner(guard: &mut MutexGuard<Buffer>) -> bool {
-		match FiestaNetworkClient::get_next_size_inner(guard) {
-			Ok(s) => {
+	/* incoming packet bodies are taken from and returned to `pool` */
+	pub fn with_buffer_pool(mut self, pool: BufferPool) -> Self {
+		self.pool = pool;
+		self
+	}
+
+	/* for processors building outgoing packets */
+	pub fn buffer_pool(&self) -> &BufferPool {
+		&self.pool
+	}
+
+	/* `notify` is used by worker threads to wake the reactor once reading can resume */
+	pub fn with_backpressure(mut self, backpressure: ReadBackpressure, notify: Notifier) -> Self {
+		self.backpressure = Some(backpressure);
+		self.notify = Mutex::new(Some(notify));
+		self
+	}
+
+	/* how the client reaches its reactor from worker threads, without one sends wait for the next event */
+	pub fn with_notifier(mut self, notify: Notifier) -> Self {
+		self.notify = Mutex::new(Some(notify));
+		self
+	}
+
+	/* usually the handler's, so the numbers add up server wide */
+	pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+		self.metrics = metrics;
+		self
+	}
+
+	pub fn with_capture(mut self, capture: Arc<PacketCapture>) -> Self {
+		self.capture = Some(capture);
+		self
+	}
+
+	pub fn with_audit(mut self, audit: Arc<AuditSink>) -> Self {
+		self.audit = Some(audit);
+		self
+	}
+
+	/* another framing than the game's for both directions, compression is left to it as well */
+	pub fn with_codec(mut self, codec: Arc<Codec>) -> Self {
+		self.codec = Some(codec);
+		self
+	}
+
+	fn codec(&self) -> &Codec {
+		match self.codec {
+			Some(ref codec) => &**codec,
+			None => &LengthPrefix,
+		}
+	}
+
+	/* to the server's AuditSink if it has one, e.g. AuditKind::Auth from a login handler */
+	pub fn audit(&self, kind: AuditKind) {
+		if let Some(ref audit) = self.audit {
+			audit.record(&AuditRecord {
+				at:			SystemTime::now(),
+				token:		self.id,
+				addr:		self.real_addr(),
+				label:		self.label(),
+				kind:		kind,
+			});
+		}
+	}
+
+	/* shared with the handler, which updates it on ServerMessage::SetTrace */
+	pub fn with_trace(mut self, trace: Arc<RwLock<TraceFilter>>) -> Self {
+		self.trace = trace;
+		self
+	}
+
+	fn traced(&self, header: u16) -> bool {
+		self.trace.read().map(|filter| filter.matches(header)).unwrap_or(false)
+	}
+
+	/* traces and captures an inbound packet, called by the handler when it is dispatched */
+	pub fn record_inbound(&self, packet: &FiestaPacket) {
+		if self.traced(packet.header) {
+			info!(target: "trace", "{} -> {}", self.describe(), packet);
+		}
+		if let Some(ref capture) = self.capture {
+			let mut data = vec![(packet.header >> 8) as u8, packet.header as u8];
+			data.extend_from_slice(&packet.data.to_vec()[..]);
+			capture.record(Direction::Inbound, self.id, &data[..]);
+		}
+	}
+
+	pub fn with_byte_rate_limit(mut self, limit: ByteRateLimit) -> Self {
+		self.byte_rate_limit = Arc::new(RwLock::new(Some(limit)));
+		self
+	}
+
+	/* shared with the handler, which replaces it on reload */
+	pub fn with_shared_byte_rate_limit(mut self, limit: Arc<RwLock<Option<ByteRateLimit>>>) -> Self {
+		self.byte_rate_limit = limit;
+		self
+	}
+
+	/* shared with the handler, a PROXY header naming a banned address closes the client */
+	pub fn with_ban_list(mut self, banned: Arc<RwLock<HashSet<IpAddr>>>) -> Self {
+		self.banned = banned;
+		self
+	}
+
+	/* packets not allowed in the client's protocol state never reach the processor */
+	pub fn with_state_rules(mut self, rules: Arc<StateRules>) -> Self {
+		self.state_rules = Some(rules);
+		self
+	}
+
+	/* the first bytes on the wire will be a PROXY v1/v2 header from a load balancer */
+	pub fn expect_proxy_header(mut self) -> Self {
+		self.io_mut().proxy_pending = true;
+		self
+	}
+
+	/* compressed frames from the peer are inflated, and ours are compressed once it sent one */
+	#[cfg(feature = "compression")]
+	pub fn with_compression(mut self, compression: Compression) -> Self {
+		self.compression = Some(compression);
+		self
+	}
+
+	/* everything read from and written to the socket goes through the tls session from now on */
+	#[cfg(feature = "tls")]
+	pub fn with_tls(mut self, config: &Arc<TlsConfig>) -> Self {
+		self.tls = Some(Mutex::new(TlsSession::new(config)));
+		self
+	}
+
+	#[cfg(feature = "tokio")]
+	pub fn with_send_waker(mut self, waker: Arc<AtomicWaker>) -> Self {
+		self.send_waker = Some(waker);
+		self
+	}
+
+	/* only while building the client, nobody else can hold the lock yet */
+	fn io_mut(&mut self) -> &mut ClientIo {
+		match self.io.get_mut() {
+			Ok(io) => io,
+			Err(poisoned) => poisoned.into_inner(),
+		}
+	}
+
+	pub fn can_read_next_packet(&self) -> bool {
+		let mut guard = self.recover(self.io.lock());
+		FiestaNetworkClient::can_read_next_packet_inner(&mut guard.read_buffer, &self.limits)
+	}
+
+	fn can_read_next_packet_inner(read_buffer: &mut Buffer, limits: &FrameLimits) -> bool {
+		let available = read_buffer.bytes_remaining();
+		match FiestaNetworkClient::get_next_size_inner(read_buffer, available, limits) {
+			Ok(Some((size, prefix))) => {
 				let total_size =
-						s
-					+	2	/* header */
-					+	if s > 255 { 2 } else { 1 };	/* size data */
+						size as usize
+					+	2		/* header */
+					+	prefix;	/* size data */
 
-				guard.bytes_remaining() >= total_size as usize
+				read_buffer.bytes_remaining() >= total_size
 			},
-			Err(_) => false,
+			_ => false,
+		}
+	}
+
+	/* Ok(false) if there is no complete frame buffered yet, a truncated one is an Io error wrapping a BufferError */
+	pub fn read_next_packet(&self) -> FiestaResult<bool> {
+		let mut io = try!(self.io.lock());
+		let mut packet_queue_guard = try!(self.packet_queue.lock());
+
+		Ok(try!(self.decode_next(&mut io.read_buffer, &mut packet_queue_guard)))
+	}
+
+	/* the next frame from the ring buffer into the queue, decrypted and inflated */
+	fn decode_next(&self, read_buffer: &mut Buffer, packet_queue: &mut MutexGuard<VecDeque<FiestaPacket>>) -> Result<bool, Error> {
+		#[cfg(feature = "crypto")]
+		{
+			let mut decryption = self.recover(self.decryption.lock());
+			if let Some(ref mut decryption) = *decryption {
+				/* the opcode is still encrypted, its size is checked once it isn't */
+				let read = try!(FiestaNetworkClient::read_next_packet_inner(self.codec(), read_buffer, packet_queue, &self.encrypted_limits, &self.pool));
+				if read {
+					let packet = packet_queue.pop_back().unwrap();
+					let mut body = packet.data.to_vec();
+					let header = decryption.decrypt(packet.header, &mut body[..]);
+					try!(self.limits.check(header, body.len()));
+					let mut decrypted = FiestaPacket::from_pool(&self.pool, header, body.len());
+					decrypted.data.append(&body[..]);
+					packet_queue.push_back(decrypted);
+					try!(self.inflate_queued(packet_queue));
+				}
+				return Ok(read);
+			}
 		}
+		let read = try!(FiestaNetworkClient::read_next_packet_inner(self.codec(), read_buffer, packet_queue, &self.limits, &self.pool));
+		try!(self.inflate_queued(packet_queue));
+		Ok(read)
 	}
 
-	pub fn read_next_packet(&self) {
-		let mut read_buffer_guard = self.read_buffer.lock().unwrap();
-		let mut packet_queue_guard = self.packet_queue.lock().unwrap();
+	/* decrypts what the client sends from its next frame on, e.g. right after its seed went out */
+	#[cfg(feature = "crypto")]
+	pub fn set_keystream(&self, keystream: Keystream) {
+		*self.recover(self.decryption.lock()) = Some(Decryption::new(keystream));
+	}
+
+	/* call it once the new seed is sent. frames the client sent before it saw the seed are still */
+	/* decrypted with the old one, for up to `window` */
+	#[cfg(feature = "crypto")]
+	pub fn rotate_keystream(&self, seed: u16, window: Duration) -> FiestaResult<()> {
+		match *try!(self.decryption.lock()) {
+			Some(ref mut decryption) => {
+				decryption.rotate(seed, window);
+				Ok(())
+			},
+			None => Err(FiestaNetError::Io(Error::new(ErrorKind::InvalidInput, "no keystream to rotate"))),
+		}
+	}
 
-		FiestaNetworkClient::read_next_packet_inner(&mut read_buffer_guard, &mut packet_queue_guard);
+	/* swaps compressed packets in the queue for their inflated selves, the ones done already lost the flag */
+	fn inflate_queued(&self, queue: &mut VecDeque<FiestaPacket>) -> Result<(), Error> {
+		#[cfg(feature = "compression")]
+		{
+			if self.compression.is_none() {
+				return Ok(());
+			}
+			for packet in queue.iter_mut().filter(|packet| packet.header & COMPRESSED_FLAG != 0) {
+				let header = packet.header & !COMPRESSED_FLAG;
+				let body = try!(compression::inflate(&packet.data.to_vec()[..], self.limits.max_frame_size()));
+				try!(self.limits.check(header, body.len()));
+				let mut inflated = FiestaPacket::from_pool(&self.pool, header, body.len());
+				inflated.data.append(&body[..]);
+				*packet = inflated;
+				self.peer_compresses.store(true, Ordering::SeqCst);
+			}
+		}
+		Ok(())
 	}
 
 	fn read_next_packet_inner(
-			read_buffer: &mut MutexGuard<Buffer>, 
-			packet_queue: &mut MutexGuard<LinkedList<FiestaPacket>>) {
+			codec: &Codec,
+			read_buffer: &mut Buffer,
+			packet_queue: &mut MutexGuard<VecDeque<FiestaPacket>>,
+			limits: &FrameLimits,
+			pool: &BufferPool) -> Result<bool, Error> {
 
-		if FiestaNetworkClient::can_read_next_packet_inner(read_buffer) {
-			let size = match FiestaNetworkClient::get_next_size_inner(read_buffer) {
-				Ok(s) => s,
-				Err(_) => return,
-			};
-			let mut packet = FiestaPacket::new(0, size as usize);
+		match try!(codec.decode(read_buffer, limits, pool)) {
+			Some(packet) => {
+				packet_queue.push_back(packet);
+				Ok(true)
+			},
+			None => Ok(false),
+		}
+	}
 
-			if size > 255 {
-				read_buffer.advance_read(3);
-			} else {
-				read_buffer.advance_read(1);
+	fn get_next_size(&self) -> Result<Option<(u16, usize)>, Error> {
+		let mut io = self.recover(self.io.lock());
+		let available = io.read_buffer.bytes_remaining();
+		FiestaNetworkClient::get_next_size_inner(&mut io.read_buffer, available, &self.limits)
+	}
+
+	/* (body size, size prefix length), Ok(None) while the prefix isn't complete, */
+	/* Err if the declared size is over the limit and the client has to go */
+	fn get_next_size_inner<B: BinaryPeekable>(guard: &mut B, available: usize, limits: &FrameLimits) -> Result<Option<(u16, usize)>, Error> {
+		Ok(try!(framing::next_frame_size(guard, available, limits)))
+	}
+
+	/* queues every complete frame in `bytes` without copying, returns what's left of a partial one */
+	fn read_shared_packets(
+			bytes: SharedBytes,
+			packet_queue: &mut MutexGuard<VecDeque<FiestaPacket>>,
+			limits: &FrameLimits) -> Result<SharedBytes, Error> {
+
+		let mut rest = bytes;
+		loop {
+			let available = rest.len();
+			let (size, prefix) = match try!(FiestaNetworkClient::get_next_size_inner(&mut rest, available, limits)) {
+				Some(next) => next,
+				None => return Ok(rest),
 			};
+			let total_size = prefix + 2 + size as usize;
+			if available < total_size {
+				return Ok(rest);
+			}
 
-			packet.header = read_buffer.read_u16().unwrap();
-			let body = read_buffer.read_bytes(size as usize).unwrap();
-			packet.data.append(&body[..]);
-			packet_queue.push_back(packet);
+			let header = try!(rest.peek_u16(prefix));
+			try!(limits.check(header, size as usize));
+
+			packet_queue.push_back(FiestaPacket::from_shared(header, rest.slice(prefix + 2, total_size)));
+			rest = rest.slice(total_size, available);
 		}
 	}
 
-	fn get_next_size(&self) -> Result<u16, Error> {
-		let mut guard = self.read_buffer.lock().unwrap();
-		FiestaNetworkClient::get_next_size_inner(&mut guard)
-	}
+	/* like read_socket, but into a chunk packets can keep pointing into */
+	fn read_socket_shared(&self, chunk: &mut Arc<Vec<u8>>, read_size: usize) -> Result<Option<SharedBytes>, Error> {
+		if Arc::get_mut(chunk).is_none() || chunk.len() != read_size {
+			/* packets from the last read are still around, they keep the old chunk */
+			*chunk = Arc::new(vec![0; read_size]);
+		}
 
-	fn get_next_size_inner(guard: &mut MutexGuard<Buffer>) -> Result<u16, Error> {
-		if guard.bytes_remaining() < 3 {
-			Err(Error::new(ErrorKind::Other, "to little data left"))
-		} else {
-			let small_size = try!(guard.peek_u8(0));
-			if small_size > 0 {
-				Ok(small_size as u16)
-			} else {
-				let mut big_size = try!(guard.peek_u16(1));
+		let size = {
+			let data = Arc::get_mut(chunk).unwrap();
+			try!(self.transport.read(&mut data[..]))
+		};
+		match size {
+			0		=> Ok(None),
+			size	=> Ok(Some(SharedBytes::new(chunk.clone(), 0, size))),
+		}
+	}
 
-				if (big_size as usize) > 2048 {
-					/* this should never actually happen with real data */
-					/* casting 0 here will still let it read 5 bytes (size + header) */
-					big_size = 0;
-				};
+	/* frames can only be sliced out of fresh chunks, not out of the ring buffer */
+	fn can_read_shared(&self, io: &ClientIo) -> bool {
+		#[cfg(feature = "tls")]
+		{
+			if self.tls.is_some() {
+				return false;
+			}
+		}
+		#[cfg(feature = "crypto")]
+		{
+			/* decrypting needs bodies of their own */
+			if self.recover(self.decryption.lock()).is_some() {
+				return false;
+			}
+		}
+		self.codec.is_none() && io.read_buffer.bytes_remaining() == 0 && !io.proxy_pending
+	}
 
-				Ok(big_size)
+	/* Ok(None) on EOF, otherwise the number of bytes appended to `read_buffer` */
+	fn read_socket(&self, read_buffer: &mut Buffer, read_size: usize) -> Result<Option<usize>, Error> {
+		#[cfg(feature = "tls")]
+		{
+			if let Some(ref tls) = self.tls {
+				return self.recover(tls.lock()).read(&*self.transport, read_buffer);
 			}
 		}
+
+		if read_buffer.free() == 0 {
+			/* a full buffer would look like EOF below */
+			return Ok(Some(0));
+		}
+		/* straight into the ring, no bounce buffer */
+		match try!(read_buffer.read_from(&mut TransportIo(&*self.transport), read_size)) {
+			0		=> Ok(None),
+			size	=> Ok(Some(size)),
+		}
+	}
+
+	/* a read that filled the whole chunk probably left more behind, one that came back mostly empty didn't */
+	fn adapt_read_size(io: &mut ClientIo, read: usize) {
+		if read >= io.read_size {
+			io.read_size = min(io.read_size * 2, MAX_READ_CHUNK_SIZE);
+		} else if read < io.read_size / 4 && io.read_size > READ_CHUNK_SIZE {
+			io.read_size /= 2;
+		}
 	}
 
-	pub fn readable(&self, event_loop: &mut EventLoop<FiestaHandler>, token: Token, disconnect: &mut bool) {
-		let mut buffer = [0; 2048]; /* maybe not allocate this every time again? */
-		let mut inner_client_guard = self.client.lock().unwrap();
-		let mut read_buffer_guard = self.read_buffer.lock().unwrap();
+	/* true once the socket has nothing more to give until the next event, `budget` is reduced by what was read */
+	pub fn readable(&self, timers: &mut Timers, token: Token, budget: &mut usize, disconnect: &mut Option<DisconnectReason>) -> bool {
+		#[cfg(feature = "spans")]
+		let _entered = self.span.enter();
+		let mut guard = self.recover(self.io.lock());
+		let io = &mut *guard;
+
+		if self.can_read_shared(io) {
+			let read_size = io.read_size;
+			let read_buffer = &mut io.read_buffer;
+			let result = self.read_socket_shared(&mut io.read_chunk, read_size).and_then(|bytes| match bytes {
+				Some(bytes) => {
+					let size = bytes.len();
+					let mut packet_queue_guard = self.recover(self.packet_queue.lock());
+					let rest = try!(FiestaNetworkClient::read_shared_packets(bytes, &mut packet_queue_guard, &self.limits));
+					try!(self.inflate_queued(&mut packet_queue_guard));
+					/* the partial frame waits in the ring buffer for the rest of it */
+					try!(read_buffer.append(rest.as_slice()));
+					Ok(Some(size))
+				},
+				None => Ok(None),
+			});
+			if let Ok(Some(size)) = result {
+				FiestaNetworkClient::adapt_read_size(io, size);
+				*budget = budget.saturating_sub(size);
+			}
+			return self.handle_read_result(timers, result, token, disconnect);
+		}
+
+		let result = self.read_socket(&mut io.read_buffer, io.read_size);
+		if let Ok(Some(size)) = result {
+			FiestaNetworkClient::adapt_read_size(io, size);
+			*budget = budget.saturating_sub(size);
+		}
+		let drained = self.handle_read_result(timers, result, token, disconnect);
+
+		/* the PROXY header has to be gone before the framing sees any of it */
+		if !self.consume_proxy_header(&mut io.read_buffer, &mut io.proxy_pending, disconnect) {
+			return drained;
+		}
+
+		let mut packet_queue_guard = self.recover(self.packet_queue.lock());
+		loop {
+			match self.decode_next(&mut io.read_buffer, &mut packet_queue_guard) {
+				Ok(true)	=> {},
+				Ok(false)	=> break,
+				Err(e)		=> {
+					/* oversized or malformed frame, there's no resyncing the stream after that */
+					warn!(target: "network", "failed to read packet from {}: {}", self.describe(), e);
+					self.metrics.frame_error();
+					*disconnect = Some(self.close(DisconnectReason::ReadError(e)));
+					break;
+				}
+			}
+		}
+		drained
+	}
 
-		match inner_client_guard.read(&mut buffer[..]) {
-			Ok(size) if size > 0 => {
-				/* read some data */
+	/* true if there's no point in reading again before the next event */
+	fn handle_read_result(&self, timers: &mut Timers, result: Result<Option<usize>, Error>,
+			token: Token, disconnect: &mut Option<DisconnectReason>) -> bool {
+		match result {
+			Ok(Some(size)) => {
+				/* read some data (may be 0 while a tls handshake is in progress) */
 				info!(target: "network", "read {} bytes from {:?}", size, token);
-				read_buffer_guard.append(&buffer[0..size]);
+				self.metrics.bytes_read(size);
+				self.counters.bytes_read(size);
+				self.check_byte_rate(timers, size, disconnect);
+				false
 			},
-			Ok(_) => {
+			Ok(None) => {
 				/* size == 0 */
 				debug!(target: "network", "read 0 bytes from {:?}", self.id());
-				/* this usually means a disconect */
-				/* no need to deregister, we use oneshot. */
-				// event_loop.deregister(&*inner_client_guard).unwrap();
-				inner_client_guard.shutdown(Shutdown::Both).unwrap();
-				self.set_alive(false);
-				*disconnect = true;
+				/* this usually means a disconect, the handler deregisters the socket */
+				*disconnect = Some(self.close(DisconnectReason::PeerClosed));
+				true
+			},
+			Err(ref e) if is_transient(e) => {
+				/* spurious wakeup or a signal, re-registering will bring us back if there is more */
+				debug!(target: "network", "transient error while receiving data from {:?}: {}", token, e);
+				true
 			},
 			Err(e) => {
 				/* some error while receiving data.. */
 				warn!(target: "network", "error while receiving data: '{:#?}'", e);
-				/* no need to deregister, we use oneshot. */
-				// event_loop.deregister(&*inner_client_guard);
-				inner_client_guard.shutdown(Shutdown::Both).unwrap();
-				self.set_alive(false);
-				*disconnect = true;
+				*disconnect = Some(self.close(DisconnectReason::ReadError(e)));
+				true
 			}
 		}
+	}
 
-		/* this is no longer needed, as it is a mutex, I like to drop it ASAP */
-		drop(inner_client_guard);
-		
-		let mut packet_queue_guard = self.packet_queue.lock().unwrap();
-		while FiestaNetworkClient::can_read_next_packet_inner(&mut read_buffer_guard) {
-			FiestaNetworkClient::read_next_packet_inner(&mut read_buffer_guard, &mut packet_queue_guard);
+	#[cfg(feature = "tls")]
+	fn writeable_tls(&self, tls: &Mutex<TlsSession>, token: Token, disconnect: &mut Option<DisconnectReason>) -> bool {
+		let mut io = self.recover(self.io.lock());
+		let guard = &mut io.write_buffer;
+		self.drain_outbound(guard);
+		let mut session = self.recover(tls.lock());
+
+		let result = session.write(&*self.transport, guard);
+		self.outbound.buffered_now(guard);
+		match result {
+			Ok(s) => {
+				debug!(target: "network", "wrote {} tls bytes to {:?}", s, token);
+				self.metrics.bytes_written(s);
+				self.counters.bytes_written(s);
+				if guard.bytes_remaining() == 0 && !session.wants_write() {
+					/* nothing left to flush, handshake included, unless a frame came in meanwhile */
+					return self.write_done(guard, disconnect);
+				}
+				false
+			},
+			Err(ref e) if is_transient(e) => {
+				/* keep the writable interest, we'll be back */
+				debug!(target: "network", "transient error while writing to tls socket ({:?}): {}", token, e);
+				true
+			},
+			Err(e) => {
+				warn!(target: "network", "error while writing to tls socket ({:?}): {:#?}", token, e);
+				*disconnect = Some(self.close(DisconnectReason::WriteStall));
+				true
+			}
 		}
 	}
 
-	pub fn writeable(&self, event_loop: &mut EventLoop<FiestaHandler>, token: Token, disconnect: &mut bool) {
-		let mut buf = [0; 1024];
-		let mut guard = self.write_buffer.lock().unwrap();
-		match guard.peek_max(0, 1024, &mut buf[..]) {
-			Ok(size) if size > 0	=> {
-				let mut inner_client_guard = self.client.lock().unwrap();
-				match inner_client_guard.write(&buf[0..size]) {
-					Ok(s) if s > 0 => {
-						debug!(target: "network", "wrote {} bytes to {:?}", s, token);
-						guard.advance_read(s);
-					},
-					Ok(_) => {
-						/* size == 0 */
-						warn!(target: "network", "wrote 0 bytes for {:?}, shutting down the socket.", token);
-						/* no need to deregister, we use oneshot. */
-						inner_client_guard.shutdown(Shutdown::Both).unwrap();
-						self.set_alive(false);
-						*disconnect = true;
-					},
-					Err(e) => {
-						/* error while writing */
-						warn!(target: "network", "error while writing to socket ({:?}): {:#?}", token, e);
-						/* no need to deregister, we use oneshot. */
-						inner_client_guard.shutdown(Shutdown::Both).unwrap();
-						self.set_alive(false);
-						*disconnect = true;
+	/* returns whether framing may proceed */
+	fn consume_proxy_header(&self, read_buffer: &mut Buffer, pending: &mut bool, disconnect: &mut Option<DisconnectReason>) -> bool {
+		if !*pending {
+			return true;
+		}
+
+		let available = read_buffer.bytes_remaining();
+		let data = match read_buffer.peek_bytes(0, available) {
+			Ok(data) => data,
+			Err(_) => return false,
+		};
+
+		match proxy::parse(&data[..]) {
+			ProxyHeader::Incomplete if available < BUFFERSIZE => false,
+			ProxyHeader::Done { consumed, source } => {
+				read_buffer.advance_read(consumed);
+				*pending = false;
+				if let Some(source) = source {
+					let source = normalize_addr(source);
+					*self.recover(self.proxied_addr.lock()) = Some(source);
+					/* the handler only saw the proxy's address when it accepted the connection */
+					let banned = match self.banned.read() {
+						Ok(banned) => banned.contains(&source.ip()),
+						Err(poisoned) => poisoned.into_inner().contains(&source.ip()),
+					};
+					if banned {
+						info!(target: "network", "dropping {}, {} is banned.", self.describe(), source.ip());
+						self.audit(AuditKind::Kick(format!("banned {}", source.ip())));
+						*disconnect = Some(self.close(DisconnectReason::Kicked));
+						return false;
 					}
 				}
+				debug!(target: "network", "PROXY header accepted for {}", self.describe());
+				true
+			},
+			_ => {
+				warn!(target: "network", "invalid PROXY header from {}, disconnecting.", self.describe());
+				*disconnect = Some(self.close(DisconnectReason::ProtocolViolation));
+				false
+			}
+		}
+	}
+
+	/* true once there's nothing to write or the socket won't take more until the next event */
+	pub fn writeable(&self, token: Token, disconnect: &mut Option<DisconnectReason>) -> bool {
+		#[cfg(feature = "spans")]
+		let _entered = self.span.enter();
+		#[cfg(feature = "tls")]
+		{
+			if let Some(ref tls) = self.tls {
+				return self.writeable_tls(tls, token, disconnect);
+			}
+		}
+
+		let mut io = self.recover(self.io.lock());
+		let guard = &mut io.write_buffer;
+		self.drain_outbound(guard);
+		match self.transport.flush() {
+			Ok(()) => {},
+			/* what the transport holds back goes first, keep the writable interest */
+			Err(ref e) if is_transient(e) => return true,
+			Err(e) => {
+				warn!(target: "network", "error while flushing the transport of {:?}: {}", token, e);
+				*disconnect = Some(self.close(DisconnectReason::WriteStall));
+				return true;
+			}
+		}
+		if guard.bytes_remaining() == 0 && self.write_done(guard, disconnect) {
+			/* nothing to send, don't wake up for writable until append_send wants it again */
+			return true;
+		}
+
+		let result = guard.write_to(&*self.transport);
+		self.outbound.buffered_now(guard);
+		match result {
+			Ok(s) if s > 0 => {
+				debug!(target: "network", "wrote {} bytes to {:?}", s, token);
+				self.metrics.bytes_written(s);
+				self.counters.bytes_written(s);
+				if guard.bytes_remaining() == 0 {
+					/* flushed, the reregister after this event drops the writable bit */
+					return self.write_done(guard, disconnect);
+				}
+				false
+			},
+			Ok(_) => {
+				/* size == 0 */
+				warn!(target: "network", "wrote 0 bytes for {:?}, shutting down the socket.", token);
+				*disconnect = Some(self.close(DisconnectReason::WriteStall));
+				true
 			},
-			Ok(_)	=> {
-				/* read 0 bytes from send buffer..  */
-				/* TODO: we might want to unregister it from the loop until new data arrives */
-				let interest = self.interest();
-				let interest = interest ^ EventSet::writable();
-				self.set_interest(interest);
+			Err(ref e) if is_transient(e) => {
+				/* socket buffer full, keep the data and the writable interest */
+				debug!(target: "network", "transient error while writing to socket ({:?}): {}", token, e);
+				true
 			},
-			Err(e)		=> {
-				warn!(target: "network", "error while reading from write_buffer ({:?}): {:#?}", token, e);
-				let inner_client_guard = self.client.lock().unwrap();
-				/* no need to deregister, we use oneshot */
-				inner_client_guard.shutdown(Shutdown::Both).unwrap();
-				*disconnect = true;
+			Err(e) => {
+				/* error while writing */
+				warn!(target: "network", "error while writing to socket ({:?}): {:#?}", token, e);
+				*disconnect = Some(self.close(DisconnectReason::WriteStall));
+				true
+			}
+		}
+	}
+
+	fn check_byte_rate(&self, timers: &mut Timers, size: usize, disconnect: &mut Option<DisconnectReason>) {
+		let limit = match self.byte_rate_limit.read().ok().and_then(|limit| *limit) {
+			Some(limit) => limit,
+			None => return,
+		};
+
+		let now = Instant::now();
+		let (window_start, bytes) = {
+			let mut window = self.recover(self.byte_window.lock());
+			if now.duration_since(window.0) >= Duration::from_secs(1) {
+				*window = (now, 0);
 			}
+			window.1 += size;
+			*window
 		};
+		if bytes <= limit.bytes_per_sec {
+			return;
+		}
+
+		/* key=value so alerting can pick it up from the log */
+		warn!(target: "flood", "event=byte_flood client={:?} addr={} bytes={} limit={} action={:?}",
+			self.id, self.real_addr().map(|a| a.to_string()).unwrap_or("-".to_string()), bytes, limit.bytes_per_sec, limit.action);
+
+		match limit.action {
+			FloodAction::Disconnect => {
+				*disconnect = Some(self.close(DisconnectReason::ProtocolViolation));
+			},
+			FloodAction::Throttle => {
+				if !self.throttled.swap(true, Ordering::SeqCst) {
+					let rest = Duration::from_secs(1) - now.duration_since(window_start);
+					timers.schedule(rest + Duration::from_millis(1), ClientTimeout::Unthrottle(self.id));
+				}
+			},
+		}
 	}
 
-	pub fn alive(&self) -> bool {
-		let guard = self.is_alive.lock().unwrap();
-		(*guard).clone()
+	#[cfg(feature = "spans")]
+	pub fn span(&self) -> &Span {
+		&self.span
 	}
 
-	pub fn id(&self) -> Token {
-		self.id
+	pub fn stats(&self) -> ClientStats {
+		let send_frames = match self.io.lock() {
+			Ok(io) => io.write_buffer.frame_count(),
+			Err(_) => 0,
+		} + self.outbound.queued_frames();
+		let send_bytes = self.outbound.pending();
+		let mut stats = self.counters.snapshot(self.id, self.real_addr(), self.in_flight(), send_frames, send_bytes);
+		stats.label = self.label();
+		stats
 	}
 
-	fn set_alive(&self, value: bool) {
-		let mut guard = self.is_alive.lock().unwrap();
-		*guard = value;
+	pub fn handshake_done(&self) -> bool {
+		self.handshake_done.load(Ordering::SeqCst)
 	}
 
-	pub fn interest(&self) -> EventSet {
-		let guard = self.interest.lock().unwrap();
-		(*guard).clone()
+	pub fn throttled(&self) -> bool {
+		self.throttled.load(Ordering::SeqCst)
 	}
 
-	fn set_interest(&self, interest: EventSet) {
-		let mut guard = self.interest.lock().unwrap();
-		*guard = interest;
+	pub fn in_flight(&self) -> usize {
+		self.in_flight.load(Ordering::SeqCst)
 	}
 
-	pub fn append_send(&self, buffer: &[u8]) {
-		let mut guard = self.write_buffer.lock().unwrap();
-		guard.append(buffer);
-		let mut interest_guard = self.interest.lock().unwrap();
-		if !interest_guard.is_writable() {
-			*interest_guard = (*interest_guard) | EventSet::writable();
-		}
+	pub fn read_paused(&self) -> bool {
+		self.read_paused.load(Ordering::SeqCst)
 	}
-}
 
-impl FiestaHandler {
-	pub fn new(listener: TcpListener, processor: Box<PacketProcessor>) -> FiestaHandler {
-		FiestaHandler {
-			listener:			listener,
-			clients:			HashMap::new(),
-			token_count:		0,
-			processor:			processor,
-		}
+	pub fn protocol_state(&self) -> ProtocolState {
+		*self.recover(self.protocol_state.lock())
 	}
 
-	fn server_ready(&mut self, event_loop: &mut EventLoop<Self>, token: Token, events: EventSet) {
-		if events.is_readable() {
-			/* we may accept a client */
-			match self.listener.accept() {
-				Ok(Some(client)) => {
-					/* successfully accepted a client */
-					let token = self.get_next_token();
-					event_loop.register_opt(&client, token, EventSet::all(), PollOpt::oneshot()).unwrap();
-					self.clients.insert(
-						token, 
-						Arc::new(
-							RwLock::new(
-								Box::new(
-									FiestaNetworkClient::new(client, token)))));
-					info!(target: "network", "accepted client with {:?}", token);
-				},
-				Ok(None) => {
-					/* WOULDBLOCK / EAGAIN */
-					info!(target: "network", "WOULDBLOCK while accepting client.");
-				},
-				Err(e) => {
-					/* unexpected error */
-					panic!("unexpected error: {:#?}", e);
-				}
+	/* sends the seed, the client's first packet, and decrypts what comes back with it */
+	pub fn start_handshake(&self, handshake: &SeedHandshake) -> FiestaResult<()> {
+		let seed = handshake.next_seed();
+		#[cfg(feature = "crypto")]
+		{
+			if let Some(table) = handshake.table() {
+				self.set_keystream(Keystream::new(table, seed));
 			}
 		}
+		try!(self.append_frame(try!(NcMiscSeedAck { seed: seed }.encode())));
+		self.set_protocol_state(ProtocolState::SeedSent);
+		Ok(())
 	}
 
-	fn get_next_token(&mut self) -> Token {
-		self.token_count += 1;
-		Token(self.token_count)
+	/* the processor moves the client along, e.g. to Authenticated once the login checked out */
+	pub fn set_protocol_state(&self, state: ProtocolState) {
+		let mut current = self.recover(self.protocol_state.lock());
+		debug!(target: "network", "{:?}: {:?} -> {:?}", self.id, *current, state);
+		*current = state;
 	}
 
-	fn get_current_token(&self) -> Token {
-		Token(self.token_count)
+	pub fn protocol_version(&self) -> Option<Arc<ProtocolVersion>> {
+		self.recover(self.protocol_version.read()).clone()
 	}
 
-	fn client_ready(&mut self, event_loop: &mut EventLoop<Self>, token: Token, events: EventSet) {
-		let mut client_disconnect = false;
-		let mut packets_to_process = Vec::new();
+	pub fn set_protocol_version(&self, version: Arc<ProtocolVersion>) {
+		*self.recover(self.protocol_version.write()) = Some(version);
+	}
 
-		if events.is_readable() {
-			let client = self.clients.get(&token).unwrap();
-			let client_guard = client.read().unwrap();
-			client_guard.readable(event_loop, token, &mut client_disconnect);
+	/* Err with what to do about it if the client may not send `header` in its current state */
+	pub fn check_state(&self, header: u16) -> Result<(), ViolationAction> {
+		let rules = match self.state_rules {
+			Some(ref rules) => rules,
+			None => return Ok(()),
+		};
+		let state = self.protocol_state();
+		if rules.permits(state, header) {
+			return Ok(());
+		}
+		warn!(target: "flood", "event=state_violation client={:?} addr={} opcode={:#06x} state={:?} action={:?}",
+			self.id, self.real_addr().map(|a| a.to_string()).unwrap_or("-".to_string()), header, state, rules.action());
+		if rules.action() == ViolationAction::Disconnect {
+			self.close_for(DisconnectReason::ProtocolViolation);
+		}
+		Err(rules.action())
+	}
 
-			let mut packet_queue_guard = client_guard.packet_queue.lock().unwrap();
-			while !packet_queue_guard.is_empty() {
-				let packet = packet_queue_guard.pop_front().unwrap();
-				packets_to_process.push(
-					Arc::new(
-						RwLock::new(
-							Box::new(
-								PacketProcessingInfo::new(
-									packet,
-									client.clone())))));
+	/* true if `header` was a heartbeat, which is dealt with here and not passed on. `header` is the */
+	/* one off the wire, the keepalive opcodes are the canonical ones */
+	pub fn handle_keepalive(&self, keepalive: &Keepalive, header: u16) -> bool {
+		let header = match self.protocol_version() {
+			Some(version)	=> version.to_canonical(header),
+			None			=> header,
+		};
+		if header == keepalive.request {
+			/* through send(), so the version, codec and compression apply like to any other packet */
+			if let Err(e) = self.send(FiestaPacket::new(keepalive.response, 0)) {
+				warn!(target: "network", "failed to answer the heartbeat of {}: {}", self.describe(), e);
 			}
+			true
+		} else if header == keepalive.response {
+			self.keepalive_missed.store(0, Ordering::SeqCst);
+			true
+		} else {
+			false
 		}
+	}
 
-		if events.is_writable() {
-			let client = self.clients.get_mut(&token).unwrap();
-			let guard = client.read().unwrap();
-			guard.writeable(event_loop, token, &mut client_disconnect);
+	/* sends the next heartbeat, false if the client missed too many already */
+	pub fn send_keepalive(&self, keepalive: &Keepalive) -> bool {
+		if self.keepalive_missed.fetch_add(1, Ordering::SeqCst) >= keepalive.max_missed {
+			return false;
+		}
+		if let Err(e) = self.send(FiestaPacket::new(keepalive.request, 0)) {
+			warn!(target: "network", "failed to send a heartbeat to {}: {}", self.describe(), e);
 		}
+		true
+	}
 
-		for packet in packets_to_process.into_iter() {
-			self.processor.process_packet(packet);
+	/* called on the reactor thread when packets are passed on to the processor */
+	pub fn packets_dispatched(&self, count: usize) {
+		if count > 0 {
+			self.handshake_done.store(true, Ordering::SeqCst);
+			self.metrics.packets_received(count);
+			self.counters.packets_received(count);
+		}
+		let in_flight = self.in_flight.fetch_add(count, Ordering::SeqCst) + count;
+		let backpressure = match self.backpressure {
+			Some(backpressure) => backpressure,
+			None => return,
 		};
 
-		/* we need to have this down here, because of borrows.. */
-		if client_disconnect {
-			self.clients.remove(&token);
-			info!(target: "network", "client {:?} disconnected.", token);
-		} else {
-			/* re-register */
-			let client = self.clients.get(&token).unwrap();
-			let client_borrow = client.read().unwrap();
-			let inner_client_guard = client_borrow.client.lock().unwrap();
-			let interest = client_borrow.interest();
-			event_loop.reregister(&*inner_client_guard, token, interest, PollOpt::oneshot()).unwrap();
+		if in_flight >= backpressure.pause_at && !self.read_paused.swap(true, Ordering::SeqCst) {
+			debug!(target: "network", "pausing reads from {}, {} packets in flight", self.describe(), in_flight);
+			/* the workers may have caught up before the flag was visible to them */
+			if self.in_flight() <= backpressure.resume_at {
+				self.read_paused.store(false, Ordering::SeqCst);
+			}
 		}
 	}
-}
 
-impl Handler for FiestaHandler {
-	type Timeout = usize;
-	type Message = ();
+	/* one packet dispatched outside the reactor, see PacketProcessingInfo::dispatched() */
+	pub fn packet_in_flight(&self) {
+		self.in_flight.fetch_add(1, Ordering::SeqCst);
+	}
+
+	/* called when a counted PacketProcessingInfo for this client is dropped, on any thread */
+	pub fn packet_processed(&self) {
+		/* saturates, a packet that was never counted must not wrap the count around and pause reads for good */
+		let mut current = self.in_flight();
+		let in_flight = loop {
+			if current == 0 {
+				warn!(target: "network", "{}: a packet was processed that was never counted in flight", self.describe());
+				break 0;
+			}
+			match self.in_flight.compare_exchange(current, current - 1, Ordering::SeqCst, Ordering::SeqCst) {
+				Ok(_) => break current - 1,
+				Err(actual) => current = actual,
+			}
+		};
+		let backpressure = match self.backpressure {
+			Some(backpressure) => backpressure,
+			None => return,
+		};
 
-	fn ready(&mut self, event_loop: &mut EventLoop<Self>, token: Token, events: EventSet) {
-		match token {
-			SERVER_TOKEN 	=> self.server_ready(event_loop, SERVER_TOKEN, events),
-			t 				=> self.client_ready(event_loop, t, events),
+		if in_flight <= backpressure.resume_at && self.read_paused.swap(false, Ordering::SeqCst) {
+			if let Ok(notify) = self.notify.lock() {
+				if let Some(ref sender) = *notify {
+					if let Err(e) = sender.send(ServerMessage::ResumeRead(self.id)) {
+						warn!(target: "network", "failed to resume reads for {}: {:?}", self.describe(), e);
+					}
+				}
+			}
 		}
 	}
-}
 
-impl FiestaPacket {
-	pub fn new(header: u16, size: usize) -> Self {
-		FiestaPacket {
-			header:			header,
-			data:			Buffer::with_capacity(size),
+	pub fn alive(&self) -> bool {
+		let guard = self.recover(self.is_alive.lock());
+		(*guard).clone() && !self.is_poisoned()
+	}
+
+	/* a handler that panics with one of our locks held leaves it poisoned, and whatever it guards */
+	/* maybe half updated. the guard is handed out anyway so the reactor carries on, but the socket */
+	/* is shut down and the client goes with DisconnectReason::Poisoned. nothing else is locked */
+	/* here, the poisoned lock may be the one close_for() would take */
+	fn recover<G>(&self, result: LockResult<G>) -> G {
+		match result {
+			Ok(guard) => guard,
+			Err(poisoned) => {
+				if !self.poisoned.swap(true, Ordering::SeqCst) {
+					error!(target: "network", "{:?}: a lock was poisoned by a panic, closing the client", self.id);
+					let _ = self.transport.shutdown();
+				}
+				poisoned.into_inner()
+			}
 		}
 	}
-}
\ No newline at end of file
+
+	pub fn is_poisoned(&self) -> bool {
+		self.poisoned.load(Ordering::SeqCst)
+	}
+
+	pub fn id(&self) -> Token {
+		self.id
+	}
+
+	/* the other end of the socket as it was accepted, a proxy's address behind one, see real_addr() */
+	pub fn peer_addr(&self) -> Option<SocketAddr> {
+		self.peer_addr
+	}
+
+	/* which of the server's addresses the client connected to */
+	pub fn local_addr(&self) -> Option<SocketAddr> {
+		self.local_addr
+	}
+
+	/* the address the connection really came from, honouring a PROXY header */
+	pub fn real_addr(&self) -> Option<SocketAddr> {
+		let proxied = self.recover(self.proxied_addr.lock());
+		(*proxied).or(self.peer_addr)
+	}
+
+	/* shows up in describe(), and so in every log line about this client, and on its span */
+	pub fn set_label(&self, label: Option<String>) {
+		#[cfg(feature = "spans")]
+		self.span.record("label", &label.as_ref().map_or("", |label| label.as_str()));
+		match self.label.write() {
+			Ok(mut current) => *current = label,
+			Err(poisoned) => *poisoned.into_inner() = label,
+		}
+	}
+
+	pub fn label(&self) -> Option<String> {
+		match self.label.read() {
+			Ok(label) => label.clone(),
+			Err(poisoned) => poisoned.into_inner().clone(),
+		}
+	}
+
+	pub fn extensions(&self) -> MutexGuard<Extensions> {
+		match self.extensions.lock() {
+			Ok(extensions) => extensions,
+			Err(poisoned) => poisoned.into_inner(),
+		}
+	}
+
+	fn group_refs(&self) -> MutexGuard<Vec<GroupRef>> {
+		match self.groups.lock() {
+			Ok(groups) => groups,
+			Err(poisoned) => poisoned.into_inner(),
+		}
+	}
+
+	/* called by ClientGroup::join and leave */
+	pub fn joined(&self, group: &ClientGroup) {
+		let mut groups = self.group_refs();
+		if !groups.iter().any(|joined| joined.is(group)) {
+			groups.push(group.downgrade());
+		}
+	}
+
+	pub fn left(&self, group: &ClientGroup) {
+		self.group_refs().retain(|joined| joined.upgrade().is_some() && !joined.is(group));
+	}
+
+	/* the groups it is in that still exist */
+	pub fn groups(&self) -> Vec<ClientGroup> {
+		self.group_refs().iter().filter_map(|group| group.upgrade()).collect()
+	}
+
+	/* "Token(n) @ ip:port [label]", for log lines */
+	pub fn describe(&self) -> String {
+		let described = match self.real_addr() {
+			Some(addr)	=> format!("{:?} @ {}", self.id, addr),
+			None		=> format!("{:?}", self.id),
+		};
+		match self.label() {
+			Some(label)	=> format!("{} [{}]", described, label),
+			None		=> described,
+		}
+	}
+
+	/* safe from any thread, the reactor cleans up once the socket reports the shutdown */
+	pub fn disconnect(&self) {
+		self.close_for(DisconnectReason::Kicked);
+	}
+
+	/* like disconnect(), but the reactor removes it on its next wakeup and audits the kick. */
+	/* a client without an event loop, e.g. on the tokio frontend, is just disconnected */
+	pub fn kick(&self) {
+		if self.notify_reactor(ServerMessage::Kick(self.id)).is_err() {
+			self.disconnect();
+		}
+	}
+
+	/* stops reading and shuts the socket down once everything queued is written, so a kick */
+	/* doesn't race the packet saying why. one that doesn't read it in time is dropped anyway */
+	pub fn close_after_flush(&self) -> FiestaResult<()> {
+		if self.flush_then_close.swap(true, Ordering::SeqCst) {
+			return Ok(());
+		}
+		self.notify_reactor(ServerMessage::CloseAfterFlush(self.id))
+	}
+
+	pub fn closing_after_flush(&self) -> bool {
+		self.flush_then_close.load(Ordering::SeqCst)
+	}
+
+	/* like disconnect(), the reactor reports `reason` unless an earlier one is pending */
+	fn close_for(&self, reason: DisconnectReason) {
+		{
+			let mut closing = self.recover(self.closing.lock());
+			if closing.is_none() {
+				*closing = Some(reason);
+			}
+		}
+		let _ = self.transport.shutdown();
+		self.set_alive(false);
+	}
+
+	/* on the reactor, when it notices the connection is over. returns the reason close_for() */
+	/* left if there is one, EOF after a kick is still a kick */
+	pub fn close(&self, reason: DisconnectReason) -> DisconnectReason {
+		let _ = self.transport.shutdown();
+		self.set_alive(false);
+		let recorded = self.recover(self.closing.lock()).take();
+		if self.is_poisoned() {
+			return DisconnectReason::Poisoned;
+		}
+		recorded.unwrap_or(reason)
+	}
+
+	fn set_alive(&self, value: bool) {
+		let mut guard = self.recover(self.is_alive.lock());
+		*guard = value;
+	}
+
+	pub fn interest(&self) -> Interest {
+		let guard = self.recover(self.interest.lock());
+		let mut interest = (*guard).clone();
+		if self.read_paused() || self.throttled() {
+			/* leave the bytes in the kernel until the workers catch up */
+			interest = without(interest, Interest::READABLE);
+		}
+		if self.flush_then_close.load(Ordering::SeqCst) {
+			/* writable even with nothing queued, that's where it gets closed */
+			return without(interest, Interest::READABLE) | Interest::WRITABLE;
+		}
+
+		if self.transport.wants_write() {
+			return interest | Interest::WRITABLE;
+		}
+		#[cfg(feature = "tls")]
+		{
+			/* the handshake needs to write even when the application doesn't */
+			if let Some(ref tls) = self.tls {
+				if self.recover(tls.lock()).wants_write() {
+					return interest | Interest::WRITABLE;
+				}
+			}
+		}
+
+		interest
+	}
+
+	fn handle_full_send_buffer(&self, needed: usize) -> FiestaResult<()> {
+		match self.send_policy {
+			SlowConsumerPolicy::DropOldest => {
+				/* the reactor makes the room when it moves the frame into the send buffer */
+				if needed <= self.outbound.capacity() {
+					return Ok(());
+				}
+				warn!(target: "network", "send buffer of {} is full, dropping new frame.", self.describe());
+			},
+			SlowConsumerPolicy::RejectNew => {
+				debug!(target: "network", "send buffer of {} is full, rejecting frame.", self.describe());
+			},
+			SlowConsumerPolicy::Disconnect => {
+				warn!(target: "network", "send buffer of {} is full, disconnecting slow client.", self.describe());
+				self.close_for(DisconnectReason::WriteStall);
+			},
+		}
+		Err(FiestaNetError::SendBufferFull(self.id))
+	}
+
+	fn set_interest(&self, interest: Interest) {
+		let mut guard = self.recover(self.interest.lock());
+		*guard = interest;
+	}
+
+	/* moves the frames sent since the last call into the send buffer, reactor only */
+	fn drain_outbound(&self, guard: &mut FrameQueue) {
+		let dropped = self.outbound.drain_into(guard, self.send_policy == SlowConsumerPolicy::DropOldest);
+		if dropped > 0 {
+			debug!(target: "network", "dropped {} unsent frames for {}", dropped, self.describe());
+		}
+	}
+
+	/* drops the writable bit if nothing was sent in the meantime. append_send sets it again under */
+	/* the same lock after queueing a frame, so the frame is either drained here or wakes us up */
+	fn clear_writable_if_idle(&self, guard: &mut FrameQueue) -> bool {
+		let mut interest = self.recover(self.interest.lock());
+		self.drain_outbound(guard);
+		if guard.bytes_remaining() > 0 {
+			return false;
+		}
+		*interest = without(*interest, Interest::WRITABLE);
+		true
+	}
+
+	/* clear_writable_if_idle, and the end of the connection if close_after_flush() asked for it */
+	fn write_done(&self, guard: &mut FrameQueue, disconnect: &mut Option<DisconnectReason>) -> bool {
+		if !self.clear_writable_if_idle(guard) {
+			return false;
+		}
+		if self.flush_then_close.load(Ordering::SeqCst) {
+			debug!(target: "network", "everything sent to {}, closing it.", self.describe());
+			*disconnect = Some(self.close(DisconnectReason::Kicked));
+		}
+		true
+	}
+
+	fn reregister(&self, registry: &Registry, interest: Interest) -> Result<(), Error> {
+		self.transport.reregister(registry, self.id, interest)
+	}
+
+	fn deregister(&self, registry: &Registry) -> Result<(), Error> {
+		self.transport.deregister(registry)
+	}
+
+	/* records `interest` as the registered one, false if it already was */
+	fn update_registered(&self, interest: Interest) -> bool {
+		let mut io = self.recover(self.io.lock());
+		if io.registered == interest {
+			return false;
+		}
+		io.registered = interest;
+		true
+	}
+
+	/* runs `bytes` through the framing code as if they came off the socket, returns the complete packets */
+	/* meant for tests, a client driven by the event loop never needs it */
+	pub fn receive_bytes(&self, bytes: &[u8]) -> FiestaResult<Vec<FiestaPacket>> {
+		try!(self.io.lock()).read_buffer.extend(bytes);
+		while try!(self.read_next_packet()) {}
+		let mut queue = try!(self.packet_queue.lock());
+		Ok(queue.drain(..).collect())
+	}
+
+	/* bytes queued that haven't been written to the socket yet */
+	pub fn pending_send(&self) -> usize {
+		self.outbound.pending()
+	}
+
+	/* everything queued for sending, taken out without touching the socket */
+	/* used by tests and by the tokio frontend, which writes it out itself */
+	pub fn take_sent(&self) -> FiestaResult<Vec<u8>> {
+		let mut io = try!(self.io.lock());
+		let guard = &mut io.write_buffer;
+		self.drain_outbound(guard);
+		let sent = guard.take_all();
+		self.outbound.buffered_now(guard);
+		Ok(sent)
+	}
+
+	/* frames `body` under the opcode the client's protocol version uses for `header` */
+	pub fn send_packet(&self, header: u16, body: &[u8]) -> FiestaResult<()> {
+		let header = match self.protocol_version() {
+			Some(version) => version.to_wire(header),
+			None => header,
+		};
+		self.queue_frame(try!(self.frame(header, body.to_vec())))
+	}
+
+	/* like send_packet, without copying the body. the packet's header is the canonical opcode */
+	pub fn send(&self, mut packet: FiestaPacket) -> FiestaResult<()> {
+		if let Some(version) = self.protocol_version() {
+			packet.header = version.to_wire(packet.header);
+		}
+		if let Some(ref codec) = self.codec {
+			return self.queue_frame(try!(FiestaNetworkClient::encode_with(&**codec, &packet)));
+		}
+		#[cfg(feature = "compression")]
+		{
+			if self.compression.is_some() && self.peer_compresses.load(Ordering::SeqCst) {
+				let body = packet.data.to_vec();
+				return self.queue_frame(try!(self.frame(packet.header, body)));
+			}
+		}
+		self.queue_frame(try!(OutFrame::packet(packet)))
+	}
+
+	/* opcodes of the frames that haven't been written out completely, oldest first */
+	pub fn queued_opcodes(&self) -> FiestaResult<Vec<u16>> {
+		let mut io = try!(self.io.lock());
+		self.drain_outbound(&mut io.write_buffer);
+		Ok(io.write_buffer.opcodes())
+	}
+
+	/* the frame is encoded now, with the protocol version the client has at this point */
+	pub fn send_after(&self, packet: &FiestaPacket, delay: Duration) -> FiestaResult<()> {
+		let header = match self.protocol_version() {
+			Some(version) => version.to_wire(packet.header),
+			None => packet.header,
+		};
+		let frame = try!(self.frame(header, packet.data.to_vec()));
+		self.notify_reactor(ServerMessage::SendAt(self.id, Instant::now() + delay, frame))
+	}
+
+	/* `work` runs on the client's reactor thread at `at`, whether or not the client is still connected */
+	pub fn schedule<F: FnOnce() + Send + 'static>(&self, at: Instant, work: F) -> FiestaResult<()> {
+		self.notify_reactor(ServerMessage::Schedule(at, Task::new(work)))
+	}
+
+	fn notify_reactor(&self, message: ServerMessage) -> FiestaResult<()> {
+		match *try!(self.notify.lock()) {
+			Some(ref notify) => notify.send(message),
+			None => Err(FiestaNetError::Notify(format!("{} isn't served by an event loop", self.describe()))),
+		}
+	}
+
+	fn frame(&self, header: u16, body: Vec<u8>) -> FiestaResult<OutFrame> {
+		if let Some(ref codec) = self.codec {
+			let mut packet = FiestaPacket::new(header, body.len());
+			packet.data.append(&body[..]);
+			return FiestaNetworkClient::encode_with(&**codec, &packet);
+		}
+		#[cfg(feature = "compression")]
+		{
+			if let Some(ref compression) = self.compression {
+				if self.peer_compresses.load(Ordering::SeqCst) {
+					return Ok(OutFrame::encoded(try!(compression.encode(header, &body[..]))));
+				}
+			}
+		}
+		Ok(try!(OutFrame::new(header, body)))
+	}
+
+	fn encode_with(codec: &Codec, packet: &FiestaPacket) -> FiestaResult<OutFrame> {
+		let mut buffer = Buffer::with_capacity(packet.data.bytes_remaining() + FRAME_OVERHEAD);
+		try!(codec.encode(packet, &mut buffer));
+		Ok(OutFrame::encoded(buffer.to_vec()))
+	}
+
+	pub fn append_send(&self, buffer: &[u8]) -> FiestaResult<()> {
+		self.append_frame(buffer.to_vec())
+	}
+
+	/* queues an encoded frame without copying it, the reactor writes it out */
+	pub fn append_frame(&self, frame: Vec<u8>) -> FiestaResult<()> {
+		self.queue_frame(OutFrame::encoded(frame))
+	}
+
+	fn queue_frame(&self, frame: OutFrame) -> FiestaResult<()> {
+		#[cfg(feature = "spans")]
+		let _entered = self.span.enter();
+		let len = frame.len();
+		if !self.outbound.has_room(len) {
+			try!(self.handle_full_send_buffer(len));
+		}
+		self.counters.packet_sent();
+		let traced = frame.opcode().map(|opcode| self.traced(opcode)).unwrap_or(false);
+		if traced || self.capture.is_some() {
+			/* only these need the frame in one piece */
+			let bytes = frame.to_vec();
+			let packet = strip_size_prefix(&bytes[..]);
+			if traced {
+				info!(target: "trace", "{} <- packet {}, {} bytes\n{}", self.describe(),
+					OpcodeName(((packet[0] as u16) << 8) | packet[1] as u16), packet.len() - 2, HexDump(&packet[2..]));
+			}
+			if let Some(ref capture) = self.capture {
+				capture.record(Direction::Outbound, self.id, packet);
+			}
+		}
+		self.outbound.push(frame);
+		let mut interest_guard = try!(self.interest.lock());
+		if !interest_guard.is_writable() {
+			*interest_guard = (*interest_guard) | Interest::WRITABLE;
+			/* the reactor only picks up the new interest when it reregisters the socket */
+			if let Some(ref notify) = *try!(self.notify.lock()) {
+				if let Err(e) = notify.mark_dirty(self.id) {
+					warn!(target: "network", "failed to wake the reactor for {}: {}", self.describe(), e);
+				}
+			}
+		}
+		#[cfg(feature = "tokio")]
+		{
+			if let Some(ref waker) = self.send_waker {
+				waker.wake();
+			}
+		}
+		Ok(())
+	}
+}
+
+impl FiestaHandler {
+	/* registers `listener` with SERVER_TOKEN and the waker for other threads with WAKER_TOKEN */
+	pub fn new(registry: &Registry, mut listener: TcpListener, processor: Box<PacketProcessor>) -> FiestaResult<FiestaHandler> {
+		let mut handler = try!(FiestaHandler::without_listener(registry, processor));
+		try!(registry.register(&mut listener, SERVER_TOKEN, Interest::READABLE));
+		handler.listeners.insert(SERVER_TOKEN, listener);
+		Ok(handler)
+	}
+
+	/* for a reactor that only serves clients handed over by another one, or listens with add_listener() */
+	pub fn without_listener(registry: &Registry, processor: Box<PacketProcessor>) -> FiestaResult<FiestaHandler> {
+		let (notifier, messages) = try!(Notifier::new(registry));
+
+		Ok(FiestaHandler {
+			listeners:			HashMap::new(),
+			max_accepts:		DEFAULT_ACCEPTS_PER_TICK,
+			pending_accepts:	Vec::new(),
+			draining:			false,
+			closing_deadline:	None,
+			messages:			messages,
+			notifier:			notifier,
+			timers:				Timers::new(),
+			scheduled:			HashMap::new(),
+			next_scheduled:		0,
+			tick_clock:			None,
+			running:			true,
+			clients:			HashMap::new(),
+			token_count:		0,
+			token_stride:		1,
+			peers:				Vec::new(),
+			next_peer:			0,
+			siblings:			Vec::new(),
+			processor:			processor,
+			proxy_protocol:		false,
+			socket_options:		SocketOptions::default(),
+			buffer_size:		BUFFERSIZE,
+			write_buffer_size:	BUFFERSIZE,
+			send_policy:		SlowConsumerPolicy::Disconnect,
+			poll_strategy:		PollStrategy::default(),
+			max_clients:		None,
+			frame_limits:		Arc::new(FrameLimits::default()),
+			state_rules:		None,
+			keepalive:			None,
+			seed_handshake:		None,
+			pool:				BufferPool::default(),
+			backpressure:		None,
+			byte_rate_limit:	Arc::new(RwLock::new(None)),
+			handshake_timeout:	None,
+			metrics:			Arc::new(Metrics::new()),
+			capture:			None,
+			audit:				None,
+			codec:				None,
+			trace:				Arc::new(RwLock::new(TraceFilter::Off)),
+			#[cfg(feature = "admin")]
+			admin:				None,
+			banned:				Arc::new(RwLock::new(HashSet::new())),
+			config_bans:		HashSet::new(),
+			config_path:		None,
+			#[cfg(feature = "tls")]
+			tls_config:			None,
+			#[cfg(feature = "compression")]
+			compression:		None,
+			#[cfg(feature = "websocket")]
+			websocket:			false,
+		})
+	}
+
+	/* for other threads, e.g. ServerHandle */
+	pub fn notifier(&self) -> Notifier {
+		self.notifier.clone()
+	}
+
+	pub fn set_socket_options(&mut self, options: SocketOptions) {
+		self.socket_options = options;
+	}
+
+	/* size of the read buffer of every new client */
+	pub fn set_buffer_size(&mut self, size: usize) {
+		self.buffer_size = size;
+	}
+
+	pub fn set_write_buffer(&mut self, size: usize, policy: SlowConsumerPolicy) {
+		self.write_buffer_size = size;
+		self.send_policy = policy;
+	}
+
+	/* fairness towards connected clients during a connection burst, at least 1 */
+	pub fn set_max_accepts_per_tick(&mut self, max: usize) {
+		self.max_accepts = if max > 0 { max } else { 1 };
+	}
+
+	pub fn set_poll_strategy(&mut self, strategy: PollStrategy) {
+		self.poll_strategy = strategy;
+	}
+
+	/* per reactor */
+	pub fn set_max_clients(&mut self, max_clients: Option<usize>) {
+		self.max_clients = max_clients;
+		self.publish_capacity();
+	}
+
+	/* every reactor has the same limit, so any of them can tell the whole server's */
+	fn publish_capacity(&self) {
+		self.metrics.set_capacity(self.max_clients.map(|max| max * self.token_stride));
+	}
+
+	pub fn set_frame_limits(&mut self, limits: FrameLimits) {
+		self.frame_limits = Arc::new(limits);
+	}
+
+	/* only affects clients accepted after the call */
+	pub fn set_state_rules(&mut self, rules: Option<StateRules>) {
+		self.state_rules = rules.map(Arc::new);
+	}
+
+	/* shared by all clients for incoming packet bodies */
+	pub fn buffer_pool(&self) -> &BufferPool {
+		&self.pool
+	}
+
+	pub fn set_backpressure(&mut self, backpressure: Option<ReadBackpressure>) {
+		self.backpressure = backpressure;
+	}
+
+	/* applies to connected clients right away */
+	pub fn set_byte_rate_limit(&mut self, limit: Option<ByteRateLimit>) {
+		match self.byte_rate_limit.write() {
+			Ok(mut current) => *current = limit,
+			Err(_) => warn!(target: "network", "byte rate limit lock poisoned, not changing it."),
+		}
+	}
+
+	pub fn metrics(&self) -> Arc<Metrics> {
+		self.metrics.clone()
+	}
+
+	/* reactors of one server count into the same metrics */
+	pub fn set_metrics(&mut self, metrics: Arc<Metrics>) {
+		self.metrics = metrics;
+		self.publish_capacity();
+	}
+
+	/* reactor `index` of `count`, call before anything is registered so tokens stay unique across them */
+	pub fn set_reactor_index(&mut self, index: usize, count: usize) {
+		self.token_count = index;
+		self.token_stride = count;
+		self.publish_capacity();
+	}
+
+	/* accepted clients are spread over this reactor and `peers`, round robin */
+	pub fn set_peers(&mut self, peers: Vec<Notifier>) {
+		self.peers = peers;
+		self.next_peer = 0;
+	}
+
+	pub fn set_siblings(&mut self, siblings: Vec<Notifier>) {
+		self.siblings = siblings;
+	}
+
+	/* the reactors of one server share their bans, call before any client is accepted */
+	pub fn set_ban_list(&mut self, banned: Arc<RwLock<HashSet<IpAddr>>>) {
+		self.banned = banned;
+	}
+
+	/* only affects clients accepted after the call */
+	pub fn set_capture(&mut self, capture: Option<Arc<PacketCapture>>) {
+		self.capture = capture;
+	}
+
+	/* connects, logins, kicks and disconnects of the clients accepted from now on */
+	pub fn set_audit(&mut self, audit: Option<Arc<AuditSink>>) {
+		self.audit = audit;
+	}
+
+	/* for the clients accepted from now on, None is the game's framing */
+	pub fn set_codec(&mut self, codec: Option<Arc<Codec>>) {
+		self.codec = codec;
+	}
+
+	/* applies to connected clients right away */
+	pub fn set_trace(&mut self, filter: TraceFilter) {
+		match self.trace.write() {
+			Ok(mut trace) => *trace = filter,
+			Err(_) => warn!(target: "network", "trace filter lock poisoned, not changing it."),
+		}
+	}
+
+	/* a snapshot of every connected client */
+	pub fn client_stats<'a>(&'a self) -> Box<Iterator<Item = ClientStats> + 'a> {
+		Box::new(self.clients.values().filter_map(|client| client.read().ok().map(|client| client.stats())))
+	}
+
+	/* serves the admin console on `listener`, see AdminConsole */
+	#[cfg(feature = "admin")]
+	pub fn set_admin(&mut self, registry: &Registry, listener: TcpListener) -> FiestaResult<Token> {
+		let token = self.get_next_token();
+		let mut admin = AdminConsole::new(listener, token);
+		try!(admin.register(registry));
+		self.admin = Some(admin);
+		Ok(token)
+	}
+
+	/* disconnects a client, false if there is no such client */
+	pub fn kick(&mut self, registry: &Registry, token: Token) -> bool {
+		self.kick_for(registry, token, "kicked")
+	}
+
+	fn kick_for(&mut self, registry: &Registry, token: Token, reason: &str) -> bool {
+		let known = match self.clients.get(&token) {
+			Some(client) => {
+				if let Ok(client) = client.read() {
+					client.audit(AuditKind::Kick(reason.to_string()));
+				}
+				true
+			},
+			None => false,
+		};
+		self.remove_client(registry, token, DisconnectReason::Kicked);
+		known
+	}
+
+	/* refuses new connections from `ip` and drops the connected ones, returns how many were dropped */
+	/* behind a proxy, clients whose PROXY header hasn't come in yet are checked once it does */
+	pub fn ban(&mut self, registry: &Registry, ip: IpAddr) -> usize {
+		let ip = normalize_addr(SocketAddr::new(ip, 0)).ip();
+		self.banned_mut().insert(ip);
+		self.drop_banned(registry)
+	}
+
+	/* drops the connected clients whose address is banned, the one from their PROXY header if they sent one */
+	fn drop_banned(&mut self, registry: &Registry) -> usize {
+		let dropped: Vec<(Token, IpAddr)> = {
+			let banned = self.banned();
+			self.clients.iter()
+				.filter_map(|(token, client)| client.read().ok().and_then(|client| client.real_addr()).map(|addr| (*token, addr.ip())))
+				.filter(|&(_, ip)| banned.contains(&ip))
+				.collect()
+		};
+		for &(token, ip) in dropped.iter() {
+			self.kick_for(registry, token, &format!("banned {}", ip));
+		}
+		dropped.len()
+	}
+
+	pub fn unban(&mut self, ip: IpAddr) -> bool {
+		self.banned_mut().remove(&normalize_addr(SocketAddr::new(ip, 0)).ip())
+	}
+
+	pub fn is_banned(&self, ip: &IpAddr) -> bool {
+		self.banned().contains(ip)
+	}
+
+	fn banned(&self) -> RwLockReadGuard<HashSet<IpAddr>> {
+		match self.banned.read() {
+			Ok(banned) => banned,
+			Err(poisoned) => poisoned.into_inner(),
+		}
+	}
+
+	fn banned_mut(&self) -> RwLockWriteGuard<HashSet<IpAddr>> {
+		match self.banned.write() {
+			Ok(banned) => banned,
+			Err(poisoned) => poisoned.into_inner(),
+		}
+	}
+
+	/* the file the admin console's reload reads, see config::load() */
+	pub fn set_config_path<P: AsRef<Path>>(&mut self, path: Option<P>) {
+		self.config_path = path.map(|path| path.as_ref().to_path_buf());
+	}
+
+	/* applies what changed to this reactor, returns how many clients a new ban dropped */
+	pub fn reload(&mut self, registry: &Registry, config: &RuntimeConfig) -> usize {
+		if let Some(max_clients) = config.max_clients {
+			self.max_clients = Some(max_clients);
+			self.publish_capacity();
+		}
+		if let Some(size) = config.max_frame_size {
+			/* the buffers of connected clients were sized for the old limit */
+			if size + FRAME_OVERHEAD > min(self.buffer_size, self.write_buffer_size) {
+				warn!(target: "network", "not raising max_frame_size to {}, a frame wouldn't fit in the client buffers.", size);
+			} else {
+				let mut limits = (*self.frame_limits).clone();
+				limits.set_max_frame_size(size);
+				self.frame_limits = Arc::new(limits);
+			}
+		}
+		if let Some(limit) = config.byte_rate_limit {
+			self.set_byte_rate_limit(limit);
+		}
+		if let Some(ref trace) = config.trace {
+			self.set_trace(trace.clone());
+		}
+
+		let mut kicked = 0;
+		if let Some(ref banned) = config.banned {
+			let banned: HashSet<IpAddr> = banned.iter().map(|ip| normalize_addr(SocketAddr::new(*ip, 0)).ip()).collect();
+			for ip in self.config_bans.difference(&banned) {
+				self.banned_mut().remove(ip);
+			}
+			for ip in banned.difference(&self.config_bans) {
+				self.banned_mut().insert(*ip);
+			}
+			self.config_bans = banned;
+			/* every ban, a client may have come in through another reactor or a proxy since it was set */
+			kicked += self.drop_banned(registry);
+		}
+		kicked
+	}
+
+	/* reads the config file again and applies it here and on every other reactor */
+	pub fn reload_config_file(&mut self, registry: &Registry) -> FiestaResult<usize> {
+		let path = match self.config_path {
+			Some(ref path) => path.clone(),
+			None => return Err(FiestaNetError::from(Error::new(ErrorKind::NotFound, "the server wasn't started from a config file"))),
+		};
+		let runtime = try!(try!(config::load(&path)).runtime());
+		for sibling in self.siblings.iter() {
+			try!(sibling.send(ServerMessage::Reload(runtime.clone())));
+		}
+		let kicked = self.reload(registry, &runtime);
+		info!(target: "network", "reloaded {}, {} clients dropped.", path.display(), kicked);
+		Ok(kicked)
+	}
+
+	/* calls the processor's tick() every `interval`, None stops it */
+	pub fn set_tick(&mut self, interval: Option<Duration>) {
+		/* the old clock's tick would keep firing next to the new one */
+		self.timers.cancel(ClientTimeout::Tick);
+		self.tick_clock = interval.map(TickClock::new);
+		if let Some(ref clock) = self.tick_clock {
+			self.timers.schedule_at(clock.next_deadline(), ClientTimeout::Tick);
+		}
+	}
+
+	/* None passes heartbeats on to the processor like any other packet */
+	pub fn set_keepalive(&mut self, keepalive: Option<Keepalive>) {
+		self.keepalive = keepalive;
+	}
+
+	/* only affects clients accepted after the call */
+	pub fn set_seed_handshake(&mut self, handshake: Option<SeedHandshake>) {
+		self.seed_handshake = handshake.map(Arc::new);
+	}
+
+	/* only affects clients accepted after the call */
+	#[cfg(feature = "compression")]
+	pub fn set_compression(&mut self, compression: Option<Compression>) {
+		self.compression = compression;
+	}
+
+	/* how long a new client has to send its first packet, None waits forever */
+	pub fn set_handshake_timeout(&mut self, timeout: Option<Duration>) {
+		self.handshake_timeout = timeout;
+	}
+
+	/* only enable this behind a proxy, otherwise any client can claim any address */
+	pub fn set_proxy_protocol(&mut self, enabled: bool) {
+		self.proxy_protocol = enabled;
+	}
+
+	/* only affects clients accepted after the call */
+	#[cfg(feature = "tls")]
+	pub fn set_tls_config(&mut self, config: Option<Arc<TlsConfig>>) {
+		self.tls_config = config;
+	}
+
+	#[cfg(feature = "tls")]
+	fn wrap_client(&self, client: FiestaNetworkClient) -> FiestaNetworkClient {
+		match self.tls_config {
+			Some(ref config)	=> client.with_tls(config),
+			None				=> client,
+		}
+	}
+
+	#[cfg(not(feature = "tls"))]
+	fn wrap_client(&self, client: FiestaNetworkClient) -> FiestaNetworkClient {
+		client
+	}
+
+	/* only affects clients accepted after the call */
+	#[cfg(feature = "websocket")]
+	pub fn set_websocket(&mut self, enabled: bool) {
+		self.websocket = enabled;
+	}
+
+	#[cfg(feature = "websocket")]
+	fn new_client(&self, stream: TcpStream, token: Token) -> FiestaNetworkClient {
+		if self.websocket {
+			return FiestaNetworkClient::new(WebSocketTransport::new(stream), token);
+		}
+		FiestaNetworkClient::new(stream, token)
+	}
+
+	#[cfg(not(feature = "websocket"))]
+	fn new_client(&self, stream: TcpStream, token: Token) -> FiestaNetworkClient {
+		FiestaNetworkClient::new(stream, token)
+	}
+
+	/* for additional listeners, e.g. a separate v4 socket next to a v6 one */
+	pub fn add_listener(&mut self, registry: &Registry, mut listener: TcpListener) -> FiestaResult<Token> {
+		let token = self.get_next_token();
+		if !self.draining {
+			try!(registry.register(&mut listener, token, Interest::READABLE));
+		}
+		self.listeners.insert(token, listener);
+		Ok(token)
+	}
+
+	/* for handing the listeners over to another process */
+	pub fn listener_fds(&self) -> Vec<RawFd> {
+		self.listeners.values().map(|listener| listener.as_raw_fd()).collect()
+	}
+
+	/* what the listeners are bound to, with the port the OS picked for port 0 */
+	pub fn local_addrs(&self) -> Vec<SocketAddr> {
+		let mut addrs: Vec<SocketAddr> = self.listeners.values().filter_map(|listener| listener.local_addr().ok()).collect();
+		addrs.sort();
+		addrs
+	}
+
+	/* for maintenance: stop accepting and let the connected clients leave on their own */
+	pub fn set_draining(&mut self, registry: &Registry, draining: bool) -> FiestaResult<()> {
+		if draining == self.draining {
+			return Ok(());
+		}
+		for (token, listener) in self.listeners.iter_mut() {
+			if draining {
+				try!(registry.deregister(listener));
+			} else {
+				/* whatever queued up in the backlog meanwhile is reported right away */
+				try!(registry.register(listener, *token, Interest::READABLE));
+			}
+		}
+		if draining {
+			self.pending_accepts.clear();
+		}
+		self.draining = draining;
+		info!(target: "network", "{} new connections, {} clients connected.",
+			if draining { "draining, refusing" } else { "no longer draining, accepting" }, self.clients.len());
+		Ok(())
+	}
+
+	pub fn draining(&self) -> bool {
+		self.draining
+	}
+
+	/* stops accepting, then stops the event loop once every client's sends are out or `grace` is over */
+	pub fn shutdown_gracefully(&mut self, registry: &Registry, grace: Duration) {
+		if let Err(e) = self.set_draining(registry, true) {
+			warn!(target: "network", "failed to stop accepting: {}", e);
+		}
+		info!(target: "network", "shutting down gracefully, {} clients get up to {:?} to flush.", self.clients.len(), grace);
+		self.closing_deadline = Some(Instant::now() + grace);
+	}
+
+	/* whether the event loop stopped through shutdown_gracefully() */
+	pub fn stopped_gracefully(&self) -> bool {
+		self.closing_deadline.is_some() && !self.running
+	}
+
+	/* while shutting down gracefully: nothing left for the workers and nothing left to send */
+	fn check_closing(&mut self, registry: &Registry) {
+		let deadline = match self.closing_deadline {
+			Some(deadline) => deadline,
+			None => return,
+		};
+		let flushed = self.clients.values().all(|client| match client.read() {
+			Ok(client) => client.in_flight() == 0 && client.pending_send() == 0,
+			Err(_) => true,
+		});
+		if !flushed && Instant::now() < deadline {
+			return;
+		}
+
+		if !flushed {
+			warn!(target: "network", "grace period over, closing clients with unsent data.");
+		}
+		let tokens: Vec<Token> = self.clients.keys().cloned().collect();
+		for token in tokens.into_iter() {
+			self.remove_client(registry, token, DisconnectReason::Kicked);
+		}
+		self.running = false;
+	}
+
+	fn server_ready(&mut self, registry: &Registry, token: Token, event: &Event) -> FiestaResult<()> {
+		if !event.is_readable() {
+			return Ok(());
+		}
+		self.accept_batch(registry, token)
+	}
+
+	/* listeners are edge triggered, the backlog has to be emptied or we won't hear about it again; */
+	/* past max_accepts the listener is queued in pending_accepts and the rest waits for the next tick */
+	fn accept_batch(&mut self, registry: &Registry, token: Token) -> FiestaResult<()> {
+		let mut count = 0;
+		let result = loop {
+			if count == self.max_accepts {
+				if !self.pending_accepts.contains(&token) {
+					self.pending_accepts.push(token);
+				}
+				break Ok(());
+			}
+			let accepted = match self.listeners.get(&token) {
+				Some(listener)	=> listener.accept(),
+				None			=> break Err(FiestaNetError::UnknownClient(token)),
+			};
+			match accepted {
+				Ok((client, _)) => {
+					count += 1;
+					self.accept_client(registry, client);
+				},
+				Err(ref e) if is_transient(e) => {
+					/* WOULDBLOCK / EAGAIN, that was all of them */
+					debug!(target: "network", "WOULDBLOCK while accepting client.");
+					break Ok(());
+				},
+				Err(e) => {
+					/* unexpected error, but the other listeners and clients are fine */
+					break Err(FiestaNetError::from(e));
+				}
+			}
+		};
+		self.metrics.accept_batch(count);
+		result
+	}
+
+	fn accept_client(&mut self, registry: &Registry, mut client: TcpStream) {
+		if let Some(addr) = client.peer_addr().ok().map(normalize_addr) {
+			if self.is_banned(&addr.ip()) {
+				info!(target: "network", "refusing connection from banned address {}.", addr.ip());
+				self.metrics.connection_refused();
+				let _ = client.shutdown(Shutdown::Both);
+				return;
+			}
+		}
+		if !self.peers.is_empty() {
+			self.next_peer = (self.next_peer + 1) % (self.peers.len() + 1);
+			if self.next_peer > 0 {
+				if let Err(e) = self.peers[self.next_peer - 1].send(ServerMessage::Adopt(client)) {
+					/* the stream went down with the message */
+					warn!(target: "network", "failed to hand a client over to reactor {}: {}", self.next_peer, e);
+				}
+				return;
+			}
+		}
+		if let Some(max) = self.max_clients {
+			if self.clients.len() >= max {
+				warn!(target: "network", "client limit of {} reached, refusing connection.", max);
+				self.metrics.connection_refused();
+				let _ = client.shutdown(Shutdown::Both);
+				return;
+			}
+		}
+		let token = self.get_next_token();
+		if let Err(e) = self.socket_options.apply(&client) {
+			/* not worth dropping the client over */
+			warn!(target: "network", "failed to set socket options for {:?}: {}", token, e);
+		}
+		if let Err(e) = registry.register(&mut client, token, Interest::READABLE | Interest::WRITABLE) {
+			/* only this connection is affected, keep accepting */
+			warn!(target: "network", "failed to register new client {:?}, dropping it: {}", token, e);
+			let _ = client.shutdown(Shutdown::Both);
+			return;
+		}
+		let mut client = self.wrap_client(
+			self.new_client(client, token)
+				.with_buffer_size(self.buffer_size)
+				.with_write_buffer(self.write_buffer_size, self.send_policy)
+				.with_frame_limits(self.frame_limits.clone())
+				.with_buffer_pool(self.pool.clone())
+				.with_metrics(self.metrics.clone()));
+		if self.proxy_protocol {
+			client = client.expect_proxy_header().with_ban_list(self.banned.clone());
+		}
+		client = client.with_notifier(self.notifier.clone());
+		if let Some(ref rules) = self.state_rules {
+			client = client.with_state_rules(rules.clone());
+		}
+		if let Some(backpressure) = self.backpressure {
+			client = client.with_backpressure(backpressure, self.notifier.clone());
+		}
+		client = client.with_shared_byte_rate_limit(self.byte_rate_limit.clone());
+		if let Some(ref capture) = self.capture {
+			client = client.with_capture(capture.clone());
+		}
+		if let Some(ref audit) = self.audit {
+			client = client.with_audit(audit.clone());
+		}
+		if let Some(ref codec) = self.codec {
+			client = client.with_codec(codec.clone());
+		}
+		client = client.with_trace(self.trace.clone());
+		#[cfg(feature = "compression")]
+		{
+			if let Some(compression) = self.compression {
+				client = client.with_compression(compression);
+			}
+		}
+		if let Some(timeout) = self.handshake_timeout {
+			self.timers.schedule(timeout, ClientTimeout::Handshake(token));
+		}
+		if let Some(interval) = self.keepalive.and_then(|keepalive| keepalive.interval) {
+			self.timers.schedule(interval, ClientTimeout::Keepalive(token));
+		}
+		info!(target: "network", "accepted client {}", client.describe());
+		client.audit(AuditKind::Connect);
+		self.metrics.connection_accepted();
+		let handshake = self.seed_handshake.as_ref().map(|handshake| client.start_handshake(handshake));
+		self.clients.insert(
+			token, 
+			Arc::new(
+				RwLock::new(
+					Box::new(client))));
+		if let Some(Err(e)) = handshake {
+			warn!(target: "network", "failed to send the seed to {:?}, dropping it: {}", token, e);
+			self.remove_client(registry, token, DisconnectReason::WriteStall);
+		}
+	}
+
+	fn get_next_token(&mut self) -> Token {
+		self.token_count += self.token_stride;
+		Token(self.token_count)
+	}
+
+	fn get_current_token(&self) -> Token {
+		Token(self.token_count)
+	}
+
+	/* closes the client if it isn't yet and tells the processor, it never sees another event */
+	fn remove_client(&mut self, registry: &Registry, token: Token, reason: DisconnectReason) {
+		let client = match self.clients.remove(&token) {
+			Some(client) => client,
+			None => return,
+		};
+		let reason = match client.read() {
+			Ok(guard) => {
+				let _ = guard.deregister(registry);
+				let reason = guard.close(reason);
+				info!(target: "network", "client {} disconnected: {}.", guard.describe(), reason);
+				guard.audit(AuditKind::Disconnect(reason.to_string()));
+				reason
+			},
+			Err(_) => reason,
+		};
+		self.metrics.disconnected(&reason);
+		self.processor.on_disconnect(&ClientHandle::new(client.clone()), &reason);
+	}
+
+	fn resume_read(&mut self, registry: &Registry, token: Token) -> FiestaResult<()> {
+		if let Some(client) = self.clients.get(&token) {
+			debug!(target: "network", "resuming reads from {}", try!(client.read()).describe());
+		}
+		self.refresh_interest(registry, token, true)
+	}
+
+	/* one pass over every client that got something to send since the last tick */
+	fn flush_dirty(&mut self, registry: &Registry) {
+		let dirty = match self.notifier.take_dirty() {
+			Ok(dirty) => dirty,
+			Err(e) => {
+				warn!(target: "network", "failed to collect clients with pending sends: {}", e);
+				return;
+			}
+		};
+		for token in dirty.into_iter() {
+			if let Err(e) = self.refresh_interest(registry, token, false) {
+				warn!(target: "network", "failed to flush {:?}: {}", token, e);
+				self.remove_client(registry, token, socket_error(&e));
+			}
+		}
+	}
+
+	/* reregisters with whatever the client wants now, unless that's what it's registered with and not `force` */
+	fn refresh_interest(&mut self, registry: &Registry, token: Token, force: bool) -> FiestaResult<()> {
+		/* the client may be gone by now */
+		if let Some(client) = self.clients.get(&token) {
+			let client = try!(client.read());
+			let interest = client.interest();
+			if client.update_registered(interest) || force {
+				/* re-registering reports readiness that is already there, edge triggered or not */
+				try!(client.reregister(registry, interest));
+			}
+		}
+		Ok(())
+	}
+
+	/* the console is taken out of the handler while its commands run on the handler */
+	#[cfg(feature = "admin")]
+	fn admin_ready(&mut self, registry: &Registry, token: Token, event: &Event) -> FiestaResult<()> {
+		let mut admin = try!(self.admin.take().ok_or(FiestaNetError::UnknownClient(token)));
+		let result = self.drive_admin(registry, &mut admin, token, event);
+		if result.is_err() && !admin.is_listener(token) {
+			admin.close(registry, token);
+		}
+		self.admin = Some(admin);
+		result
+	}
+
+	#[cfg(feature = "admin")]
+	fn drive_admin(&mut self, registry: &Registry, admin: &mut AdminConsole, token: Token, event: &Event) -> FiestaResult<()> {
+		if admin.is_listener(token) {
+			while let Some(stream) = try!(admin.accept()) {
+				let session = self.get_next_token();
+				try!(admin.add_session(registry, session, stream));
+				try!(admin.flush(registry, session));
+			}
+			return Ok(());
+		}
+
+		if event.is_readable() || event.is_read_closed() {
+			match try!(admin.read_commands(token)) {
+				Some(commands) => {
+					for command in commands.into_iter() {
+						let reply = self.run_admin_command(registry, admin, token, command);
+						admin.reply(token, &reply);
+					}
+				},
+				None => {
+					admin.close(registry, token);
+					return Ok(());
+				}
+			}
+		}
+		admin.flush(registry, token)
+	}
+
+	#[cfg(feature = "admin")]
+	fn run_admin_command(&mut self, registry: &Registry, admin: &mut AdminConsole, session: Token, command: AdminCommand) -> String {
+		debug!(target: "network", "admin session {:?}: {:?}", session, command);
+		match command {
+			AdminCommand::Clients => {
+				let mut reply = String::new();
+				for stats in self.client_stats() {
+					reply.push_str(&format!("{:>6} {:<40} connected {:>6}s idle {:>6}s in {:>10} out {:>10} {}\n",
+						stats.token.0,
+						stats.addr.map(|addr| addr.to_string()).unwrap_or("-".to_string()),
+						stats.connected_for.as_secs(), stats.idle_for.as_secs(), stats.bytes_in, stats.bytes_out,
+						stats.label.unwrap_or(String::new())));
+				}
+				reply.push_str(&format!("{} clients\n", self.clients.len()));
+				reply
+			},
+			AdminCommand::Kick(token) => {
+				if self.kick(registry, token) {
+					info!(target: "network", "admin kicked {:?}.", token);
+					format!("kicked {}\n", token.0)
+				} else {
+					format!("no client {}\n", token.0)
+				}
+			},
+			AdminCommand::Ban(ip) => {
+				let kicked = self.ban(registry, ip);
+				for sibling in self.siblings.iter() {
+					if let Err(e) = sibling.send(ServerMessage::Ban(ip)) {
+						warn!(target: "network", "failed to pass the ban of {} on: {}", ip, e);
+					}
+				}
+				info!(target: "network", "admin banned {}, {} clients dropped.", ip, kicked);
+				format!("banned {}, {} clients dropped\n", ip, kicked)
+			},
+			AdminCommand::Unban(ip) => {
+				if self.unban(ip) {
+					info!(target: "network", "admin unbanned {}.", ip);
+					format!("unbanned {}\n", ip)
+				} else {
+					format!("{} wasn't banned\n", ip)
+				}
+			},
+			AdminCommand::Drain(draining) => {
+				for sibling in self.siblings.iter() {
+					if let Err(e) = sibling.send(ServerMessage::Drain(draining)) {
+						return format!("error: {}\n", e);
+					}
+				}
+				match self.set_draining(registry, draining) {
+					Ok(()) if draining	=> format!("draining, {} clients connected\n", self.clients.len()),
+					Ok(())				=> "accepting again\n".to_string(),
+					Err(e)				=> format!("error: {}\n", e),
+				}
+			},
+			AdminCommand::Reload => {
+				match self.reload_config_file(registry) {
+					Ok(kicked)	=> format!("reloaded, {} clients dropped\n", kicked),
+					Err(e)		=> format!("error: {}\n", e),
+				}
+			},
+			AdminCommand::Stats => {
+				let mut reply = String::new();
+				for (name, value) in self.metrics.snapshot() {
+					reply.push_str(&format!("{} {}\n", name, value));
+				}
+				reply
+			},
+			AdminCommand::Shutdown => {
+				info!(target: "network", "shutdown requested from admin session {:?}.", session);
+				self.running = false;
+				"shutting down\n".to_string()
+			},
+			AdminCommand::Help => ADMIN_HELP.to_string(),
+			AdminCommand::Quit => {
+				admin.quit(session);
+				"bye\n".to_string()
+			},
+			AdminCommand::Invalid(reason) => format!("error: {}\n", reason),
+		}
+	}
+
+	#[cfg(feature = "admin")]
+	fn is_admin(&self, token: Token) -> bool {
+		self.admin.as_ref().map_or(false, |admin| admin.owns(token))
+	}
+
+	#[cfg(not(feature = "admin"))]
+	fn is_admin(&self, token: Token) -> bool {
+		false
+	}
+
+	#[cfg(not(feature = "admin"))]
+	fn admin_ready(&mut self, registry: &Registry, token: Token, event: &Event) -> FiestaResult<()> {
+		Ok(())
+	}
+
+	fn client_ready(&mut self, registry: &Registry, token: Token, event: &Event) -> FiestaResult<()> {
+		let mut client_disconnect = None;
+		let mut packets_to_process = Vec::new();
+		let edge = self.poll_strategy == PollStrategy::Edge;
+		/* with Edge, something is left that the poll won't report again by itself */
+		let mut rearm = false;
+
+		/* a hangup or error is only noticed by reading */
+		if event.is_readable() || event.is_read_closed() || event.is_error() {
+			let client = try!(self.clients.get(&token).ok_or(FiestaNetError::UnknownClient(token)));
+			let client_guard = try!(client.read());
+			/* until WouldBlock, level triggered too, so a big frame doesn't take an event per chunk */
+			let mut budget = MAX_READ_PER_EVENT;
+			let mut drained = client_guard.readable(&mut self.timers, token, &mut budget, &mut client_disconnect);
+			let mut reads = 1;
+			while !drained && client_disconnect.is_none() && budget > 0 && reads < MAX_READS_PER_EVENT
+					&& !client_guard.read_paused() && !client_guard.throttled() {
+				drained = client_guard.readable(&mut self.timers, token, &mut budget, &mut client_disconnect);
+				reads += 1;
+			}
+			rearm = rearm || !drained;
+			if client_guard.is_poisoned() {
+				client_disconnect = Some(DisconnectReason::Poisoned);
+			}
+
+			let mut packet_queue_guard = client_guard.recover(client_guard.packet_queue.lock());
+			packets_to_process.reserve(packet_queue_guard.len());
+			while let Some(packet) = packet_queue_guard.pop_front() {
+				match client_guard.check_state(packet.header) {
+					Ok(()) => {},
+					Err(ViolationAction::Drop) => continue,
+					Err(ViolationAction::Disconnect) => {
+						client_disconnect = Some(DisconnectReason::ProtocolViolation);
+						break;
+					}
+				}
+				if let Some(ref keepalive) = self.keepalive {
+					if client_guard.handle_keepalive(keepalive, packet.header) {
+						client_guard.handshake_done.store(true, Ordering::SeqCst);
+						continue;
+					}
+				}
+				client_guard.record_inbound(&packet);
+				packets_to_process.push(PacketProcessingInfo::new(packet, client.clone()).counted());
+			}
+			client_guard.packets_dispatched(packets_to_process.len());
+		}
+
+		if event.is_writable() && client_disconnect.is_none() {
+			let client = try!(self.clients.get(&token).ok_or(FiestaNetError::UnknownClient(token)));
+			let guard = try!(client.read());
+			let mut drained = guard.writeable(token, &mut client_disconnect);
+			let mut writes = 1;
+			while edge && !drained && client_disconnect.is_none() && writes < MAX_IO_PER_EVENT {
+				drained = guard.writeable(token, &mut client_disconnect);
+				writes += 1;
+			}
+			rearm = rearm || !drained;
+			if guard.is_poisoned() && client_disconnect.is_none() {
+				client_disconnect = Some(DisconnectReason::Poisoned);
+			}
+		}
+
+		for packet in packets_to_process.into_iter() {
+			self.processor.process_packet(packet);
+		};
+
+		/* we need to have this down here, because of borrows.. */
+		if let Some(reason) = client_disconnect {
+			self.remove_client(registry, token, reason);
+		} else {
+			/* re-register, this re-arms the edge so anything left unread is reported again */
+			let client = try!(self.clients.get(&token).ok_or(FiestaNetError::UnknownClient(token)));
+			let client_borrow = try!(client.read());
+			let interest = client_borrow.interest();
+			let changed = client_borrow.update_registered(interest);
+			if !edge || changed || rearm {
+				try!(client_borrow.reregister(registry, interest));
+			}
+		}
+		Ok(())
+	}
+
+	/* polls until a Shutdown message or the admin console stops the server */
+	pub fn run(&mut self, poll: &mut Poll) -> FiestaResult<()> {
+		let mut events = Events::with_capacity(1024);
+		self.running = true;
+		while self.running {
+			/* don't block while a listener still has a backlog */
+			let mut timeout = if self.pending_accepts.is_empty() {
+				self.timers.next_wait()
+			} else {
+				Some(Duration::from_secs(0))
+			};
+			if self.closing_deadline.is_some() {
+				/* the workers don't wake us when they're done, look again every now and then */
+				let recheck = Duration::from_millis(50);
+				timeout = Some(timeout.map_or(recheck, |timeout| min(timeout, recheck)));
+			}
+			if let Err(e) = poll.poll(&mut events, timeout) {
+				if e.kind() == ErrorKind::Interrupted {
+					continue;
+				}
+				return Err(From::from(e));
+			}
+
+			for event in events.iter() {
+				let token = event.token();
+				if token == WAKER_TOKEN {
+					while let Ok(message) = self.messages.try_recv() {
+						self.notify(poll.registry(), message);
+					}
+				} else {
+					self.ready(poll.registry(), token, event);
+				}
+			}
+
+			self.flush_dirty(poll.registry());
+
+			for token in mem::replace(&mut self.pending_accepts, Vec::new()).into_iter() {
+				if let Err(e) = self.accept_batch(poll.registry(), token) {
+					warn!(target: "network", "error while accepting on {:?}: {}", token, e);
+				}
+			}
+
+			for timeout in self.timers.expired().into_iter() {
+				self.timeout(poll.registry(), timeout);
+			}
+
+			self.check_closing(poll.registry());
+		}
+		info!(target: "network", "event loop stopped.");
+		Ok(())
+	}
+
+	fn ready(&mut self, registry: &Registry, token: Token, event: &Event) {
+		let result = if self.listeners.contains_key(&token) {
+			self.server_ready(registry, token, event)
+		} else if self.is_admin(token) {
+			self.admin_ready(registry, token, event)
+		} else {
+			self.client_ready(registry, token, event)
+		};
+
+		if let Err(e) = result {
+			warn!(target: "network", "error while handling event for {:?}: {}", token, e);
+			if !self.listeners.contains_key(&token) {
+				self.remove_client(registry, token, socket_error(&e));
+			}
+		}
+	}
+
+	fn notify(&mut self, registry: &Registry, msg: ServerMessage) {
+		match msg {
+			ServerMessage::Shutdown => {
+				info!(target: "network", "shutting down the event loop.");
+				self.running = false;
+			},
+			ServerMessage::GracefulShutdown(grace) => {
+				self.shutdown_gracefully(registry, grace);
+			},
+			ServerMessage::ResumeRead(token) => {
+				if let Err(e) = self.resume_read(registry, token) {
+					warn!(target: "network", "failed to resume reads for {:?}: {}", token, e);
+					self.remove_client(registry, token, socket_error(&e));
+				}
+			},
+			ServerMessage::Adopt(stream) => {
+				self.accept_client(registry, stream);
+			},
+			ServerMessage::Drain(draining) => {
+				if let Err(e) = self.set_draining(registry, draining) {
+					warn!(target: "network", "failed to {} the listeners: {}", if draining { "deregister" } else { "register" }, e);
+				}
+			},
+			ServerMessage::SetTrace(filter) => {
+				info!(target: "network", "packet trace filter is now {:?}", filter);
+				self.set_trace(filter);
+			},
+			ServerMessage::Ban(ip) => {
+				let kicked = self.ban(registry, ip);
+				info!(target: "network", "banned {}, {} clients dropped.", ip, kicked);
+			},
+			ServerMessage::Reload(config) => {
+				let kicked = self.reload(registry, &config);
+				info!(target: "network", "config reloaded, {} clients dropped.", kicked);
+			},
+			ServerMessage::SendAt(token, deadline, frame) => {
+				self.schedule_at(deadline, Scheduled::Send(token, frame));
+			},
+			ServerMessage::Schedule(deadline, task) => {
+				self.schedule_at(deadline, Scheduled::Run(task));
+			},
+			ServerMessage::Clients(reply) => {
+				/* the asker may have given up already */
+				let _ = reply.send(self.client_stats().collect());
+			},
+			ServerMessage::CloseAfterFlush(token) => {
+				self.timers.schedule(CLOSE_FLUSH_TIMEOUT, ClientTimeout::FlushDeadline(token));
+				if let Err(e) = self.refresh_interest(registry, token, true) {
+					warn!(target: "network", "failed to flush {:?}: {}", token, e);
+					self.remove_client(registry, token, socket_error(&e));
+				}
+			},
+			ServerMessage::Kick(token) => {
+				self.kick(registry, token);
+			}
+		}
+	}
+
+	fn schedule_at(&mut self, deadline: Instant, work: Scheduled) {
+		let id = self.next_scheduled;
+		self.next_scheduled = self.next_scheduled.wrapping_add(1);
+		self.scheduled.insert(id, work);
+		self.timers.schedule_at(deadline, ClientTimeout::Scheduled(id));
+	}
+
+	fn run_scheduled(&mut self, registry: &Registry, id: usize) {
+		match self.scheduled.remove(&id) {
+			Some(Scheduled::Send(token, frame)) => {
+				let sent = match self.clients.get(&token).map(|client| client.read()) {
+					Some(Ok(client)) => client.queue_frame(frame),
+					/* gone in the meantime, nothing to do */
+					_ => return,
+				};
+				match sent {
+					Ok(()) => {
+						if let Err(e) = self.refresh_interest(registry, token, false) {
+							warn!(target: "network", "failed to flush {:?}: {}", token, e);
+							self.remove_client(registry, token, socket_error(&e));
+						}
+					},
+					Err(e) => warn!(target: "network", "delayed send to {:?} failed: {}", token, e),
+				}
+			},
+			Some(Scheduled::Run(task)) => {
+				/* a panicking task must not take the reactor down with it */
+				if panic::catch_unwind(AssertUnwindSafe(|| task.run())).is_err() {
+					warn!(target: "threading", "scheduled task panicked.");
+				}
+			},
+			None => {},
+		}
+	}
+
+	fn timeout(&mut self, registry: &Registry, timeout: ClientTimeout) {
+		match timeout {
+			ClientTimeout::Unthrottle(token) => {
+				let resumed = match self.clients.get(&token).map(|client| client.read()) {
+					Some(Ok(client)) => {
+						client.throttled.store(false, Ordering::SeqCst);
+						true
+					},
+					_ => false,
+				};
+				if resumed {
+					if let Err(e) = self.resume_read(registry, token) {
+						warn!(target: "network", "failed to unthrottle {:?}: {}", token, e);
+						self.remove_client(registry, token, socket_error(&e));
+					}
+				}
+			},
+			ClientTimeout::Handshake(token) => {
+				let expired = match self.clients.get(&token).map(|client| client.read()) {
+					Some(Ok(client)) => !client.handshake_done(),
+					_ => false,
+				};
+				if expired {
+					warn!(target: "network", "no handshake from {:?} in time, dropping it.", token);
+					self.remove_client(registry, token, DisconnectReason::IdleTimeout);
+				}
+			},
+			ClientTimeout::Keepalive(token) => {
+				let keepalive = match self.keepalive {
+					Some(keepalive) => keepalive,
+					None => return,
+				};
+				/* None once the client is gone, which ends its heartbeats */
+				let alive = match self.clients.get(&token).map(|client| client.read()) {
+					Some(Ok(client)) => Some(client.send_keepalive(&keepalive)),
+					_ => None,
+				};
+				match alive {
+					Some(true) => {
+						if let Some(interval) = keepalive.interval {
+							self.timers.schedule(interval, ClientTimeout::Keepalive(token));
+						}
+					},
+					Some(false) => {
+						warn!(target: "network", "{:?} missed {} heartbeats, dropping it.", token, keepalive.max_missed);
+						self.metrics.keepalive_timeout();
+						self.remove_client(registry, token, DisconnectReason::IdleTimeout);
+					},
+					None => {},
+				}
+			},
+			ClientTimeout::Scheduled(id) => {
+				self.run_scheduled(registry, id);
+			},
+			ClientTimeout::Tick => {
+				let (tick, next) = match self.tick_clock {
+					Some(ref mut clock) => (clock.advance(Instant::now()), clock.next_deadline()),
+					/* set_tick(None) in the meantime */
+					None => return,
+				};
+				if let Some(tick) = tick {
+					if tick.skipped > 0 {
+						warn!(target: "threading", "tick loop fell behind, skipped {} ticks before tick {}.", tick.skipped, tick.number);
+					}
+					self.processor.tick(tick);
+				}
+				self.timers.schedule_at(next, ClientTimeout::Tick);
+			},
+			ClientTimeout::FlushDeadline(token) => {
+				let stuck = match self.clients.get(&token).map(|client| client.read()) {
+					Some(Ok(client)) => client.closing_after_flush(),
+					_ => false,
+				};
+				if stuck {
+					warn!(target: "network", "{:?} didn't take its last packets in time, dropping it.", token);
+					self.remove_client(registry, token, DisconnectReason::WriteStall);
+				}
+			}
+		}
+	}
+}
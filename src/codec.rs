@@ -0,0 +1,73 @@
+use std::io::{Error, ErrorKind};
+
+use body::{InlineBytes, INLINE_BODY_SIZE};
+use buffer::*;
+use framing;
+use limits::FrameLimits;
+use packet::FiestaPacket;
+use pool::BufferPool;
+
+/* turns a connection's byte stream into packets and packets back into bytes, so the reactors */
+/* and workers can carry other framings than the game's, e.g. an inter-server protocol */
+pub trait Codec: Send + Sync + 'static {
+	/* takes the next complete packet out of `buffer`, Ok(None) while it isn't all there. */
+	/* an Err drops the client, there's no finding the next frame after a broken one */
+	fn decode(&self, buffer: &mut Buffer, limits: &FrameLimits, pool: &BufferPool) -> Result<Option<FiestaPacket>, Error>;
+
+	/* appends the whole frame of `packet` to `buffer`, reserving the room it needs */
+	fn encode(&self, packet: &FiestaPacket, buffer: &mut Buffer) -> Result<(), Error>;
+}
+
+/* the game's framing: a u8 size, or 0 and a u16 size, then the u16 opcode and the body. */
+/* clients use it unless they are given another Codec, with some shortcuts only it can take */
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LengthPrefix;
+
+impl Codec for LengthPrefix {
+	fn decode(&self, buffer: &mut Buffer, limits: &FrameLimits, pool: &BufferPool) -> Result<Option<FiestaPacket>, Error> {
+		let available = buffer.bytes_remaining();
+		let (size, prefix) = match try!(framing::next_frame_size(buffer, available, limits)) {
+			Some(next) => next,
+			None => return Ok(None),
+		};
+		if available < prefix + 2 + size as usize {
+			return Ok(None);
+		}
+		buffer.advance_read(prefix);
+
+		let header = try!(buffer.read_u16());
+		try!(limits.check(header, size as usize));
+
+		if size as usize <= INLINE_BODY_SIZE {
+			/* most packets are this small, copying them into the packet beats any allocation */
+			let mut body = InlineBytes::with_len(size as usize);
+			try!(buffer.read_to_slice(body.as_mut_slice()));
+			return Ok(Some(FiestaPacket::from_inline(header, body)));
+		}
+		let mut packet = FiestaPacket::from_pool(pool, header, size as usize);
+		try!(buffer.read_into(packet.data.make_mut(), size as usize));
+		Ok(Some(packet))
+	}
+
+	fn encode(&self, packet: &FiestaPacket, buffer: &mut Buffer) -> Result<(), Error> {
+		let (first, second) = packet.data.segments();
+		let size = first.len() + second.len();
+		if size > 0xffff {
+			/* the size prefix can't say more, a cut off size would desync the peer */
+			return Err(Error::new(ErrorKind::InvalidInput, "packet body doesn't fit in a frame"));
+		}
+		/* the longest prefix and the opcode */
+		buffer.reserve(size + 5);
+		if size > 0 && size < 0x100 {
+			try!(buffer.append_u8(size as u8));
+		} else {
+			/* 0 marks the extended size */
+			try!(buffer.append_u8(0));
+			try!(buffer.append_u16(size as u16, Endianness::Big));
+		}
+		try!(buffer.append_u16(packet.header, Endianness::Big));
+		try!(buffer.append(first));
+		try!(buffer.append(second));
+		Ok(())
+	}
+}
@@ -0,0 +1,66 @@
+use std::io::{Error, ErrorKind, Read, Write};
+
+use flate2;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+
+use packet::FiestaPacket;
+
+/* set on the header of a zlib compressed frame. no opcode uses a department this high, */
+/* and game clients never see it: a server only compresses for peers that sent a compressed frame first */
+pub const COMPRESSED_FLAG: u16 = 0x8000;
+
+/* for links between our own servers, where big frames (character data, guild lists) go back and forth */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Compression {
+	/* smaller bodies go out as they are */
+	pub threshold:		usize,
+	/* zlib level, 0 to 9 */
+	pub level:			u32,
+}
+
+impl Default for Compression {
+	fn default() -> Self {
+		Compression {
+			threshold:		512,
+			level:			6,
+		}
+	}
+}
+
+impl Compression {
+	/* the frame for `header` and `body`, compressed if the body is big enough and actually shrinks */
+	pub fn encode(&self, header: u16, body: &[u8]) -> Result<Vec<u8>, Error> {
+		match self.compress(body) {
+			Some(compressed) => FiestaPacket::encode(header | COMPRESSED_FLAG, &compressed[..]),
+			None => FiestaPacket::encode(header, body),
+		}
+	}
+
+	fn compress(&self, body: &[u8]) -> Option<Vec<u8>> {
+		if body.len() < self.threshold {
+			return None;
+		}
+		let mut encoder = ZlibEncoder::new(Vec::with_capacity(body.len()), flate2::Compression::new(self.level));
+		let compressed = match encoder.write_all(body).and_then(|_| encoder.finish()) {
+			Ok(compressed) => compressed,
+			Err(_) => return None,
+		};
+		/* the size prefix can't say more than 0xffff */
+		if compressed.len() < body.len() && compressed.len() <= 0xffff {
+			Some(compressed)
+		} else {
+			None
+		}
+	}
+}
+
+/* a body that inflates past `max` is refused, a small frame shouldn't be able to cost megabytes */
+pub fn inflate(body: &[u8], max: usize) -> Result<Vec<u8>, Error> {
+	let mut inflated = Vec::new();
+	try!(ZlibDecoder::new(body).take(max as u64 + 1).read_to_end(&mut inflated));
+	if inflated.len() > max {
+		return Err(Error::new(ErrorKind::InvalidData, format!("compressed frame inflates past {} bytes", max)));
+	}
+	Ok(inflated)
+}
@@ -0,0 +1,327 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::fs::File;
+use std::io::{Error, Read};
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+use std::time::Duration;
+use toml;
+#[cfg(feature = "yaml")]
+use serde_yaml;
+
+use limits::{DEFAULT_MAX_FRAME_SIZE, ByteRateLimit, FloodAction, SlowConsumerPolicy};
+use listener::IpMode;
+use server::FiestaServerBuilder;
+use sockopt::SocketOptions;
+use trace::TraceFilter;
+
+/* the biggest frame has to fit: body, 2 bytes header, 3 bytes extended size */
+pub const FRAME_OVERHEAD: usize = 2 + 3;
+/* u16 extended size */
+pub const MAX_FRAME_SIZE: usize = 0xffff;
+pub const MAX_THREADS: usize = 256;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ServerConfig {
+	pub name:				Option<String>,
+	pub port:				Option<u16>,
+	pub address:			Option<String>,
+	/* "v4", "v6" or "dual" */
+	pub ip_mode:			Option<String>,
+	pub threads:			Option<usize>,
+	pub buffer_size:		Option<usize>,
+	pub write_buffer_size:	Option<usize>,
+	/* "disconnect", "drop_oldest" or "reject_new" */
+	pub slow_consumer:		Option<String>,
+	pub max_clients:		Option<usize>,
+	pub max_frame_size:		Option<usize>,
+	pub proxy_protocol:		Option<bool>,
+	pub socket:				Option<SocketConfig>,
+	pub byte_rate_limit:	Option<RateLimitConfig>,
+	/* addresses refused on connect, an empty list lifts the bans of an earlier load */
+	pub banned:				Option<Vec<String>>,
+	/* packet trace: "off", "all" or opcodes like "0x0801, 0x0c01" */
+	pub trace:				Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SocketConfig {
+	pub nodelay:			Option<bool>,
+	pub keepalive_secs:		Option<u64>,
+	pub linger_secs:		Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimitConfig {
+	/* 0 turns the limit off */
+	pub bytes_per_sec:		usize,
+	/* "disconnect" (default) or "throttle" */
+	pub action:				Option<String>,
+}
+
+/* what a running server picks up again on reload, None keeps the current value */
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RuntimeConfig {
+	/* new clients only, connected ones beyond the limit stay */
+	pub max_clients:		Option<usize>,
+	/* new clients only */
+	pub max_frame_size:		Option<usize>,
+	/* Some(None) turns the limit off */
+	pub byte_rate_limit:	Option<Option<ByteRateLimit>>,
+	/* replaces the bans of the last load, bans from the admin console are kept */
+	pub banned:				Option<HashSet<IpAddr>>,
+	pub trace:				Option<TraceFilter>,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+	Io(Error),
+	Parse(String),
+	Invalid { field: &'static str, reason: String },
+}
+
+impl fmt::Display for ConfigError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			ConfigError::Io(ref e)							=> write!(f, "could not read config file: {}", e),
+			ConfigError::Parse(ref e)						=> write!(f, "could not parse config file: {}", e),
+			ConfigError::Invalid { ref field, ref reason }	=> write!(f, "invalid value for `{}`: {}", field, reason),
+		}
+	}
+}
+
+impl From<Error> for ConfigError {
+	fn from(e: Error) -> Self {
+		ConfigError::Io(e)
+	}
+}
+
+fn invalid(field: &'static str, reason: String) -> ConfigError {
+	ConfigError::Invalid { field: field, reason: reason }
+}
+
+/* picks the format from the file extension, anything but .yml/.yaml is read as toml */
+pub fn load<P: AsRef<Path>>(path: P) -> Result<ServerConfig, ConfigError> {
+	let path = path.as_ref();
+	let mut contents = String::new();
+	try!(try!(File::open(path)).read_to_string(&mut contents));
+
+	let config = match path.extension().and_then(|e| e.to_str()) {
+		Some("yml") | Some("yaml")	=> try!(from_yaml_str(&contents)),
+		_							=> try!(from_toml_str(&contents)),
+	};
+	try!(config.validate());
+
+	info!(target: "config", "loaded server config from {}", path.display());
+	Ok(config)
+}
+
+pub fn from_toml_str(contents: &str) -> Result<ServerConfig, ConfigError> {
+	toml::from_str(contents).map_err(|e| ConfigError::Parse(format!("{}", e)))
+}
+
+#[cfg(feature = "yaml")]
+pub fn from_yaml_str(contents: &str) -> Result<ServerConfig, ConfigError> {
+	serde_yaml::from_str(contents).map_err(|e| ConfigError::Parse(format!("{}", e)))
+}
+
+#[cfg(not(feature = "yaml"))]
+pub fn from_yaml_str(contents: &str) -> Result<ServerConfig, ConfigError> {
+	Err(ConfigError::Parse("yaml support requires the `yaml` feature".to_string()))
+}
+
+impl ServerConfig {
+	pub fn validate(&self) -> Result<(), ConfigError> {
+		if self.port == Some(0) {
+			return Err(invalid("port", "must not be 0".to_string()));
+		}
+		if let Some(ref address) = self.address {
+			try!(parse_address(address));
+		}
+		if let Some(ref ip_mode) = self.ip_mode {
+			try!(parse_ip_mode(ip_mode));
+		}
+		if let Some(threads) = self.threads {
+			if threads == 0 || threads > MAX_THREADS {
+				return Err(invalid("threads", format!("must be between 1 and {}, got {}", MAX_THREADS, threads)));
+			}
+		}
+		let max_frame_size = self.max_frame_size.unwrap_or(DEFAULT_MAX_FRAME_SIZE);
+		if max_frame_size == 0 || max_frame_size > MAX_FRAME_SIZE {
+			return Err(invalid("max_frame_size", format!("must be between 1 and {}, got {}", MAX_FRAME_SIZE, max_frame_size)));
+		}
+		if let Some(size) = self.buffer_size {
+			if size < max_frame_size + FRAME_OVERHEAD {
+				return Err(invalid("buffer_size", format!("must be at least {} bytes to hold a full frame, got {}", max_frame_size + FRAME_OVERHEAD, size)));
+			}
+		}
+		if let Some(size) = self.write_buffer_size {
+			if size < max_frame_size + FRAME_OVERHEAD {
+				return Err(invalid("write_buffer_size", format!("must be at least {} bytes to hold a full frame, got {}", max_frame_size + FRAME_OVERHEAD, size)));
+			}
+		}
+		if let Some(ref policy) = self.slow_consumer {
+			try!(parse_slow_consumer(policy));
+		}
+		if self.max_clients == Some(0) {
+			return Err(invalid("max_clients", "must not be 0, leave it out for no limit".to_string()));
+		}
+		if let Some(ref limit) = self.byte_rate_limit {
+			try!(limit.to_limit());
+		}
+		if let Some(ref banned) = self.banned {
+			for ip in banned.iter() {
+				try!(parse_ban(ip));
+			}
+		}
+		if let Some(ref trace) = self.trace {
+			try!(parse_trace(trace));
+		}
+		Ok(())
+	}
+
+	/* the settings that can change while the server runs, see FiestaHandler::reload() */
+	pub fn runtime(&self) -> Result<RuntimeConfig, ConfigError> {
+		try!(self.validate());
+
+		let mut runtime = RuntimeConfig::default();
+		runtime.max_clients = self.max_clients;
+		runtime.max_frame_size = self.max_frame_size;
+		if let Some(ref limit) = self.byte_rate_limit {
+			runtime.byte_rate_limit = Some(try!(limit.to_limit()));
+		}
+		if let Some(ref banned) = self.banned {
+			let mut ips = HashSet::new();
+			for ip in banned.iter() {
+				ips.insert(try!(parse_ban(ip)));
+			}
+			runtime.banned = Some(ips);
+		}
+		if let Some(ref trace) = self.trace {
+			runtime.trace = Some(try!(parse_trace(trace)));
+		}
+		Ok(runtime)
+	}
+
+	/* settings missing from the file keep whatever `builder` already has */
+	pub fn apply(&self, builder: FiestaServerBuilder) -> Result<FiestaServerBuilder, ConfigError> {
+		try!(self.validate());
+
+		let mut builder = builder;
+		if let Some(ref name) = self.name {
+			builder = builder.name(name.clone());
+		}
+		if let Some(port) = self.port {
+			builder = builder.port(port);
+		}
+		if let Some(ref address) = self.address {
+			builder = builder.address(try!(parse_address(address)));
+		}
+		if let Some(ref ip_mode) = self.ip_mode {
+			builder = builder.ip_mode(try!(parse_ip_mode(ip_mode)));
+		}
+		if let Some(threads) = self.threads {
+			builder = builder.threads(threads);
+		}
+		if let Some(size) = self.buffer_size {
+			builder = builder.buffer_size(size);
+		}
+		if let Some(size) = self.write_buffer_size {
+			builder = builder.write_buffer_size(size);
+		}
+		if let Some(ref policy) = self.slow_consumer {
+			builder = builder.slow_consumer_policy(try!(parse_slow_consumer(policy)));
+		}
+		if let Some(max) = self.max_clients {
+			builder = builder.max_clients(max);
+		}
+		if let Some(size) = self.max_frame_size {
+			builder = builder.max_frame_size(size);
+		}
+		if let Some(enabled) = self.proxy_protocol {
+			builder = builder.proxy_protocol(enabled);
+		}
+		if let Some(ref socket) = self.socket {
+			builder = builder.socket_options(socket.to_options());
+		}
+		builder = builder.runtime_config(try!(self.runtime()));
+
+		Ok(builder)
+	}
+}
+
+impl SocketConfig {
+	pub fn to_options(&self) -> SocketOptions {
+		let defaults = SocketOptions::default();
+		SocketOptions {
+			nodelay:		self.nodelay.unwrap_or(defaults.nodelay),
+			keepalive:		self.keepalive_secs.map(Duration::from_secs).or(defaults.keepalive),
+			linger:			self.linger_secs.map(Duration::from_secs).or(defaults.linger),
+		}
+	}
+}
+
+impl RateLimitConfig {
+	pub fn to_limit(&self) -> Result<Option<ByteRateLimit>, ConfigError> {
+		let action = match self.action.as_ref().map(|action| &action[..]) {
+			None | Some("disconnect")	=> FloodAction::Disconnect,
+			Some("throttle")			=> FloodAction::Throttle,
+			Some(other)					=> return Err(invalid("byte_rate_limit.action", format!("expected disconnect or throttle, got '{}'", other))),
+		};
+		if self.bytes_per_sec == 0 {
+			return Ok(None);
+		}
+		Ok(Some(ByteRateLimit {
+			bytes_per_sec:	self.bytes_per_sec,
+			action:			action,
+		}))
+	}
+}
+
+fn parse_address(address: &str) -> Result<SocketAddr, ConfigError> {
+	address.parse().map_err(|_| {
+		invalid("address", format!("'{}' is not an ip:port pair (use [::1]:9010 for v6)", address))
+	})
+}
+
+fn parse_slow_consumer(policy: &str) -> Result<SlowConsumerPolicy, ConfigError> {
+	match policy {
+		"disconnect"	=> Ok(SlowConsumerPolicy::Disconnect),
+		"drop_oldest"	=> Ok(SlowConsumerPolicy::DropOldest),
+		"reject_new"	=> Ok(SlowConsumerPolicy::RejectNew),
+		other			=> Err(invalid("slow_consumer", format!("expected one of disconnect, drop_oldest, reject_new, got '{}'", other))),
+	}
+}
+
+fn parse_ip_mode(ip_mode: &str) -> Result<IpMode, ConfigError> {
+	match ip_mode {
+		"v4"	=> Ok(IpMode::V4Only),
+		"v6"	=> Ok(IpMode::V6Only),
+		"dual"	=> Ok(IpMode::DualStack),
+		other	=> Err(invalid("ip_mode", format!("expected one of v4, v6, dual, got '{}'", other))),
+	}
+}
+
+fn parse_ban(ip: &str) -> Result<IpAddr, ConfigError> {
+	ip.parse().map_err(|_| invalid("banned", format!("'{}' is not an ip address", ip)))
+}
+
+fn parse_trace(trace: &str) -> Result<TraceFilter, ConfigError> {
+	match trace.trim() {
+		"off"	=> return Ok(TraceFilter::Off),
+		"all"	=> return Ok(TraceFilter::All),
+		_		=> {},
+	}
+	let mut opcodes = Vec::new();
+	for opcode in trace.split(',').map(|opcode| opcode.trim()) {
+		let digits = opcode.trim_left_matches("0x");
+		match u16::from_str_radix(digits, 16) {
+			Ok(opcode)	=> opcodes.push(opcode),
+			Err(_)		=> return Err(invalid("trace", format!("expected off, all or hex opcodes like 0x0801, got '{}'", opcode))),
+		}
+	}
+	Ok(TraceFilter::opcodes(&opcodes[..]))
+}
@@ -0,0 +1,682 @@
+use std::cmp::min;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{Error, ErrorKind, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Condvar, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread::{self, Builder};
+use std::time::{Duration, Instant};
+
+use buffer::{Buffer, BinaryReadable};
+use packet::FiestaPacket;
+#[cfg(feature = "compression")]
+use compression;
+#[cfg(feature = "compression")]
+use compression::{Compression, COMPRESSED_FLAG};
+use error::FiestaResult;
+use framing::next_frame_size;
+use limits::FrameLimits;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinkState {
+	Connecting,
+	/* the handshake is out, packets can be sent */
+	Connected,
+	/* with the reason, another attempt follows after the backoff */
+	Disconnected(String),
+	/* stop() was called, the link is gone for good */
+	Stopped,
+}
+
+/* the other end of an outbound link, e.g. a zone server's view of the world server */
+/* all calls come from the link's own thread */
+pub trait LinkProcessor: Send + 'static {
+	/* sent first thing on every connect, so the remote side learns who we are again after a drop */
+	fn handshake(&mut self) -> Vec<FiestaPacket>;
+
+	fn process_packet(&mut self, link: &LinkHandle, packet: FiestaPacket);
+
+	fn state_changed(&mut self, link: &LinkHandle, state: &LinkState) {
+	}
+}
+
+/* wait `initial` after the first failed attempt, twice as long after each further one, at most `max` */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Backoff {
+	pub initial:		Duration,
+	pub max:			Duration,
+}
+
+impl Default for Backoff {
+	fn default() -> Self {
+		Backoff {
+			initial:		Duration::from_millis(500),
+			max:			Duration::from_secs(30),
+		}
+	}
+}
+
+/* where a link connects to, a host name is looked up again before every attempt */
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Target {
+	Addr(SocketAddr),
+	Host(String, u16),
+}
+
+impl fmt::Display for Target {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			Target::Addr(ref addr)			=> write!(f, "{}", addr),
+			Target::Host(ref host, port)	=> write!(f, "{}:{}", host, port),
+		}
+	}
+}
+
+/* a supervised connection to another server: dropped links are noticed on read, */
+/* reconnected with backoff and the handshake is sent again. one blocking thread per link */
+#[derive(Clone)]
+pub struct FiestaConnector {
+	target:			Target,
+	limits:			FrameLimits,
+	backoff:		Backoff,
+	connect_timeout:	Duration,
+	#[cfg(feature = "compression")]
+	compression:	Option<Compression>,
+}
+
+/* how often a link waiting on a quiet socket looks for calls that timed out */
+const CALL_TICK: Duration = Duration::from_millis(100);
+
+/* what a call's callback gets: the response without the call id, or why there is none */
+pub type CallResult = Result<FiestaPacket, Error>;
+
+type CallCallback = Box<FnOnce(CallResult) + Send>;
+
+struct PendingCall {
+	response_header:	u16,
+	deadline:		Instant,
+	callback:		CallCallback,
+}
+
+struct LinkShared {
+	/* the write half while connected */
+	stream:			Mutex<Option<TcpStream>>,
+	/* which of the target's addresses the current connection went to */
+	peer_addr:		Mutex<Option<SocketAddr>>,
+	state:			Mutex<LinkState>,
+	/* connect or last read, whichever was later */
+	last_received:	Mutex<Instant>,
+	stopped:		Mutex<bool>,
+	/* cuts the backoff short on stop() */
+	wakeup:			Condvar,
+	/* calls waiting for their response, by call id */
+	calls:			Mutex<HashMap<u32, PendingCall>>,
+	next_call:		AtomicUsize,
+}
+
+/* cheap to clone, sends from any thread */
+#[derive(Clone)]
+pub struct LinkHandle {
+	target:			String,
+	shared:			Arc<LinkShared>,
+	#[cfg(feature = "compression")]
+	compression:	Option<Compression>,
+}
+
+impl FiestaConnector {
+	pub fn new(target: SocketAddr) -> Self {
+		FiestaConnector::with_target(Target::Addr(target))
+	}
+
+	/* resolved on the link's thread, every A/AAAA record is tried in turn until one connects */
+	pub fn to_host<S: Into<String>>(host: S, port: u16) -> Self {
+		FiestaConnector::with_target(Target::Host(host.into(), port))
+	}
+
+	/* "ip:port", "[v6]:port" or "host:port", as it would be written in a config file */
+	pub fn parse(target: &str) -> Result<Self, Error> {
+		if let Ok(addr) = target.parse() {
+			return Ok(FiestaConnector::new(addr));
+		}
+		let invalid = || Error::new(ErrorKind::InvalidInput, format!("'{}' is not a host:port pair", target));
+		let colon = try!(target.rfind(':').ok_or_else(&invalid));
+		let port = try!(target[colon + 1..].parse().map_err(|_| invalid()));
+		if colon == 0 {
+			return Err(invalid());
+		}
+		Ok(FiestaConnector::to_host(&target[..colon], port))
+	}
+
+	fn with_target(target: Target) -> Self {
+		FiestaConnector {
+			target:			target,
+			/* the other server is trusted, don't be the one refusing big frames */
+			limits:			FrameLimits::new(0xffff),
+			backoff:		Backoff::default(),
+			connect_timeout:	Duration::from_secs(5),
+			#[cfg(feature = "compression")]
+			compression:	None,
+		}
+	}
+
+	pub fn with_frame_limits(mut self, limits: FrameLimits) -> Self {
+		self.limits = limits;
+		self
+	}
+
+	pub fn with_backoff(mut self, backoff: Backoff) -> Self {
+		self.backoff = backoff;
+		self
+	}
+
+	pub fn with_connect_timeout(mut self, timeout: Duration) -> Self {
+		self.connect_timeout = timeout;
+		self
+	}
+
+	/* the link's frames above the threshold go out compressed, the listener needs compression as well */
+	#[cfg(feature = "compression")]
+	pub fn with_compression(mut self, compression: Compression) -> Self {
+		self.compression = Some(compression);
+		self
+	}
+
+	/* connects on a background thread and keeps reconnecting until stop() */
+	pub fn spawn<P: LinkProcessor>(self, processor: P) -> Result<LinkHandle, Error> {
+		let link = LinkHandle {
+			target:			self.target.to_string(),
+			shared:			Arc::new(LinkShared {
+				stream:			Mutex::new(None),
+				peer_addr:		Mutex::new(None),
+				state:			Mutex::new(LinkState::Connecting),
+				last_received:	Mutex::new(Instant::now()),
+				stopped:		Mutex::new(false),
+				wakeup:			Condvar::new(),
+				calls:			Mutex::new(HashMap::new()),
+				next_call:		AtomicUsize::new(1),
+			}),
+			#[cfg(feature = "compression")]
+			compression:	self.compression,
+		};
+		let supervised = link.clone();
+		try!(Builder::new()
+			.name(format!("LINK {}", self.target))
+			.spawn(move || self.supervise(supervised, processor)));
+		Ok(link)
+	}
+
+	fn supervise<P: LinkProcessor>(&self, link: LinkHandle, mut processor: P) {
+		let mut delay = self.backoff.initial;
+		while !link.stopped() {
+			link.change_state(&mut processor, LinkState::Connecting);
+			let (was_connected, e) = self.session(&link, &mut processor);
+			if link.stopped() {
+				break;
+			}
+			if was_connected {
+				delay = self.backoff.initial;
+			}
+			warn!(target: "network", "link to {} down, retrying in {:?}: {}", self.target, delay, e);
+			link.change_state(&mut processor, LinkState::Disconnected(format!("{}", e)));
+			link.sleep(delay);
+			delay = min(delay * 2, self.backoff.max);
+		}
+		link.change_state(&mut processor, LinkState::Stopped);
+		info!(target: "network", "link to {} stopped.", self.target);
+	}
+
+	/* one connection from connect to drop, true if the handshake got out */
+	fn session<P: LinkProcessor>(&self, link: &LinkHandle, processor: &mut P) -> (bool, Error) {
+		let stream = match self.connect() {
+			Ok(stream) => stream,
+			Err(e) => return (false, e),
+		};
+		*link.shared.peer_addr.lock().unwrap() = stream.peer_addr().ok();
+		let _ = stream.set_nodelay(true);
+		let writer = match stream.try_clone() {
+			Ok(writer) => writer,
+			Err(e) => return (false, e),
+		};
+		*link.shared.stream.lock().unwrap() = Some(writer);
+		*link.shared.last_received.lock().unwrap() = Instant::now();
+		/* stop() may have come in before the stream was there to shut down */
+		if link.stopped() {
+			link.close();
+			return (false, Error::new(ErrorKind::Interrupted, "stopped"));
+		}
+
+		for packet in processor.handshake().iter() {
+			if let Err(e) = link.write(packet) {
+				link.close();
+				return (false, e);
+			}
+		}
+		info!(target: "network", "link to {} up.", self.target);
+		link.change_state(processor, LinkState::Connected);
+
+		let e = self.read_frames(link, processor, stream);
+		link.close();
+		link.fail_calls(&e);
+		(true, e)
+	}
+
+	/* blocks, but only the link's own thread */
+	fn resolve(&self) -> Result<Vec<SocketAddr>, Error> {
+		match self.target {
+			Target::Addr(addr) => Ok(vec![addr]),
+			Target::Host(ref host, port) => Ok(try!((&host[..], port).to_socket_addrs()).collect()),
+		}
+	}
+
+	fn connect(&self) -> Result<TcpStream, Error> {
+		let mut last_error = Error::new(ErrorKind::NotFound, format!("{} has no addresses", self.target));
+		for addr in try!(self.resolve()).into_iter() {
+			match TcpStream::connect_timeout(&addr, self.connect_timeout) {
+				Ok(stream) => return Ok(stream),
+				Err(e) => {
+					debug!(target: "network", "connecting to {} ({}) failed: {}", self.target, addr, e);
+					last_error = e;
+				}
+			}
+		}
+		Err(last_error)
+	}
+
+	/* dispatches frames until the stream fails, which is always how it ends */
+	fn read_frames<P: LinkProcessor>(&self, link: &LinkHandle, processor: &mut P, mut stream: TcpStream) -> Error {
+		let mut buffer = Buffer::with_capacity(self.limits.max_frame_size() + 5);
+		let mut chunk = [0; 4096];
+		if let Err(e) = stream.set_read_timeout(Some(CALL_TICK)) {
+			return e;
+		}
+		loop {
+			link.expire_calls();
+			let size = match stream.read(&mut chunk) {
+				Ok(0) => return Error::new(ErrorKind::UnexpectedEof, "closed by the remote side"),
+				Ok(size) => size,
+				Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+				/* the read timeout, only there for expire_calls */
+				Err(ref e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => continue,
+				Err(e) => return e,
+			};
+			*link.shared.last_received.lock().unwrap() = Instant::now();
+			buffer.extend(&chunk[0..size]);
+
+			loop {
+				let available = buffer.bytes_remaining();
+				let (size, prefix) = match next_frame_size(&mut buffer, available, &self.limits) {
+					Ok(Some(next)) => next,
+					Ok(None) => break,
+					Err(e) => return From::from(e),
+				};
+				if available < prefix + 2 + size as usize {
+					break;
+				}
+				buffer.advance_read(prefix);
+				let header = match buffer.read_u16() {
+					Ok(header) => header,
+					Err(e) => return From::from(e),
+				};
+				let mut packet = FiestaPacket::new(header, size as usize);
+				if let Err(e) = buffer.read_into(packet.data.make_mut(), size as usize) {
+					return From::from(e);
+				}
+				#[cfg(feature = "compression")]
+				{
+					if self.compression.is_some() && header & COMPRESSED_FLAG != 0 {
+						packet = match compression::inflate(&packet.data.to_vec()[..], self.limits.max_frame_size()) {
+							Ok(body) => {
+								let mut inflated = FiestaPacket::new(header & !COMPRESSED_FLAG, body.len());
+								inflated.data.append(&body[..]);
+								inflated
+							},
+							Err(e) => return e,
+						};
+					}
+				}
+				if let Some(packet) = link.complete_call(packet) {
+					processor.process_packet(link, packet);
+				}
+			}
+		}
+	}
+}
+
+impl LinkHandle {
+	/* as given to the connector */
+	pub fn target(&self) -> &str {
+		&self.target
+	}
+
+	/* the resolved address of the current or last connection */
+	pub fn peer_addr(&self) -> Option<SocketAddr> {
+		*self.shared.peer_addr.lock().unwrap()
+	}
+
+	pub fn state(&self) -> LinkState {
+		self.shared.state.lock().unwrap().clone()
+	}
+
+	pub fn is_connected(&self) -> bool {
+		self.state() == LinkState::Connected
+	}
+
+	/* fails while the link is down, nothing is queued for after the reconnect */
+	pub fn send(&self, packet: &FiestaPacket) -> FiestaResult<()> {
+		try!(self.write(packet));
+		Ok(())
+	}
+
+	/* sends `packet` with a call id in front of its body. `callback` runs on the link's thread once */
+	/* a `response_header` packet with the same id comes back, see split_call, or with TimedOut after */
+	/* `timeout`. calls still waiting when the link goes down fail right then, nothing is resent. */
+	/* returns the call id, on Err the callback is dropped without being run */
+	pub fn call<F>(&self, packet: &FiestaPacket, response_header: u16, timeout: Duration, callback: F) -> FiestaResult<u32>
+			where F: FnOnce(CallResult) + Send + 'static {
+		match self.start_call(packet, response_header, timeout, Box::new(callback)) {
+			Ok(id) => Ok(id),
+			Err((e, _)) => Err(From::from(e)),
+		}
+	}
+
+	/* call() for threads that can wait for the answer. never from the link's own thread, */
+	/* e.g. in process_packet, the response would only be read after this gave up */
+	pub fn request(&self, packet: &FiestaPacket, response_header: u16, timeout: Duration) -> FiestaResult<FiestaPacket> {
+		wait_for(|callback| self.call(packet, response_header, timeout, callback))
+	}
+
+	/* the calls still waiting for a response */
+	pub fn pending_calls(&self) -> usize {
+		self.shared.calls.lock().unwrap().len()
+	}
+
+	pub fn idle_for(&self) -> Duration {
+		self.shared.last_received.lock().unwrap().elapsed()
+	}
+
+	/* drops the current connection, the link connects again after the backoff */
+	pub fn reconnect(&self) {
+		self.close();
+	}
+
+	/* closes the connection and ends the link's thread */
+	pub fn stop(&self) {
+		*self.shared.stopped.lock().unwrap() = true;
+		self.shared.wakeup.notify_all();
+		if let Some(ref stream) = *self.shared.stream.lock().unwrap() {
+			let _ = stream.shutdown(Shutdown::Both);
+		}
+	}
+
+	fn stopped(&self) -> bool {
+		*self.shared.stopped.lock().unwrap()
+	}
+
+	/* hands the callback back if the packet didn't go out */
+	fn start_call(&self, packet: &FiestaPacket, response_header: u16, timeout: Duration, callback: CallCallback)
+			-> Result<u32, (Error, CallCallback)> {
+		let id = self.shared.next_call.fetch_add(1, Ordering::SeqCst) as u32;
+		/* in before the write, the response may be read before write() even returns */
+		self.shared.calls.lock().unwrap().insert(id, PendingCall {
+			response_header:	response_header,
+			deadline:		Instant::now() + timeout,
+			callback:		callback,
+		});
+		let body = packet.data.to_vec();
+		if let Err(e) = self.write(&call_packet(id, packet.header, &body[..])) {
+			return match self.shared.calls.lock().unwrap().remove(&id) {
+				Some(call) => Err((e, call.callback)),
+				/* already failed by the link going down, the callback ran with that */
+				None => Ok(id),
+			};
+		}
+		Ok(id)
+	}
+
+	/* runs the callback of the call `packet` answers, Some(packet) if it answers none */
+	fn complete_call(&self, packet: FiestaPacket) -> Option<FiestaPacket> {
+		let (call, response) = {
+			let mut calls = self.shared.calls.lock().unwrap();
+			/* most packets aren't responses, don't copy their bodies to find out */
+			if !calls.values().any(|call| call.response_header == packet.header) {
+				return Some(packet);
+			}
+			let (id, response) = match split_call(&packet) {
+				Some(split) => split,
+				None => return Some(packet),
+			};
+			match calls.get(&id).map(|call| call.response_header) {
+				Some(header) if header == packet.header => {},
+				_ => return Some(packet),
+			}
+			(calls.remove(&id), response)
+		};
+		if let Some(call) = call {
+			(call.callback)(Ok(response));
+		}
+		None
+	}
+
+	fn expire_calls(&self) {
+		let expired: Vec<PendingCall> = {
+			let mut calls = self.shared.calls.lock().unwrap();
+			if calls.is_empty() {
+				return;
+			}
+			let now = Instant::now();
+			let ids: Vec<u32> = calls.iter().filter(|&(_, call)| call.deadline <= now).map(|(&id, _)| id).collect();
+			ids.iter().filter_map(|id| calls.remove(id)).collect()
+		};
+		for call in expired.into_iter() {
+			let e = Error::new(ErrorKind::TimedOut, format!("no response from {} in time", self.target));
+			(call.callback)(Err(e));
+		}
+	}
+
+	fn fail_calls(&self, cause: &Error) {
+		let failed: Vec<PendingCall> = self.shared.calls.lock().unwrap().drain().map(|(_, call)| call).collect();
+		for call in failed.into_iter() {
+			let e = Error::new(cause.kind(), format!("link to {} went down: {}", self.target, cause));
+			(call.callback)(Err(e));
+		}
+	}
+
+	fn write(&self, packet: &FiestaPacket) -> Result<(), Error> {
+		#[cfg(feature = "compression")]
+		{
+			if let Some(ref compression) = self.compression {
+				return self.write_frame(&try!(compression.encode(packet.header, &packet.data.to_vec()[..]))[..]);
+			}
+		}
+		self.write_frame(&try!(FiestaPacket::encode(packet.header, &packet.data.to_vec()[..]))[..])
+	}
+
+	fn write_frame(&self, frame: &[u8]) -> Result<(), Error> {
+		match *self.shared.stream.lock().unwrap() {
+			Some(ref mut stream) => stream.write_all(frame),
+			None => Err(Error::new(ErrorKind::NotConnected, format!("link to {} is down", self.target))),
+		}
+	}
+
+	fn close(&self) {
+		if let Some(stream) = self.shared.stream.lock().unwrap().take() {
+			let _ = stream.shutdown(Shutdown::Both);
+		}
+	}
+
+	fn change_state<P: LinkProcessor>(&self, processor: &mut P, state: LinkState) {
+		*self.shared.state.lock().unwrap() = state.clone();
+		processor.state_changed(self, &state);
+	}
+
+	fn sleep(&self, delay: Duration) {
+		let stopped = self.shared.stopped.lock().unwrap();
+		if !*stopped {
+			let _ = self.shared.wakeup.wait_timeout(stopped, delay);
+		}
+	}
+}
+
+/* pings every connected link each `interval`, one that hasn't sent anything for `timeout` is reconnected */
+/* the peer has to answer the ping with something for this to work */
+#[derive(Debug, Clone)]
+pub struct HealthCheck {
+	pub interval:		Duration,
+	pub timeout:		Duration,
+	pub ping_header:	u16,
+	pub ping_body:		Vec<u8>,
+}
+
+/* several links to the same peer, so one slow query doesn't hold up the others behind it on a single socket */
+pub struct LinkPool {
+	links:			Vec<LinkHandle>,
+	next:			AtomicUsize,
+	/* links idle for longer than this are skipped by send() */
+	timeout:		Option<Duration>,
+	/* shared with the health check thread, cleared by stop() */
+	running:		Arc<Mutex<bool>>,
+}
+
+impl LinkPool {
+	/* `size` links to the connector's target, `processor(i)` builds the processor for link i */
+	pub fn spawn<P, F>(connector: FiestaConnector, size: usize, mut processor: F) -> Result<LinkPool, Error>
+			where P: LinkProcessor, F: FnMut(usize) -> P {
+		if size == 0 {
+			return Err(Error::new(ErrorKind::InvalidInput, "a link pool needs at least one link"));
+		}
+		let mut links = Vec::new();
+		for i in 0..size {
+			links.push(try!(connector.clone().spawn(processor(i))));
+		}
+		Ok(LinkPool {
+			links:			links,
+			next:			AtomicUsize::new(0),
+			timeout:		None,
+			running:		Arc::new(Mutex::new(true)),
+		})
+	}
+
+	pub fn with_health_check(mut self, check: HealthCheck) -> Result<LinkPool, Error> {
+		self.timeout = Some(check.timeout);
+		let ping = try!(FiestaPacket::encode(check.ping_header, &check.ping_body[..]));
+		let links = self.links.clone();
+		let running = self.running.clone();
+		try!(Builder::new()
+			.name(format!("LINKPOOL {}", links[0].target()))
+			.spawn(move || health_check(links, running, check, ping)));
+		Ok(self)
+	}
+
+	/* round robin over the healthy links, fails only if none of them took the packet */
+	pub fn send(&self, packet: &FiestaPacket) -> FiestaResult<()> {
+		let frame = try!(FiestaPacket::encode(packet.header, &packet.data.to_vec()[..]));
+		let start = self.next.fetch_add(1, Ordering::SeqCst);
+		let mut last_error = None;
+		for i in 0..self.links.len() {
+			let link = &self.links[(start + i) % self.links.len()];
+			if !self.healthy(link) {
+				continue;
+			}
+			match link.write_frame(&frame[..]) {
+				Ok(()) => return Ok(()),
+				Err(e) => last_error = Some(e),
+			}
+		}
+		Err(From::from(last_error.unwrap_or(Error::new(ErrorKind::NotConnected, "no link in the pool is up"))))
+	}
+
+	/* LinkHandle::call on the next healthy link, like send() a link that can't take it is skipped */
+	pub fn call<F>(&self, packet: &FiestaPacket, response_header: u16, timeout: Duration, callback: F) -> FiestaResult<u32>
+			where F: FnOnce(CallResult) + Send + 'static {
+		let start = self.next.fetch_add(1, Ordering::SeqCst);
+		let mut callback: CallCallback = Box::new(callback);
+		let mut last_error = None;
+		for i in 0..self.links.len() {
+			let link = &self.links[(start + i) % self.links.len()];
+			if !self.healthy(link) {
+				continue;
+			}
+			match link.start_call(packet, response_header, timeout, callback) {
+				Ok(id) => return Ok(id),
+				Err((e, unused)) => {
+					last_error = Some(e);
+					callback = unused;
+				}
+			}
+		}
+		Err(From::from(last_error.unwrap_or(Error::new(ErrorKind::NotConnected, "no link in the pool is up"))))
+	}
+
+	pub fn request(&self, packet: &FiestaPacket, response_header: u16, timeout: Duration) -> FiestaResult<FiestaPacket> {
+		wait_for(|callback| self.call(packet, response_header, timeout, callback))
+	}
+
+	pub fn links(&self) -> &[LinkHandle] {
+		&self.links[..]
+	}
+
+	pub fn healthy_links(&self) -> usize {
+		self.links.iter().filter(|link| self.healthy(link)).count()
+	}
+
+	pub fn stop(&self) {
+		*self.running.lock().unwrap() = false;
+		for link in self.links.iter() {
+			link.stop();
+		}
+	}
+
+	fn healthy(&self, link: &LinkHandle) -> bool {
+		link.is_connected() && self.timeout.map_or(true, |timeout| link.idle_for() < timeout)
+	}
+}
+
+/* a packet that carries call `id`, both for the call and for the response to it */
+pub fn call_packet(id: u32, header: u16, body: &[u8]) -> FiestaPacket {
+	let mut packet = FiestaPacket::new(header, body.len() + 4);
+	packet.data.append(&[id as u8, (id >> 8) as u8, (id >> 16) as u8, (id >> 24) as u8]);
+	packet.data.append(body);
+	packet
+}
+
+/* the call id and the packet as it was before call_packet, None if the body is too short for an id. */
+/* the side answering calls uses it on the request and puts the same id on its response */
+pub fn split_call(packet: &FiestaPacket) -> Option<(u32, FiestaPacket)> {
+	let body = packet.data.to_vec();
+	if body.len() < 4 {
+		return None;
+	}
+	let id = body[0] as u32 | (body[1] as u32) << 8 | (body[2] as u32) << 16 | (body[3] as u32) << 24;
+	let mut inner = FiestaPacket::new(packet.header, body.len() - 4);
+	inner.data.append(&body[4..]);
+	Some((id, inner))
+}
+
+/* blocks until the callback `start` was given runs */
+fn wait_for<F>(start: F) -> FiestaResult<FiestaPacket> where F: FnOnce(CallCallback) -> FiestaResult<u32> {
+	let (sender, receiver) = mpsc::channel();
+	try!(start(Box::new(move |result| {
+		let _ = sender.send(result);
+	})));
+	match receiver.recv() {
+		Ok(result) => Ok(try!(result)),
+		/* the link's thread is gone without running it */
+		Err(_) => Err(From::from(Error::new(ErrorKind::BrokenPipe, "the link stopped before the call finished"))),
+	}
+}
+
+fn health_check(links: Vec<LinkHandle>, running: Arc<Mutex<bool>>, check: HealthCheck, ping: Vec<u8>) {
+	while *running.lock().unwrap() {
+		for link in links.iter().filter(|link| link.is_connected()) {
+			if link.idle_for() >= check.timeout {
+				warn!(target: "network", "link to {} silent for {:?}, reconnecting.", link.target(), link.idle_for());
+				link.reconnect();
+			} else if let Err(e) = link.write_frame(&ping[..]) {
+				debug!(target: "network", "failed to ping {}: {}", link.target(), e);
+			}
+		}
+		thread::sleep(check.interval);
+	}
+}
@@ -0,0 +1,80 @@
+use std::error;
+use std::fmt;
+use std::io;
+use std::sync::PoisonError;
+use mio::Token;
+
+#[cfg(feature = "server")]
+use config::ConfigError;
+
+#[derive(Debug)]
+pub enum FiestaNetError {
+	Io(io::Error),
+	/* an event or request for a token the handler doesn't know (anymore) */
+	UnknownClient(Token),
+	/* a thread panicked while holding this lock */
+	Poisoned(&'static str),
+	/* the client's write buffer is full and the slow consumer policy refused the send */
+	SendBufferFull(Token),
+	/* the event loop's notify channel is full or closed */
+	Notify(String),
+	#[cfg(feature = "server")]
+	Config(ConfigError),
+}
+
+pub type FiestaResult<T> = Result<T, FiestaNetError>;
+
+/* errors that only mean "try again later", the socket itself is fine */
+pub fn is_transient(e: &io::Error) -> bool {
+	match e.kind() {
+		io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted	=> true,
+		_														=> false,
+	}
+}
+
+impl fmt::Display for FiestaNetError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			FiestaNetError::Io(ref e)				=> write!(f, "i/o error: {}", e),
+			FiestaNetError::UnknownClient(token)	=> write!(f, "unknown client {:?}", token),
+			FiestaNetError::Poisoned(what)			=> write!(f, "lock poisoned: {}", what),
+			FiestaNetError::SendBufferFull(token)	=> write!(f, "send buffer of {:?} is full", token),
+			FiestaNetError::Notify(ref e)			=> write!(f, "failed to notify the event loop: {}", e),
+			#[cfg(feature = "server")]
+			FiestaNetError::Config(ref e)			=> write!(f, "{}", e),
+		}
+	}
+}
+
+impl error::Error for FiestaNetError {
+	fn description(&self) -> &str {
+		match *self {
+			FiestaNetError::Io(_)				=> "i/o error",
+			FiestaNetError::UnknownClient(_)	=> "unknown client",
+			FiestaNetError::Poisoned(_)			=> "lock poisoned",
+			FiestaNetError::SendBufferFull(_)	=> "send buffer full",
+			FiestaNetError::Notify(_)			=> "failed to notify the event loop",
+			#[cfg(feature = "server")]
+			FiestaNetError::Config(_)			=> "invalid configuration",
+		}
+	}
+}
+
+impl From<io::Error> for FiestaNetError {
+	fn from(e: io::Error) -> Self {
+		FiestaNetError::Io(e)
+	}
+}
+
+#[cfg(feature = "server")]
+impl From<ConfigError> for FiestaNetError {
+	fn from(e: ConfigError) -> Self {
+		FiestaNetError::Config(e)
+	}
+}
+
+impl<T> From<PoisonError<T>> for FiestaNetError {
+	fn from(_: PoisonError<T>) -> Self {
+		FiestaNetError::Poisoned("client")
+	}
+}
@@ -0,0 +1,50 @@
+use std::io::{Error, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread::{Builder, JoinHandle};
+use std::time::Duration;
+
+use metrics::Metrics;
+
+/* requests are served one after another, a client that connects and sends nothing can't hold up */
+/* the scrapes behind it for longer than this */
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/* serves GET /metrics on a side port, one request per connection, no keep-alive */
+pub fn serve(addr: SocketAddr, server: String, metrics: Arc<Metrics>) -> Result<JoinHandle<()>, Error> {
+	let listener = try!(TcpListener::bind(addr));
+	info!(target: "network", "serving prometheus metrics on http://{}/metrics", addr);
+
+	Builder::new()
+		.name("METRICS".to_string())
+		.spawn(move || {
+			for stream in listener.incoming() {
+				match stream {
+					Ok(stream) => {
+						if let Err(e) = respond(stream, &server, &metrics) {
+							debug!(target: "network", "metrics request failed: {}", e);
+						}
+					},
+					Err(e) => warn!(target: "network", "failed to accept metrics connection: {}", e),
+				}
+			}
+		})
+}
+
+fn respond(mut stream: TcpStream, server: &str, metrics: &Metrics) -> Result<(), Error> {
+	try!(stream.set_read_timeout(Some(REQUEST_TIMEOUT)));
+	try!(stream.set_write_timeout(Some(REQUEST_TIMEOUT)));
+	/* the request line is all we care about */
+	let mut request = [0; 1024];
+	let size = try!(stream.read(&mut request));
+	let request = String::from_utf8_lossy(&request[0..size]);
+
+	let (status, body) = if request.starts_with("GET /metrics ") || request.starts_with("GET /metrics?") {
+		("200 OK", metrics.render_prometheus(server))
+	} else {
+		("404 Not Found", "not found\n".to_string())
+	};
+
+	write!(stream, "HTTP/1.0 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+		status, body.len(), body)
+}
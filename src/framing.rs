@@ -0,0 +1,126 @@
+use std::error;
+use std::fmt;
+use std::io::{Error, ErrorKind};
+
+use body::SharedBytes;
+use buffer::{BinaryPeekable, BufferError};
+use packet::FiestaPacket;
+use limits::FrameLimits;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FrameError {
+	/* the declared body is bigger than FrameLimits::max_frame_size */
+	Oversized { size: usize, limit: usize },
+	/* FrameLimits::check doesn't allow this size for the opcode */
+	UnexpectedSize { header: u16, size: usize },
+	/* the data ends inside a frame, it would take `needed` bytes */
+	Truncated { needed: usize, available: usize },
+}
+
+impl fmt::Display for FrameError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			FrameError::Oversized { size, limit }			=> write!(f, "frame of {} bytes exceeds the limit of {}", size, limit),
+			FrameError::UnexpectedSize { header, size }		=> write!(f, "unexpected size {} for opcode {:#06x}", size, header),
+			FrameError::Truncated { needed, available }		=> write!(f, "truncated frame, wanted {} bytes, only {} available", needed, available),
+		}
+	}
+}
+
+impl error::Error for FrameError {
+	fn description(&self) -> &str {
+		match *self {
+			FrameError::Oversized { .. }		=> "frame too large",
+			FrameError::UnexpectedSize { .. }	=> "unexpected frame size",
+			FrameError::Truncated { .. }		=> "truncated frame",
+		}
+	}
+}
+
+impl From<BufferError> for FrameError {
+	fn from(e: BufferError) -> Self {
+		match e {
+			BufferError::Underflow { requested, available }	=> FrameError::Truncated { needed: requested, available: available },
+			/* peeking never writes, and only ever the fixed size integers */
+			BufferError::Overflow { .. } | BufferError::TooWide { .. }	=> unreachable!(),
+		}
+	}
+}
+
+/* like BufferError, the FrameError stays reachable through get_ref() */
+impl From<FrameError> for Error {
+	fn from(e: FrameError) -> Self {
+		let kind = match e {
+			FrameError::Truncated { .. }	=> ErrorKind::UnexpectedEof,
+			_								=> ErrorKind::InvalidData,
+		};
+		Error::new(kind, e)
+	}
+}
+
+/* (body size, size prefix length), Ok(None) while the prefix isn't complete, */
+/* Err if the declared size is over the limit and the client has to go */
+pub fn next_frame_size<B: BinaryPeekable>(data: &mut B, available: usize, limits: &FrameLimits) -> Result<Option<(u16, usize)>, FrameError> {
+	if available < 3 {
+		return Ok(None);
+	}
+
+	let small_size = try!(data.peek_u8(0));
+	let (size, prefix) = if small_size > 0 {
+		(small_size as u16, 1)
+	} else if available < 5 {
+		/* extended size: 0 marker, u16 size, then the header */
+		return Ok(None);
+	} else {
+		(try!(data.peek_u16(1)), 3)
+	};
+
+	if (size as usize) > limits.max_frame_size() {
+		return Err(FrameError::Oversized { size: size as usize, limit: limits.max_frame_size() });
+	}
+
+	Ok(Some((size, prefix)))
+}
+
+/* decode_stream_with the default limits */
+pub fn decode_stream(bytes: &[u8]) -> Vec<Result<FiestaPacket, FrameError>> {
+	decode_stream_with(bytes, &FrameLimits::default())
+}
+
+/* every frame in `bytes`, the same way a client's stream is framed but without a socket */
+/* an oversized or truncated frame ends the list, the rest can't be framed anymore; */
+/* a frame with the wrong size for its opcode is reported and skipped */
+pub fn decode_stream_with(bytes: &[u8], limits: &FrameLimits) -> Vec<Result<FiestaPacket, FrameError>> {
+	let mut rest = SharedBytes::from_vec(bytes.to_vec());
+	let mut packets = Vec::new();
+
+	while !rest.is_empty() {
+		let available = rest.len();
+		let (size, prefix) = match next_frame_size(&mut rest, available, limits) {
+			Ok(Some(next)) => next,
+			Ok(None) => {
+				let needed = if rest.as_slice()[0] == 0 { 5 } else { 3 };
+				packets.push(Err(FrameError::Truncated { needed: needed, available: available }));
+				break;
+			},
+			Err(e) => {
+				packets.push(Err(e));
+				break;
+			}
+		};
+		let total_size = prefix + 2 + size as usize;
+		if available < total_size {
+			packets.push(Err(FrameError::Truncated { needed: total_size, available: available }));
+			break;
+		}
+
+		let header = ((rest.as_slice()[prefix] as u16) << 8) | rest.as_slice()[prefix + 1] as u16;
+		if limits.check(header, size as usize).is_ok() {
+			packets.push(Ok(FiestaPacket::from_shared(header, rest.slice(prefix + 2, total_size))));
+		} else {
+			packets.push(Err(FrameError::UnexpectedSize { header: header, size: size as usize }));
+		}
+		rest = rest.slice(total_size, available);
+	}
+	packets
+}
@@ -0,0 +1,162 @@
+use std::any::Any;
+use std::net::SocketAddr;
+use std::sync::{Arc, RwLock, Weak};
+use std::time::{Duration, Instant};
+use mio::Token;
+
+use body::SharedBytes;
+use client::FiestaNetworkClient;
+use packet::FiestaPacket;
+use error::{FiestaNetError, FiestaResult};
+#[cfg(feature = "crypto")]
+use keystream::Keystream;
+use protocol::ProtocolState;
+
+/* what game logic keeps instead of the client itself, e.g. in an entity component: the token and */
+/* a weak reference, so a handle never keeps a gone client alive. cheap to clone, safe to keep */
+/* across an await. sends go through the client's send queue, kicks through the reactor's channel, */
+/* everything on a client that is gone fails with UnknownClient or does nothing */
+#[derive(Clone)]
+pub struct ClientHandle {
+	id:				Token,
+	client:			Weak<RwLock<Box<FiestaNetworkClient>>>,
+}
+
+impl ClientHandle {
+	pub fn new(client: Arc<RwLock<Box<FiestaNetworkClient>>>) -> Self {
+		/* the token never changes, a poisoned lock still has it */
+		let id = match client.read() {
+			Ok(client)	=> client.id(),
+			Err(e)		=> e.into_inner().id(),
+		};
+		ClientHandle {
+			id:				id,
+			client:			Arc::downgrade(&client),
+		}
+	}
+
+	pub fn id(&self) -> Token {
+		self.id
+	}
+
+	/* None once the reactor let go of the client */
+	pub fn client(&self) -> Option<Arc<RwLock<Box<FiestaNetworkClient>>>> {
+		self.client.upgrade()
+	}
+
+	fn with<R, F: FnOnce(&FiestaNetworkClient) -> R>(&self, f: F) -> Option<R> {
+		let client = match self.client.upgrade() {
+			Some(client) => client,
+			None => return None,
+		};
+		let guard = match client.read() {
+			Ok(guard) => guard,
+			Err(poisoned) => poisoned.into_inner(),
+		};
+		let result = f(&guard);
+		Some(result)
+	}
+
+	fn try_with<R, F: FnOnce(&FiestaNetworkClient) -> FiestaResult<R>>(&self, f: F) -> FiestaResult<R> {
+		match self.with(f) {
+			Some(result) => result,
+			None => Err(FiestaNetError::UnknownClient(self.id)),
+		}
+	}
+
+	/* FiestaNetworkClient::describe(), just the token once the client is gone */
+	pub fn describe(&self) -> String {
+		self.with(|client| client.describe()).unwrap_or_else(|| format!("{:?}", self.id))
+	}
+
+	/* honours a PROXY header like FiestaNetworkClient::real_addr() */
+	pub fn addr(&self) -> Option<SocketAddr> {
+		self.with(|client| client.real_addr()).and_then(|addr| addr)
+	}
+
+	pub fn local_addr(&self) -> Option<SocketAddr> {
+		self.with(|client| client.local_addr()).and_then(|addr| addr)
+	}
+
+	pub fn set_label(&self, label: Option<String>) {
+		self.with(|client| client.set_label(label));
+	}
+
+	pub fn send(&self, packet: &FiestaPacket) -> FiestaResult<()> {
+		self.try_with(|client| {
+			/* the one copy, the frame is written from it */
+			let body = SharedBytes::from_vec(packet.data.to_vec());
+			client.send(FiestaPacket::from_shared(packet.header, body))
+		})
+	}
+
+	/* a body that goes to many clients, e.g. a broadcast, is copied once instead of once per send */
+	pub fn send_shared(&self, header: u16, body: SharedBytes) -> FiestaResult<()> {
+		self.try_with(|client| client.send(FiestaPacket::from_shared(header, body)))
+	}
+
+	/* e.g. a respawn notice, sent by the reactor without a timer thread of its own */
+	pub fn send_after(&self, packet: &FiestaPacket, delay: Duration) -> FiestaResult<()> {
+		self.try_with(|client| client.send_after(packet, delay))
+	}
+
+	/* runs on the reactor thread, so it should only queue sends or hand work off, not block */
+	pub fn schedule<F: FnOnce() + Send + 'static>(&self, at: Instant, work: F) -> FiestaResult<()> {
+		self.try_with(|client| client.schedule(at, work))
+	}
+
+	pub fn is_connected(&self) -> bool {
+		self.with(|client| client.alive()).unwrap_or(false)
+	}
+
+	/* the reactor drops it on its next wakeup, see FiestaNetworkClient::kick */
+	pub fn kick(&self) {
+		self.with(|client| client.kick());
+	}
+
+	pub fn disconnect(&self) {
+		self.with(|client| client.disconnect());
+	}
+
+	/* e.g. after a login failure, the packet saying so goes out first */
+	pub fn close_after_flush(&self) -> FiestaResult<()> {
+		self.try_with(|client| client.close_after_flush())
+	}
+
+	pub fn set_protocol_state(&self, state: ProtocolState) {
+		self.with(|client| client.set_protocol_state(state));
+	}
+
+	/* one value per type, see Extensions */
+	pub fn insert_extension<T: Any + Send + Sync>(&self, value: T) -> Option<Arc<T>> {
+		self.with(|client| client.extensions().insert(value)).and_then(|old| old)
+	}
+
+	pub fn extension<T: Any + Send + Sync>(&self) -> Option<Arc<T>> {
+		self.with(|client| client.extensions().get::<T>()).and_then(|value| value)
+	}
+
+	pub fn remove_extension<T: Any + Send + Sync>(&self) -> Option<Arc<T>> {
+		self.with(|client| client.extensions().remove::<T>()).and_then(|value| value)
+	}
+
+	#[cfg(feature = "crypto")]
+	pub fn set_keystream(&self, keystream: Keystream) {
+		self.with(|client| client.set_keystream(keystream));
+	}
+
+	/* e.g. on a zone transfer, after the new seed is sent, see FiestaNetworkClient::rotate_keystream */
+	#[cfg(feature = "crypto")]
+	pub fn rotate_keystream(&self, seed: u16, window: Duration) -> FiestaResult<()> {
+		self.try_with(|client| client.rotate_keystream(seed, window))
+	}
+}
+
+/* two handles are equal if they point at the same connection */
+impl PartialEq for ClientHandle {
+	fn eq(&self, other: &ClientHandle) -> bool {
+		self.id == other.id && Weak::ptr_eq(&self.client, &other.client)
+	}
+}
+
+impl Eq for ClientHandle {}
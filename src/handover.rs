@@ -0,0 +1,164 @@
+use std::env;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::mem;
+use std::net;
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::thread;
+use libc;
+
+use error::FiestaResult;
+use server::ServerHandle;
+
+/* systemd hands sockets over starting at this fd */
+const LISTEN_FDS_START: RawFd = 3;
+/* more than any server binds, well below the kernel's SCM_MAX_FD */
+pub const MAX_HANDOVER_FDS: usize = 64;
+
+/* the listeners systemd passed through socket activation, empty if it didn't */
+/* the environment is cleared so processes started from here don't pick them up too */
+pub fn systemd_listeners() -> FiestaResult<Vec<net::TcpListener>> {
+	let pid = env::var("LISTEN_PID").ok().and_then(|pid| pid.parse::<u32>().ok());
+	let count = env::var("LISTEN_FDS").ok().and_then(|count| count.parse::<usize>().ok());
+	env::remove_var("LISTEN_PID");
+	env::remove_var("LISTEN_FDS");
+	env::remove_var("LISTEN_FDNAMES");
+
+	let count = match (pid, count) {
+		/* meant for another process, e.g. our parent */
+		(Some(pid), Some(count)) if pid == unsafe { libc::getpid() } as u32 => count,
+		_ => return Ok(Vec::new()),
+	};
+	let mut listeners = Vec::new();
+	for fd in LISTEN_FDS_START..LISTEN_FDS_START + count as RawFd {
+		unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) };
+		listeners.push(try!(adopt(fd)));
+	}
+	info!(target: "network", "inherited {} listener(s) from systemd.", listeners.len());
+	Ok(listeners)
+}
+
+/* asks the process serving handover on `path` for its listeners, see FiestaServerBuilder::handover_path() */
+/* the old process stops accepting once they're sent, connections queue up in the shared backlog meanwhile */
+pub fn receive_listeners<P: AsRef<Path>>(path: P) -> FiestaResult<Vec<net::TcpListener>> {
+	let stream = try!(UnixStream::connect(path.as_ref()));
+	let mut listeners = Vec::new();
+	for fd in try!(receive_fds(&stream)).into_iter() {
+		listeners.push(try!(adopt(fd)));
+	}
+	info!(target: "network", "took over {} listener(s) through {}.", listeners.len(), path.as_ref().display());
+	Ok(listeners)
+}
+
+/* serves a single handover on `path`: the next process to connect gets `fds`, then this server drains */
+pub fn offer_listeners(path: PathBuf, fds: Vec<RawFd>, server: ServerHandle) -> FiestaResult<()> {
+	if fds.len() > MAX_HANDOVER_FDS {
+		return Err(From::from(Error::new(ErrorKind::InvalidInput, "too many listeners to hand over")));
+	}
+	/* left behind by a process that didn't get to clean up */
+	let _ = fs::remove_file(&path);
+	let socket = try!(UnixListener::bind(&path));
+	try!(thread::Builder::new()
+		.name("HANDOVER".to_string())
+		.spawn(move || {
+			for stream in socket.incoming() {
+				let result = stream.and_then(|stream| send_fds(&stream, &fds[..]));
+				match result {
+					Ok(()) => break,
+					/* the next process may well try again */
+					Err(e) => warn!(target: "network", "listener handover failed: {}", e),
+				}
+			}
+			let _ = fs::remove_file(&path);
+			info!(target: "network", "listeners handed over, draining. stop this process once its clients are gone.");
+			if let Err(e) = server.drain(true) {
+				warn!(target: "network", "failed to stop accepting after the handover: {}", e);
+			}
+		}));
+	Ok(())
+}
+
+/* the fd has to be a listening stream socket, anything else is refused */
+fn adopt(fd: RawFd) -> FiestaResult<net::TcpListener> {
+	let (mut kind, mut listening): (libc::c_int, libc::c_int) = (0, 0);
+	let mut len = mem::size_of::<libc::c_int>() as libc::socklen_t;
+	let ok = unsafe {
+		libc::getsockopt(fd, libc::SOL_SOCKET, libc::SO_TYPE, &mut kind as *mut _ as *mut libc::c_void, &mut len) == 0
+			&& libc::getsockopt(fd, libc::SOL_SOCKET, libc::SO_ACCEPTCONN, &mut listening as *mut _ as *mut libc::c_void, &mut len) == 0
+	};
+	if !ok || kind != libc::SOCK_STREAM || listening == 0 {
+		return Err(From::from(Error::new(ErrorKind::InvalidInput, format!("inherited fd {} is not a listening tcp socket", fd))));
+	}
+	let listener = unsafe { net::TcpListener::from_raw_fd(fd) };
+	try!(listener.set_nonblocking(true));
+	Ok(listener)
+}
+
+/* one byte of payload carries the count, the fds travel as SCM_RIGHTS */
+fn send_fds(stream: &UnixStream, fds: &[RawFd]) -> Result<(), Error> {
+	let mut count = [fds.len() as u8];
+	let mut iov = libc::iovec { iov_base: count.as_mut_ptr() as *mut libc::c_void, iov_len: 1 };
+	let data_len = (fds.len() * mem::size_of::<RawFd>()) as u32;
+	let mut control = vec![0u8; unsafe { libc::CMSG_SPACE(data_len) } as usize];
+
+	let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+	msg.msg_iov = &mut iov;
+	msg.msg_iovlen = 1;
+	msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+	msg.msg_controllen = control.len() as _;
+	unsafe {
+		let cmsg = libc::CMSG_FIRSTHDR(&msg);
+		(*cmsg).cmsg_level = libc::SOL_SOCKET;
+		(*cmsg).cmsg_type = libc::SCM_RIGHTS;
+		(*cmsg).cmsg_len = libc::CMSG_LEN(data_len) as _;
+		ptr::copy_nonoverlapping(fds.as_ptr(), libc::CMSG_DATA(cmsg) as *mut RawFd, fds.len());
+		if libc::sendmsg(stream.as_raw_fd(), &msg, 0) < 0 {
+			return Err(Error::last_os_error());
+		}
+	}
+	Ok(())
+}
+
+fn receive_fds(stream: &UnixStream) -> Result<Vec<RawFd>, Error> {
+	let mut count = [0u8];
+	let mut iov = libc::iovec { iov_base: count.as_mut_ptr() as *mut libc::c_void, iov_len: 1 };
+	let mut control = vec![0u8; unsafe { libc::CMSG_SPACE((MAX_HANDOVER_FDS * mem::size_of::<RawFd>()) as u32) } as usize];
+
+	let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+	msg.msg_iov = &mut iov;
+	msg.msg_iovlen = 1;
+	msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+	msg.msg_controllen = control.len() as _;
+	let size = unsafe { libc::recvmsg(stream.as_raw_fd(), &mut msg, libc::MSG_CMSG_CLOEXEC) };
+	if size < 0 {
+		return Err(Error::last_os_error());
+	}
+	if size == 0 {
+		return Err(Error::new(ErrorKind::UnexpectedEof, "handover socket closed before the listeners were sent"));
+	}
+
+	let mut fds = Vec::new();
+	unsafe {
+		let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+		while !cmsg.is_null() {
+			if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SCM_RIGHTS {
+				let data = libc::CMSG_DATA(cmsg) as *const RawFd;
+				let received = ((*cmsg).cmsg_len as usize - libc::CMSG_LEN(0) as usize) / mem::size_of::<RawFd>();
+				for i in 0..received {
+					fds.push(ptr::read_unaligned(data.offset(i as isize)));
+				}
+			}
+			cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+		}
+	}
+	if msg.msg_flags & libc::MSG_CTRUNC != 0 || fds.len() != count[0] as usize {
+		for fd in fds.into_iter() {
+			unsafe { libc::close(fd) };
+		}
+		return Err(Error::new(ErrorKind::InvalidData, "got a different number of listeners than announced"));
+	}
+	Ok(fds)
+}
@@ -0,0 +1,77 @@
+use std::io::{Error, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread::{Builder, JoinHandle};
+use std::time::{Duration, Instant};
+use serde_json;
+
+use metrics::Metrics;
+use processing::PacketProcessingThreadPool;
+
+/* like the metrics exporter, an idle connection must not fail the liveness probes behind it */
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Serialize)]
+struct Status<'a> {
+	server:			&'a str,
+	uptime_secs:	u64,
+	connections:	usize,
+	workers:		usize,
+	/* packets waiting for a worker */
+	queue_depth:	usize,
+}
+
+/* serves GET /healthz and GET /status on a side port, one request per connection like the metrics exporter */
+pub fn serve(addr: SocketAddr, server: String, metrics: Arc<Metrics>, pool: Option<PacketProcessingThreadPool>) -> Result<JoinHandle<()>, Error> {
+	let listener = try!(TcpListener::bind(addr));
+	let started = Instant::now();
+	info!(target: "network", "serving health checks on http://{}/healthz", addr);
+
+	Builder::new()
+		.name("HEALTH".to_string())
+		.spawn(move || {
+			for stream in listener.incoming() {
+				match stream {
+					Ok(stream) => {
+						let status = Status {
+							server:			&server,
+							uptime_secs:	started.elapsed().as_secs(),
+							connections:	metrics.connections_active(),
+							workers:		pool.as_ref().map_or(0, |pool| pool.size()),
+							queue_depth:	pool.as_ref().map_or(0, |pool| pool.queued()),
+						};
+						if let Err(e) = respond(stream, &status) {
+							debug!(target: "network", "health request failed: {}", e);
+						}
+					},
+					Err(e) => warn!(target: "network", "failed to accept health check connection: {}", e),
+				}
+			}
+		})
+}
+
+fn respond(mut stream: TcpStream, status: &Status) -> Result<(), Error> {
+	try!(stream.set_read_timeout(Some(REQUEST_TIMEOUT)));
+	try!(stream.set_write_timeout(Some(REQUEST_TIMEOUT)));
+	let mut request = [0; 1024];
+	let size = try!(stream.read(&mut request));
+	let request = String::from_utf8_lossy(&request[0..size]);
+
+	let (code, kind, body) = if is_get(&request, "/healthz") {
+		("200 OK", "text/plain", "ok\n".to_string())
+	} else if is_get(&request, "/status") {
+		match serde_json::to_string(status) {
+			Ok(json)	=> ("200 OK", "application/json", json),
+			Err(e)		=> ("500 Internal Server Error", "text/plain", format!("{}\n", e)),
+		}
+	} else {
+		("404 Not Found", "text/plain", "not found\n".to_string())
+	};
+
+	write!(stream, "HTTP/1.0 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+		code, kind, body.len(), body)
+}
+
+fn is_get(request: &str, path: &str) -> bool {
+	request.starts_with(&format!("GET {} ", path)) || request.starts_with(&format!("GET {}?", path))
+}
@@ -0,0 +1,77 @@
+use std::fmt;
+use std::io::{Error, ErrorKind};
+
+const BYTES_PER_LINE: usize = 16;
+
+/* classic offset / hex / ascii dump, one line per 16 bytes:
+ * 0000  08 20 01 00 00 00 00 00  00 00 00 00 00 00 00 00  |. ..............|
+ */
+pub fn write_dump(f: &mut fmt::Formatter, bytes: &[u8]) -> fmt::Result {
+	for (line, chunk) in bytes.chunks(BYTES_PER_LINE).enumerate() {
+		try!(write!(f, "{:04x}  ", line * BYTES_PER_LINE));
+		for i in 0..BYTES_PER_LINE {
+			match chunk.get(i) {
+				Some(b)	=> try!(write!(f, "{:02x} ", b)),
+				None	=> try!(write!(f, "   ")),
+			}
+			if i == BYTES_PER_LINE / 2 - 1 {
+				try!(write!(f, " "));
+			}
+		}
+		try!(write!(f, " |"));
+		for &b in chunk {
+			let c = if b >= 0x20 && b < 0x7f { b as char } else { '.' };
+			try!(write!(f, "{}", c));
+		}
+		try!(writeln!(f, "|"));
+	}
+	Ok(())
+}
+
+/* for format strings, "{}" is the full dump */
+pub struct HexDump<'a>(pub &'a [u8]);
+
+impl<'a> fmt::Display for HexDump<'a> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write_dump(f, self.0)
+	}
+}
+
+/* "08 20 01", for single line log output */
+pub fn write_hex(f: &mut fmt::Formatter, bytes: &[u8]) -> fmt::Result {
+	for (i, b) in bytes.iter().enumerate() {
+		if i > 0 {
+			try!(write!(f, " "));
+		}
+		try!(write!(f, "{:02x}", b));
+	}
+	Ok(())
+}
+
+/* whitespace between the digits is ignored, and so are the offsets and the ascii column of a */
+/* write_dump, so dumps can be pasted straight into tests */
+pub fn parse_hex(hex: &str) -> Result<Vec<u8>, Error> {
+	let digits: Vec<char> = hex.lines()
+		.flat_map(|line| match line.find('|') {
+			/* a write_dump line, the first word is the offset */
+			Some(ascii)	=> line[0..ascii].split_whitespace().skip(1).collect::<Vec<&str>>(),
+			None		=> line.split_whitespace().collect(),
+		})
+		.flat_map(|word| word.chars())
+		.collect();
+	if digits.len() % 2 != 0 {
+		return Err(Error::new(ErrorKind::InvalidData, "odd number of hex digits"));
+	}
+
+	let mut bytes = Vec::with_capacity(digits.len() / 2);
+	for pair in digits.chunks(2) {
+		let high = pair[0].to_digit(16);
+		let low = pair[1].to_digit(16);
+		match (high, low) {
+			(Some(high), Some(low))	=> bytes.push((high * 16 + low) as u8),
+			_						=> return Err(Error::new(ErrorKind::InvalidData,
+				format!("'{}{}' is not a hex byte", pair[0], pair[1]))),
+		}
+	}
+	Ok(bytes)
+}
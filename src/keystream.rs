@@ -0,0 +1,106 @@
+use std::mem;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use opcodes;
+
+/* the game's xor cipher on what clients send: opcode and body, not the size prefix, are xor-ed */
+/* with a table starting at the seed the server handed out, the position carries over from frame */
+/* to frame and wraps at the end of the table. the table comes with the client, it isn't in here */
+#[derive(Clone)]
+pub struct Keystream {
+	table:			Arc<Vec<u8>>,
+	position:		usize,
+}
+
+impl Keystream {
+	pub fn new(table: Arc<Vec<u8>>, seed: u16) -> Self {
+		assert!(!table.is_empty(), "a keystream needs a table");
+		let position = seed as usize % table.len();
+		Keystream {
+			table:			table,
+			position:		position,
+		}
+	}
+
+	/* same table, starting over at another seed */
+	pub fn reseed(&self, seed: u16) -> Self {
+		Keystream::new(self.table.clone(), seed)
+	}
+
+	pub fn position(&self) -> usize {
+		self.position
+	}
+
+	pub fn apply(&mut self, bytes: &mut [u8]) {
+		for byte in bytes.iter_mut() {
+			*byte ^= self.table[self.position];
+			self.position = (self.position + 1) % self.table.len();
+		}
+	}
+
+	/* what apply() would make of the opcode, without moving on */
+	fn peek_header(&self, header: u16) -> u16 {
+		let mut probe = self.clone();
+		let mut bytes = [(header >> 8) as u8, header as u8];
+		probe.apply(&mut bytes);
+		((bytes[0] as u16) << 8) | bytes[1] as u16
+	}
+}
+
+/* a client's inbound keystream with the one it replaced, for frames the client sent before */
+/* it saw the new seed. while the window is open every frame goes to whichever key turns its */
+/* opcode into a known one, the new key first. the first frame under the new key closes it */
+pub struct Decryption {
+	current:		Keystream,
+	previous:		Option<(Keystream, Instant)>,
+}
+
+impl Decryption {
+	pub fn new(keystream: Keystream) -> Self {
+		Decryption {
+			current:		keystream,
+			previous:		None,
+		}
+	}
+
+	pub fn rotate(&mut self, seed: u16, window: Duration) {
+		let next = self.current.reseed(seed);
+		let previous = mem::replace(&mut self.current, next);
+		self.previous = Some((previous, Instant::now() + window));
+	}
+
+	pub fn rotating(&self) -> bool {
+		self.previous.is_some()
+	}
+
+	/* the opcode and body of one frame, in place, the opcode as read from the wire */
+	pub fn decrypt(&mut self, header: u16, body: &mut [u8]) -> u16 {
+		let use_previous = match self.previous {
+			Some((_, deadline)) if deadline <= Instant::now() => {
+				self.previous = None;
+				false
+			},
+			Some((ref previous, _)) => {
+				!known(self.current.peek_header(header)) && known(previous.peek_header(header))
+			},
+			None => false,
+		};
+		if !use_previous {
+			/* the client has the new seed, nothing older can follow */
+			self.previous = None;
+		}
+		let keystream = match self.previous {
+			Some((ref mut previous, _)) => previous,
+			None => &mut self.current,
+		};
+		let mut bytes = [(header >> 8) as u8, header as u8];
+		keystream.apply(&mut bytes);
+		keystream.apply(body);
+		((bytes[0] as u16) << 8) | bytes[1] as u16
+	}
+}
+
+fn known(header: u16) -> bool {
+	opcodes::name(header).is_some()
+}
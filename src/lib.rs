@@ -1,16 +1,262 @@
 #![allow(dead_code)]
 #![allow(unused_variables)]
-#![feature(convert)]
 
 #[macro_use]
 extern crate log;
 extern crate mio;
-extern crate chan;
+#[cfg(feature = "threads")]
 extern crate threadpool;
+#[cfg(feature = "server")]
+extern crate net2;
+extern crate libc;
+#[cfg(feature = "server")]
+extern crate serde;
+#[cfg(feature = "server")]
+#[macro_use]
+extern crate serde_derive;
+#[cfg(feature = "server")]
+extern crate toml;
+#[cfg(feature = "yaml")]
+extern crate serde_yaml;
+#[cfg(feature = "tls")]
+extern crate rustls;
+#[cfg(feature = "spans")]
+#[macro_use]
+extern crate tracing;
+#[cfg(feature = "spans")]
+extern crate tracing_log;
+#[cfg(feature = "health")]
+extern crate serde_json;
+#[cfg(feature = "signals")]
+extern crate signal_hook;
+#[cfg(feature = "compression")]
+extern crate flate2;
+#[cfg(feature = "rayon")]
+extern crate rayon;
+#[cfg(feature = "tokio")]
+extern crate tokio;
+#[cfg(feature = "tokio")]
+extern crate tokio_util;
+#[cfg(feature = "tokio")]
+extern crate bytes;
+#[cfg(feature = "tokio")]
+extern crate futures_core;
+#[cfg(feature = "tokio")]
+extern crate futures_util;
+#[cfg(feature = "websocket")]
+extern crate sha1;
+#[cfg(feature = "websocket")]
+extern crate base64;
 
+#[cfg(feature = "admin")]
+mod admin;
+mod body;
 mod buffer;
+mod codec;
+#[cfg(feature = "server")]
+mod audit;
+#[cfg(feature = "server")]
+mod bus;
+#[cfg(feature = "capture")]
+mod capture;
+#[cfg(feature = "server")]
 mod client;
+#[cfg(feature = "compression")]
+mod compression;
+#[cfg(feature = "server")]
+mod connector;
+mod error;
+mod framing;
+#[cfg(feature = "server")]
+mod handle;
+#[cfg(feature = "server")]
+mod handover;
+#[cfg(feature = "crypto")]
+mod keystream;
+mod hexdump;
+mod limits;
+#[cfg(feature = "metrics")]
+mod metrics;
+#[cfg(feature = "server")]
+mod mitm;
+#[cfg(feature = "server")]
+mod outbound;
+mod packet;
+#[cfg(feature = "server")]
+mod session;
+#[cfg(feature = "server")]
+mod spatial;
+#[cfg(feature = "server")]
+mod stats;
+#[cfg(feature = "server")]
+mod trace;
+#[cfg(feature = "server")]
+mod transport;
+#[cfg(feature = "server")]
+mod version;
+#[cfg(feature = "prometheus")]
+mod exporter;
+#[cfg(feature = "health")]
+mod health;
+#[cfg(feature = "server")]
+mod listener;
+mod pool;
+#[cfg(feature = "server")]
+mod protocol;
+#[cfg(feature = "server")]
+mod reactor;
+#[cfg(feature = "signals")]
+mod signals;
+#[cfg(feature = "server")]
+mod sockopt;
+#[cfg(feature = "spans")]
+mod spans;
+#[cfg(feature = "tls")]
+mod tls;
+#[cfg(feature = "tokio")]
+mod tokio_net;
+#[cfg(feature = "websocket")]
+mod websocket;
+#[cfg(feature = "server")]
 mod processing;
+#[cfg(feature = "server")]
+mod server;
+#[cfg(feature = "server")]
+pub mod config;
+#[cfg(feature = "server")]
+pub mod login;
+pub mod opcodes;
+pub mod packets;
+pub mod prelude;
+#[cfg(feature = "server")]
+pub mod presets;
+#[cfg(feature = "server")]
+pub mod proxy;
+#[cfg(feature = "server")]
+pub mod replay;
+#[cfg(feature = "crypto")]
+pub mod shn;
+#[cfg(feature = "server")]
+pub mod testing;
+
+pub use buffer::{
+	Buffer,
+	BinaryReadable,
+	BinaryPeekable,
+	BufferError,
+	Endianness,
+	SendBuffer,
+};
+pub use packet::FiestaPacket;
+#[cfg(feature = "server")]
+pub use client::{
+	ClientTimeout,
+	DisconnectReason,
+	FiestaHandler,
+	FiestaNetworkClient,
+	ServerMessage,
+	SERVER_TOKEN,
+};
+pub use error::{
+	FiestaNetError,
+	FiestaResult,
+};
+pub use limits::{
+	ByteRateLimit,
+	FloodAction,
+	FrameLimits,
+	OpcodeSize,
+	ReadBackpressure,
+	SlowConsumerPolicy,
+};
+#[cfg(feature = "server")]
+pub use listener::IpMode;
+#[cfg(feature = "server")]
+pub use protocol::{Keepalive, ProtocolState, SeedHandshake, StateRules, ViolationAction};
+#[cfg(feature = "server")]
+pub use version::{ProtocolVersion, VersionLayer, VersionRouter, VersionTable};
+#[cfg(feature = "server")]
+pub use server::{
+	FiestaServerBuilder,
+	FiestaServer,
+	ServerHandle,
+};
+#[cfg(feature = "server")]
+pub use sockopt::SocketOptions;
+#[cfg(feature = "tls")]
+pub use tls::TlsConfig;
+#[cfg(feature = "websocket")]
+pub use websocket::WebSocketTransport;
+#[cfg(feature = "compression")]
+pub use compression::{Compression, COMPRESSED_FLAG};
+pub use pool::BufferPool;
+#[cfg(feature = "capture")]
+pub use capture::{Direction, PacketCapture, LINKTYPE_FIESTA};
+#[cfg(feature = "metrics")]
+pub use metrics::Metrics;
+#[cfg(feature = "server")]
+pub use stats::ClientStats;
+#[cfg(feature = "server")]
+pub use trace::TraceFilter;
+#[cfg(feature = "server")]
+pub use transport::{MemoryPeer, MemoryTransport, Transport, TransportIo};
+#[cfg(feature = "spans")]
+pub use spans::bridge_log;
+pub use body::{InlineBytes, PacketBody, SharedBytes, INLINE_BODY_SIZE};
+pub use codec::{Codec, LengthPrefix};
+#[cfg(feature = "crypto")]
+pub use keystream::Keystream;
+#[cfg(feature = "server")]
+pub use audit::{AuditKind, AuditRecord, AuditSink, JsonLinesAudit};
+#[cfg(feature = "server")]
+pub use bus::{Bus, ClientGroup, Event, Subscriber, Subscription, ALL_TOPICS};
+#[cfg(feature = "server")]
+pub use session::{Extensions, SessionState, SessionStore, SessionToken};
+#[cfg(feature = "server")]
+pub use spatial::SpatialGrid;
+pub use framing::{decode_stream, decode_stream_with, FrameError};
+#[cfg(feature = "server")]
+pub use mitm::{FiestaProxy, Inspector};
+#[cfg(feature = "server")]
+pub use connector::{call_packet, split_call, Backoff, CallResult, FiestaConnector, HealthCheck, LinkHandle, LinkPool, LinkProcessor, LinkState};
+#[cfg(feature = "admin")]
+pub use admin::AdminCommand;
+#[cfg(feature = "server")]
+pub use reactor::{Notifier, PollStrategy};
+#[cfg(feature = "server")]
+pub use handle::ClientHandle;
+#[cfg(feature = "server")]
+pub use handover::{receive_listeners, systemd_listeners, MAX_HANDOVER_FDS};
+#[cfg(feature = "tokio")]
+pub use tokio_net::{AsyncHandler, FiestaCodec, HandlerFuture, ProcessorHandler, TokioServer};
+#[cfg(feature = "server")]
+pub use processing::{
+	InlineProcessor,
+	Middleware,
+	MiddlewareChain,
+	Next,
+	DeadLetter,
+	DisconnectHook,
+	Outgoing,
+	ReplyHandler,
+	ReplyProcessor,
+	Route,
+	Router,
+	Target,
+	deliver,
+	Tick,
+	TickHook,
+	Dispatch,
+	OverflowPolicy,
+	PanicPolicy,
+	QueueLimit,
+	PacketProcessor,
+	PacketProcessingInfo,
+};
+#[cfg(feature = "threads")]
+pub use processing::PacketProcessingThreadPool;
+#[cfg(feature = "rayon")]
+pub use processing::RayonProcessingPool;
 
 #[test]
 fn it_works() {
@@ -0,0 +1,104 @@
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind};
+
+/* retail clients never send bodies anywhere near this */
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 2048;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpcodeSize {
+	Exact(usize),
+	AtMost(usize),
+}
+
+/* what append_send does when a client's write buffer is full */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SlowConsumerPolicy {
+	Disconnect,
+	/* throw away queued frames that haven't been sent yet, oldest first */
+	DropOldest,
+	/* keep the queue, fail the new send */
+	RejectNew,
+}
+
+/* stop reading from a client while this many of its packets wait for a worker */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReadBackpressure {
+	pub pause_at:		usize,
+	pub resume_at:		usize,
+}
+
+impl Default for ReadBackpressure {
+	fn default() -> Self {
+		ReadBackpressure {
+			pause_at:		256,
+			resume_at:		64,
+		}
+	}
+}
+
+/* what happens to a client that goes over its ByteRateLimit */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FloodAction {
+	Disconnect,
+	/* stop reading from it for the rest of the second */
+	Throttle,
+}
+
+/* independent of packets, counts raw bytes read from the socket per second */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteRateLimit {
+	pub bytes_per_sec:	usize,
+	pub action:			FloodAction,
+}
+
+/* shared by all clients of a handler */
+#[derive(Debug, Clone)]
+pub struct FrameLimits {
+	max_frame_size:		usize,
+	opcode_sizes:		HashMap<u16, OpcodeSize>,
+}
+
+impl FrameLimits {
+	pub fn new(max_frame_size: usize) -> Self {
+		FrameLimits {
+			max_frame_size:		max_frame_size,
+			opcode_sizes:		HashMap::new(),
+		}
+	}
+
+	/* body size (without header) a packet with `header` has to have */
+	pub fn with_opcode_size(mut self, header: u16, size: OpcodeSize) -> Self {
+		self.opcode_sizes.insert(header, size);
+		self
+	}
+
+	pub fn set_max_frame_size(&mut self, size: usize) {
+		self.max_frame_size = size;
+	}
+
+	pub fn max_frame_size(&self) -> usize {
+		self.max_frame_size
+	}
+
+	/* InvalidData if the declared size can't be right for this opcode */
+	pub fn check(&self, header: u16, size: usize) -> Result<(), Error> {
+		let ok = match self.opcode_sizes.get(&header) {
+			Some(&OpcodeSize::Exact(expected))	=> size == expected,
+			Some(&OpcodeSize::AtMost(max))		=> size <= max,
+			None								=> true,
+		};
+
+		if ok {
+			Ok(())
+		} else {
+			Err(Error::new(ErrorKind::InvalidData,
+				format!("unexpected size {} for opcode {:#06x}", size, header)))
+		}
+	}
+}
+
+impl Default for FrameLimits {
+	fn default() -> Self {
+		FrameLimits::new(DEFAULT_MAX_FRAME_SIZE)
+	}
+}
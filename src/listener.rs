@@ -0,0 +1,78 @@
+use std::io::Error;
+use std::net::{SocketAddr, IpAddr, Ipv4Addr, Ipv6Addr};
+use std::os::unix::io::{IntoRawFd, FromRawFd};
+use mio::net::TcpListener;
+use net2::TcpBuilder;
+use net2::unix::UnixTcpBuilderExt;
+
+pub const LISTEN_BACKLOG: i32 = 128;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IpMode {
+	V4Only,
+	V6Only,
+	DualStack,
+}
+
+/* binds every listener needed to serve `port` in the given mode */
+/* with `reuse_port` every call gets its own listeners on the same port and the kernel spreads connections over them */
+pub fn bind(port: u16, mode: IpMode, reuse_port: bool) -> Result<Vec<TcpListener>, Error> {
+	let any_v4 = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), port);
+	let any_v6 = SocketAddr::new(IpAddr::V6(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 0)), port);
+
+	match mode {
+		IpMode::V4Only		=> Ok(vec![try!(bind_addr(&any_v4, true, reuse_port))]),
+		IpMode::V6Only		=> Ok(vec![try!(bind_addr(&any_v6, true, reuse_port))]),
+		IpMode::DualStack	=> {
+			match bind_addr(&any_v6, false, reuse_port) {
+				Ok(listener) => Ok(vec![listener]),
+				Err(e) => {
+					/* some platforms refuse v4-mapped sockets, fall back to one listener per family */
+					info!(target: "network", "dual-stack socket unavailable ({}), binding v4 and v6 separately", e);
+					let v6 = try!(bind_addr(&any_v6, true, reuse_port));
+					let v4 = try!(bind_addr(&any_v4, true, reuse_port));
+					Ok(vec![v6, v4])
+				}
+			}
+		}
+	}
+}
+
+/* `v6_only` is ignored for v4 addresses */
+pub fn bind_addr(address: &SocketAddr, v6_only: bool, reuse_port: bool) -> Result<TcpListener, Error> {
+	let builder = match *address {
+		SocketAddr::V4(_) => try!(TcpBuilder::new_v4()),
+		SocketAddr::V6(_) => {
+			let builder = try!(TcpBuilder::new_v6());
+			try!(builder.only_v6(v6_only));
+			builder
+		},
+	};
+	try!(builder.reuse_address(true));
+	if reuse_port {
+		try!(builder.reuse_port(true));
+	}
+	try!(builder.bind(address));
+
+	let listener = try!(builder.listen(LISTEN_BACKLOG));
+	try!(listener.set_nonblocking(true));
+	debug!(target: "network", "bound listener on {}", address);
+
+	Ok(unsafe { TcpListener::from_raw_fd(listener.into_raw_fd()) })
+}
+
+/* dual-stack sockets report v4 peers as ::ffff:a.b.c.d, which is confusing in logs */
+pub fn normalize_addr(address: SocketAddr) -> SocketAddr {
+	match address {
+		SocketAddr::V6(v6) => {
+			let s = v6.ip().segments();
+			if s[0] == 0 && s[1] == 0 && s[2] == 0 && s[3] == 0 && s[4] == 0 && s[5] == 0xffff {
+				let ip = Ipv4Addr::new((s[6] >> 8) as u8, s[6] as u8, (s[7] >> 8) as u8, s[7] as u8);
+				SocketAddr::new(IpAddr::V4(ip), v6.port())
+			} else {
+				address
+			}
+		},
+		_ => address,
+	}
+}
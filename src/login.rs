@@ -0,0 +1,211 @@
+use std::fmt;
+use std::sync::Arc;
+
+use audit::AuditKind;
+use client::FiestaNetworkClient;
+use error::FiestaNetError;
+use packet::FiestaPacket;
+use processing::{Middleware, Next, PacketProcessingInfo};
+use protocol::ProtocolState;
+
+/* where the login packets sit, the defaults are the retail NA client's */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoginOpcodes {
+	pub version_req:		u16,
+	pub version_ok:			u16,
+	pub version_wrong:		u16,
+	pub login_req:			u16,
+	pub login_fail:			u16,
+}
+
+impl Default for LoginOpcodes {
+	fn default() -> Self {
+		LoginOpcodes {
+			version_req:		0x0c65,
+			version_ok:			0x0c67,
+			version_wrong:		0x0c66,
+			login_req:			0x0c06,
+			login_fail:			0x0c09,
+		}
+	}
+}
+
+/* widths of the zero padded string fields, they changed between client builds */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoginLayout {
+	pub version_len:		usize,
+	pub user_len:			usize,
+	pub password_len:		usize,
+}
+
+impl Default for LoginLayout {
+	fn default() -> Self {
+		LoginLayout {
+			version_len:		64,
+			user_len:			256,
+			password_len:		16,
+		}
+	}
+}
+
+#[derive(Clone, PartialEq, Eq)]
+pub struct Credentials {
+	pub user:				String,
+	pub password:			String,
+}
+
+/* keeps passwords out of the log */
+impl fmt::Debug for Credentials {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "Credentials {{ user: {:?}, password: <{} chars> }}", self.user, self.password.len())
+	}
+}
+
+/* sent back in the login fail packet, the client picks its message box by the code */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoginError {
+	InvalidCredentials,
+	Banned,
+	AlreadyLoggedIn,
+	Maintenance,
+	Other(u16),
+}
+
+impl LoginError {
+	pub fn code(&self) -> u16 {
+		match *self {
+			LoginError::InvalidCredentials	=> 0x44,
+			LoginError::Banned				=> 0x45,
+			LoginError::AlreadyLoggedIn		=> 0x46,
+			LoginError::Maintenance			=> 0x47,
+			LoginError::Other(code)			=> code,
+		}
+	}
+}
+
+/* user code behind the login sequence, called on a worker thread */
+pub trait LoginValidator: Send + Sync + 'static {
+	fn check_version(&self, version: &str) -> bool {
+		true
+	}
+
+	fn authenticate(&self, client: &FiestaNetworkClient, credentials: &Credentials) -> Result<(), LoginError>;
+}
+
+/* answers the version check and the login request, everything else goes on down the chain. */
+/* a successful login moves the client to ProtocolState::Authenticated and is passed on as well, */
+/* so the processor can send the world list. pair it with StateRules to keep the order enforced */
+pub struct LoginLayer {
+	validator:		Arc<LoginValidator>,
+	opcodes:		LoginOpcodes,
+	layout:			LoginLayout,
+}
+
+impl LoginLayer {
+	pub fn new<V: LoginValidator>(validator: V) -> Self {
+		LoginLayer {
+			validator:		Arc::new(validator),
+			opcodes:		LoginOpcodes::default(),
+			layout:			LoginLayout::default(),
+		}
+	}
+
+	pub fn with_opcodes(mut self, opcodes: LoginOpcodes) -> Self {
+		self.opcodes = opcodes;
+		self
+	}
+
+	pub fn with_layout(mut self, layout: LoginLayout) -> Self {
+		self.layout = layout;
+		self
+	}
+
+	fn version_check(&self, client: &FiestaNetworkClient, body: &[u8]) {
+		let version = match fixed_string(body, 0, self.layout.version_len) {
+			Some(version) => version,
+			None => {
+				warn!(target: "network", "{:?}: short version check packet.", client.id());
+				client.disconnect();
+				return;
+			}
+		};
+		let reply = if self.validator.check_version(&version) {
+			self.opcodes.version_ok
+		} else {
+			info!(target: "network", "{:?}: refusing client version {}.", client.id(), version);
+			self.opcodes.version_wrong
+		};
+		reply_to(client, reply, &[]);
+	}
+
+	/* true if the packet should go on to the processor */
+	fn login(&self, client: &FiestaNetworkClient, body: &[u8]) -> bool {
+		let credentials = match (fixed_string(body, 0, self.layout.user_len),
+				fixed_string(body, self.layout.user_len, self.layout.password_len)) {
+			(Some(user), Some(password)) => Credentials { user: user, password: password },
+			_ => {
+				warn!(target: "network", "{:?}: short login packet.", client.id());
+				client.disconnect();
+				return false;
+			}
+		};
+		match self.validator.authenticate(client, &credentials) {
+			Ok(()) => {
+				info!(target: "network", "{:?}: logged in as {}.", client.id(), credentials.user);
+				client.audit(AuditKind::Auth(credentials.user.clone()));
+				client.set_protocol_state(ProtocolState::Authenticated);
+				true
+			},
+			Err(e) => {
+				info!(target: "network", "{:?}: login as {} refused: {:?}", client.id(), credentials.user, e);
+				client.audit(AuditKind::AuthFailed(credentials.user.clone(), format!("{:?}", e)));
+				let code = e.code();
+				reply_to(client, self.opcodes.login_fail, &[code as u8, (code >> 8) as u8]);
+				false
+			}
+		}
+	}
+}
+
+impl Middleware for LoginLayer {
+	fn handle(&self, info: PacketProcessingInfo, next: Next) {
+		let pass_on = {
+			let (header, body) = (info.opcode(), info.packet.data.to_vec());
+			let client_lock = match info.client.client() {
+				Some(client) => client,
+				None => return,
+			};
+			let client = match client_lock.read() {
+				Ok(client) => client,
+				Err(_) => return,
+			};
+			if header == self.opcodes.version_req {
+				self.version_check(&client, &body[..]);
+				false
+			} else if header == self.opcodes.login_req {
+				self.login(&client, &body[..])
+			} else {
+				true
+			}
+		};
+		if pass_on {
+			next.run(info);
+		}
+	}
+}
+
+/* `len` bytes at `offset`, up to the first zero */
+pub fn fixed_string(body: &[u8], offset: usize, len: usize) -> Option<String> {
+	if body.len() < offset + len {
+		return None;
+	}
+	let field = &body[offset..offset + len];
+	let end = field.iter().position(|&b| b == 0).unwrap_or(len);
+	Some(String::from_utf8_lossy(&field[..end]).into_owned())
+}
+
+fn reply_to(client: &FiestaNetworkClient, header: u16, body: &[u8]) {
+	if let Err(e) = FiestaPacket::encode(header, body).map_err(FiestaNetError::from).and_then(|frame| client.append_frame(frame)) {
+		warn!(target: "network", "{:?}: failed to answer login packet: {}", client.id(), e);
+	}
+}
@@ -0,0 +1,163 @@
+use std::fmt::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[cfg(feature = "server")]
+use client::DisconnectReason;
+
+/* server wide counters, shared by the handler and all its clients */
+#[derive(Default)]
+pub struct Metrics {
+	connections_accepted:	AtomicUsize,
+	connections_refused:	AtomicUsize,
+	connections_closed:		AtomicUsize,
+	bytes_in:				AtomicUsize,
+	bytes_out:				AtomicUsize,
+	packets_in:				AtomicUsize,
+	frame_errors:			AtomicUsize,
+	unknown_opcodes:		AtomicUsize,
+	slow_handlers:			AtomicUsize,
+	keepalive_timeouts:		AtomicUsize,
+	/* connections_closed by DisconnectReason */
+	peer_closed:			AtomicUsize,
+	read_errors:			AtomicUsize,
+	write_stalls:			AtomicUsize,
+	kicks:					AtomicUsize,
+	protocol_violations:	AtomicUsize,
+	idle_timeouts:			AtomicUsize,
+	poisoned:				AtomicUsize,
+	accepts:				AtomicUsize,
+	accept_wakeups:			AtomicUsize,
+	last_accept_batch:		AtomicUsize,
+	/* max_clients over all reactors, 0 without a limit */
+	capacity:				AtomicUsize,
+}
+
+/* (name, type, help, value) */
+type Sample = (&'static str, &'static str, &'static str, usize);
+
+impl Metrics {
+	pub fn new() -> Self {
+		Metrics::default()
+	}
+
+	pub fn connection_accepted(&self) {
+		self.connections_accepted.fetch_add(1, Ordering::Relaxed);
+	}
+
+	pub fn connection_refused(&self) {
+		self.connections_refused.fetch_add(1, Ordering::Relaxed);
+	}
+
+	pub fn connection_closed(&self) {
+		self.connections_closed.fetch_add(1, Ordering::Relaxed);
+	}
+
+	/* counts it as closed as well */
+	#[cfg(feature = "server")]
+	pub fn disconnected(&self, reason: &DisconnectReason) {
+		self.connection_closed();
+		let counter = match *reason {
+			DisconnectReason::PeerClosed		=> &self.peer_closed,
+			DisconnectReason::ReadError(_)		=> &self.read_errors,
+			DisconnectReason::WriteStall		=> &self.write_stalls,
+			DisconnectReason::Kicked			=> &self.kicks,
+			DisconnectReason::ProtocolViolation	=> &self.protocol_violations,
+			DisconnectReason::IdleTimeout		=> &self.idle_timeouts,
+			DisconnectReason::Poisoned			=> &self.poisoned,
+		};
+		counter.fetch_add(1, Ordering::Relaxed);
+	}
+
+	pub fn bytes_read(&self, bytes: usize) {
+		self.bytes_in.fetch_add(bytes, Ordering::Relaxed);
+	}
+
+	pub fn bytes_written(&self, bytes: usize) {
+		self.bytes_out.fetch_add(bytes, Ordering::Relaxed);
+	}
+
+	pub fn packets_received(&self, count: usize) {
+		self.packets_in.fetch_add(count, Ordering::Relaxed);
+	}
+
+	pub fn frame_error(&self) {
+		self.frame_errors.fetch_add(1, Ordering::Relaxed);
+	}
+
+	pub fn unknown_opcode(&self) {
+		self.unknown_opcodes.fetch_add(1, Ordering::Relaxed);
+	}
+
+	pub fn slow_handler(&self) {
+		self.slow_handlers.fetch_add(1, Ordering::Relaxed);
+	}
+
+	pub fn keepalive_timeout(&self) {
+		self.keepalive_timeouts.fetch_add(1, Ordering::Relaxed);
+	}
+
+	/* connections taken off a listener in one go */
+	pub fn accept_batch(&self, count: usize) {
+		self.accepts.fetch_add(count, Ordering::Relaxed);
+		self.accept_wakeups.fetch_add(1, Ordering::Relaxed);
+		self.last_accept_batch.store(count, Ordering::Relaxed);
+	}
+
+	pub fn connections_active(&self) -> usize {
+		let accepted = self.connections_accepted.load(Ordering::Relaxed);
+		accepted.saturating_sub(self.connections_closed.load(Ordering::Relaxed))
+	}
+
+	pub fn set_capacity(&self, capacity: Option<usize>) {
+		self.capacity.store(capacity.unwrap_or(0), Ordering::Relaxed);
+	}
+
+	pub fn capacity(&self) -> Option<usize> {
+		match self.capacity.load(Ordering::Relaxed) {
+			0			=> None,
+			capacity	=> Some(capacity),
+		}
+	}
+
+	fn samples(&self) -> Vec<Sample> {
+		vec![
+			("fiesta_connections_accepted_total", "counter", "Accepted client connections.", self.connections_accepted.load(Ordering::Relaxed)),
+			("fiesta_connections_refused_total", "counter", "Connections refused because of the client limit.", self.connections_refused.load(Ordering::Relaxed)),
+			("fiesta_connections_closed_total", "counter", "Client connections that were closed.", self.connections_closed.load(Ordering::Relaxed)),
+			("fiesta_connections_active", "gauge", "Currently connected clients.", self.connections_active()),
+			("fiesta_disconnects_peer_closed_total", "counter", "Clients that closed the connection themselves.", self.peer_closed.load(Ordering::Relaxed)),
+			("fiesta_disconnects_read_error_total", "counter", "Clients dropped for a socket error or a frame that couldn't be read.", self.read_errors.load(Ordering::Relaxed)),
+			("fiesta_disconnects_write_stall_total", "counter", "Clients dropped for a failed write or a full send buffer.", self.write_stalls.load(Ordering::Relaxed)),
+			("fiesta_disconnects_kicked_total", "counter", "Clients kicked, banned or closed on shutdown.", self.kicks.load(Ordering::Relaxed)),
+			("fiesta_disconnects_protocol_violation_total", "counter", "Clients dropped for packets they weren't allowed to send, floods or bad PROXY headers.", self.protocol_violations.load(Ordering::Relaxed)),
+			("fiesta_disconnects_idle_timeout_total", "counter", "Clients dropped for a missing handshake or heartbeat answer.", self.idle_timeouts.load(Ordering::Relaxed)),
+			("fiesta_disconnects_poisoned_total", "counter", "Clients dropped because a panicking handler poisoned one of their locks.", self.poisoned.load(Ordering::Relaxed)),
+			("fiesta_bytes_received_total", "counter", "Bytes read from clients.", self.bytes_in.load(Ordering::Relaxed)),
+			("fiesta_bytes_sent_total", "counter", "Bytes written to clients.", self.bytes_out.load(Ordering::Relaxed)),
+			("fiesta_packets_received_total", "counter", "Packets handed to the processor.", self.packets_in.load(Ordering::Relaxed)),
+			("fiesta_frame_errors_total", "counter", "Clients dropped for oversized or malformed frames.", self.frame_errors.load(Ordering::Relaxed)),
+			("fiesta_unknown_opcodes_total", "counter", "Packets no route was registered for.", self.unknown_opcodes.load(Ordering::Relaxed)),
+			("fiesta_slow_handlers_total", "counter", "Packets whose processing took longer than the slow handler budget.", self.slow_handlers.load(Ordering::Relaxed)),
+			("fiesta_keepalive_timeouts_total", "counter", "Clients dropped for leaving too many heartbeats unanswered.", self.keepalive_timeouts.load(Ordering::Relaxed)),
+			("fiesta_accepts_total", "counter", "Connections taken off the listeners, refused ones included.", self.accepts.load(Ordering::Relaxed)),
+			("fiesta_accept_wakeups_total", "counter", "Accept passes over a listener, divide accepts by this for accepts per wakeup.", self.accept_wakeups.load(Ordering::Relaxed)),
+			("fiesta_accept_batch_last", "gauge", "Connections accepted in the most recent pass.", self.last_accept_batch.load(Ordering::Relaxed)),
+		]
+	}
+
+	/* (name, value) of every counter, for the admin console */
+	pub fn snapshot(&self) -> Vec<(&'static str, usize)> {
+		self.samples().into_iter().map(|(name, _, _, value)| (name, value)).collect()
+	}
+
+	/* prometheus text format, labelled with the server name */
+	pub fn render_prometheus(&self, server: &str) -> String {
+		let mut out = String::new();
+		for (name, kind, help, value) in self.samples() {
+			let _ = writeln!(out, "# HELP {} {}", name, help);
+			let _ = writeln!(out, "# TYPE {} {}", name, kind);
+			let _ = writeln!(out, "{}{{server=\"{}\"}} {}", name, server, value);
+		}
+		out
+	}
+}
@@ -0,0 +1,148 @@
+use std::io::{Error, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread::{Builder, JoinHandle};
+
+use buffer::{Buffer, BinaryReadable};
+use capture::Direction;
+use packet::FiestaPacket;
+use error::FiestaResult;
+use framing::next_frame_size;
+use limits::FrameLimits;
+use opcodes::OpcodeName;
+
+/* sees every packet going through a FiestaProxy, returning false drops it */
+/* Direction::Inbound is client to server, Outbound is server to client */
+pub trait Inspector: Send + Sync + 'static {
+	fn inspect(&self, session: usize, direction: Direction, packet: &mut FiestaPacket) -> bool;
+}
+
+impl<F> Inspector for F where F: Fn(usize, Direction, &mut FiestaPacket) -> bool + Send + Sync + 'static {
+	fn inspect(&self, session: usize, direction: Direction, packet: &mut FiestaPacket) -> bool {
+		self(session, direction, packet)
+	}
+}
+
+/* sits between a game client and a server, every frame is decoded, inspected and encoded again */
+/* meant for protocol work, it uses two blocking threads per connection */
+pub struct FiestaProxy {
+	listen:			SocketAddr,
+	upstream:		SocketAddr,
+	limits:			FrameLimits,
+	inspector:		Arc<Inspector>,
+}
+
+impl FiestaProxy {
+	pub fn new<I: Inspector>(listen: SocketAddr, upstream: SocketAddr, inspector: I) -> Self {
+		FiestaProxy {
+			listen:			listen,
+			upstream:		upstream,
+			/* the proxy shouldn't be the one refusing big frames */
+			limits:			FrameLimits::new(0xffff),
+			inspector:		Arc::new(inspector),
+		}
+	}
+
+	pub fn with_frame_limits(mut self, limits: FrameLimits) -> Self {
+		self.limits = limits;
+		self
+	}
+
+	/* accepts clients on a background thread */
+	pub fn spawn(self) -> Result<JoinHandle<()>, Error> {
+		let listener = try!(TcpListener::bind(self.listen));
+		Builder::new()
+			.name("PROXY".to_string())
+			.spawn(move || self.accept_loop(listener))
+	}
+
+	/* blocks forever */
+	pub fn run(self) -> FiestaResult<()> {
+		let listener = try!(TcpListener::bind(self.listen));
+		self.accept_loop(listener);
+		Ok(())
+	}
+
+	fn accept_loop(&self, listener: TcpListener) {
+		info!(target: "network", "proxying {} to {}", self.listen, self.upstream);
+		let sessions = AtomicUsize::new(0);
+		for client in listener.incoming() {
+			let client = match client {
+				Ok(client) => client,
+				Err(e) => {
+					warn!(target: "network", "failed to accept proxy client: {}", e);
+					continue;
+				}
+			};
+			let session = sessions.fetch_add(1, Ordering::SeqCst);
+			if let Err(e) = self.start_session(session, client) {
+				warn!(target: "network", "proxy session {} failed to start: {}", session, e);
+			}
+		}
+	}
+
+	fn start_session(&self, session: usize, client: TcpStream) -> Result<(), Error> {
+		let server = try!(TcpStream::connect(self.upstream));
+		info!(target: "network", "proxy session {}: {:?} <-> {}", session, client.peer_addr().ok(), self.upstream);
+
+		let (client_read, server_write) = (try!(client.try_clone()), try!(server.try_clone()));
+		let limits = self.limits.clone();
+		let inspector = self.inspector.clone();
+		try!(Builder::new()
+			.name(format!("PROXY {} up", session))
+			.spawn(move || relay(session, Direction::Inbound, client_read, server_write, &limits, &*inspector)));
+
+		let limits = self.limits.clone();
+		let inspector = self.inspector.clone();
+		try!(Builder::new()
+			.name(format!("PROXY {} down", session))
+			.spawn(move || relay(session, Direction::Outbound, server, client, &limits, &*inspector)));
+		Ok(())
+	}
+}
+
+/* one direction of a session, until either side closes or the stream can't be framed */
+fn relay(session: usize, direction: Direction, mut from: TcpStream, mut to: TcpStream, limits: &FrameLimits, inspector: &Inspector) {
+	if let Err(e) = relay_frames(session, direction, &mut from, &mut to, limits, inspector) {
+		warn!(target: "network", "proxy session {} {:?}: {}", session, direction, e);
+	}
+	debug!(target: "network", "proxy session {} {:?} closed", session, direction);
+	/* take the other direction down with us */
+	let _ = from.shutdown(Shutdown::Both);
+	let _ = to.shutdown(Shutdown::Both);
+}
+
+fn relay_frames(session: usize, direction: Direction, from: &mut TcpStream, to: &mut TcpStream, limits: &FrameLimits, inspector: &Inspector) -> Result<(), Error> {
+	let mut buffer = Buffer::with_capacity(limits.max_frame_size() + 5);
+	let mut chunk = [0; 4096];
+	loop {
+		let size = try!(from.read(&mut chunk));
+		if size == 0 {
+			return Ok(());
+		}
+		buffer.extend(&chunk[0..size]);
+
+		loop {
+			let available = buffer.bytes_remaining();
+			let (size, prefix) = match try!(next_frame_size(&mut buffer, available, limits)) {
+				Some(next) => next,
+				None => break,
+			};
+			if available < prefix + 2 + size as usize {
+				break;
+			}
+			buffer.advance_read(prefix);
+			let header = try!(buffer.read_u16());
+			let mut packet = FiestaPacket::new(header, size as usize);
+			try!(buffer.read_into(packet.data.make_mut(), size as usize));
+
+			if inspector.inspect(session, direction, &mut packet) {
+				/* re-encoded, so an extended size on a small frame comes out in the short form */
+				try!(to.write_all(&try!(FiestaPacket::encode(packet.header, &packet.data.to_vec()[..]))));
+			} else {
+				debug!(target: "network", "proxy session {} dropped packet {}", session, OpcodeName(header));
+			}
+		}
+	}
+}
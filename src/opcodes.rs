@@ -0,0 +1,56 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{Error, ErrorKind, Read};
+use std::path::Path;
+use std::sync::RwLock;
+
+use packets::Opcode;
+
+/* names registered at runtime, they win over the ones generated from spec/packets.spec */
+static NAMES: RwLock<Option<HashMap<u16, String>>> = RwLock::new(None);
+
+pub fn register<S: Into<String>>(opcode: u16, name: S) {
+	if let Ok(mut names) = NAMES.write() {
+		names.get_or_insert_with(HashMap::new).insert(opcode, name.into());
+	}
+}
+
+pub fn name(opcode: u16) -> Option<String> {
+	let registered = NAMES.read().ok().and_then(|names| names.as_ref().and_then(|names| names.get(&opcode).cloned()));
+	registered.or_else(|| Opcode::from_header(opcode).map(|known| known.name().to_string()))
+}
+
+/* one "0x0c06 NC_USER_LOGIN_REQ" per line, # starts a comment. returns how many were registered */
+pub fn load_names<P: AsRef<Path>>(path: P) -> Result<usize, Error> {
+	let mut text = String::new();
+	try!(try!(File::open(path)).read_to_string(&mut text));
+
+	let mut parsed = Vec::new();
+	for line in text.lines().map(|line| line.trim()).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+		let mut words = line.split_whitespace();
+		match (words.next().and_then(|op| u16::from_str_radix(op.trim_left_matches("0x"), 16).ok()), words.next()) {
+			(Some(opcode), Some(name))	=> parsed.push((opcode, name.to_string())),
+			_							=> return Err(Error::new(ErrorKind::InvalidData, format!("bad line: {}", line))),
+		}
+	}
+	/* all or nothing, a bad line doesn't leave half the file registered */
+	let count = parsed.len();
+	for (opcode, name) in parsed {
+		register(opcode, name);
+	}
+	Ok(count)
+}
+
+/* for log lines: "NC_USER_LOGIN_REQ (0x0c06)", or just the hex for opcodes nobody named */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpcodeName(pub u16);
+
+impl fmt::Display for OpcodeName {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match name(self.0) {
+			Some(name)	=> write!(f, "{} ({:#06x})", name, self.0),
+			None		=> write!(f, "{:#06x}", self.0),
+		}
+	}
+}
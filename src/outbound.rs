@@ -0,0 +1,363 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{Error, ErrorKind};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+
+use capture::strip_size_prefix;
+use packet::FiestaPacket;
+use transport::{Transport, MAX_IOVECS};
+
+/* one outgoing frame the way it was handed over. the size prefix and opcode are put in front */
+/* of the body when it is written, the body itself is never copied into a send buffer */
+pub struct OutFrame {
+	head:			[u8; 5],
+	head_len:		usize,
+	body:			FrameBody,
+}
+
+enum FrameBody {
+	Bytes(Vec<u8>),
+	Packet(FiestaPacket),
+}
+
+impl OutFrame {
+	pub fn new(header: u16, body: Vec<u8>) -> Result<Self, Error> {
+		OutFrame::with_head(header, body.len(), FrameBody::Bytes(body))
+	}
+
+	/* the packet's header is sent as it is, translated to the wire opcode already */
+	pub fn packet(packet: FiestaPacket) -> Result<Self, Error> {
+		let (header, size) = (packet.header, packet.data.bytes_remaining());
+		OutFrame::with_head(header, size, FrameBody::Packet(packet))
+	}
+
+	/* `frame` is complete already, size prefix included */
+	pub fn encoded(frame: Vec<u8>) -> Self {
+		OutFrame {
+			head:			[0; 5],
+			head_len:		0,
+			body:			FrameBody::Bytes(frame),
+		}
+	}
+
+	/* same prefix as FiestaPacket::encode, and refused like there for bodies it can't describe */
+	fn with_head(header: u16, size: usize, body: FrameBody) -> Result<Self, Error> {
+		if size > 0xffff {
+			return Err(Error::new(ErrorKind::InvalidInput, "packet body doesn't fit in a frame"));
+		}
+		let mut head = [0; 5];
+		let len = if size > 0 && size < 0x100 {
+			head[0] = size as u8;
+			1
+		} else {
+			/* 0 marks the extended size */
+			head[1] = (size >> 8) as u8;
+			head[2] = size as u8;
+			3
+		};
+		head[len] = (header >> 8) as u8;
+		head[len + 1] = header as u8;
+		Ok(OutFrame {
+			head:			head,
+			head_len:		len + 2,
+			body:			body,
+		})
+	}
+
+	/* in wire order, some of them may be empty */
+	pub fn slices(&self) -> [&[u8]; 3] {
+		let (first, second) = match self.body {
+			FrameBody::Bytes(ref bytes)		=> (&bytes[..], &[][..]),
+			FrameBody::Packet(ref packet)	=> packet.data.segments(),
+		};
+		[&self.head[..self.head_len], first, second]
+	}
+
+	pub fn len(&self) -> usize {
+		self.slices().iter().map(|slice| slice.len()).sum()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+
+	pub fn opcode(&self) -> Option<u16> {
+		if self.head_len > 0 {
+			let head = &self.head[self.head_len - 2..self.head_len];
+			return Some(((head[0] as u16) << 8) | head[1] as u16);
+		}
+		let packet = match self.body {
+			FrameBody::Bytes(ref bytes) => strip_size_prefix(&bytes[..]),
+			FrameBody::Packet(_) => return None,
+		};
+		if packet.len() < 2 {
+			return None;
+		}
+		Some(((packet[0] as u16) << 8) | packet[1] as u16)
+	}
+
+	/* the whole frame in one piece, for captures and traces */
+	pub fn to_vec(&self) -> Vec<u8> {
+		let mut frame = Vec::with_capacity(self.len());
+		for slice in self.slices().iter() {
+			frame.extend_from_slice(slice);
+		}
+		frame
+	}
+}
+
+impl fmt::Debug for OutFrame {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self.opcode() {
+			Some(opcode)	=> write!(f, "OutFrame {{ opcode: {:#06x}, length: {} }}", opcode, self.len()),
+			None			=> write!(f, "OutFrame {{ length: {} }}", self.len()),
+		}
+	}
+}
+
+/* the frames the reactor is writing out, oldest first */
+pub struct FrameQueue {
+	frames:			VecDeque<OutFrame>,
+	/* bytes of the front frame that are already on the wire */
+	front_sent:		usize,
+	/* unsent bytes of all frames */
+	bytes:			usize,
+	capacity:		usize,
+}
+
+impl FrameQueue {
+	pub fn with_capacity(capacity: usize) -> Self {
+		FrameQueue {
+			frames:			VecDeque::new(),
+			front_sent:		0,
+			bytes:			0,
+			capacity:		capacity,
+		}
+	}
+
+	pub fn bytes_remaining(&self) -> usize {
+		self.bytes
+	}
+
+	pub fn capacity(&self) -> usize {
+		self.capacity
+	}
+
+	pub fn free(&self) -> usize {
+		self.capacity - self.bytes
+	}
+
+	pub fn frame_count(&self) -> usize {
+		self.frames.len()
+	}
+
+	/* opcodes of the frames not completely written yet, in the order they go out */
+	pub fn opcodes(&self) -> Vec<u16> {
+		self.frames.iter().filter_map(|frame| frame.opcode()).collect()
+	}
+
+	pub fn push_frame(&mut self, frame: OutFrame) -> Result<(), Error> {
+		let len = frame.len();
+		if len > self.free() {
+			return Err(Error::new(ErrorKind::Other, "send buffer full"));
+		}
+		self.bytes += len;
+		self.frames.push_back(frame);
+		Ok(())
+	}
+
+	/* the unsent rest of the front frame's first non-empty piece, for writers that take one slice at a time */
+	pub fn front_chunk(&self) -> &[u8] {
+		let frame = match self.frames.front() {
+			Some(frame) => frame,
+			None => return &[],
+		};
+		let mut skip = self.front_sent;
+		for &slice in frame.slices().iter() {
+			if skip < slice.len() {
+				return &slice[skip..];
+			}
+			skip -= slice.len();
+		}
+		&[]
+	}
+
+	/* as many frames as fit in one writev, straight from where they are */
+	pub fn write_to(&mut self, transport: &Transport) -> Result<usize, Error> {
+		let written = {
+			let mut parts: [&[u8]; MAX_IOVECS] = [&[]; MAX_IOVECS];
+			let mut count = 0;
+			let mut skip = self.front_sent;
+			'frames: for frame in self.frames.iter() {
+				for &slice in frame.slices().iter() {
+					if skip >= slice.len() {
+						skip -= slice.len();
+						continue;
+					}
+					if count == MAX_IOVECS {
+						break 'frames;
+					}
+					let part = &slice[skip..];
+					skip = 0;
+					parts[count] = part;
+					count += 1;
+				}
+			}
+			try!(transport.write_vectored(&parts[..count]))
+		};
+
+		self.consume(written);
+		Ok(written)
+	}
+
+	/* `bytes` have been written to the socket */
+	pub fn consume(&mut self, bytes: usize) {
+		let mut bytes = bytes;
+		while bytes > 0 {
+			let left = match self.frames.front() {
+				Some(front) => front.len() - self.front_sent,
+				None => break,
+			};
+			if bytes >= left {
+				self.frames.pop_front();
+				self.front_sent = 0;
+				self.bytes -= left;
+				bytes -= left;
+			} else {
+				self.front_sent += bytes;
+				self.bytes -= bytes;
+				bytes = 0;
+			}
+		}
+	}
+
+	/* everything unsent in one piece, the queue is empty afterwards */
+	pub fn take_all(&mut self) -> Vec<u8> {
+		let mut sent = Vec::with_capacity(self.bytes);
+		for frame in self.frames.drain(..) {
+			for slice in frame.slices().iter() {
+				sent.extend_from_slice(slice);
+			}
+		}
+		sent.drain(..self.front_sent);
+		self.front_sent = 0;
+		self.bytes = 0;
+		sent
+	}
+
+	/* drops whole unsent frames from the front until `needed` bytes are free */
+	/* returns the number of dropped frames, or None if that isn't possible */
+	pub fn drop_oldest(&mut self, needed: usize) -> Option<usize> {
+		if needed > self.capacity || (self.front_sent > 0 && needed > self.free()) {
+			/* the front frame is half on the wire, dropping the rest would corrupt the stream */
+			return None;
+		}
+
+		let mut dropped = 0;
+		while self.free() < needed {
+			match self.frames.pop_front() {
+				Some(frame) => {
+					self.bytes -= frame.len();
+					dropped += 1;
+				},
+				None => return None,
+			}
+		}
+		Some(dropped)
+	}
+}
+
+/* the way out for a client's frames: any thread pushes owned frames without waiting on the */
+/* reactor, which moves them into the FrameQueue it writes from. the sizes are kept in atomics */
+/* so senders can check the limit without locking anything */
+pub struct SendQueue {
+	sender:			Mutex<Sender<OutFrame>>,
+	/* only the reactor takes from it, the lock is never contended */
+	receiver:		Mutex<Receiver<OutFrame>>,
+	capacity:		usize,
+	/* pushed but not moved into the queue yet */
+	queued_bytes:	AtomicUsize,
+	queued_frames:	AtomicUsize,
+	/* what the FrameQueue held when the reactor last touched it */
+	buffered:		AtomicUsize,
+}
+
+impl SendQueue {
+	pub fn new(capacity: usize) -> Self {
+		let (sender, receiver) = mpsc::channel();
+		SendQueue {
+			sender:			Mutex::new(sender),
+			receiver:		Mutex::new(receiver),
+			capacity:		capacity,
+			queued_bytes:	AtomicUsize::new(0),
+			queued_frames:	AtomicUsize::new(0),
+			buffered:		AtomicUsize::new(0),
+		}
+	}
+
+	pub fn capacity(&self) -> usize {
+		self.capacity
+	}
+
+	/* queued and buffered, may be a frame or two off while the reactor is draining */
+	pub fn pending(&self) -> usize {
+		self.queued_bytes.load(Ordering::SeqCst) + self.buffered.load(Ordering::SeqCst)
+	}
+
+	/* frames that haven't reached the FrameQueue yet */
+	pub fn queued_frames(&self) -> usize {
+		self.queued_frames.load(Ordering::SeqCst)
+	}
+
+	pub fn has_room(&self, bytes: usize) -> bool {
+		self.pending() + bytes <= self.capacity
+	}
+
+	pub fn push(&self, frame: OutFrame) {
+		self.queued_bytes.fetch_add(frame.len(), Ordering::SeqCst);
+		self.queued_frames.fetch_add(1, Ordering::SeqCst);
+		/* a clone per call would do as well, the lock is held for a channel push only */
+		let sender = match self.sender.lock() {
+			Ok(sender) => sender,
+			Err(poisoned) => poisoned.into_inner(),
+		};
+		/* the receiver lives as long as we do */
+		let _ = sender.send(frame);
+	}
+
+	/* moves everything pushed so far into `frames`. a frame that doesn't fit makes room by */
+	/* dropping the oldest unsent ones if `drop_oldest`, otherwise it is dropped itself. */
+	/* returns how many frames were lost either way */
+	pub fn drain_into(&self, frames: &mut FrameQueue, drop_oldest: bool) -> usize {
+		let receiver = match self.receiver.lock() {
+			Ok(receiver) => receiver,
+			Err(poisoned) => poisoned.into_inner(),
+		};
+		let mut dropped = 0;
+		while let Ok(frame) = receiver.try_recv() {
+			let len = frame.len();
+			self.queued_bytes.fetch_sub(len, Ordering::SeqCst);
+			self.queued_frames.fetch_sub(1, Ordering::SeqCst);
+			if len > frames.free() {
+				match if drop_oldest { frames.drop_oldest(len) } else { None } {
+					Some(count) => dropped += count,
+					None => {
+						dropped += 1;
+						continue;
+					}
+				}
+			}
+			/* can't fail, there's room now */
+			let _ = frames.push_frame(frame);
+		}
+		self.buffered_now(frames);
+		dropped
+	}
+
+	/* after anything else changed the queue, e.g. a write to the socket */
+	pub fn buffered_now(&self, frames: &FrameQueue) {
+		self.buffered.store(frames.bytes_remaining(), Ordering::SeqCst);
+	}
+}
@@ -0,0 +1,108 @@
+use std::fmt;
+use std::io::{Error, ErrorKind};
+use std::mem;
+
+use body::{InlineBytes, PacketBody, SharedBytes};
+use buffer::*;
+use opcodes::OpcodeName;
+use pool::BufferPool;
+
+pub struct FiestaPacket {
+	pub header:			u16,
+	pub data:			PacketBody,
+	/* where an owned `data` goes back to once the packet is dropped */
+	pool:				Option<BufferPool>,
+}
+
+impl FiestaPacket {
+	pub fn new(header: u16, size: usize) -> Self {
+		FiestaPacket {
+			header:			header,
+			data:			PacketBody::Owned(Buffer::with_capacity(size)),
+			pool:			None,
+		}
+	}
+
+	/* the body buffer is recycled when the packet is dropped */
+	pub fn from_pool(pool: &BufferPool, header: u16, size: usize) -> Self {
+		FiestaPacket {
+			header:			header,
+			data:			PacketBody::Owned(pool.get(size)),
+			pool:			Some(pool.clone()),
+		}
+	}
+
+	/* the body stays in the chunk it was read into, handlers that modify it get their own copy */
+	pub fn from_shared(header: u16, data: SharedBytes) -> Self {
+		FiestaPacket {
+			header:			header,
+			data:			PacketBody::Shared(data),
+			pool:			None,
+		}
+	}
+
+	pub fn from_inline(header: u16, data: InlineBytes) -> Self {
+		FiestaPacket {
+			header:			header,
+			data:			PacketBody::Inline(data),
+			pool:			None,
+		}
+	}
+}
+
+impl FiestaPacket {
+	/* size prefix, header and body, the way read_next_packet expects them. InvalidInput if the */
+	/* body is too big for the size prefix */
+	pub fn encode(header: u16, body: &[u8]) -> Result<Vec<u8>, Error> {
+		let size = body.len();
+		if size > 0xffff {
+			return Err(Error::new(ErrorKind::InvalidInput, "packet body doesn't fit in a frame"));
+		}
+		let mut frame = Vec::with_capacity(body.len() + 5);
+		if size > 0 && size < 0x100 {
+			frame.push(size as u8);
+		} else {
+			/* 0 marks the extended size */
+			frame.push(0);
+			frame.push((size >> 8) as u8);
+			frame.push(size as u8);
+		}
+		frame.push((header >> 8) as u8);
+		frame.push(header as u8);
+		frame.extend_from_slice(body);
+		Ok(frame)
+	}
+
+	/* for tests: the body as hex, see hexdump::parse_hex */
+	pub fn from_hex_str(header: u16, hex: &str) -> Result<Self, Error> {
+		Ok(FiestaPacket {
+			header:			header,
+			data:			PacketBody::Owned(try!(Buffer::from_hex_str(hex))),
+			pool:			None,
+		})
+	}
+}
+
+impl fmt::Debug for FiestaPacket {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "FiestaPacket {{ header: {:#06x}, length: {}, data: {:?} }}", self.header, self.data.bytes_remaining(), self.data)
+	}
+}
+
+/* header line followed by a hex dump of the body */
+impl fmt::Display for FiestaPacket {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		try!(writeln!(f, "packet {}, {} bytes", OpcodeName(self.header), self.data.bytes_remaining()));
+		write!(f, "{}", self.data)
+	}
+}
+
+impl Drop for FiestaPacket {
+	fn drop(&mut self) {
+		if let Some(pool) = self.pool.take() {
+			if let PacketBody::Owned(data) = mem::replace(&mut self.data, PacketBody::Owned(Buffer::with_capacity(0))) {
+				pool.put(data);
+			}
+		}
+	}
+}
@@ -0,0 +1,116 @@
+use std::convert::TryFrom;
+use std::error;
+use std::fmt;
+use std::io::Error;
+
+use body::SharedBytes;
+use buffer::{BinaryReadable, BufferError, Endianness};
+use packet::FiestaPacket;
+
+/* a packet layout from spec/packets.spec, the structs below are generated by build.rs, */
+/* along with ClientPacket and ServerPacket for matching on whatever came in */
+pub trait Packet: Sized {
+	const OPCODE: u16;
+	const NAME: &'static str;
+
+	/* the layout only, bytes after the last field are ignored, the client pads some packets */
+	fn decode_from(body: &mut SharedBytes) -> Result<Self, BufferError>;
+	fn encode_body(&self, body: &mut Vec<u8>);
+
+	/* the spec's max_len and range constraints */
+	fn validate(&self) -> Result<(), ValidationError> {
+		Ok(())
+	}
+
+	/* decoded and validated, so handlers only ever see packets the spec allows */
+	fn decode(body: &[u8]) -> Result<Self, DecodeError> {
+		let packet = try!(Self::decode_from(&mut SharedBytes::from_vec(body.to_vec()))
+			.map_err(|e| DecodeError::Malformed(Self::OPCODE, e)));
+		try!(packet.validate().map_err(|e| DecodeError::Invalid(Self::OPCODE, e)));
+		Ok(packet)
+	}
+
+	fn from_packet(packet: &FiestaPacket) -> Result<Self, DecodeError> {
+		Self::decode(&packet.data.to_vec()[..])
+	}
+
+	/* the whole frame, ready for append_send */
+	fn encode(&self) -> Result<Vec<u8>, Error> {
+		let mut body = Vec::new();
+		self.encode_body(&mut body);
+		FiestaPacket::encode(Self::OPCODE, &body[..])
+	}
+}
+
+/* what was wrong with a field */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Violation {
+	TooLong { max: usize, len: usize },
+	OutOfRange { min: i128, max: i128, value: i128 },
+}
+
+/* a decoded field the spec doesn't allow */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ValidationError {
+	pub field:			&'static str,
+	pub violation:		Violation,
+}
+
+impl fmt::Display for ValidationError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self.violation {
+			Violation::TooLong { max, len }				=> write!(f, "{} is {} long, at most {} allowed", self.field, len, max),
+			Violation::OutOfRange { min, max, value }	=> write!(f, "{} is {}, not in {}..={}", self.field, value, min, max),
+		}
+	}
+}
+
+/* why a FiestaPacket didn't turn into a Packet, a ClientPacket or a ServerPacket */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+	/* not in the spec, or not sent from that side */
+	UnknownOpcode(u16),
+	/* shorter than its layout */
+	Malformed(u16, BufferError),
+	/* fits the layout, breaks a constraint */
+	Invalid(u16, ValidationError),
+}
+
+impl fmt::Display for DecodeError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			DecodeError::UnknownOpcode(header)		=> write!(f, "no packet layout for opcode {:#06x}", header),
+			DecodeError::Malformed(header, ref e)	=> write!(f, "malformed packet {:#06x}: {}", header, e),
+			DecodeError::Invalid(header, ref e)		=> write!(f, "invalid packet {:#06x}: {}", header, e),
+		}
+	}
+}
+
+impl error::Error for DecodeError {
+	fn description(&self) -> &str {
+		match *self {
+			DecodeError::UnknownOpcode(_)	=> "unknown opcode",
+			DecodeError::Malformed(..)		=> "malformed packet",
+			DecodeError::Invalid(..)		=> "invalid packet",
+		}
+	}
+}
+
+fn read_string(body: &mut SharedBytes, size: usize) -> Result<String, BufferError> {
+	let bytes = try!(body.read_bytes(size));
+	let end = bytes.iter().position(|&b| b == 0).unwrap_or(size);
+	Ok(String::from_utf8_lossy(&bytes[..end]).into_owned())
+}
+
+fn put_uint(body: &mut Vec<u8>, value: u64, size: usize) {
+	body.extend((0..size).map(|i| (value >> (8 * i)) as u8));
+}
+
+/* cut off or zero padded to exactly `size` bytes */
+fn put_fixed(body: &mut Vec<u8>, bytes: &[u8], size: usize) {
+	let len = if bytes.len() < size { bytes.len() } else { size };
+	body.extend_from_slice(&bytes[..len]);
+	body.extend((len..size).map(|_| 0u8));
+}
+
+include!(concat!(env!("OUT_DIR"), "/packets.rs"));
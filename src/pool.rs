@@ -0,0 +1,79 @@
+use std::sync::{Arc, Mutex};
+
+use buffer::Buffer;
+
+/* packet bodies are rounded up to one of these, anything bigger isn't pooled */
+const MIN_CLASS_SIZE: usize = 64;
+const SIZE_CLASSES: usize = 7;		/* 64 .. 4096 */
+pub const DEFAULT_BUFFERS_PER_CLASS: usize = 1024;
+
+/* cheap to clone, all clones share the same free lists */
+#[derive(Clone)]
+pub struct BufferPool {
+	classes:			Arc<Vec<Mutex<Vec<Buffer>>>>,
+	max_per_class:		usize,
+}
+
+fn class_of(capacity: usize) -> Option<usize> {
+	let mut size = MIN_CLASS_SIZE;
+	for class in 0..SIZE_CLASSES {
+		if capacity <= size {
+			return Some(class);
+		}
+		size *= 2;
+	}
+	None
+}
+
+fn class_size(class: usize) -> usize {
+	MIN_CLASS_SIZE << class
+}
+
+impl BufferPool {
+	pub fn new(max_per_class: usize) -> Self {
+		BufferPool {
+			classes:			Arc::new((0..SIZE_CLASSES).map(|_| Mutex::new(Vec::new())).collect()),
+			max_per_class:		max_per_class,
+		}
+	}
+
+	/* an empty buffer that can hold at least `capacity` bytes */
+	pub fn get(&self, capacity: usize) -> Buffer {
+		match class_of(capacity) {
+			Some(class) => {
+				let recycled = match self.classes[class].lock() {
+					Ok(mut free) => free.pop(),
+					Err(_) => None,
+				};
+				recycled.unwrap_or_else(|| Buffer::with_capacity(class_size(class)))
+			},
+			None => Buffer::with_capacity(capacity),
+		}
+	}
+
+	pub fn put(&self, mut buffer: Buffer) {
+		let class = match class_of(buffer.capacity()) {
+			/* only buffers that came from us have exactly a class size */
+			Some(class) if class_size(class) == buffer.capacity() => class,
+			_ => return,
+		};
+
+		if let Ok(mut free) = self.classes[class].lock() {
+			if free.len() < self.max_per_class {
+				buffer.clear();
+				free.push(buffer);
+			}
+		}
+	}
+
+	/* number of idle buffers, for statistics */
+	pub fn idle(&self) -> usize {
+		self.classes.iter().map(|c| c.lock().map(|free| free.len()).unwrap_or(0)).sum()
+	}
+}
+
+impl Default for BufferPool {
+	fn default() -> Self {
+		BufferPool::new(DEFAULT_BUFFERS_PER_CLASS)
+	}
+}
@@ -0,0 +1,17 @@
+/* what a server built on this crate usually needs, `use fiesta_net::prelude::*;` */
+pub use buffer::{Buffer, BinaryReadable, BinaryPeekable};
+pub use error::{FiestaNetError, FiestaResult};
+pub use packet::FiestaPacket;
+#[cfg(feature = "server")]
+pub use handle::ClientHandle;
+#[cfg(feature = "server")]
+pub use client::DisconnectReason;
+#[cfg(feature = "server")]
+pub use processing::{PacketProcessor, PacketProcessingInfo, Router, Tick};
+#[cfg(feature = "server")]
+pub use server::{FiestaServerBuilder, FiestaServer, ServerHandle};
+
+/* the mio types the api takes and hands out, use these instead of depending on mio directly */
+/* so the versions can't drift apart */
+pub use mio::{Events, Interest, Poll, Registry, Token};
+pub use mio::net::{TcpListener, TcpStream};
@@ -0,0 +1,47 @@
+#[cfg(feature = "prometheus")]
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+use processing::Router;
+use protocol::{Keepalive, SeedHandshake};
+use server::FiestaServerBuilder;
+
+pub const LOGIN_SERVER_PORT: u16 = 9010;
+pub const ZONE_SERVER_PORT: u16 = 9210;
+/* /metrics with the prometheus feature, on localhost only, metrics_addr() moves it */
+pub const LOGIN_METRICS_PORT: u16 = 9110;
+pub const ZONE_METRICS_PORT: u16 = 9310;
+
+/* what every preset has: the seed goes out first, heartbeats are answered on the reactor. */
+/* with the crypto feature, keystream_table() on the result decrypts what clients send */
+fn preset(name: &str, port: u16) -> FiestaServerBuilder {
+	FiestaServerBuilder::new()
+		.name(name)
+		.port(port)
+		.seed_handshake(Some(SeedHandshake::new()))
+		.keepalive(Some(Keepalive::default()))
+}
+
+#[cfg(feature = "prometheus")]
+fn with_metrics(builder: FiestaServerBuilder, port: u16) -> FiestaServerBuilder {
+	builder.metrics_addr(SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), port))
+}
+
+#[cfg(not(feature = "prometheus"))]
+fn with_metrics(builder: FiestaServerBuilder, _port: u16) -> FiestaServerBuilder {
+	builder
+}
+
+/* login servers mostly sit idle, a couple of workers are enough */
+pub fn login_server() -> FiestaServerBuilder {
+	with_metrics(preset("login", LOGIN_SERVER_PORT).threads(2), LOGIN_METRICS_PORT)
+}
+
+pub fn zone_server() -> FiestaServerBuilder {
+	with_metrics(preset("zone", ZONE_SERVER_PORT).threads(8), ZONE_METRICS_PORT)
+}
+
+/* the processor for a preset: add routes, pass it to build(). unknown opcodes show up in the */
+/* server's metrics */
+pub fn router(server: &FiestaServerBuilder) -> Router {
+	Router::new().with_metrics(server.metrics())
+}
@@ -0,0 +1,81 @@
+use std::panic::{self, AssertUnwindSafe};
+
+use client::DisconnectReason;
+use handle::ClientHandle;
+use opcodes::OpcodeName;
+use processing::{PacketProcessor, PacketProcessingInfo, PanicPolicy, Tick};
+
+/* runs the processor right on the reactor thread, for services so small that handing every */
+/* packet to a worker costs more than processing it. a slow handler stalls the whole reactor */
+pub struct InlineProcessor {
+	processor:		Box<PacketProcessor>,
+	/* a fresh copy for when `processor` panics */
+	template:		Box<PacketProcessor>,
+	panic_policy:	PanicPolicy,
+}
+
+impl InlineProcessor {
+	pub fn new(processor: Box<PacketProcessor>) -> Self {
+		InlineProcessor {
+			template:		processor.clone(),
+			processor:		processor,
+			panic_policy:	PanicPolicy::KeepClient,
+		}
+	}
+
+	pub fn with_panic_policy(mut self, policy: PanicPolicy) -> Self {
+		self.panic_policy = policy;
+		self
+	}
+}
+
+impl PacketProcessor for InlineProcessor {
+	fn process_packet(&mut self, info: PacketProcessingInfo) {
+		let (client, header) = (info.client.clone(), Some(info.opcode()));
+
+		/* a panic must not take the reactor and all of its clients down with it */
+		let result = {
+			let processor = &mut self.processor;
+			panic::catch_unwind(AssertUnwindSafe(|| processor.process_packet(info)))
+		};
+		if result.is_err() {
+			warn!(target: "threading", "processor panicked on packet {:?} on the reactor thread, restarting it.",
+				header.map(|h| OpcodeName(h).to_string()));
+			self.processor = self.template.clone();
+			if self.panic_policy == PanicPolicy::Disconnect {
+				warn!(target: "threading", "disconnecting {} after the panic.", client.describe());
+				client.disconnect();
+			}
+		}
+	}
+
+	fn tick(&mut self, tick: Tick) {
+		let result = {
+			let processor = &mut self.processor;
+			panic::catch_unwind(AssertUnwindSafe(|| processor.tick(tick)))
+		};
+		if result.is_err() {
+			warn!(target: "threading", "processor panicked on tick {}, restarting it.", tick.number);
+			self.processor = self.template.clone();
+		}
+	}
+
+	fn on_disconnect(&mut self, client: &ClientHandle, reason: &DisconnectReason) {
+		let result = {
+			let processor = &mut self.processor;
+			panic::catch_unwind(AssertUnwindSafe(|| processor.on_disconnect(client, reason)))
+		};
+		if result.is_err() {
+			warn!(target: "threading", "processor panicked on the disconnect of {:?}, restarting it.", client.id());
+			self.processor = self.template.clone();
+		}
+	}
+
+	fn clone(&self) -> Box<PacketProcessor> {
+		Box::new(InlineProcessor {
+			processor:		self.template.clone(),
+			template:		self.template.clone(),
+			panic_policy:	self.panic_policy,
+		})
+	}
+}
@@ -0,0 +1,92 @@
+use std::sync::Arc;
+
+use client::DisconnectReason;
+use handle::ClientHandle;
+use super::packetproc::PacketProcessingInfo;
+use super::tick::Tick;
+use super::traits::PacketProcessor;
+
+/* runs around the processor, e.g. for decryption, logging or rate limiting */
+/* not calling `next.run(info)` drops the packet */
+pub trait Middleware: Send + Sync + 'static {
+	fn handle(&self, info: PacketProcessingInfo, next: Next);
+}
+
+impl<F> Middleware for F where F: Fn(PacketProcessingInfo, Next) + Send + Sync + 'static {
+	fn handle(&self, info: PacketProcessingInfo, next: Next) {
+		self(info, next)
+	}
+}
+
+/* the rest of the chain, ending with the processor */
+pub struct Next<'a> {
+	rest:			&'a [Box<Middleware>],
+	processor:		&'a mut Box<PacketProcessor>,
+}
+
+impl<'a> Next<'a> {
+	pub fn run(self, info: PacketProcessingInfo) {
+		match self.rest.split_first() {
+			Some((middleware, rest)) => middleware.handle(info, Next {
+				rest:			rest,
+				processor:		self.processor,
+			}),
+			None => self.processor.process_packet(info),
+		}
+	}
+}
+
+/* the first middleware added sees the packet first */
+pub struct MiddlewareChain {
+	middleware:		Arc<Vec<Box<Middleware>>>,
+	processor:		Box<PacketProcessor>,
+}
+
+impl MiddlewareChain {
+	pub fn new(processor: Box<PacketProcessor>) -> Self {
+		MiddlewareChain::from_parts(processor, Vec::new())
+	}
+
+	pub fn from_parts(processor: Box<PacketProcessor>, middleware: Vec<Box<Middleware>>) -> Self {
+		MiddlewareChain {
+			middleware:		Arc::new(middleware),
+			processor:		processor,
+		}
+	}
+
+	/* only before the chain is cloned, clones share their middleware */
+	pub fn with<M: Middleware>(mut self, middleware: M) -> Self {
+		if let Some(chain) = Arc::get_mut(&mut self.middleware) {
+			chain.push(Box::new(middleware));
+		} else {
+			warn!(target: "threading", "middleware chain is already shared, ignoring new middleware.");
+		}
+		self
+	}
+}
+
+impl PacketProcessor for MiddlewareChain {
+	fn process_packet(&mut self, info: PacketProcessingInfo) {
+		let next = Next {
+			rest:			&self.middleware[..],
+			processor:		&mut self.processor,
+		};
+		next.run(info);
+	}
+
+	/* ticks don't go through the middleware */
+	fn tick(&mut self, tick: Tick) {
+		self.processor.tick(tick);
+	}
+
+	fn on_disconnect(&mut self, client: &ClientHandle, reason: &DisconnectReason) {
+		self.processor.on_disconnect(client, reason);
+	}
+
+	fn clone(&self) -> Box<PacketProcessor> {
+		Box::new(MiddlewareChain {
+			middleware:		self.middleware.clone(),
+			processor:		self.processor.clone(),
+		})
+	}
+}
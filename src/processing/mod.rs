@@ -1,6 +1,15 @@
 // TMP
+mod inline;
+mod middleware;
 mod packetproc;
+#[cfg(feature = "rayon")]
+mod rayon_pool;
+mod reply;
+mod router;
+mod tick;
 mod traits;
+#[cfg(feature = "threads")]
+mod workers;
 
 
 
@@ -8,8 +17,43 @@ mod traits;
 pub use self::traits::{
 	PacketProcessor,
 };
+pub use self::inline::{
+	InlineProcessor,
+};
+pub use self::middleware::{
+	Middleware,
+	MiddlewareChain,
+	Next,
+};
+pub use self::reply::{
+	Outgoing,
+	ReplyHandler,
+	ReplyProcessor,
+	Target,
+	deliver,
+};
+pub use self::router::{
+	DeadLetter,
+	DisconnectHook,
+	Route,
+	Router,
+	TickHook,
+};
+pub use self::tick::{
+	Tick,
+	TickClock,
+};
+#[cfg(feature = "rayon")]
+pub use self::rayon_pool::{
+	RayonProcessingPool,
+};
 // TMP
 pub use self::packetproc::{
-	PacketProcessingThreadPool,
+	Dispatch,
+	OverflowPolicy,
+	PanicPolicy,
+	QueueLimit,
 	PacketProcessingInfo,
-};
\ No newline at end of file
+};
+#[cfg(feature = "threads")]
+pub use self::workers::PacketProcessingThreadPool;
\ No newline at end of file
@@ -1,89 +1,123 @@
-use std::thread::{JoinHandle, Builder};
+use std::time::Duration;
 use std::sync::{Arc, RwLock};
-use chan::{Receiver, Sender, async};
-use client;
-use client::*;
+use mio::Token;
+use client::FiestaNetworkClient;
+use handle::ClientHandle;
+use packet::FiestaPacket;
+#[cfg(feature = "spans")]
+use spans;
+#[cfg(feature = "spans")]
+use tracing::Span;
 
-pub trait PacketProcessor: Send + 'static {
-	fn process_packet(&mut self, info: Arc<RwLock<Box<PacketProcessingInfo>>>);
-	fn clone(&self) -> Box<PacketProcessor>;
+/* how packets are spread over the workers */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dispatch {
+	/* any idle worker takes the next packet, packets of one client may be processed out of order */
+	Shared,
+	/* every client is pinned to one worker, its packets are processed in the order they arrived */
+	PerClient,
 }
 
-pub struct PacketProcessingThreadPool {
-	thread_handles:					Arc<RwLock<Vec<JoinHandle<()>>>>,
-	packet_receiver:				Receiver<Arc<RwLock<Box<PacketProcessingInfo>>>>,
-	packet_sender:					Sender<Arc<RwLock<Box<PacketProcessingInfo>>>>,
-	processor:						Box<PacketProcessor>,
+/* what happens to the client whose packet made a processor panic */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PanicPolicy {
+	KeepClient,
+	Disconnect,
 }
 
-pub struct PacketProcessingInfo {
-	pub packet:			Arc<RwLock<FiestaPacket>>,
-	pub client:			Arc<RwLock<Box<FiestaNetworkClient>>>,
+/* what the reactor does with a packet when its worker queue is full */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+	/* wait this long for room, then drop the packet */
+	Block(Duration),
+	Drop,
+	/* drop the packet and disconnect the client that sent it */
+	Disconnect,
 }
 
-impl PacketProcessingInfo {
-	pub fn new(packet: FiestaPacket, client: Arc<RwLock<Box<FiestaNetworkClient>>>) -> Self {
-		PacketProcessingInfo {
-			packet:		Arc::new(RwLock::new(packet)),
-			client:		client.clone(),
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueueLimit {
+	pub capacity:		usize,
+	pub overflow:		OverflowPolicy,
+}
+
+impl Default for QueueLimit {
+	fn default() -> Self {
+		QueueLimit {
+			capacity:		16 * 1024,
+			overflow:		OverflowPolicy::Block(Duration::from_millis(10)),
 		}
 	}
 }
 
-// a test
-// unsafe impl Send for PacketProcessingInfo { }
+/* counts a packet out of its client's in flight packets once the processor is done with it */
+struct InFlight {
+	client:			ClientHandle,
+}
 
-impl PacketProcessingThreadPool {
-	pub fn new(threads: usize, processor: Box<PacketProcessor>) -> PacketProcessingThreadPool {
-		let (s, r) = async();
+impl Drop for InFlight {
+	fn drop(&mut self) {
+		if let Some(client) = self.client.client() {
+			match client.read() {
+				Ok(client) => client.packet_processed(),
+				Err(poisoned) => poisoned.into_inner().packet_processed(),
+			}
+		}
+	}
+}
 
-		let mut result = PacketProcessingThreadPool {
-			thread_handles:				Arc::new(RwLock::new(Vec::with_capacity(threads))),
-			packet_receiver:			r,
-			packet_sender:				s,
-			processor:					processor.clone(),
-		};
-		for i in 0..threads {
-			result.start_new_thread(i);
-			debug!(target: "threading", "started packet processing thread {}", i);
-		};
+/* one packet on its way to a processor, passed along by value. everything in it is owned or */
+/* shared through a ClientHandle, so it goes to any worker thread as it is */
+pub struct PacketProcessingInfo {
+	/* the client's, known without locking it, e.g. to pick a worker */
+	pub token:			Token,
+	/* the opcode as the processors see it and the body */
+	pub packet:			FiestaPacket,
+	pub client:			ClientHandle,
+	/* child of the client's connection span */
+	#[cfg(feature = "spans")]
+	pub span:			Span,
+	/* only on a packet that was counted in flight, see counted() */
+	in_flight:			Option<InFlight>,
+}
 
-		result
+impl PacketProcessingInfo {
+	pub fn new(packet: FiestaPacket, client: Arc<RwLock<Box<FiestaNetworkClient>>>) -> Self {
+		#[cfg(feature = "spans")]
+		let span = match client.read() {
+			Ok(guard) => spans::packet_span(guard.span(), packet.header, packet.data.bytes_remaining()),
+			Err(_) => Span::none(),
+		};
+		let client = ClientHandle::new(client);
+		PacketProcessingInfo {
+			token:		client.id(),
+			packet:		packet,
+			client:		client,
+			#[cfg(feature = "spans")]
+			span:		span,
+			in_flight:	None,
+		}
 	}
 
-	pub fn start_new_thread(&mut self, id: usize) {
-		let rec = self.packet_receiver.clone();
-		let mut processor = self.processor.clone();
-
-		let handle = Builder::new()
-			.name(format!("WRKR {}", id))
-			.spawn(move || {
-				for packet in rec.iter() {
-					processor.process_packet(packet);
-				}
-			}).unwrap();
-		let mut handles = self.thread_handles.write().unwrap();
-		handles.push(handle);
+	/* for a packet its client already counted in flight, e.g. with packets_dispatched(). it is */
+	/* counted out again when the info is dropped, whichever thread that happens on */
+	pub fn counted(mut self) -> Self {
+		self.in_flight = Some(InFlight { client: self.client.clone() });
+		self
 	}
-}
 
-impl Clone for PacketProcessingThreadPool {
-	fn clone(&self) -> Self {
-		PacketProcessingThreadPool {
-			thread_handles:			self.thread_handles.clone(),
-			packet_receiver:		self.packet_receiver.clone(),
-			packet_sender:			self.packet_sender.clone(),
-			processor:				self.processor.clone(),
+	/* counts the packet in flight and out again once it's dropped, for packets dispatched outside */
+	/* the reactor, e.g. by MockClient */
+	pub fn dispatched(packet: FiestaPacket, client: Arc<RwLock<Box<FiestaNetworkClient>>>) -> Self {
+		match client.read() {
+			Ok(guard) => guard.packet_in_flight(),
+			Err(poisoned) => poisoned.into_inner().packet_in_flight(),
 		}
+		PacketProcessingInfo::new(packet, client).counted()
 	}
-} 
 
-impl PacketProcessor for PacketProcessingThreadPool {
-	fn process_packet(&mut self, info: Arc<RwLock<Box<PacketProcessingInfo>>>) {
-		self.packet_sender.send(info);
+	pub fn opcode(&self) -> u16 {
+		self.packet.header
 	}
+}
 
-	fn clone(&self) -> Box<PacketProcessor> {
-		Box::new(<PacketProcessingThreadPool as Clone>::clone(&self))
-	}
-}
\ No newline at end of file
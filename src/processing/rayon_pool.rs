@@ -0,0 +1,146 @@
+use std::io::{Error, ErrorKind};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use rayon::{ThreadPool, ThreadPoolBuilder};
+
+use client::DisconnectReason;
+use error::{FiestaNetError, FiestaResult};
+use handle::ClientHandle;
+use opcodes::OpcodeName;
+
+use super::packetproc::{PacketProcessingInfo, PanicPolicy};
+use super::tick::Tick;
+use super::traits::PacketProcessor;
+
+/* a drop-in for PacketProcessingThreadPool on rayon's work-stealing scheduler, for services */
+/* whose handlers range from trivial to very slow. idle workers steal queued packets from busy */
+/* ones, so one slow packet doesn't hold up the ones queued behind it. packets of one client */
+/* may be processed out of order, there is no per client dispatch */
+pub struct RayonProcessingPool {
+	pool:			Arc<ThreadPool>,
+	/* one processor per rayon worker, only ever locked by that worker */
+	processors:		Arc<Vec<Mutex<Box<PacketProcessor>>>>,
+	template:		Arc<Mutex<Box<PacketProcessor>>>,
+	panic_policy:	Arc<RwLock<PanicPolicy>>,
+	/* spawned and not finished yet */
+	queued:			Arc<AtomicUsize>,
+}
+
+impl RayonProcessingPool {
+	pub fn new(threads: usize, processor: Box<PacketProcessor>) -> FiestaResult<RayonProcessingPool> {
+		if threads == 0 {
+			return Err(FiestaNetError::from(Error::new(ErrorKind::InvalidInput, "a pool needs at least one worker thread")));
+		}
+		let pool = try!(ThreadPoolBuilder::new()
+			.num_threads(threads)
+			.thread_name(|id| format!("RAYN {}", id))
+			.build()
+			.map_err(|e| Error::new(ErrorKind::Other, e.to_string())));
+		debug!(target: "threading", "started rayon processing pool with {} threads", threads);
+
+		Ok(RayonProcessingPool {
+			pool:			Arc::new(pool),
+			processors:		Arc::new((0..threads).map(|_| Mutex::new(processor.clone())).collect()),
+			template:		Arc::new(Mutex::new(processor)),
+			panic_policy:	Arc::new(RwLock::new(PanicPolicy::KeepClient)),
+			queued:			Arc::new(AtomicUsize::new(0)),
+		})
+	}
+
+	pub fn size(&self) -> usize {
+		self.pool.current_num_threads()
+	}
+
+	/* packets and ticks waiting for or being processed */
+	pub fn queued(&self) -> usize {
+		self.queued.load(Ordering::SeqCst)
+	}
+
+	/* applies to all workers, including the running ones */
+	pub fn set_panic_policy(&self, policy: PanicPolicy) -> FiestaResult<()> {
+		*try!(self.panic_policy.write()) = policy;
+		Ok(())
+	}
+
+	/* runs `job` with the processor of the worker it landed on. if it panics the processor is */
+	/* replaced and `on_panic` gets the policy */
+	fn spawn<F, P>(&self, job: F, on_panic: P)
+		where F: FnOnce(&mut Box<PacketProcessor>) + Send + 'static, P: FnOnce(PanicPolicy) + Send + 'static {
+		let processors = self.processors.clone();
+		let template = self.template.clone();
+		let panic_policy = self.panic_policy.clone();
+		let queued = self.queued.clone();
+		queued.fetch_add(1, Ordering::SeqCst);
+		self.pool.spawn(move || {
+			/* always Some on one of our own workers */
+			let index = ::rayon::current_thread_index().unwrap_or(0);
+			let mut processor = match processors[index].lock() {
+				Ok(processor) => processor,
+				Err(poisoned) => poisoned.into_inner(),
+			};
+			let result = {
+				let processor = &mut *processor;
+				panic::catch_unwind(AssertUnwindSafe(|| job(processor)))
+			};
+			if result.is_err() {
+				/* the old processor may have been left half way through an update */
+				if let Ok(template) = template.lock() {
+					*processor = template.clone();
+				}
+				on_panic(panic_policy.read().map(|policy| *policy).unwrap_or(PanicPolicy::KeepClient));
+			}
+			queued.fetch_sub(1, Ordering::SeqCst);
+		});
+	}
+}
+
+impl Clone for RayonProcessingPool {
+	fn clone(&self) -> Self {
+		RayonProcessingPool {
+			pool:			self.pool.clone(),
+			processors:		self.processors.clone(),
+			template:		self.template.clone(),
+			panic_policy:	self.panic_policy.clone(),
+			queued:			self.queued.clone(),
+		}
+	}
+}
+
+impl PacketProcessor for RayonProcessingPool {
+	fn process_packet(&mut self, info: PacketProcessingInfo) {
+		let (client, header) = (info.client.clone(), Some(info.opcode()));
+		self.spawn(move |processor| processor.process_packet(info), move |policy| {
+			warn!(target: "threading", "processor panicked on packet {:?} in worker {:?}, restarting it.",
+				header.map(|h| OpcodeName(h).to_string()), ::rayon::current_thread_index());
+			if policy == PanicPolicy::Disconnect {
+				warn!(target: "threading", "disconnecting {} after the panic.", client.describe());
+				client.disconnect();
+			}
+		});
+	}
+
+	/* one tick is one world update, it runs on whichever worker is free first */
+	fn tick(&mut self, tick: Tick) {
+		self.spawn(move |processor| processor.tick(tick), move |_| {
+			warn!(target: "threading", "processor panicked on tick {}, restarting it.", tick.number);
+		});
+	}
+
+	fn on_disconnect(&mut self, client: &ClientHandle, reason: &DisconnectReason) {
+		let (client, reason) = (client.clone(), reason.clone());
+		let id = client.id();
+		/* the handle alone wouldn't keep the client around until the processor saw it */
+		let keep = client.client();
+		self.spawn(move |processor| {
+			processor.on_disconnect(&client, &reason);
+			drop(keep);
+		}, move |_| {
+			warn!(target: "threading", "processor panicked on the disconnect of {:?}, restarting it.", id);
+		});
+	}
+
+	fn clone(&self) -> Box<PacketProcessor> {
+		Box::new(<RayonProcessingPool as Clone>::clone(&self))
+	}
+}
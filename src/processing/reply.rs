@@ -0,0 +1,96 @@
+use std::sync::Arc;
+
+use bus::ClientGroup;
+use handle::ClientHandle;
+use opcodes::OpcodeName;
+use packet::FiestaPacket;
+use super::packetproc::PacketProcessingInfo;
+use super::traits::PacketProcessor;
+
+/* who an Outgoing packet is for */
+pub enum Target {
+	/* the client whose packet is being handled */
+	Sender,
+	Client(ClientHandle),
+	Group(ClientGroup),
+}
+
+/* a packet a ReplyHandler wants sent, with the canonical opcode like ClientHandle::send */
+pub struct Outgoing {
+	pub target:			Target,
+	pub packet:			FiestaPacket,
+}
+
+impl Outgoing {
+	pub fn reply(packet: FiestaPacket) -> Self {
+		Outgoing { target: Target::Sender, packet: packet }
+	}
+
+	pub fn to(client: ClientHandle, packet: FiestaPacket) -> Self {
+		Outgoing { target: Target::Client(client), packet: packet }
+	}
+
+	pub fn group(group: ClientGroup, packet: FiestaPacket) -> Self {
+		Outgoing { target: Target::Group(group), packet: packet }
+	}
+}
+
+/* a handler that says what to send instead of sending it, so it can be tested by looking at */
+/* what it returns. the packets go out in order once it is done */
+pub trait ReplyHandler: Send + Sync + 'static {
+	fn handle(&self, packet: &FiestaPacket, client: &ClientHandle) -> Vec<Outgoing>;
+}
+
+impl<F> ReplyHandler for F where F: Fn(&FiestaPacket, &ClientHandle) -> Vec<Outgoing> + Send + Sync + 'static {
+	fn handle(&self, packet: &FiestaPacket, client: &ClientHandle) -> Vec<Outgoing> {
+		self(packet, client)
+	}
+}
+
+/* sends what a ReplyHandler returned. a client that is gone by now is skipped, like any send */
+pub fn deliver(outgoing: Vec<Outgoing>, sender: &ClientHandle) {
+	for outgoing in outgoing.into_iter() {
+		let result = match outgoing.target {
+			Target::Sender => sender.send(&outgoing.packet),
+			Target::Client(ref client) => client.send(&outgoing.packet),
+			Target::Group(ref group) => {
+				group.send(&outgoing.packet);
+				Ok(())
+			},
+		};
+		if let Err(e) = result {
+			debug!(target: "network", "{:?}: reply {} not sent: {}", sender.id(), OpcodeName(outgoing.packet.header), e);
+		}
+	}
+}
+
+/* runs a ReplyHandler on every packet, e.g. as the server's processor or behind a Router route */
+pub struct ReplyProcessor {
+	handler:		Arc<ReplyHandler>,
+}
+
+impl ReplyProcessor {
+	pub fn new<H: ReplyHandler>(handler: H) -> Self {
+		ReplyProcessor {
+			handler:		Arc::new(handler),
+		}
+	}
+}
+
+/* what a Router route does with a ReplyHandler */
+pub fn handle_with(handler: &ReplyHandler, info: PacketProcessingInfo) {
+	let outgoing = handler.handle(&info.packet, &info.client);
+	deliver(outgoing, &info.client);
+}
+
+impl PacketProcessor for ReplyProcessor {
+	fn process_packet(&mut self, info: PacketProcessingInfo) {
+		handle_with(&*self.handler, info);
+	}
+
+	fn clone(&self) -> Box<PacketProcessor> {
+		Box::new(ReplyProcessor {
+			handler:		self.handler.clone(),
+		})
+	}
+}
@@ -0,0 +1,150 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use client::DisconnectReason;
+use packet::FiestaPacket;
+use handle::ClientHandle;
+use metrics::Metrics;
+use opcodes::OpcodeName;
+use super::packetproc::PacketProcessingInfo;
+use super::reply::{handle_with, ReplyHandler};
+use super::tick::Tick;
+use super::traits::PacketProcessor;
+
+pub type Route = Fn(PacketProcessingInfo) + Send + Sync;
+pub type DeadLetter = Fn(&FiestaPacket, &ClientHandle) + Send + Sync;
+pub type TickHook = Fn(Tick) + Send + Sync;
+pub type DisconnectHook = Fn(&ClientHandle, &DisconnectReason) + Send + Sync;
+
+/* dispatches on the opcode, packets nobody handles go to the dead letter hook */
+pub struct Router {
+	routes:			Arc<HashMap<u16, Arc<Route>>>,
+	dead_letter:	Option<Arc<DeadLetter>>,
+	on_tick:		Option<Arc<TickHook>>,
+	on_disconnect:	Option<Arc<DisconnectHook>>,
+	/* drop clients that send an opcode without a route */
+	strict:			bool,
+	unknown:		Arc<AtomicUsize>,
+	metrics:		Option<Arc<Metrics>>,
+}
+
+impl Router {
+	pub fn new() -> Self {
+		Router {
+			routes:			Arc::new(HashMap::new()),
+			dead_letter:	None,
+			on_tick:		None,
+			on_disconnect:	None,
+			strict:			false,
+			unknown:		Arc::new(AtomicUsize::new(0)),
+			metrics:		None,
+		}
+	}
+
+	/* only before the router is cloned, clones share their routes */
+	pub fn route<F>(mut self, header: u16, route: F) -> Self
+			where F: Fn(PacketProcessingInfo) + Send + Sync + 'static {
+		if let Some(routes) = Arc::get_mut(&mut self.routes) {
+			routes.insert(header, Arc::new(route));
+		} else {
+			warn!(target: "threading", "router is already shared, ignoring route for {:#06x}.", header);
+		}
+		self
+	}
+
+	/* a route that returns what to send instead of sending it, see ReplyHandler */
+	pub fn reply<H: ReplyHandler>(self, header: u16, handler: H) -> Self {
+		let handler = Arc::new(handler);
+		self.route(header, move |info| handle_with(&*handler, info))
+	}
+
+	/* sees every packet without a route, e.g. to log or capture it for protocol research */
+	pub fn dead_letter<F>(mut self, hook: F) -> Self where F: Fn(&FiestaPacket, &ClientHandle) + Send + Sync + 'static {
+		self.dead_letter = Some(Arc::new(hook));
+		self
+	}
+
+	/* the world update, see FiestaServerBuilder::tick */
+	pub fn on_tick<F>(mut self, hook: F) -> Self where F: Fn(Tick) + Send + Sync + 'static {
+		self.on_tick = Some(Arc::new(hook));
+		self
+	}
+
+	/* e.g. to take the character out of the world, see PacketProcessor::on_disconnect */
+	pub fn on_disconnect<F>(mut self, hook: F) -> Self where F: Fn(&ClientHandle, &DisconnectReason) + Send + Sync + 'static {
+		self.on_disconnect = Some(Arc::new(hook));
+		self
+	}
+
+	pub fn strict(mut self, strict: bool) -> Self {
+		self.strict = strict;
+		self
+	}
+
+	/* counts unknown opcodes into the server's metrics too */
+	pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+		self.metrics = Some(metrics);
+		self
+	}
+
+	/* packets without a route so far, over all clones */
+	pub fn unknown_count(&self) -> usize {
+		self.unknown.load(Ordering::Relaxed)
+	}
+
+	fn unknown_opcode(&self, info: PacketProcessingInfo) {
+		self.unknown.fetch_add(1, Ordering::Relaxed);
+		if let Some(ref metrics) = self.metrics {
+			metrics.unknown_opcode();
+		}
+		let (client, packet) = (&info.client, &info.packet);
+		debug!(target: "network", "{:?}: no route for packet {}.", info.token, OpcodeName(packet.header));
+		if let Some(ref hook) = self.dead_letter {
+			hook(packet, client);
+		}
+		if self.strict {
+			warn!(target: "network", "{:?}: dropping client for unknown packet {}.", info.token, OpcodeName(packet.header));
+			client.disconnect();
+		}
+	}
+}
+
+impl Default for Router {
+	fn default() -> Self {
+		Router::new()
+	}
+}
+
+impl PacketProcessor for Router {
+	fn process_packet(&mut self, info: PacketProcessingInfo) {
+		match self.routes.get(&info.opcode()).cloned() {
+			Some(route) => route(info),
+			None => self.unknown_opcode(info),
+		}
+	}
+
+	fn tick(&mut self, tick: Tick) {
+		if let Some(ref hook) = self.on_tick {
+			hook(tick);
+		}
+	}
+
+	fn on_disconnect(&mut self, client: &ClientHandle, reason: &DisconnectReason) {
+		if let Some(ref hook) = self.on_disconnect {
+			hook(client, reason);
+		}
+	}
+
+	fn clone(&self) -> Box<PacketProcessor> {
+		Box::new(Router {
+			routes:			self.routes.clone(),
+			dead_letter:	self.dead_letter.clone(),
+			on_tick:		self.on_tick.clone(),
+			on_disconnect:	self.on_disconnect.clone(),
+			strict:			self.strict,
+			unknown:		self.unknown.clone(),
+			metrics:		self.metrics.clone(),
+		})
+	}
+}
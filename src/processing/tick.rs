@@ -0,0 +1,60 @@
+use std::time::{Duration, Instant};
+
+/* handed to PacketProcessor::tick() every interval, see FiestaServerBuilder::tick */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tick {
+	/* counts up from 0, skipped ticks included */
+	pub number:			u64,
+	/* when the tick was due, not when it ran */
+	pub scheduled:		Instant,
+	pub interval:		Duration,
+	/* ticks dropped right before this one because the reactor fell behind */
+	pub skipped:		u64,
+}
+
+/* keeps ticks on a fixed grid: the next one is due an interval after the last one was due, */
+/* not after it ran, so one late tick doesn't push back all the ones after it */
+pub struct TickClock {
+	interval:		Duration,
+	next:			Instant,
+	number:			u64,
+}
+
+impl TickClock {
+	pub fn new(interval: Duration) -> Self {
+		/* a zero interval would never let the reactor poll */
+		let interval = if interval < Duration::from_millis(1) { Duration::from_millis(1) } else { interval };
+		TickClock {
+			interval:		interval,
+			next:			Instant::now() + interval,
+			number:			0,
+		}
+	}
+
+	pub fn next_deadline(&self) -> Instant {
+		self.next
+	}
+
+	/* the tick that is due at `now`, None if it's early. ticks that were missed completely */
+	/* are skipped instead of run back to back, the game only cares about the current state */
+	pub fn advance(&mut self, now: Instant) -> Option<Tick> {
+		if now < self.next {
+			return None;
+		}
+		let mut skipped = 0;
+		while self.next + self.interval <= now {
+			self.next += self.interval;
+			self.number += 1;
+			skipped += 1;
+		}
+		let tick = Tick {
+			number:			self.number,
+			scheduled:		self.next,
+			interval:		self.interval,
+			skipped:		skipped,
+		};
+		self.next += self.interval;
+		self.number += 1;
+		Some(tick)
+	}
+}
@@ -1,12 +1,18 @@
-use std::sync::{ 
-	Arc, 
-	Mutex, 
-	RwLock
-};
-
+use client::DisconnectReason;
+use handle::ClientHandle;
 use super::packetproc::*;
+use super::tick::Tick;
 
 pub trait PacketProcessor: Send + 'static {
-	fn process_packet(&mut self, info: Arc<RwLock<Box<PacketProcessingInfo>>>);
+	fn process_packet(&mut self, info: PacketProcessingInfo);
 	fn clone(&self) -> Box<PacketProcessor>;
+
+	/* the world update, only called with FiestaServerBuilder::tick. with the thread pool */
+	/* it runs on one worker at a time, ahead of any queued packets */
+	fn tick(&mut self, tick: Tick) {
+	}
+
+	/* the client is gone, the thread pool queues this behind the packets it still has */
+	fn on_disconnect(&mut self, client: &ClientHandle, reason: &DisconnectReason) {
+	}
 }
\ No newline at end of file
@@ -0,0 +1,399 @@
+use std::thread;
+use std::thread::{JoinHandle, Builder};
+use std::time::{Duration, Instant};
+use std::panic;
+use std::panic::AssertUnwindSafe;
+use std::io::{Error, ErrorKind};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::{Arc, Condvar, Mutex, RwLock};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use client::{DisconnectReason, FiestaNetworkClient};
+use error::{FiestaNetError, FiestaResult};
+use handle::ClientHandle;
+use metrics::Metrics;
+use opcodes::OpcodeName;
+
+use super::packetproc::*;
+use super::tick::Tick;
+use super::traits::PacketProcessor;
+
+/* read by the running workers, so it lives behind a lock shared with every clone of the pool */
+#[derive(Clone)]
+struct WorkerSettings {
+	panic_policy:		PanicPolicy,
+	/* process_packet calls taking longer than this are logged */
+	slow_budget:		Option<Duration>,
+	metrics:			Option<Arc<Metrics>>,
+}
+
+/* ticks go into the top lane, the world update shouldn't wait behind a burst of packets */
+const TICK_PRIORITY: u8 = ::std::u8::MAX;
+
+enum Job {
+	Packet(PacketProcessingInfo),
+	Tick(Tick),
+	/* the Arc keeps the client around until the processor saw it, the handle alone wouldn't */
+	Disconnect(ClientHandle, DisconnectReason, Option<Arc<RwLock<Box<FiestaNetworkClient>>>>),
+	/* the worker that takes this exits, used to shrink the pool */
+	Retire,
+}
+
+/* one FIFO lane per priority, workers always empty the highest lane first */
+struct Lanes {
+	lanes:				Mutex<BTreeMap<u8, VecDeque<Job>>>,
+	ready:				Condvar,
+}
+
+impl Lanes {
+	fn push(&self, priority: u8, job: Job) {
+		let mut lanes = match self.lanes.lock() {
+			Ok(lanes) => lanes,
+			Err(poisoned) => poisoned.into_inner(),
+		};
+		lanes.entry(priority).or_insert_with(VecDeque::new).push_back(job);
+		self.ready.notify_one();
+	}
+
+	/* blocks until there is a job */
+	fn pop(&self) -> Job {
+		let mut lanes = match self.lanes.lock() {
+			Ok(lanes) => lanes,
+			Err(poisoned) => poisoned.into_inner(),
+		};
+		loop {
+			let job = match lanes.iter_mut().rev().find(|&(_, ref lane)| !lane.is_empty()) {
+				Some((_, lane)) => lane.pop_front(),
+				None => None,
+			};
+			if let Some(job) = job {
+				return job;
+			}
+			lanes = match self.ready.wait(lanes) {
+				Ok(lanes) => lanes,
+				Err(poisoned) => poisoned.into_inner(),
+			};
+		}
+	}
+}
+
+struct Queue {
+	jobs:							Arc<Lanes>,
+	/* packets sent but not yet taken by a worker */
+	len:							Arc<AtomicUsize>,
+}
+
+impl Queue {
+	fn new() -> Self {
+		Queue {
+			jobs:			Arc::new(Lanes { lanes: Mutex::new(BTreeMap::new()), ready: Condvar::new() }),
+			len:			Arc::new(AtomicUsize::new(0)),
+		}
+	}
+
+	fn has_room(&self, limit: &QueueLimit) -> bool {
+		let timeout = match limit.overflow {
+			OverflowPolicy::Block(timeout)	=> timeout,
+			_								=> Duration::from_millis(0),
+		};
+
+		let start = Instant::now();
+		loop {
+			if self.len.load(Ordering::SeqCst) < limit.capacity {
+				return true;
+			}
+			if start.elapsed() >= timeout {
+				return false;
+			}
+			thread::sleep(Duration::from_millis(1));
+		}
+	}
+}
+
+pub struct PacketProcessingThreadPool {
+	thread_handles:					Arc<RwLock<Vec<JoinHandle<()>>>>,
+	/* a single queue all workers take from, or one per worker with Dispatch::PerClient */
+	queues:							Arc<RwLock<Vec<Queue>>>,
+	dispatch:						Dispatch,
+	settings:						Arc<RwLock<WorkerSettings>>,
+	queue_limit:					QueueLimit,
+	/* header -> priority, higher goes first, everything else is 0 */
+	priorities:						Arc<HashMap<u16, u8>>,
+	/* number of workers, not counting retired ones that are still finishing their queue */
+	size:							Arc<AtomicUsize>,
+	/* only for thread names */
+	next_id:						Arc<AtomicUsize>,
+	processor:						Box<PacketProcessor>,
+}
+
+impl PacketProcessingThreadPool {
+	pub fn new(threads: usize, processor: Box<PacketProcessor>) -> FiestaResult<PacketProcessingThreadPool> {
+		PacketProcessingThreadPool::with_dispatch(threads, processor, Dispatch::Shared)
+	}
+
+	pub fn with_dispatch(threads: usize, processor: Box<PacketProcessor>, dispatch: Dispatch) -> FiestaResult<PacketProcessingThreadPool> {
+		let queue_count = match dispatch {
+			Dispatch::Shared	=> 1,
+			Dispatch::PerClient	=> threads,
+		};
+		let queues = (0..queue_count).map(|_| Queue::new()).collect();
+
+		let mut result = PacketProcessingThreadPool {
+			thread_handles:				Arc::new(RwLock::new(Vec::with_capacity(threads))),
+			queues:						Arc::new(RwLock::new(queues)),
+			dispatch:					dispatch,
+			settings:					Arc::new(RwLock::new(WorkerSettings {
+				panic_policy:			PanicPolicy::KeepClient,
+				slow_budget:			None,
+				metrics:				None,
+			})),
+			queue_limit:				QueueLimit::default(),
+			priorities:					Arc::new(HashMap::new()),
+			size:						Arc::new(AtomicUsize::new(threads)),
+			next_id:					Arc::new(AtomicUsize::new(threads)),
+			processor:					processor.clone(),
+		};
+		for i in 0..threads {
+			try!(result.start_new_thread(i));
+			debug!(target: "threading", "started packet processing thread {}", i);
+		};
+
+		Ok(result)
+	}
+
+	pub fn dispatch(&self) -> Dispatch {
+		self.dispatch
+	}
+
+	pub fn size(&self) -> usize {
+		self.size.load(Ordering::SeqCst)
+	}
+
+	/* packets waiting for a worker, over all queues */
+	pub fn queued(&self) -> usize {
+		self.queues.read().map(|queues| queues.iter().map(|queue| queue.len.load(Ordering::SeqCst)).sum()).unwrap_or(0)
+	}
+
+	/* adds workers or retires the surplus ones once they get to their poison message */
+	/* with Dispatch::PerClient some clients move to another worker, their packets may briefly overlap */
+	pub fn resize(&mut self, threads: usize) -> FiestaResult<()> {
+		if threads == 0 {
+			return Err(FiestaNetError::from(Error::new(ErrorKind::InvalidInput, "a pool needs at least one worker thread")));
+		}
+
+		let current = self.size();
+		if threads > current {
+			for i in current..threads {
+				let id = match self.dispatch {
+					Dispatch::Shared	=> self.next_id.fetch_add(1, Ordering::SeqCst),
+					Dispatch::PerClient	=> {
+						try!(self.queues.write()).push(Queue::new());
+						i
+					},
+				};
+				try!(self.start_new_thread(id));
+			}
+		} else {
+			let mut queues = try!(self.queues.write());
+			match self.dispatch {
+				Dispatch::Shared => {
+					for _ in threads..current {
+						queues[0].jobs.push(0, Job::Retire);
+					}
+				},
+				Dispatch::PerClient => {
+					/* what's already queued is still processed before the retirement */
+					for queue in queues.split_off(threads).into_iter() {
+						queue.jobs.push(0, Job::Retire);
+					}
+				},
+			}
+		}
+
+		self.size.store(threads, Ordering::SeqCst);
+		info!(target: "threading", "resized packet processing pool from {} to {} workers", current, threads);
+		Ok(())
+	}
+
+	/* only affects clones made after the call, i.e. set it before handing the pool to a handler */
+	pub fn set_queue_limit(&mut self, limit: QueueLimit) {
+		self.queue_limit = limit;
+	}
+
+	/* like set_queue_limit, set it before handing the pool to a handler */
+	pub fn set_priorities(&mut self, priorities: HashMap<u16, u8>) {
+		self.priorities = Arc::new(priorities);
+	}
+
+	/* applies to all workers, including the running ones */
+	pub fn set_panic_policy(&self, policy: PanicPolicy) -> FiestaResult<()> {
+		try!(self.settings.write()).panic_policy = policy;
+		Ok(())
+	}
+
+	/* a handler running longer than `budget` starves the other clients on its worker, None turns the check off */
+	pub fn set_slow_handler_budget(&self, budget: Option<Duration>) -> FiestaResult<()> {
+		try!(self.settings.write()).slow_budget = budget;
+		Ok(())
+	}
+
+	/* slow handlers are counted here as well as logged */
+	pub fn set_metrics(&self, metrics: Arc<Metrics>) -> FiestaResult<()> {
+		try!(self.settings.write()).metrics = Some(metrics);
+		Ok(())
+	}
+
+	/* with Dispatch::PerClient the worker takes from the queue with the same index */
+	pub fn start_new_thread(&mut self, id: usize) -> FiestaResult<()> {
+		let (rec, len) = {
+			let queues = try!(self.queues.read());
+			let queue = &queues[id % queues.len()];
+			(queue.jobs.clone(), queue.len.clone())
+		};
+		let template = self.processor.clone();
+		let settings = self.settings.clone();
+
+		let handle = try!(Builder::new()
+			.name(format!("WRKR {}", id))
+			.spawn(move || {
+				let mut processor = template.clone();
+				loop {
+					let packet = match rec.pop() {
+						Job::Packet(packet) => {
+							len.fetch_sub(1, Ordering::SeqCst);
+							packet
+						},
+						Job::Tick(tick) => {
+							if panic::catch_unwind(AssertUnwindSafe(|| processor.tick(tick))).is_err() {
+								warn!(target: "threading", "processor panicked on tick {} in worker {}, restarting it.", tick.number, id);
+								processor = template.clone();
+							}
+							continue;
+						},
+						Job::Disconnect(client, reason, _keep) => {
+							if panic::catch_unwind(AssertUnwindSafe(|| processor.on_disconnect(&client, &reason))).is_err() {
+								warn!(target: "threading", "processor panicked on the disconnect of {:?} in worker {}, restarting it.", client.id(), id);
+								processor = template.clone();
+							}
+							continue;
+						},
+						Job::Retire => {
+							debug!(target: "threading", "packet processing thread {} retired", id);
+							break;
+						}
+					};
+					let (header, client) = (Some(packet.opcode()), packet.client.clone());
+					#[cfg(feature = "spans")]
+					let span = packet.span.clone();
+					#[cfg(feature = "spans")]
+					let _entered = span.enter();
+
+					let started = Instant::now();
+					let result = panic::catch_unwind(AssertUnwindSafe(|| processor.process_packet(packet)));
+					let elapsed = started.elapsed();
+					if let Ok(settings) = settings.read() {
+						match settings.slow_budget {
+							Some(budget) if elapsed > budget => {
+								warn!(target: "threading", "processor took {:?} on packet {:?} in worker {}, the budget is {:?}.",
+									elapsed, header.map(|h| OpcodeName(h).to_string()), id, budget);
+								if let Some(ref metrics) = settings.metrics {
+									metrics.slow_handler();
+								}
+							},
+							_ => (),
+						}
+					}
+
+					if result.is_err() {
+						warn!(target: "threading", "processor panicked on packet {:?} in worker {}, restarting it.",
+							header.map(|h| OpcodeName(h).to_string()), id);
+						/* the old processor may have been left half way through an update */
+						processor = template.clone();
+
+						let policy = settings.read().map(|s| s.panic_policy).unwrap_or(PanicPolicy::KeepClient);
+						if policy == PanicPolicy::Disconnect {
+							warn!(target: "threading", "disconnecting {} after the panic.", client.describe());
+							client.disconnect();
+						}
+					}
+				}
+			}));
+		let mut handles = try!(self.thread_handles.write());
+		handles.push(handle);
+		Ok(())
+	}
+}
+
+impl Clone for PacketProcessingThreadPool {
+	fn clone(&self) -> Self {
+		PacketProcessingThreadPool {
+			thread_handles:			self.thread_handles.clone(),
+			queues:					self.queues.clone(),
+			dispatch:				self.dispatch,
+			settings:				self.settings.clone(),
+			queue_limit:			self.queue_limit,
+			priorities:				self.priorities.clone(),
+			size:					self.size.clone(),
+			next_id:				self.next_id.clone(),
+			processor:				self.processor.clone(),
+		}
+	}
+} 
+
+impl PacketProcessor for PacketProcessingThreadPool {
+	fn process_packet(&mut self, info: PacketProcessingInfo) {
+		let queues = match self.queues.read() {
+			Ok(queues) => queues,
+			Err(_) => {
+				warn!(target: "threading", "packet queues poisoned, dropping packet.");
+				return;
+			}
+		};
+		let index = match self.dispatch {
+			Dispatch::Shared	=> 0,
+			Dispatch::PerClient	=> info.token.0 % queues.len(),
+		};
+		let priority = self.priorities.get(&info.opcode()).cloned().unwrap_or(0);
+
+		let queue = &queues[index];
+		if !queue.has_room(&self.queue_limit) {
+			let client = &info.client;
+			warn!(target: "threading", "worker queue full, dropping packet from {}", client.describe());
+			if self.queue_limit.overflow == OverflowPolicy::Disconnect {
+				client.disconnect();
+			}
+			/* dropping `info` takes the packet off the client's in flight count */
+			return;
+		}
+
+		queue.len.fetch_add(1, Ordering::SeqCst);
+		queue.jobs.push(priority, Job::Packet(info));
+	}
+
+	/* to the first queue only, one tick is one world update however many workers there are */
+	fn tick(&mut self, tick: Tick) {
+		match self.queues.read() {
+			Ok(queues) => queues[0].jobs.push(TICK_PRIORITY, Job::Tick(tick)),
+			Err(_) => warn!(target: "threading", "packet queues poisoned, dropping tick {}.", tick.number),
+		}
+	}
+
+	/* in the lowest lane of the client's queue, after anything of it that is waiting there */
+	fn on_disconnect(&mut self, client: &ClientHandle, reason: &DisconnectReason) {
+		let queues = match self.queues.read() {
+			Ok(queues) => queues,
+			Err(_) => {
+				warn!(target: "threading", "packet queues poisoned, dropping the disconnect of {:?}.", client.id());
+				return;
+			}
+		};
+		let index = match self.dispatch {
+			Dispatch::Shared	=> 0,
+			Dispatch::PerClient	=> client.id().0 % queues.len(),
+		};
+		queues[index].jobs.push(0, Job::Disconnect(client.clone(), reason.clone(), client.client()));
+	}
+
+	fn clone(&self) -> Box<PacketProcessor> {
+		Box::new(<PacketProcessingThreadPool as Clone>::clone(&self))
+	}
+}
\ No newline at end of file
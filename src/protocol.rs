@@ -0,0 +1,134 @@
+use std::collections::{HashMap, HashSet};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+#[cfg(feature = "crypto")]
+use std::sync::Arc;
+use std::time::Duration;
+
+use packets::{NcMiscHeartbeatAck, NcMiscHeartbeatReq, Packet};
+
+/* the client's xor table is this long, bigger seeds would only wrap around */
+pub const SEED_RANGE: u16 = 499;
+
+/* how far a client got, moved along by the processor with FiestaNetworkClient::set_protocol_state() */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ProtocolState {
+	Connected,
+	/* the xor seed went out */
+	SeedSent,
+	Authenticated,
+	InGame,
+}
+
+impl Default for ProtocolState {
+	fn default() -> Self {
+		ProtocolState::Connected
+	}
+}
+
+/* what happens to a packet its client isn't allowed to send yet */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ViolationAction {
+	/* never reaches the processor, the client stays */
+	Drop,
+	Disconnect,
+}
+
+/* which opcodes a client may send in which state, checked before a packet is dispatched. */
+/* a state without an allow() accepts everything, so InGame doesn't have to list the whole protocol */
+#[derive(Debug, Clone)]
+pub struct StateRules {
+	allowed:		HashMap<ProtocolState, HashSet<u16>>,
+	/* in every state, e.g. a ping */
+	always:			HashSet<u16>,
+	action:			ViolationAction,
+}
+
+impl StateRules {
+	pub fn new(action: ViolationAction) -> Self {
+		StateRules {
+			allowed:		HashMap::new(),
+			always:			HashSet::new(),
+			action:			action,
+		}
+	}
+
+	pub fn allow(mut self, state: ProtocolState, opcodes: &[u16]) -> Self {
+		self.allowed.entry(state).or_insert_with(HashSet::new).extend(opcodes.iter().cloned());
+		self
+	}
+
+	pub fn allow_always(mut self, opcodes: &[u16]) -> Self {
+		self.always.extend(opcodes.iter().cloned());
+		self
+	}
+
+	pub fn permits(&self, state: ProtocolState, header: u16) -> bool {
+		if self.always.contains(&header) {
+			return true;
+		}
+		match self.allowed.get(&state) {
+			Some(opcodes)	=> opcodes.contains(&header),
+			None			=> true,
+		}
+	}
+
+	pub fn action(&self) -> ViolationAction {
+		self.action
+	}
+}
+
+/* the heartbeat, answered and checked on the reactor thread so it never waits behind a busy worker */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Keepalive {
+	/* sent by the server every `interval`, the client's own are answered with `response` */
+	pub request:		u16,
+	pub response:		u16,
+	/* None only answers, nobody gets dropped */
+	pub interval:		Option<Duration>,
+	/* unanswered requests in a row before the client is dropped */
+	pub max_missed:		usize,
+}
+
+impl Default for Keepalive {
+	fn default() -> Self {
+		Keepalive {
+			request:		NcMiscHeartbeatReq::OPCODE,
+			response:		NcMiscHeartbeatAck::OPCODE,
+			interval:		Some(Duration::from_secs(15)),
+			max_missed:		3,
+		}
+	}
+}
+
+/* what a new client is sent before anything else: NC_MISC_SEED_ACK with a random seed, after */
+/* which the client is in SeedSent. with the client's xor table, what it sends from then on is */
+/* decrypted starting at that seed */
+#[derive(Clone, Default)]
+pub struct SeedHandshake {
+	#[cfg(feature = "crypto")]
+	table:			Option<Arc<Vec<u8>>>,
+}
+
+impl SeedHandshake {
+	pub fn new() -> Self {
+		SeedHandshake::default()
+	}
+
+	/* the table comes with the game client, see Keystream */
+	#[cfg(feature = "crypto")]
+	pub fn with_table(mut self, table: Arc<Vec<u8>>) -> Self {
+		self.table = Some(table);
+		self
+	}
+
+	#[cfg(feature = "crypto")]
+	pub fn table(&self) -> Option<Arc<Vec<u8>>> {
+		self.table.clone()
+	}
+
+	/* a fresh one per client, so one client's traffic doesn't give away another's keystream */
+	pub fn next_seed(&self) -> u16 {
+		(RandomState::new().build_hasher().finish() % SEED_RANGE as u64) as u16
+	}
+}
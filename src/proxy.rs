@@ -0,0 +1,114 @@
+use std::net::{SocketAddr, IpAddr, Ipv4Addr, Ipv6Addr};
+use std::str;
+
+const V1_PREFIX: &'static [u8] = b"PROXY ";
+const V1_MAX_LENGTH: usize = 107;
+const V2_SIGNATURE: [u8; 12] = [0x0d, 0x0a, 0x0d, 0x0a, 0x00, 0x0d, 0x0a, 0x51, 0x55, 0x49, 0x54, 0x0a];
+const V2_HEADER_LENGTH: usize = 16;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ProxyHeader {
+	/* need more data before deciding */
+	Incomplete,
+	/* `source` is None for LOCAL/UNKNOWN connections (health checks etc.) */
+	Done { consumed: usize, source: Option<SocketAddr> },
+	Invalid,
+}
+
+/* the HAProxy PROXY header in front of a connection, v1 (text) or v2 (binary) */
+pub fn parse(data: &[u8]) -> ProxyHeader {
+	if starts_with_partial(data, &V2_SIGNATURE[..]) {
+		parse_v2(data)
+	} else if starts_with_partial(data, V1_PREFIX) {
+		parse_v1(data)
+	} else {
+		ProxyHeader::Invalid
+	}
+}
+
+/* `data` is a prefix of `pattern` or starts with it */
+fn starts_with_partial(data: &[u8], pattern: &[u8]) -> bool {
+	let len = if data.len() < pattern.len() { data.len() } else { pattern.len() };
+	&data[0..len] == &pattern[0..len]
+}
+
+fn parse_v1(data: &[u8]) -> ProxyHeader {
+	let end = match data.windows(2).position(|w| w == b"\r\n") {
+		Some(end) => end,
+		None if data.len() >= V1_MAX_LENGTH => return ProxyHeader::Invalid,
+		None => return ProxyHeader::Incomplete,
+	};
+	let line = match str::from_utf8(&data[0..end]) {
+		Ok(line) => line,
+		Err(_) => return ProxyHeader::Invalid,
+	};
+
+	let parts: Vec<&str> = line.split(' ').collect();
+	let source = match parts.get(1).map(|p| *p) {
+		Some("UNKNOWN") => None,
+		Some("TCP4") | Some("TCP6") if parts.len() == 6 => {
+			let ip = match parts[2].parse::<IpAddr>() {
+				Ok(ip) => ip,
+				Err(_) => return ProxyHeader::Invalid,
+			};
+			let port = match parts[4].parse::<u16>() {
+				Ok(port) => port,
+				Err(_) => return ProxyHeader::Invalid,
+			};
+			Some(SocketAddr::new(ip, port))
+		},
+		_ => return ProxyHeader::Invalid,
+	};
+
+	ProxyHeader::Done { consumed: end + 2, source: source }
+}
+
+fn parse_v2(data: &[u8]) -> ProxyHeader {
+	if data.len() < V2_HEADER_LENGTH {
+		return ProxyHeader::Incomplete;
+	}
+
+	let version = data[12] >> 4;
+	let command = data[12] & 0x0f;
+	let family = data[13];
+	/* unlike Fiesta framing, PROXY v2 is big-endian */
+	let length = ((data[14] as usize) << 8) | (data[15] as usize);
+
+	if version != 2 || command > 1 {
+		return ProxyHeader::Invalid;
+	}
+	if data.len() < V2_HEADER_LENGTH + length {
+		return ProxyHeader::Incomplete;
+	}
+
+	let consumed = V2_HEADER_LENGTH + length;
+	let body = &data[V2_HEADER_LENGTH..consumed];
+
+	if command == 0 {
+		/* LOCAL: the proxy talking to us on its own behalf */
+		return ProxyHeader::Done { consumed: consumed, source: None };
+	}
+
+	let source = match family {
+		0x11 if body.len() >= 12 => {
+			let ip = Ipv4Addr::new(body[0], body[1], body[2], body[3]);
+			let port = ((body[8] as u16) << 8) | (body[9] as u16);
+			Some(SocketAddr::new(IpAddr::V4(ip), port))
+		},
+		0x21 if body.len() >= 36 => {
+			let mut segments = [0u16; 8];
+			for i in 0..8 {
+				segments[i] = ((body[i * 2] as u16) << 8) | (body[i * 2 + 1] as u16);
+			}
+			let ip = Ipv6Addr::new(
+				segments[0], segments[1], segments[2], segments[3],
+				segments[4], segments[5], segments[6], segments[7]);
+			let port = ((body[32] as u16) << 8) | (body[33] as u16);
+			Some(SocketAddr::new(IpAddr::V6(ip), port))
+		},
+		0x00 => None,
+		_ => return ProxyHeader::Invalid,
+	};
+
+	ProxyHeader::Done { consumed: consumed, source: source }
+}
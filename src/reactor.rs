@@ -0,0 +1,149 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::mem;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::time::{Duration, Instant};
+use mio::{Registry, Token, Waker};
+
+use client::{ClientTimeout, ServerMessage};
+use error::{FiestaNetError, FiestaResult};
+
+/* the poll is woken through this one, client tokens count up from 1 so they never get here */
+pub const WAKER_TOKEN: Token = Token(::std::usize::MAX);
+
+/* how client sockets are kept armed, mio 0.8 registrations are always edge triggered */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PollStrategy {
+	/* one read or write per event, then a reregister so whatever is left is reported again; */
+	/* what the oneshot registrations used to do, at the cost of a syscall per event */
+	Rearm,
+	/* read and write until the socket would block, reregister only when the interest changed */
+	Edge,
+}
+
+impl Default for PollStrategy {
+	fn default() -> Self {
+		PollStrategy::Rearm
+	}
+}
+
+/* what mio's old event loop channel did: queue the message, then wake the poll */
+#[derive(Clone)]
+pub struct Notifier {
+	sender:			Sender<ServerMessage>,
+	waker:			Arc<Waker>,
+	/* clients whose interest changed since the last tick, the reactor reregisters them all in one pass */
+	dirty:			Arc<Mutex<HashSet<Token>>>,
+}
+
+impl Notifier {
+	/* the receiving end is drained by the handler whenever WAKER_TOKEN fires */
+	pub fn new(registry: &Registry) -> FiestaResult<(Notifier, Receiver<ServerMessage>)> {
+		let waker = try!(Waker::new(registry, WAKER_TOKEN));
+		let (sender, receiver) = mpsc::channel();
+		Ok((Notifier {
+			sender:			sender,
+			waker:			Arc::new(waker),
+			dirty:			Arc::new(Mutex::new(HashSet::new())),
+		}, receiver))
+	}
+
+	/* only the first client marked in a tick wakes the poll, a broadcast to every client costs one wakeup */
+	pub fn mark_dirty(&self, token: Token) -> FiestaResult<()> {
+		let wake = {
+			let mut dirty = try!(self.dirty.lock());
+			let wake = dirty.is_empty();
+			dirty.insert(token);
+			wake
+		};
+		if wake {
+			try!(self.waker.wake());
+		}
+		Ok(())
+	}
+
+	pub fn take_dirty(&self) -> FiestaResult<HashSet<Token>> {
+		let mut dirty = try!(self.dirty.lock());
+		Ok(mem::replace(&mut *dirty, HashSet::new()))
+	}
+
+	pub fn send(&self, message: ServerMessage) -> FiestaResult<()> {
+		try!(self.sender.send(message).map_err(|e| FiestaNetError::Notify(format!("{:?}", e))));
+		try!(self.waker.wake());
+		Ok(())
+	}
+}
+
+/* mio doesn't keep timers anymore, the handler polls with the time left until the next one */
+pub struct Timers {
+	/* the sequence number keeps timeouts with the same deadline apart */
+	pending:		BTreeMap<(Instant, usize), ClientTimeout>,
+	sequence:		usize,
+}
+
+impl Timers {
+	pub fn new() -> Self {
+		Timers {
+			pending:		BTreeMap::new(),
+			sequence:		0,
+		}
+	}
+
+	pub fn schedule(&mut self, after: Duration, timeout: ClientTimeout) {
+		self.schedule_at(Instant::now() + after, timeout);
+	}
+
+	/* a deadline in the past fires on the next tick */
+	pub fn schedule_at(&mut self, deadline: Instant, timeout: ClientTimeout) {
+		self.sequence = self.sequence.wrapping_add(1);
+		self.pending.insert((deadline, self.sequence), timeout);
+	}
+
+	/* drops every pending `timeout`, returns how many there were */
+	pub fn cancel(&mut self, timeout: ClientTimeout) -> usize {
+		let keys: Vec<(Instant, usize)> = self.pending.iter().filter(|&(_, pending)| *pending == timeout).map(|(key, _)| *key).collect();
+		for key in keys.iter() {
+			self.pending.remove(key);
+		}
+		keys.len()
+	}
+
+	/* how long the poll may block, None if nothing is scheduled */
+	pub fn next_wait(&self) -> Option<Duration> {
+		self.pending.keys().next().map(|&(deadline, _)| {
+			let now = Instant::now();
+			if deadline > now { deadline - now } else { Duration::from_secs(0) }
+		})
+	}
+
+	/* removes and returns everything that is due, earliest first */
+	pub fn expired(&mut self) -> Vec<ClientTimeout> {
+		let now = Instant::now();
+		let due: Vec<(Instant, usize)> = self.pending.keys().take_while(|&&(deadline, _)| deadline <= now).cloned().collect();
+		due.into_iter().filter_map(|key| self.pending.remove(&key)).collect()
+	}
+
+	pub fn len(&self) -> usize {
+		self.pending.len()
+	}
+}
+
+/* work scheduled with ClientHandle::schedule(), run on the reactor thread so it has to be quick */
+pub struct Task(Box<FnOnce() + Send>);
+
+impl Task {
+	pub fn new<F: FnOnce() + Send + 'static>(work: F) -> Self {
+		Task(Box::new(work))
+	}
+
+	pub fn run(self) {
+		(self.0)()
+	}
+}
+
+impl fmt::Debug for Task {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "Task")
+	}
+}
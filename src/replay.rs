@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Error, ErrorKind, Read, Write};
+use std::net::{self, SocketAddr};
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::Duration;
+use mio::Token;
+
+use capture::Direction;
+use client::FiestaNetworkClient;
+use packet::FiestaPacket;
+use error::FiestaResult;
+use processing::{PacketProcessor, PacketProcessingInfo};
+use transport::{MemoryPeer, MemoryTransport};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Timing {
+	/* sleep between packets like the client did */
+	Original,
+	AsFastAsPossible,
+}
+
+#[derive(Debug, Clone)]
+pub struct CapturedPacket {
+	pub timestamp:		u64,		/* microseconds since the epoch */
+	pub direction:		Direction,
+	pub token:			Token,
+	pub header:			u16,
+	pub body:			Vec<u8>,
+}
+
+fn u16_at(data: &[u8], offset: usize) -> u16 {
+	(data[offset] as u16) | ((data[offset + 1] as u16) << 8)
+}
+
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+	(u16_at(data, offset) as u32) | ((u16_at(data, offset + 2) as u32) << 16)
+}
+
+fn invalid(what: &str) -> Error {
+	Error::new(ErrorKind::InvalidData, format!("not a fiesta capture: {}", what))
+}
+
+/* all packets of a capture written by PacketCapture, in order */
+pub fn read_capture<P: AsRef<Path>>(path: P) -> Result<Vec<CapturedPacket>, Error> {
+	let mut data = Vec::new();
+	try!(try!(File::open(path)).read_to_end(&mut data));
+
+	let mut packets = Vec::new();
+	let mut offset = 0;
+	while offset + 12 <= data.len() {
+		let block_type = u32_at(&data, offset);
+		let length = u32_at(&data, offset + 4) as usize;
+		if length < 12 || offset + length > data.len() {
+			return Err(invalid("truncated block"));
+		}
+
+		/* enhanced packet block */
+		if block_type == 6 {
+			let body = &data[offset + 8..offset + length - 4];
+			if body.len() < 20 {
+				return Err(invalid("short packet block"));
+			}
+			let timestamp = ((u32_at(body, 4) as u64) << 32) | u32_at(body, 8) as u64;
+			let captured = u32_at(body, 12) as usize;
+			if body.len() < 20 + captured || captured < 7 {
+				return Err(invalid("short packet record"));
+			}
+			let record = &body[20..20 + captured];
+			packets.push(CapturedPacket {
+				timestamp:		timestamp,
+				direction:		if record[0] == 0 { Direction::Inbound } else { Direction::Outbound },
+				token:			Token(u32_at(record, 1) as usize),
+				/* the packet itself is as on the wire */
+				header:			((record[5] as u16) << 8) | record[6] as u16,
+				body:			record[7..].to_vec(),
+			});
+		}
+		offset += length;
+	}
+	Ok(packets)
+}
+
+fn wait(timing: Timing, last: &mut Option<u64>, timestamp: u64) {
+	if timing == Timing::Original {
+		if let Some(last) = *last {
+			if timestamp > last {
+				let micros = timestamp - last;
+				thread::sleep(Duration::new(micros / 1000000, ((micros % 1000000) * 1000) as u32));
+			}
+		}
+	}
+	*last = Some(timestamp);
+}
+
+/* a client for a recorded token, on a MemoryTransport nobody reads from */
+fn replay_client(token: Token, peers: &mut Vec<MemoryPeer>) -> Arc<RwLock<Box<FiestaNetworkClient>>> {
+	let (transport, peer) = MemoryTransport::pair();
+	/* keep the other end, it is dropped with the replay */
+	peers.push(peer);
+	Arc::new(RwLock::new(Box::new(FiestaNetworkClient::new(transport, token))))
+}
+
+/* feeds the inbound packets of a capture to `processor`, returns how many were replayed */
+pub fn replay_into<P: AsRef<Path>>(path: P, processor: &mut Box<PacketProcessor>, timing: Timing) -> FiestaResult<usize> {
+	let packets = try!(read_capture(path));
+	let mut clients = HashMap::new();
+	let mut peers = Vec::new();
+	let mut last = None;
+	let mut count = 0;
+
+	for captured in packets.into_iter().filter(|p| p.direction == Direction::Inbound) {
+		wait(timing, &mut last, captured.timestamp);
+
+		if !clients.contains_key(&captured.token) {
+			let client = replay_client(captured.token, &mut peers);
+			clients.insert(captured.token, client);
+		}
+		let client = clients[&captured.token].clone();
+
+		let mut packet = FiestaPacket::new(captured.header, captured.body.len());
+		packet.data.append(&captured.body[..]);
+		/* counted in flight like the reactor does */
+		processor.process_packet(PacketProcessingInfo::dispatched(packet, client));
+		count += 1;
+	}
+
+	info!(target: "network", "replayed {} packets from {} clients", count, clients.len());
+	Ok(count)
+}
+
+/* sends the inbound packets of a capture to a live server, one connection per recorded client */
+/* whatever the server answers is read and thrown away */
+pub fn replay_to<P: AsRef<Path>>(path: P, addr: &SocketAddr, timing: Timing) -> FiestaResult<usize> {
+	let packets = try!(read_capture(path));
+	let mut connections: HashMap<Token, net::TcpStream> = HashMap::new();
+	let mut last = None;
+	let mut count = 0;
+
+	for captured in packets.into_iter().filter(|p| p.direction == Direction::Inbound) {
+		wait(timing, &mut last, captured.timestamp);
+
+		if !connections.contains_key(&captured.token) {
+			let stream = try!(net::TcpStream::connect(addr));
+			let mut drain = try!(stream.try_clone());
+			thread::spawn(move || {
+				let mut buf = [0; 4096];
+				while let Ok(n) = drain.read(&mut buf) {
+					if n == 0 {
+						break;
+					}
+				}
+			});
+			connections.insert(captured.token, stream);
+		}
+
+		let frame = try!(FiestaPacket::encode(captured.header, &captured.body[..]));
+		if let Some(stream) = connections.get_mut(&captured.token) {
+			try!(stream.write_all(&frame[..]));
+		}
+		count += 1;
+	}
+
+	info!(target: "network", "replayed {} packets to {} over {} connections", count, addr, connections.len());
+	Ok(count)
+}
@@ -0,0 +1,839 @@
+use std::collections::{HashMap, HashSet};
+use std::io::{Error, ErrorKind};
+use std::mem;
+use std::net;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use std::sync::{Arc, RwLock};
+use std::sync::mpsc;
+use std::thread;
+use mio::{Poll, Registry};
+use mio::net::TcpListener;
+
+use buffer::BUFFERSIZE;
+use audit::AuditSink;
+use capture::PacketCapture;
+use client::*;
+use codec::Codec;
+use config;
+use config::RuntimeConfig;
+use error::{FiestaNetError, FiestaResult};
+use handover;
+use limits::{FrameLimits, SlowConsumerPolicy, ReadBackpressure, ByteRateLimit};
+use reactor::{Notifier, PollStrategy};
+use listener;
+use listener::IpMode;
+use metrics::Metrics;
+use trace::TraceFilter;
+use processing::*;
+use protocol::{Keepalive, SeedHandshake, StateRules};
+use sockopt::SocketOptions;
+use stats::ClientStats;
+#[cfg(feature = "tls")]
+use tls::TlsConfig;
+#[cfg(feature = "compression")]
+use compression::Compression;
+#[cfg(feature = "prometheus")]
+use exporter;
+#[cfg(feature = "health")]
+use health;
+#[cfg(feature = "signals")]
+use signals;
+
+/* how long ServerHandle::clients() waits for each reactor */
+const CLIENTS_TIMEOUT: Duration = Duration::from_secs(1);
+
+pub struct FiestaServerBuilder {
+	name:			String,
+	port:			u16,
+	ip_mode:		IpMode,
+	address:		Option<SocketAddr>,
+	threads:		usize,
+	inline:			bool,
+	dispatch:		Dispatch,
+	panic_policy:	PanicPolicy,
+	slow_handler_budget:	Option<Duration>,
+	queue_limit:	QueueLimit,
+	priorities:		HashMap<u16, u8>,
+	middleware:		Vec<Box<Middleware>>,
+	buffer_size:	usize,
+	write_buffer_size:	usize,
+	send_policy:	SlowConsumerPolicy,
+	max_clients:	Option<usize>,
+	frame_limits:	FrameLimits,
+	state_rules:	Option<StateRules>,
+	keepalive:		Option<Keepalive>,
+	seed_handshake:	Option<SeedHandshake>,
+	tick:			Option<Duration>,
+	backpressure:	Option<ReadBackpressure>,
+	byte_rate_limit:	Option<ByteRateLimit>,
+	handshake_timeout:	Option<Duration>,
+	/* created up front so a Router can count into them before the server is built */
+	metrics:		Arc<Metrics>,
+	capture:		Option<Arc<PacketCapture>>,
+	audit:			Option<Arc<AuditSink>>,
+	codec:			Option<Arc<Codec>>,
+	proxy_protocol:	bool,
+	socket_options:	SocketOptions,
+	#[cfg(feature = "admin")]
+	admin_addr:		Option<SocketAddr>,
+	reactors:		usize,
+	reuse_port:		bool,
+	poll_strategy:	PollStrategy,
+	max_accepts:	usize,
+	runtime:		Option<RuntimeConfig>,
+	config_path:	Option<PathBuf>,
+	/* used instead of binding, from systemd or an older process */
+	inherited:		Vec<net::TcpListener>,
+	handover_path:	Option<PathBuf>,
+	#[cfg(feature = "tls")]
+	tls:			Option<Arc<TlsConfig>>,
+	#[cfg(feature = "compression")]
+	compression:	Option<Compression>,
+	#[cfg(feature = "websocket")]
+	websocket:		bool,
+	#[cfg(feature = "prometheus")]
+	metrics_addr:	Option<SocketAddr>,
+	#[cfg(feature = "health")]
+	health_addr:	Option<SocketAddr>,
+	#[cfg(feature = "signals")]
+	signal_grace:	Option<Duration>,
+}
+
+pub struct FiestaServer {
+	name:			String,
+	poll:			Poll,
+	handler:		FiestaHandler,
+	/* reactors 1..n, each one runs on its own thread */
+	reactors:		Vec<(Poll, FiestaHandler)>,
+	config_path:	Option<PathBuf>,
+	handover_path:	Option<PathBuf>,
+	#[cfg(feature = "signals")]
+	signal_grace:	Option<Duration>,
+	/* shares its workers with the one inside the handler, None when processing inline */
+	#[cfg(feature = "threads")]
+	pool:			Option<PacketProcessingThreadPool>,
+}
+
+/* lets other threads talk to a running server */
+#[derive(Clone)]
+pub struct ServerHandle {
+	/* one per reactor */
+	senders:		Vec<Notifier>,
+	metrics:		Arc<Metrics>,
+	#[cfg(feature = "threads")]
+	pool:			Option<PacketProcessingThreadPool>,
+	config_path:	Option<PathBuf>,
+}
+
+impl FiestaServerBuilder {
+	pub fn new() -> Self {
+		FiestaServerBuilder {
+			name:			"fiesta".to_string(),
+			port:			0,
+			ip_mode:		IpMode::DualStack,
+			address:		None,
+			threads:		4,
+			inline:			false,
+			dispatch:		Dispatch::Shared,
+			panic_policy:	PanicPolicy::KeepClient,
+			slow_handler_budget:	Some(Duration::from_millis(50)),
+			queue_limit:	QueueLimit::default(),
+			priorities:		HashMap::new(),
+			middleware:		Vec::new(),
+			buffer_size:	BUFFERSIZE,
+			write_buffer_size:	BUFFERSIZE,
+			send_policy:	SlowConsumerPolicy::Disconnect,
+			max_clients:	None,
+			frame_limits:	FrameLimits::default(),
+			state_rules:	None,
+			keepalive:		None,
+			seed_handshake:	None,
+			tick:			None,
+			backpressure:	Some(ReadBackpressure::default()),
+			byte_rate_limit:	None,
+			handshake_timeout:	Some(Duration::from_secs(30)),
+			metrics:		Arc::new(Metrics::new()),
+			capture:		None,
+			audit:			None,
+			codec:			None,
+			proxy_protocol:	false,
+			socket_options:	SocketOptions::default(),
+			#[cfg(feature = "admin")]
+			admin_addr:		None,
+			reactors:		1,
+			reuse_port:		false,
+			poll_strategy:	PollStrategy::default(),
+			max_accepts:	DEFAULT_ACCEPTS_PER_TICK,
+			runtime:		None,
+			config_path:	None,
+			inherited:		Vec::new(),
+			handover_path:	None,
+			#[cfg(feature = "tls")]
+			tls:			None,
+			#[cfg(feature = "compression")]
+			compression:	None,
+			#[cfg(feature = "websocket")]
+			websocket:		false,
+			#[cfg(feature = "prometheus")]
+			metrics_addr:	None,
+			#[cfg(feature = "health")]
+			health_addr:	None,
+			#[cfg(feature = "signals")]
+			signal_grace:	None,
+		}
+	}
+
+	/* only used for logging */
+	pub fn name<S: Into<String>>(mut self, name: S) -> Self {
+		self.name = name.into();
+		self
+	}
+
+	pub fn port(mut self, port: u16) -> Self {
+		self.port = port;
+		self
+	}
+
+	pub fn ip_mode(mut self, ip_mode: IpMode) -> Self {
+		self.ip_mode = ip_mode;
+		self
+	}
+
+	/* binds exactly this address instead of the wildcard(s) for `ip_mode` */
+	pub fn address(mut self, address: SocketAddr) -> Self {
+		self.address = Some(address);
+		self
+	}
+
+	pub fn threads(mut self, threads: usize) -> Self {
+		self.threads = threads;
+		self
+	}
+
+	/* process packets on the reactor threads instead of a worker pool, for services like ping servers */
+	/* and tools whose handlers are so quick the hand over would cost more. threads() is ignored then, */
+	/* as it is without the `threads` feature */
+	pub fn inline_processing(mut self, inline: bool) -> Self {
+		self.inline = inline;
+		self
+	}
+
+	/* Dispatch::PerClient keeps each client's packets in order */
+	pub fn dispatch(mut self, dispatch: Dispatch) -> Self {
+		self.dispatch = dispatch;
+		self
+	}
+
+	/* a panicking processor is always replaced, this decides whether the client goes too */
+	pub fn panic_policy(mut self, policy: PanicPolicy) -> Self {
+		self.panic_policy = policy;
+		self
+	}
+
+	/* handlers taking longer than this get a warning with their opcode, None disables the check */
+	pub fn slow_handler_budget(mut self, budget: Option<Duration>) -> Self {
+		self.slow_handler_budget = budget;
+		self
+	}
+
+	/* packets waiting for a worker, per queue */
+	pub fn queue_limit(mut self, limit: QueueLimit) -> Self {
+		self.queue_limit = limit;
+		self
+	}
+
+	/* packets with `header` skip ahead of lower priorities when the workers are behind, the default is 0 */
+	pub fn opcode_priority(mut self, header: u16, priority: u8) -> Self {
+		self.priorities.insert(header, priority);
+		self
+	}
+
+	/* wraps the processor on every worker, in the order they are added */
+	pub fn middleware<M: Middleware>(mut self, middleware: M) -> Self {
+		self.middleware.push(Box::new(middleware));
+		self
+	}
+
+	/* per client read buffer */
+	pub fn buffer_size(mut self, size: usize) -> Self {
+		self.buffer_size = size;
+		self
+	}
+
+	/* per client cap on unsent data */
+	pub fn write_buffer_size(mut self, size: usize) -> Self {
+		self.write_buffer_size = size;
+		self
+	}
+
+	pub fn slow_consumer_policy(mut self, policy: SlowConsumerPolicy) -> Self {
+		self.send_policy = policy;
+		self
+	}
+
+	pub fn max_clients(mut self, max_clients: usize) -> Self {
+		self.max_clients = Some(max_clients);
+		self
+	}
+
+	/* frames declaring a bigger body get the client disconnected */
+	pub fn max_frame_size(mut self, size: usize) -> Self {
+		self.frame_limits.set_max_frame_size(size);
+		self
+	}
+
+	pub fn frame_limits(mut self, limits: FrameLimits) -> Self {
+		self.frame_limits = limits;
+		self
+	}
+
+	/* per protocol state opcode allow lists, off by default */
+	pub fn state_rules(mut self, rules: StateRules) -> Self {
+		self.state_rules = Some(rules);
+		self
+	}
+
+	/* calls the processor's tick() every `interval`, e.g. 50ms for the world update. */
+	/* ticks come from the first reactor only and keep to their grid even when one runs late */
+	pub fn tick(mut self, interval: Duration) -> Self {
+		self.tick = Some(interval);
+		self
+	}
+
+	/* heartbeats are answered and checked on the reactor, off by default */
+	pub fn keepalive(mut self, keepalive: Option<Keepalive>) -> Self {
+		self.keepalive = keepalive;
+		self
+	}
+
+	/* NC_MISC_SEED_ACK to every new client, None if the processor does the handshake itself */
+	pub fn seed_handshake(mut self, handshake: Option<SeedHandshake>) -> Self {
+		self.seed_handshake = handshake;
+		self
+	}
+
+	/* the game client's xor table, what clients send after their seed is decrypted with it */
+	#[cfg(feature = "crypto")]
+	pub fn keystream_table(mut self, table: Arc<Vec<u8>>) -> Self {
+		let handshake = self.seed_handshake.take().unwrap_or_default();
+		self.seed_handshake = Some(handshake.with_table(table));
+		self
+	}
+
+	/* the metrics the server will count into, e.g. for Router::with_metrics */
+	pub fn metrics(&self) -> Arc<Metrics> {
+		self.metrics.clone()
+	}
+
+	/* None reads from clients no matter how far behind the workers are */
+	pub fn backpressure(mut self, backpressure: Option<ReadBackpressure>) -> Self {
+		self.backpressure = backpressure;
+		self
+	}
+
+	/* off by default */
+	pub fn byte_rate_limit(mut self, limit: Option<ByteRateLimit>) -> Self {
+		self.byte_rate_limit = limit;
+		self
+	}
+
+	/* clients that don't send a packet within this are dropped, None keeps idle sockets forever */
+	pub fn handshake_timeout(mut self, timeout: Option<Duration>) -> Self {
+		self.handshake_timeout = timeout;
+		self
+	}
+
+	/* records every packet to a pcapng file, see PacketCapture::create */
+	pub fn capture(mut self, capture: Arc<PacketCapture>) -> Self {
+		self.capture = Some(capture);
+		self
+	}
+
+	/* connects, logins, kicks and disconnects as structured records, e.g. JsonLinesAudit::create */
+	pub fn audit(mut self, audit: Arc<AuditSink>) -> Self {
+		self.audit = Some(audit);
+		self
+	}
+
+	/* frames every connection with `codec` instead of the game's size prefix */
+	pub fn codec(mut self, codec: Arc<Codec>) -> Self {
+		self.codec = Some(codec);
+		self
+	}
+
+	/* expect a PROXY v1/v2 header from a load balancer on every connection */
+	pub fn proxy_protocol(mut self, enabled: bool) -> Self {
+		self.proxy_protocol = enabled;
+		self
+	}
+
+	pub fn socket_options(mut self, options: SocketOptions) -> Self {
+		self.socket_options = options;
+		self
+	}
+
+	/* line based admin console (clients, kick, ban, stats, shutdown), keep it on a private address */
+	#[cfg(feature = "admin")]
+	pub fn admin_addr(mut self, addr: SocketAddr) -> Self {
+		self.admin_addr = Some(addr);
+		self
+	}
+
+	/* event loops, each on its own thread. max_clients applies per reactor, */
+	/* the admin console only sees the clients of the first one */
+	pub fn reactors(mut self, reactors: usize) -> Self {
+		self.reactors = reactors;
+		self
+	}
+
+	/* with more than one reactor, each gets its own SO_REUSEPORT listener instead of */
+	/* the first one accepting for all of them */
+	pub fn reuse_port(mut self, enabled: bool) -> Self {
+		self.reuse_port = enabled;
+		self
+	}
+
+	/* how many connections a listener may take per event loop tick before clients get their turn */
+	pub fn max_accepts_per_tick(mut self, max: usize) -> Self {
+		self.max_accepts = max;
+		self
+	}
+
+	/* PollStrategy::Edge saves a reregister per event, benches/poll.rs compares the two */
+	pub fn poll_strategy(mut self, strategy: PollStrategy) -> Self {
+		self.poll_strategy = strategy;
+		self
+	}
+
+	#[cfg(feature = "tls")]
+	pub fn tls(mut self, config: Arc<TlsConfig>) -> Self {
+		self.tls = Some(config);
+		self
+	}
+
+	/* every connection is a WebSocket carrying frames as binary messages, for browser tools. */
+	/* the tls setting would end up inside the WebSocket, put wss behind a terminating proxy */
+	#[cfg(feature = "websocket")]
+	pub fn websocket(mut self, enabled: bool) -> Self {
+		self.websocket = enabled;
+		self
+	}
+
+	/* for listeners other servers connect to, clients that never compress are unaffected */
+	#[cfg(feature = "compression")]
+	pub fn compression(mut self, compression: Compression) -> Self {
+		self.compression = Some(compression);
+		self
+	}
+
+	/* serves /metrics in prometheus format on this address */
+	#[cfg(feature = "prometheus")]
+	pub fn metrics_addr(mut self, addr: SocketAddr) -> Self {
+		self.metrics_addr = Some(addr);
+		self
+	}
+
+	/* serves /healthz and a JSON /status for load balancers and orchestrators */
+	#[cfg(feature = "health")]
+	pub fn health_addr(mut self, addr: SocketAddr) -> Self {
+		self.health_addr = Some(addr);
+		self
+	}
+
+	/* the limits, bans and trace filter a reload can change, ServerConfig::apply() sets this from the file */
+	pub fn runtime_config(mut self, config: RuntimeConfig) -> Self {
+		self.runtime = Some(config);
+		self
+	}
+
+	/* the file `reload` on the admin console and SIGHUP read again */
+	pub fn config_path<P: AsRef<Path>>(mut self, path: P) -> Self {
+		self.config_path = Some(path.as_ref().to_path_buf());
+		self
+	}
+
+	/* serve these instead of binding port/address, e.g. from systemd_listeners() or receive_listeners() */
+	/* with several reactors the first one accepts on them for everyone, reuse_port is ignored */
+	pub fn inherit_listeners(mut self, listeners: Vec<net::TcpListener>) -> Self {
+		self.inherited = listeners;
+		self
+	}
+
+	/* once running, hand the listeners to the next process that connects to this unix socket, then drain */
+	pub fn handover_path<P: AsRef<Path>>(mut self, path: P) -> Self {
+		self.handover_path = Some(path.as_ref().to_path_buf());
+		self
+	}
+
+	/* SIGTERM and SIGINT shut the server down gracefully with this grace period, a second one right away */
+	/* with a config_path, SIGHUP reloads it */
+	#[cfg(feature = "signals")]
+	pub fn handle_signals(mut self, grace: Duration) -> Self {
+		self.signal_grace = Some(grace);
+		self
+	}
+
+	/* binds the listener(s) and spins up the worker pool, nothing is accepted until `run()` */
+	pub fn build(mut self, processor: Box<PacketProcessor>) -> FiestaResult<FiestaServer> {
+		if self.threads == 0 && !self.inline {
+			return Err(FiestaNetError::from(Error::new(ErrorKind::InvalidInput, "a server needs at least one worker thread")));
+		}
+		let min_buffer_size = self.frame_limits.max_frame_size() + 5;
+		if self.buffer_size < min_buffer_size || self.write_buffer_size < min_buffer_size {
+			/* a frame that can't fit in the read buffer would never complete */
+			return Err(FiestaNetError::from(Error::new(ErrorKind::InvalidInput, "buffer size is smaller than the biggest allowed frame")));
+		}
+
+		if self.reactors == 0 {
+			return Err(FiestaNetError::from(Error::new(ErrorKind::InvalidInput, "a server needs at least one reactor")));
+		}
+
+		let inherited = !self.inherited.is_empty();
+		let mut listeners = if inherited {
+			let mut listeners = Vec::new();
+			for listener in mem::replace(&mut self.inherited, Vec::new()).into_iter() {
+				try!(listener.set_nonblocking(true));
+				listeners.push(TcpListener::from_std(listener));
+			}
+			listeners
+		} else {
+			try!(self.bind_listeners())
+		};
+		let reuse_port = self.reuse_port && !inherited;
+		let poll = try!(Poll::new());
+		let first = listeners.remove(0);
+
+		let processor: Box<PacketProcessor> = if self.middleware.is_empty() {
+			processor
+		} else {
+			let middleware = self.middleware.drain(..).collect();
+			Box::new(MiddlewareChain::from_parts(processor, middleware))
+		};
+		#[cfg(feature = "threads")]
+		let (pool, processor) = try!(self.start_workers(processor));
+		#[cfg(not(feature = "threads"))]
+		let processor: Box<PacketProcessor> = Box::new(InlineProcessor::new(processor).with_panic_policy(self.panic_policy));
+		/* a ban from any reactor refuses the address on all of them */
+		let banned = Arc::new(RwLock::new(HashSet::new()));
+		let mut handler = try!(FiestaHandler::new(poll.registry(), first, processor.clone()));
+		handler.set_metrics(self.metrics.clone());
+		handler.set_ban_list(banned.clone());
+		handler.set_reactor_index(0, self.reactors);
+		handler.set_tick(self.tick);
+		for listener in listeners.into_iter() {
+			try!(handler.add_listener(poll.registry(), listener));
+		}
+		self.configure(poll.registry(), &mut handler);
+
+		let mut reactors = Vec::new();
+		for index in 1..self.reactors {
+			let reactor_poll = try!(Poll::new());
+			let mut reactor = try!(FiestaHandler::without_listener(reactor_poll.registry(), processor.clone()));
+			reactor.set_reactor_index(index, self.reactors);
+			reactor.set_ban_list(banned.clone());
+			if reuse_port {
+				for listener in try!(self.bind_listeners()).into_iter() {
+					try!(reactor.add_listener(reactor_poll.registry(), listener));
+				}
+			}
+			self.configure(reactor_poll.registry(), &mut reactor);
+			reactor.set_metrics(handler.metrics());
+			reactors.push((reactor_poll, reactor));
+		}
+		if !reuse_port && !reactors.is_empty() {
+			/* the first reactor accepts for everyone */
+			handler.set_peers(reactors.iter().map(|&(_, ref reactor)| reactor.notifier()).collect());
+		}
+		/* admin commands can come in on any reactor's console, each one needs to reach all the others */
+		let mut notifiers = vec![handler.notifier()];
+		notifiers.extend(reactors.iter().map(|&(_, ref reactor)| reactor.notifier()));
+		handler.set_siblings(notifiers[1..].to_vec());
+		for (index, &mut (_, ref mut reactor)) in reactors.iter_mut().enumerate() {
+			let siblings = notifiers.iter().enumerate().filter(|&(other, _)| other != index + 1).map(|(_, notifier)| notifier.clone()).collect();
+			reactor.set_siblings(siblings);
+		}
+
+		#[cfg(feature = "admin")]
+		{
+			if let Some(addr) = self.admin_addr {
+				let admin = try!(listener::bind_addr(&addr, false, false));
+				try!(handler.set_admin(poll.registry(), admin));
+				info!(target: "network", "admin console listening on {}", addr);
+			}
+		}
+		#[cfg(feature = "threads")]
+		{
+			if let Some(ref pool) = pool {
+				try!(pool.set_slow_handler_budget(self.slow_handler_budget));
+				try!(pool.set_metrics(handler.metrics()));
+			}
+		}
+		#[cfg(feature = "prometheus")]
+		{
+			if let Some(addr) = self.metrics_addr {
+				try!(exporter::serve(addr, self.name.clone(), handler.metrics()));
+			}
+		}
+		#[cfg(feature = "health")]
+		{
+			if let Some(addr) = self.health_addr {
+				let pool = pool.as_ref().map(|pool| <PacketProcessingThreadPool as Clone>::clone(pool));
+				try!(health::serve(addr, self.name.clone(), handler.metrics(), pool));
+			}
+		}
+
+		Ok(FiestaServer {
+			name:			self.name,
+			poll:			poll,
+			handler:		handler,
+			reactors:		reactors,
+			config_path:	self.config_path,
+			handover_path:	self.handover_path,
+			#[cfg(feature = "signals")]
+			signal_grace:	self.signal_grace,
+			#[cfg(feature = "threads")]
+			pool:			pool,
+		})
+	}
+
+	/* what the reactors hand their packets to, and the pool behind it unless processing inline */
+	#[cfg(feature = "threads")]
+	fn start_workers(&self, processor: Box<PacketProcessor>) -> FiestaResult<(Option<PacketProcessingThreadPool>, Box<PacketProcessor>)> {
+		if self.inline {
+			let processor: Box<PacketProcessor> = Box::new(InlineProcessor::new(processor).with_panic_policy(self.panic_policy));
+			return Ok((None, processor));
+		}
+		let mut pool = try!(PacketProcessingThreadPool::with_dispatch(self.threads, processor, self.dispatch));
+		try!(pool.set_panic_policy(self.panic_policy));
+		pool.set_queue_limit(self.queue_limit);
+		pool.set_priorities(self.priorities.clone());
+		let processor: Box<PacketProcessor> = Box::new(<PacketProcessingThreadPool as Clone>::clone(&pool));
+		Ok((Some(pool), processor))
+	}
+
+	fn bind_listeners(&self) -> FiestaResult<Vec<TcpListener>> {
+		let reuse_port = self.reuse_port && self.reactors > 1;
+		Ok(match self.address {
+			Some(address)	=> vec![try!(listener::bind_addr(&address, self.ip_mode == IpMode::V6Only, reuse_port))],
+			None			=> try!(listener::bind(self.port, self.ip_mode, reuse_port)),
+		})
+	}
+
+	/* everything every reactor gets the same way */
+	fn configure(&self, registry: &Registry, handler: &mut FiestaHandler) {
+		handler.set_proxy_protocol(self.proxy_protocol);
+		handler.set_socket_options(self.socket_options);
+		handler.set_buffer_size(self.buffer_size);
+		handler.set_write_buffer(self.write_buffer_size, self.send_policy);
+		handler.set_poll_strategy(self.poll_strategy);
+		handler.set_max_accepts_per_tick(self.max_accepts);
+		handler.set_max_clients(self.max_clients);
+		handler.set_frame_limits(self.frame_limits.clone());
+		handler.set_state_rules(self.state_rules.clone());
+		handler.set_keepalive(self.keepalive);
+		handler.set_seed_handshake(self.seed_handshake.clone());
+		handler.set_backpressure(self.backpressure);
+		handler.set_byte_rate_limit(self.byte_rate_limit);
+		handler.set_handshake_timeout(self.handshake_timeout);
+		handler.set_capture(self.capture.clone());
+		handler.set_audit(self.audit.clone());
+		handler.set_codec(self.codec.clone());
+		handler.set_config_path(self.config_path.as_ref());
+		#[cfg(feature = "tls")]
+		handler.set_tls_config(self.tls.clone());
+		#[cfg(feature = "websocket")]
+		handler.set_websocket(self.websocket);
+		#[cfg(feature = "compression")]
+		handler.set_compression(self.compression);
+		if let Some(ref runtime) = self.runtime {
+			handler.reload(registry, runtime);
+		}
+	}
+}
+
+impl FiestaServer {
+	pub fn handle(&self) -> ServerHandle {
+		ServerHandle {
+			senders:		self.notifiers(),
+			metrics:		self.handler.metrics(),
+			#[cfg(feature = "threads")]
+			pool:			self.pool.as_ref().map(|pool| <PacketProcessingThreadPool as Clone>::clone(pool)),
+			config_path:	self.config_path.clone(),
+		}
+	}
+
+	pub fn metrics(&self) -> Arc<Metrics> {
+		self.handler.metrics()
+	}
+
+	/* every address clients can connect to, e.g. to find the port after binding port 0 */
+	pub fn local_addrs(&self) -> Vec<SocketAddr> {
+		self.handler.local_addrs()
+	}
+
+	fn notifiers(&self) -> Vec<Notifier> {
+		let mut notifiers = vec![self.handler.notifier()];
+		notifiers.extend(self.reactors.iter().map(|&(_, ref handler)| handler.notifier()));
+		notifiers
+	}
+
+	/* blocks until the event loop is shut down, the first reactor runs on the calling thread */
+	pub fn run(mut self) -> FiestaResult<()> {
+		let addrs: Vec<String> = self.local_addrs().iter().map(|addr| addr.to_string()).collect();
+		info!(target: "network", "{} server listening on {} with {} reactor(s).", self.name, addrs.join(", "), self.reactors.len() + 1);
+		let notifiers = self.notifiers();
+		if let Some(path) = self.handover_path.take() {
+			/* every reactor's listeners, with reuse_port connections keep landing in each of them */
+			let mut fds = self.handler.listener_fds();
+			for &(_, ref reactor) in self.reactors.iter() {
+				fds.extend(reactor.listener_fds());
+			}
+			try!(handover::offer_listeners(path, fds, self.handle()));
+		}
+		let mut threads = Vec::new();
+		for (index, (mut poll, mut handler)) in self.reactors.drain(..).enumerate() {
+			let thread = try!(thread::Builder::new()
+				.name(format!("REACTOR {}", index + 1))
+				.spawn(move || {
+					if let Err(e) = handler.run(&mut poll) {
+						warn!(target: "threading", "reactor {} stopped: {}", index + 1, e);
+					}
+				}));
+			threads.push(thread);
+		}
+
+		#[cfg(feature = "signals")]
+		let signals = match self.signal_grace {
+			Some(grace) => Some(try!(signals::watch(self.handle(), grace))),
+			None => None,
+		};
+
+		let result = self.handler.run(&mut self.poll);
+		/* the admin console only stops the first one, a graceful shutdown went to all of them */
+		if !self.handler.stopped_gracefully() {
+			for notifier in notifiers.iter().skip(1) {
+				let _ = notifier.send(ServerMessage::Shutdown);
+			}
+		}
+		for thread in threads.into_iter() {
+			let _ = thread.join();
+		}
+		#[cfg(feature = "signals")]
+		{
+			if let Some(signals) = signals {
+				signals.close();
+			}
+		}
+		result
+	}
+}
+
+impl ServerHandle {
+	/* to the first reactor, see broadcast() for messages every reactor needs */
+	pub fn send(&self, message: ServerMessage) -> FiestaResult<()> {
+		self.senders[0].send(message)
+	}
+
+	/* `message` is built once per reactor */
+	pub fn broadcast<F: Fn() -> ServerMessage>(&self, message: F) -> FiestaResult<()> {
+		for sender in self.senders.iter() {
+			try!(sender.send(message()));
+		}
+		Ok(())
+	}
+
+	pub fn reactors(&self) -> usize {
+		self.senders.len()
+	}
+
+	/* clients connected to any reactor right now, cheap enough to ask before every channel pick */
+	pub fn connection_count(&self) -> usize {
+		self.metrics.connections_active()
+	}
+
+	/* how many clients the server takes in total, None without max_clients */
+	pub fn capacity(&self) -> Option<usize> {
+		self.metrics.capacity()
+	}
+
+	/* token, address and counters of every connection on every reactor, e.g. for a GM tool or */
+	/* a periodic sweep. each reactor answers between two polls, a busy one makes this wait */
+	pub fn clients(&self) -> FiestaResult<Vec<ClientStats>> {
+		let (reply, answers) = mpsc::channel();
+		try!(self.broadcast(|| ServerMessage::Clients(reply.clone())));
+		let mut clients = Vec::new();
+		for _ in 0..self.senders.len() {
+			match answers.recv_timeout(CLIENTS_TIMEOUT) {
+				Ok(snapshot) => clients.extend(snapshot),
+				Err(_) => return Err(FiestaNetError::from(Error::new(ErrorKind::TimedOut, "a reactor didn't answer in time"))),
+			}
+		}
+		Ok(clients)
+	}
+
+	pub fn shutdown(&self) -> FiestaResult<()> {
+		self.broadcast(|| ServerMessage::Shutdown)
+	}
+
+	/* stop accepting, let every client flush for up to `grace`, then close them and stop */
+	pub fn shutdown_gracefully(&self, grace: Duration) -> FiestaResult<()> {
+		self.broadcast(|| ServerMessage::GracefulShutdown(grace))
+	}
+
+	/* stop accepting while connected clients are still served, false to accept again */
+	pub fn drain(&self, draining: bool) -> FiestaResult<()> {
+		self.broadcast(|| ServerMessage::Drain(draining))
+	}
+
+	/* e.g. TraceFilter::opcodes(&[0x0801]) to dump one opcode, TraceFilter::Off to stop */
+	pub fn set_trace(&self, filter: TraceFilter) -> FiestaResult<()> {
+		self.broadcast(|| ServerMessage::SetTrace(filter.clone()))
+	}
+
+	/* applies `config` on every reactor */
+	pub fn reload_with(&self, config: RuntimeConfig) -> FiestaResult<()> {
+		self.broadcast(|| ServerMessage::Reload(config.clone()))
+	}
+
+	/* reads the config_path the server was built with again, nothing changes if it doesn't load */
+	pub fn reload(&self) -> FiestaResult<()> {
+		let path = match self.config_path {
+			Some(ref path) => path,
+			None => return Err(FiestaNetError::from(Error::new(ErrorKind::NotFound, "the server wasn't started from a config file"))),
+		};
+		let runtime = try!(try!(config::load(path)).runtime());
+		info!(target: "network", "reloading {}.", path.display());
+		self.reload_with(runtime)
+	}
+
+	pub fn config_path(&self) -> Option<&Path> {
+		self.config_path.as_ref().map(|path| path.as_path())
+	}
+
+	/* 0 with inline processing */
+	#[cfg(feature = "threads")]
+	pub fn workers(&self) -> usize {
+		self.pool.as_ref().map_or(0, |pool| pool.size())
+	}
+
+	/* scale packet processing without a restart */
+	#[cfg(feature = "threads")]
+	pub fn resize_workers(&self, threads: usize) -> FiestaResult<()> {
+		match self.pool {
+			Some(ref pool) => <PacketProcessingThreadPool as Clone>::clone(pool).resize(threads),
+			None => Err(FiestaNetError::from(Error::new(ErrorKind::InvalidInput, "the server processes packets inline, it has no workers"))),
+		}
+	}
+
+	#[cfg(not(feature = "threads"))]
+	pub fn workers(&self) -> usize {
+		0
+	}
+
+	#[cfg(not(feature = "threads"))]
+	pub fn resize_workers(&self, threads: usize) -> FiestaResult<()> {
+		Err(FiestaNetError::from(Error::new(ErrorKind::InvalidInput, "worker threads require the `threads` feature")))
+	}
+}
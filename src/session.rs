@@ -0,0 +1,212 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{Error, Read};
+use std::mem;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use bus::ClientGroup;
+use handle::ClientHandle;
+use protocol::ProtocolState;
+use version::ProtocolVersion;
+
+/* whatever the handlers keep on a client, one value per type, e.g. the account once it logged in */
+#[derive(Clone, Default)]
+pub struct Extensions {
+	map:			HashMap<TypeId, Arc<Any + Send + Sync>>,
+}
+
+impl Extensions {
+	pub fn new() -> Self {
+		Extensions::default()
+	}
+
+	/* returns the value it replaced */
+	pub fn insert<T: Any + Send + Sync>(&mut self, value: T) -> Option<Arc<T>> {
+		self.map.insert(TypeId::of::<T>(), Arc::new(value)).and_then(|old| old.downcast::<T>().ok())
+	}
+
+	pub fn get<T: Any + Send + Sync>(&self) -> Option<Arc<T>> {
+		self.map.get(&TypeId::of::<T>()).cloned().and_then(|value| value.downcast::<T>().ok())
+	}
+
+	pub fn remove<T: Any + Send + Sync>(&mut self) -> Option<Arc<T>> {
+		self.map.remove(&TypeId::of::<T>()).and_then(|value| value.downcast::<T>().ok())
+	}
+
+	pub fn len(&self) -> usize {
+		self.map.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.map.is_empty()
+	}
+}
+
+/* handed to the client on login, sent back on a reconnect. random, so it can't be guessed */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SessionToken(pub u64);
+
+impl SessionToken {
+	pub fn generate() -> Result<SessionToken, Error> {
+		let mut bytes = [0u8; 8];
+		try!(try!(File::open("/dev/urandom")).read_exact(&mut bytes));
+		let mut value = 0u64;
+		for byte in bytes.iter() {
+			value = (value << 8) | *byte as u64;
+		}
+		Ok(SessionToken(value))
+	}
+}
+
+impl fmt::Display for SessionToken {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{:016x}", self.0)
+	}
+}
+
+/* what a client had when it went away, back on the new connection if it resumes in time */
+pub struct SessionState {
+	pub label:				Option<String>,
+	pub protocol_state:		ProtocolState,
+	pub protocol_version:	Option<Arc<ProtocolVersion>>,
+	pub extensions:			Extensions,
+	pub groups:				Vec<ClientGroup>,
+}
+
+struct Suspended {
+	state:			SessionState,
+	deadline:		Instant,
+}
+
+/* sessions of clients that disconnected and may be back soon, e.g. from a flaky home connection */
+/* in the middle of a zone transfer. issue() on login, suspend() from on_disconnect, resume() when */
+/* the token comes back. cheap to clone, clones share the sessions */
+#[derive(Clone)]
+pub struct SessionStore {
+	grace:			Duration,
+	suspended:		Arc<Mutex<HashMap<SessionToken, Suspended>>>,
+}
+
+impl SessionStore {
+	pub fn new(grace: Duration) -> Self {
+		SessionStore {
+			grace:			grace,
+			suspended:		Arc::new(Mutex::new(HashMap::new())),
+		}
+	}
+
+	pub fn grace(&self) -> Duration {
+		self.grace
+	}
+
+	fn suspended(&self) -> MutexGuard<HashMap<SessionToken, Suspended>> {
+		match self.suspended.lock() {
+			Ok(suspended) => suspended,
+			Err(poisoned) => poisoned.into_inner(),
+		}
+	}
+
+	/* the client keeps its token as an extension, a second call hands out the same one */
+	pub fn issue(&self, client: &ClientHandle) -> Result<SessionToken, Error> {
+		if let Some(token) = client.extension::<SessionToken>() {
+			return Ok(*token);
+		}
+		let token = try!(SessionToken::generate());
+		client.insert_extension(token);
+		Ok(token)
+	}
+
+	/* keeps the state of a client that went away for the grace period and takes it out of its */
+	/* groups. returns false for clients that were never issued a token */
+	pub fn suspend(&self, client: &ClientHandle) -> bool {
+		let token = match client.extension::<SessionToken>() {
+			Some(token) => *token,
+			None => return false,
+		};
+		let client_lock = match client.client() {
+			Some(client) => client,
+			None => return false,
+		};
+		let state = {
+			let client = match client_lock.read() {
+				Ok(client) => client,
+				Err(poisoned) => poisoned.into_inner(),
+			};
+			debug!(target: "network", "{}: session {} suspended", client.describe(), token);
+			let extensions = mem::replace(&mut *client.extensions(), Extensions::new());
+			SessionState {
+				label:				client.label(),
+				protocol_state:		client.protocol_state(),
+				protocol_version:	client.protocol_version(),
+				extensions:			extensions,
+				groups:				client.groups(),
+			}
+		};
+		/* leave() looks at the client again, so not while it is locked here */
+		for group in state.groups.iter() {
+			group.leave(client.id());
+		}
+		self.suspended().insert(token, Suspended {
+			state:			state,
+			deadline:		Instant::now() + self.grace,
+		});
+		true
+	}
+
+	/* moves the session over to `client`, false if the token is unknown or came back too late */
+	pub fn resume(&self, token: SessionToken, client: &ClientHandle) -> bool {
+		let client_lock = match client.client() {
+			Some(client) => client,
+			None => return false,
+		};
+		let suspended = match self.suspended().remove(&token) {
+			Some(suspended) => suspended,
+			None => return false,
+		};
+		if suspended.deadline <= Instant::now() {
+			return false;
+		}
+		let state = suspended.state;
+		{
+			let client = match client_lock.read() {
+				Ok(client) => client,
+				Err(poisoned) => poisoned.into_inner(),
+			};
+			client.set_label(state.label);
+			client.set_protocol_state(state.protocol_state);
+			if let Some(version) = state.protocol_version {
+				client.set_protocol_version(version);
+			}
+			*client.extensions() = state.extensions;
+			debug!(target: "network", "{}: session {} resumed", client.describe(), token);
+		}
+		for group in state.groups.into_iter() {
+			group.join(client.clone());
+		}
+		true
+	}
+
+	pub fn len(&self) -> usize {
+		self.suspended().len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+
+	/* drops the sessions whose grace ran out and returns them, e.g. to save the character */
+	pub fn purge(&self) -> Vec<(SessionToken, SessionState)> {
+		let now = Instant::now();
+		let mut suspended = self.suspended();
+		let expired: Vec<SessionToken> = suspended.iter()
+			.filter(|&(_, session)| session.deadline <= now)
+			.map(|(&token, _)| token)
+			.collect();
+		expired.into_iter()
+			.filter_map(|token| suspended.remove(&token).map(|session| (token, session.state)))
+			.collect()
+	}
+}
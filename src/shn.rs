@@ -0,0 +1,281 @@
+use std::cmp::min;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{Error, Read};
+use std::path::Path;
+
+/* the client's data tables (ItemInfo.shn, MobInfo.shn, ...): a 32 byte header nobody uses, */
+/* the size of the rest, then the table itself xor-ed with a key that depends on its length */
+const CRYPT_HEADER: usize = 32;
+const COLUMN_NAME: usize = 48;
+
+#[derive(Debug)]
+pub enum ShnError {
+	Io(Error),
+	Truncated(&'static str),
+	UnknownColumn(String),
+	WrongType { column: String, expected: &'static str },
+}
+
+impl fmt::Display for ShnError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			ShnError::Io(ref e)								=> write!(f, "could not read shn file: {}", e),
+			ShnError::Truncated(what)						=> write!(f, "shn file ends in the middle of the {}", what),
+			ShnError::UnknownColumn(ref name)				=> write!(f, "no column `{}`", name),
+			ShnError::WrongType { ref column, expected }	=> write!(f, "column `{}` is not a {}", column, expected),
+		}
+	}
+}
+
+impl From<Error> for ShnError {
+	fn from(e: Error) -> Self {
+		ShnError::Io(e)
+	}
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ShnValue {
+	Byte(u8),
+	SByte(i8),
+	UShort(u16),
+	Short(i16),
+	UInt(u32),
+	Int(i32),
+	Float(f32),
+	Text(String),
+	/* column types nobody figured out yet */
+	Raw(Vec<u8>),
+}
+
+impl ShnValue {
+	/* any of the integer types, widened */
+	pub fn as_i64(&self) -> Option<i64> {
+		match *self {
+			ShnValue::Byte(v)	=> Some(v as i64),
+			ShnValue::SByte(v)	=> Some(v as i64),
+			ShnValue::UShort(v)	=> Some(v as i64),
+			ShnValue::Short(v)	=> Some(v as i64),
+			ShnValue::UInt(v)	=> Some(v as i64),
+			ShnValue::Int(v)	=> Some(v as i64),
+			_					=> None,
+		}
+	}
+
+	pub fn as_str(&self) -> Option<&str> {
+		match *self {
+			ShnValue::Text(ref text)	=> Some(text),
+			_							=> None,
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShnColumn {
+	pub name:			String,
+	pub kind:			u32,
+	pub length:			usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct ShnTable {
+	columns:		Vec<ShnColumn>,
+	/* column name -> index */
+	index:			HashMap<String, usize>,
+	rows:			Vec<Vec<ShnValue>>,
+}
+
+/* one row, values in column order */
+pub struct ShnRow<'a> {
+	table:			&'a ShnTable,
+	values:			&'a [ShnValue],
+}
+
+/* for typed rows, e.g. an ItemInfo struct built from the columns it needs */
+pub trait FromShnRow: Sized {
+	fn from_row(row: &ShnRow) -> Result<Self, ShnError>;
+}
+
+pub fn load<P: AsRef<Path>>(path: P) -> Result<ShnTable, ShnError> {
+	let mut data = Vec::new();
+	try!(try!(File::open(path)).read_to_end(&mut data));
+	ShnTable::parse(data)
+}
+
+/* the obfuscation is symmetric, this also encrypts */
+pub fn decrypt(data: &mut [u8]) {
+	let mut key = data.len() as u8;
+	for i in (0..data.len()).rev() {
+		data[i] ^= key;
+		let mut next = (i as u8) & 0x0f;
+		next = next.wrapping_add(0x55);
+		next ^= (i as u8).wrapping_mul(11);
+		next ^= key;
+		next ^= 0xaa;
+		key = next;
+	}
+}
+
+struct Reader<'a> {
+	data:			&'a [u8],
+	offset:			usize,
+}
+
+impl<'a> Reader<'a> {
+	fn bytes(&mut self, count: usize, what: &'static str) -> Result<&'a [u8], ShnError> {
+		if self.offset + count > self.data.len() {
+			return Err(ShnError::Truncated(what));
+		}
+		let bytes = &self.data[self.offset..self.offset + count];
+		self.offset += count;
+		Ok(bytes)
+	}
+
+	fn u16(&mut self, what: &'static str) -> Result<u16, ShnError> {
+		let b = try!(self.bytes(2, what));
+		Ok((b[0] as u16) | ((b[1] as u16) << 8))
+	}
+
+	fn u32(&mut self, what: &'static str) -> Result<u32, ShnError> {
+		let b = try!(self.bytes(4, what));
+		Ok((b[0] as u32) | ((b[1] as u32) << 8) | ((b[2] as u32) << 16) | ((b[3] as u32) << 24))
+	}
+}
+
+fn text(bytes: &[u8]) -> String {
+	let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+	String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+impl ShnTable {
+	/* the whole file as read from disk */
+	pub fn parse(mut data: Vec<u8>) -> Result<ShnTable, ShnError> {
+		if data.len() < CRYPT_HEADER + 4 {
+			return Err(ShnError::Truncated("file header"));
+		}
+		let length = {
+			let mut reader = Reader { data: &data[..], offset: CRYPT_HEADER };
+			try!(reader.u32("file header")) as usize
+		};
+		/* the length counts the header and itself */
+		let start = CRYPT_HEADER + 4;
+		if length < start || length > data.len() {
+			return Err(ShnError::Truncated("table"));
+		}
+		decrypt(&mut data[start..length]);
+
+		let mut reader = Reader { data: &data[start..length], offset: 0 };
+		let _header = try!(reader.u32("table header"));
+		let row_count = try!(reader.u32("table header")) as usize;
+		let _default_row_length = try!(reader.u32("table header"));
+		let column_count = try!(reader.u32("table header")) as usize;
+
+		let mut columns = Vec::new();
+		for _ in 0..column_count {
+			let name = text(try!(reader.bytes(COLUMN_NAME, "column list")));
+			let kind = try!(reader.u32("column list"));
+			let length = try!(reader.u32("column list")) as usize;
+			columns.push(ShnColumn { name: name, kind: kind, length: length });
+		}
+
+		/* not trusting the count for the allocation */
+		let mut rows = Vec::new();
+		for _ in 0..row_count {
+			let row_length = try!(reader.u16("rows")) as usize;
+			let row_end = reader.offset + row_length.saturating_sub(2);
+			if row_end > reader.data.len() {
+				return Err(ShnError::Truncated("rows"));
+			}
+			let mut values = Vec::with_capacity(columns.len());
+			for column in columns.iter() {
+				values.push(try!(ShnTable::read_value(&mut reader, column, row_end)));
+			}
+			/* rows may carry padding after the last column */
+			reader.offset = row_end;
+			rows.push(values);
+		}
+
+		let index = columns.iter().enumerate().map(|(i, column)| (column.name.clone(), i)).collect();
+		Ok(ShnTable {
+			columns:		columns,
+			index:			index,
+			rows:			rows,
+		})
+	}
+
+	fn read_value(reader: &mut Reader, column: &ShnColumn, row_end: usize) -> Result<ShnValue, ShnError> {
+		Ok(match column.kind {
+			1 | 12 | 16			=> ShnValue::Byte(try!(reader.bytes(1, "rows"))[0]),
+			20					=> ShnValue::SByte(try!(reader.bytes(1, "rows"))[0] as i8),
+			2					=> ShnValue::UShort(try!(reader.u16("rows"))),
+			13 | 21				=> ShnValue::Short(try!(reader.u16("rows")) as i16),
+			3 | 11 | 18 | 27	=> ShnValue::UInt(try!(reader.u32("rows"))),
+			22					=> ShnValue::Int(try!(reader.u32("rows")) as i32),
+			5					=> {
+				let bits = try!(reader.u32("rows"));
+				ShnValue::Float(f32::from_bits(bits))
+			},
+			9 | 24				=> ShnValue::Text(text(try!(reader.bytes(column.length, "rows")))),
+			/* zero terminated, the only column whose size isn't fixed */
+			26					=> {
+				let size = {
+					let rest = &reader.data[min(reader.offset, row_end)..row_end];
+					rest.iter().position(|&b| b == 0).map(|end| end + 1).unwrap_or(rest.len())
+				};
+				ShnValue::Text(text(try!(reader.bytes(size, "rows"))))
+			},
+			_					=> ShnValue::Raw(try!(reader.bytes(column.length, "rows")).to_vec()),
+		})
+	}
+
+	pub fn columns(&self) -> &[ShnColumn] {
+		&self.columns[..]
+	}
+
+	pub fn len(&self) -> usize {
+		self.rows.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.rows.is_empty()
+	}
+
+	pub fn rows<'a>(&'a self) -> Box<Iterator<Item = ShnRow<'a>> + 'a> {
+		Box::new(self.rows.iter().map(move |values| ShnRow { table: self, values: &values[..] }))
+	}
+
+	/* a row that doesn't convert comes out as an Err, the rows after it still follow */
+	pub fn rows_as<'a, T: FromShnRow + 'a>(&'a self) -> Box<Iterator<Item = Result<T, ShnError>> + 'a> {
+		Box::new(self.rows().map(|row| T::from_row(&row)))
+	}
+}
+
+impl<'a> ShnRow<'a> {
+	pub fn get(&self, column: &str) -> Result<&'a ShnValue, ShnError> {
+		let values: &'a [ShnValue] = self.values;
+		match self.table.index.get(column) {
+			Some(&i) => Ok(&values[i]),
+			None => Err(ShnError::UnknownColumn(column.to_string())),
+		}
+	}
+
+	pub fn values(&self) -> &'a [ShnValue] {
+		self.values
+	}
+
+	pub fn int(&self, column: &str) -> Result<i64, ShnError> {
+		try!(self.get(column)).as_i64().ok_or(ShnError::WrongType { column: column.to_string(), expected: "number" })
+	}
+
+	pub fn text(&self, column: &str) -> Result<&'a str, ShnError> {
+		try!(self.get(column)).as_str().ok_or(ShnError::WrongType { column: column.to_string(), expected: "string" })
+	}
+
+	pub fn float(&self, column: &str) -> Result<f32, ShnError> {
+		match *try!(self.get(column)) {
+			ShnValue::Float(v)	=> Ok(v),
+			_					=> Err(ShnError::WrongType { column: column.to_string(), expected: "float" }),
+		}
+	}
+}
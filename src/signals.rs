@@ -0,0 +1,44 @@
+use std::thread;
+use std::time::Duration;
+use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM};
+use signal_hook::iterator::{Handle, Signals};
+
+use error::FiestaResult;
+use server::ServerHandle;
+
+/* the first SIGTERM/SIGINT starts a graceful shutdown, another one while that runs stops right away */
+/* SIGHUP reloads the config file, it's only caught if the server has one */
+/* close() the returned handle once the server stopped, the default behaviour doesn't come back before that */
+pub fn watch(server: ServerHandle, grace: Duration) -> FiestaResult<Handle> {
+	let mut wanted = vec![SIGTERM, SIGINT];
+	if server.config_path().is_some() {
+		wanted.push(SIGHUP);
+	}
+	let mut signals = try!(Signals::new(&wanted));
+	let handle = signals.handle();
+	try!(thread::Builder::new()
+		.name("SIGNALS".to_string())
+		.spawn(move || {
+			let mut received = 0;
+			for signal in signals.forever() {
+				if signal == SIGHUP {
+					if let Err(e) = server.reload() {
+						warn!(target: "network", "SIGHUP received, keeping the current config: {}", e);
+					}
+					continue;
+				}
+				received += 1;
+				let result = if received == 1 {
+					info!(target: "network", "signal {} received, shutting down within {:?}.", signal, grace);
+					server.shutdown_gracefully(grace)
+				} else {
+					warn!(target: "network", "signal {} received again, shutting down now.", signal);
+					server.shutdown()
+				};
+				if let Err(e) = result {
+					warn!(target: "network", "failed to stop the server: {}", e);
+				}
+			}
+		}));
+	Ok(handle)
+}
@@ -0,0 +1,45 @@
+use std::io::Error;
+use std::mem;
+use std::net;
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::time::Duration;
+use mio::net::TcpStream;
+use net2::TcpStreamExt;
+
+/* applied to every accepted socket */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SocketOptions {
+	pub nodelay:		bool,
+	pub keepalive:		Option<Duration>,
+	/* Some(0) resets the connection on close instead of lingering in TIME_WAIT */
+	pub linger:			Option<Duration>,
+}
+
+impl Default for SocketOptions {
+	fn default() -> Self {
+		SocketOptions {
+			/* game traffic is lots of tiny packets, Nagle only adds latency */
+			nodelay:		true,
+			keepalive:		None,
+			linger:			None,
+		}
+	}
+}
+
+impl SocketOptions {
+	pub fn apply(&self, stream: &TcpStream) -> Result<(), Error> {
+		/* net2 only knows about std streams, so borrow the fd for a moment */
+		let std_stream = unsafe { net::TcpStream::from_raw_fd(stream.as_raw_fd()) };
+		let result = self.apply_std(&std_stream);
+		/* the fd still belongs to `stream` */
+		mem::forget(std_stream);
+		result
+	}
+
+	fn apply_std(&self, stream: &net::TcpStream) -> Result<(), Error> {
+		try!(stream.set_nodelay(self.nodelay));
+		try!(stream.set_keepalive(self.keepalive));
+		try!(stream.set_linger(self.linger));
+		Ok(())
+	}
+}
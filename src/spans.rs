@@ -0,0 +1,24 @@
+use std::net::SocketAddr;
+use tracing_log::log::SetLoggerError;
+use mio::Token;
+use tracing::Span;
+use tracing::field;
+use tracing_log::LogTracer;
+
+/* entered whenever the reactor or a worker does something for this connection */
+pub fn connection_span(token: Token, peer: Option<SocketAddr>) -> Span {
+	let peer = peer.map(|addr| addr.to_string()).unwrap_or("-".to_string());
+	/* the label is recorded once the handlers set one */
+	info_span!("connection", token = token.0, peer = %peer, label = field::Empty)
+}
+
+/* entered while a worker processes the packet */
+pub fn packet_span(connection: &Span, header: u16, size: usize) -> Span {
+	info_span!(parent: connection, "packet", opcode = header, size = size)
+}
+
+/* turns the crate's `log` records into tracing events, so they show up inside the spans */
+/* (log 0.3 forwards to 0.4, which is what tracing-log listens to) */
+pub fn bridge_log() -> Result<(), SetLoggerError> {
+	LogTracer::init()
+}
@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use mio::Token;
+
+use body::SharedBytes;
+use handle::ClientHandle;
+use packet::FiestaPacket;
+
+type Cell = (u32, u32);
+
+struct Grid {
+	cell_size:		u32,
+	cells:			HashMap<Cell, HashMap<Token, ClientHandle>>,
+	positions:		HashMap<Token, (u32, u32)>,
+}
+
+impl Grid {
+	fn cell(&self, x: u32, y: u32) -> Cell {
+		(x / self.cell_size, y / self.cell_size)
+	}
+
+	fn remove(&mut self, client: Token) {
+		let (x, y) = match self.positions.remove(&client) {
+			Some(position) => position,
+			None => return,
+		};
+		let cell = self.cell(x, y);
+		let empty = match self.cells.get_mut(&cell) {
+			Some(members) => {
+				members.remove(&client);
+				members.is_empty()
+			},
+			None => false,
+		};
+		if empty {
+			self.cells.remove(&cell);
+		}
+	}
+}
+
+/* clients by where they are on a map, for broadcasts that only matter nearby like movement. */
+/* positions are whatever the handlers report, in map units. cells should be about the usual */
+/* radius, a query looks at every cell the circle touches. cheap to clone, clones share the grid */
+#[derive(Clone)]
+pub struct SpatialGrid {
+	grid:			Arc<RwLock<Grid>>,
+}
+
+impl SpatialGrid {
+	pub fn new(cell_size: u32) -> Self {
+		assert!(cell_size > 0, "cells of a spatial grid can't be empty");
+		SpatialGrid {
+			grid:			Arc::new(RwLock::new(Grid {
+				cell_size:		cell_size,
+				cells:			HashMap::new(),
+				positions:		HashMap::new(),
+			})),
+		}
+	}
+
+	fn read(&self) -> RwLockReadGuard<Grid> {
+		match self.grid.read() {
+			Ok(grid) => grid,
+			Err(poisoned) => poisoned.into_inner(),
+		}
+	}
+
+	fn write(&self) -> RwLockWriteGuard<Grid> {
+		match self.grid.write() {
+			Ok(grid) => grid,
+			Err(poisoned) => poisoned.into_inner(),
+		}
+	}
+
+	/* adds the client or moves it */
+	pub fn update(&self, client: &ClientHandle, x: u32, y: u32) {
+		let id = client.id();
+		let mut grid = self.write();
+		let cell = grid.cell(x, y);
+		if let Some(&(old_x, old_y)) = grid.positions.get(&id) {
+			if grid.cell(old_x, old_y) == cell {
+				grid.positions.insert(id, (x, y));
+				return;
+			}
+		}
+		grid.remove(id);
+		grid.positions.insert(id, (x, y));
+		grid.cells.entry(cell).or_insert_with(HashMap::new).insert(id, client.clone());
+	}
+
+	/* e.g. on leaving the map, disconnected clients are dropped by the next send that finds them */
+	pub fn remove(&self, client: Token) {
+		self.write().remove(client);
+	}
+
+	pub fn position(&self, client: Token) -> Option<(u32, u32)> {
+		self.read().positions.get(&client).cloned()
+	}
+
+	pub fn len(&self) -> usize {
+		self.read().positions.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+
+	/* the clients within `radius` of x, y, the edge included */
+	pub fn nearby(&self, x: u32, y: u32, radius: u32) -> Vec<ClientHandle> {
+		let grid = self.read();
+		let (min_x, min_y) = grid.cell(x.saturating_sub(radius), y.saturating_sub(radius));
+		let (max_x, max_y) = grid.cell(x.saturating_add(radius), y.saturating_add(radius));
+		let limit = radius as u64 * radius as u64;
+		let area = (max_x - min_x + 1) as u64 * (max_y - min_y + 1) as u64;
+		/* a radius bigger than the map shouldn't mean looking at millions of empty cells */
+		let cells: Vec<&HashMap<Token, ClientHandle>> = if area > grid.cells.len() as u64 {
+			grid.cells.iter()
+				.filter(|&(&(cell_x, cell_y), _)| cell_x >= min_x && cell_x <= max_x && cell_y >= min_y && cell_y <= max_y)
+				.map(|(_, members)| members)
+				.collect()
+		} else {
+			(min_x..max_x + 1)
+				.flat_map(|cell_x| (min_y..max_y + 1).map(move |cell_y| (cell_x, cell_y)))
+				.filter_map(|cell| grid.cells.get(&cell))
+				.collect()
+		};
+		let mut found = Vec::new();
+		for members in cells.iter() {
+			for (id, client) in members.iter() {
+				let (client_x, client_y) = grid.positions[id];
+				let dx = (client_x as i64 - x as i64).abs() as u64;
+				let dy = (client_y as i64 - y as i64).abs() as u64;
+				if dx * dx + dy * dy <= limit {
+					found.push(client.clone());
+				}
+			}
+		}
+		found
+	}
+
+	/* the body is copied once and shared by every frame. returns how many clients it went to */
+	pub fn send_near(&self, x: u32, y: u32, radius: u32, packet: &FiestaPacket) -> usize {
+		self.send_to(self.nearby(x, y, radius), None, packet)
+	}
+
+	/* the usual movement broadcast, everyone around `client` but the client itself */
+	pub fn send_near_except(&self, client: Token, radius: u32, packet: &FiestaPacket) -> usize {
+		match self.position(client) {
+			Some((x, y)) => self.send_to(self.nearby(x, y, radius), Some(client), packet),
+			None => 0,
+		}
+	}
+
+	fn send_to(&self, clients: Vec<ClientHandle>, except: Option<Token>, packet: &FiestaPacket) -> usize {
+		let body = SharedBytes::from_vec(packet.data.to_vec());
+		let mut sent = 0;
+		for client in clients.iter() {
+			let id = client.id();
+			if Some(id) == except {
+				continue;
+			}
+			if !client.is_connected() {
+				self.remove(id);
+				continue;
+			}
+			match client.send_shared(packet.header, body.clone()) {
+				Ok(()) => sent += 1,
+				Err(e) => debug!(target: "network", "nearby send to {:?} failed: {}", id, e),
+			}
+		}
+		sent
+	}
+}
@@ -0,0 +1,95 @@
+use std::net::SocketAddr;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use mio::Token;
+
+/* live counters of one client, updated from the reactor and the workers */
+pub struct ClientCounters {
+	connected_at:		Instant,
+	last_activity:		Mutex<Instant>,
+	bytes_in:			AtomicUsize,
+	bytes_out:			AtomicUsize,
+	packets_in:			AtomicUsize,
+	packets_out:		AtomicUsize,
+}
+
+/* a snapshot, e.g. for a GM "who" command */
+#[derive(Debug, Clone)]
+pub struct ClientStats {
+	pub token:			Token,
+	pub addr:			Option<SocketAddr>,
+	/* see FiestaNetworkClient::set_label */
+	pub label:			Option<String>,
+	pub connected_for:	Duration,
+	/* since the client last sent anything */
+	pub idle_for:		Duration,
+	pub bytes_in:		usize,
+	pub bytes_out:		usize,
+	pub packets_in:		usize,
+	pub packets_out:	usize,
+	/* packets waiting for or being processed by a worker */
+	pub in_flight:		usize,
+	/* frames and bytes not yet written to the socket */
+	pub send_frames:	usize,
+	pub send_bytes:		usize,
+}
+
+impl ClientCounters {
+	pub fn new() -> Self {
+		let now = Instant::now();
+		ClientCounters {
+			connected_at:		now,
+			last_activity:		Mutex::new(now),
+			bytes_in:			AtomicUsize::new(0),
+			bytes_out:			AtomicUsize::new(0),
+			packets_in:			AtomicUsize::new(0),
+			packets_out:		AtomicUsize::new(0),
+		}
+	}
+
+	pub fn bytes_read(&self, bytes: usize) {
+		self.bytes_in.fetch_add(bytes, Ordering::Relaxed);
+		if let Ok(mut last) = self.last_activity.lock() {
+			*last = Instant::now();
+		}
+	}
+
+	pub fn bytes_written(&self, bytes: usize) {
+		self.bytes_out.fetch_add(bytes, Ordering::Relaxed);
+	}
+
+	pub fn packets_received(&self, count: usize) {
+		self.packets_in.fetch_add(count, Ordering::Relaxed);
+	}
+
+	pub fn packet_sent(&self) {
+		self.packets_out.fetch_add(1, Ordering::Relaxed);
+	}
+
+	pub fn connected_at(&self) -> Instant {
+		self.connected_at
+	}
+
+	pub fn idle_for(&self) -> Duration {
+		self.last_activity.lock().map(|last| last.elapsed()).unwrap_or(Duration::from_secs(0))
+	}
+
+	/* the queue numbers come from the client itself */
+	pub fn snapshot(&self, token: Token, addr: Option<SocketAddr>, in_flight: usize, send_frames: usize, send_bytes: usize) -> ClientStats {
+		ClientStats {
+			token:			token,
+			addr:			addr,
+			label:			None,
+			connected_for:	self.connected_at.elapsed(),
+			idle_for:		self.idle_for(),
+			bytes_in:		self.bytes_in.load(Ordering::Relaxed),
+			bytes_out:		self.bytes_out.load(Ordering::Relaxed),
+			packets_in:		self.packets_in.load(Ordering::Relaxed),
+			packets_out:	self.packets_out.load(Ordering::Relaxed),
+			in_flight:		in_flight,
+			send_frames:	send_frames,
+			send_bytes:		send_bytes,
+		}
+	}
+}
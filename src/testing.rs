@@ -0,0 +1,201 @@
+use std::fs::File;
+use std::io::{Error, ErrorKind, Read};
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use mio::Token;
+
+use client::FiestaNetworkClient;
+use packet::FiestaPacket;
+use error::FiestaResult;
+use framing::decode_stream_with;
+use hexdump::parse_hex;
+use limits::FrameLimits;
+use processing::{PacketProcessor, PacketProcessingInfo};
+use transport::{MemoryPeer, MemoryTransport};
+
+/* a client for unit testing processors without an event loop: bytes pushed in go through the real */
+/* framing code, whatever the processor sends is collected instead of written out. */
+/* the client sits on a MemoryTransport, nothing is ever read from or written to it. */
+pub struct MockClient {
+	client:			Arc<RwLock<Box<FiestaNetworkClient>>>,
+	/* the other end, kept for as long as the client */
+	peer:			MemoryPeer,
+}
+
+impl MockClient {
+	pub fn new(token: Token) -> Result<MockClient, Error> {
+		MockClient::with_client(token, |client| client)
+	}
+
+	/* e.g. `|client| client.with_frame_limits(limits)` */
+	pub fn with_client<F>(token: Token, configure: F) -> Result<MockClient, Error>
+			where F: FnOnce(FiestaNetworkClient) -> FiestaNetworkClient {
+		let (transport, peer) = MemoryTransport::pair();
+		Ok(MockClient {
+			client:			Arc::new(RwLock::new(Box::new(configure(FiestaNetworkClient::new(transport, token))))),
+			peer:			peer,
+		})
+	}
+
+	/* the far end of the client's transport, for code that goes around the send queue */
+	pub fn peer(&self) -> &MemoryPeer {
+		&self.peer
+	}
+
+	/* what a processor's PacketProcessingInfo wraps in a ClientHandle */
+	pub fn client(&self) -> Arc<RwLock<Box<FiestaNetworkClient>>> {
+		self.client.clone()
+	}
+
+	/* raw bytes as the game client would send them, a partial frame is kept for the next call */
+	pub fn push_bytes(&self, bytes: &[u8]) -> FiestaResult<Vec<FiestaPacket>> {
+		try!(self.client.read()).receive_bytes(bytes)
+	}
+
+	/* hands one packet to `processor` on the calling thread, counted in flight like the reactor does */
+	pub fn dispatch(&self, processor: &mut Box<PacketProcessor>, packet: FiestaPacket) {
+		processor.process_packet(PacketProcessingInfo::dispatched(packet, self.client.clone()));
+	}
+
+	/* frames `bytes` and dispatches every complete packet, returns how many there were */
+	pub fn feed(&self, processor: &mut Box<PacketProcessor>, bytes: &[u8]) -> FiestaResult<usize> {
+		let packets = try!(self.push_bytes(bytes));
+		let count = packets.len();
+		for packet in packets.into_iter() {
+			self.dispatch(processor, packet);
+		}
+		Ok(count)
+	}
+
+	/* the bytes sent to this client since the last call, frames as they would go on the wire */
+	pub fn sent_bytes(&self) -> FiestaResult<Vec<u8>> {
+		try!(self.client.read()).take_sent()
+	}
+
+	/* like sent_bytes, split into packets */
+	pub fn sent(&self) -> FiestaResult<Vec<FiestaPacket>> {
+		let bytes = try!(self.sent_bytes());
+		Ok(try!(split_frames(&bytes[..])))
+	}
+}
+
+/* outbound frames are complete by construction, anything left over is a bug in the sender */
+fn split_frames(bytes: &[u8]) -> Result<Vec<FiestaPacket>, Error> {
+	let mut packets = Vec::new();
+	let mut offset = 0;
+	while offset < bytes.len() {
+		let (size, prefix) = if bytes[offset] > 0 {
+			(bytes[offset] as usize, 1)
+		} else if offset + 3 <= bytes.len() {
+			(((bytes[offset + 1] as usize) << 8) | bytes[offset + 2] as usize, 3)
+		} else {
+			return Err(Error::new(ErrorKind::InvalidData, "truncated size prefix in sent data"));
+		};
+		let start = offset + prefix;
+		if start + 2 + size > bytes.len() {
+			return Err(Error::new(ErrorKind::InvalidData, "truncated frame in sent data"));
+		}
+		let header = ((bytes[start] as u16) << 8) | bytes[start + 1] as u16;
+		let mut packet = FiestaPacket::new(header, size);
+		packet.data.append(&bytes[start + 2..start + 2 + size]);
+		packets.push(packet);
+		offset = start + 2 + size;
+	}
+	Ok(packets)
+}
+
+/* the corpus shipped with the crate, see corpus/framing.txt for the format */
+const BUILTIN_CORPUS: &'static str = include_str!("../corpus/framing.txt");
+
+/* a known-good frame and the packet it stands for */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GoldenFrame {
+	pub name:			String,
+	pub header:			u16,
+	pub body:			Vec<u8>,
+	pub frame:			Vec<u8>,
+	/* valid on the wire, but encode() would produce a different frame */
+	pub decode_only:	bool,
+}
+
+fn corpus_error(line: usize, what: String) -> Error {
+	Error::new(ErrorKind::InvalidData, format!("corpus line {}: {}", line, what))
+}
+
+fn corpus_bytes(hex: &str) -> Result<Vec<u8>, Error> {
+	if hex == "-" {
+		Ok(Vec::new())
+	} else {
+		parse_hex(hex)
+	}
+}
+
+/* `name | header | body | frame [| decode-only]` per line, # starts a comment */
+pub fn parse_corpus(text: &str) -> Result<Vec<GoldenFrame>, Error> {
+	let mut frames = Vec::new();
+	for (number, line) in text.lines().enumerate() {
+		let line = line.trim();
+		if line.is_empty() || line.starts_with('#') {
+			continue;
+		}
+
+		let fields: Vec<&str> = line.split('|').map(|field| field.trim()).collect();
+		if fields.len() < 4 || fields.len() > 5 {
+			return Err(corpus_error(number + 1, format!("expected 4 or 5 fields, got {}", fields.len())));
+		}
+		let header = match u16::from_str_radix(fields[1].trim_left_matches("0x"), 16) {
+			Ok(header)	=> header,
+			Err(e)		=> return Err(corpus_error(number + 1, format!("bad header {}: {}", fields[1], e))),
+		};
+		let decode_only = match fields.get(4) {
+			None | Some(&"")		=> false,
+			Some(&"decode-only")	=> true,
+			Some(other)				=> return Err(corpus_error(number + 1, format!("unknown flag {}", other))),
+		};
+		frames.push(GoldenFrame {
+			name:			fields[0].to_string(),
+			header:			header,
+			body:			try!(corpus_bytes(fields[2]).map_err(|e| corpus_error(number + 1, e.to_string()))),
+			frame:			try!(corpus_bytes(fields[3]).map_err(|e| corpus_error(number + 1, e.to_string()))),
+			decode_only:	decode_only,
+		});
+	}
+	Ok(frames)
+}
+
+/* a downstream server's own corpus, in the same format as the builtin one */
+pub fn load_corpus<P: AsRef<Path>>(path: P) -> Result<Vec<GoldenFrame>, Error> {
+	let mut text = String::new();
+	try!(try!(File::open(path)).read_to_string(&mut text));
+	parse_corpus(&text)
+}
+
+pub fn builtin_corpus() -> Vec<GoldenFrame> {
+	parse_corpus(BUILTIN_CORPUS).expect("the builtin corpus is malformed")
+}
+
+/* encodes and decodes the frame, Err says what didn't match */
+pub fn check_frame(golden: &GoldenFrame) -> Result<(), String> {
+	if !golden.decode_only {
+		let encoded = try!(FiestaPacket::encode(golden.header, &golden.body[..]).map_err(|e| format!("{}: {}", golden.name, e)));
+		if encoded != golden.frame {
+			return Err(format!("{}: encoded to {:?}, expected {:?}", golden.name, encoded, golden.frame));
+		}
+	}
+
+	/* the corpus may hold frames beyond the default limit */
+	let limits = FrameLimits::new(0xffff);
+	let mut decoded = decode_stream_with(&golden.frame[..], &limits);
+	if decoded.len() != 1 {
+		return Err(format!("{}: decoded into {} frames", golden.name, decoded.len()));
+	}
+	match decoded.remove(0) {
+		Ok(packet) => {
+			if packet.header != golden.header || packet.data.to_vec() != golden.body {
+				return Err(format!("{}: decoded to {:?}", golden.name, packet));
+			}
+		},
+		Err(e) => return Err(format!("{}: {}", golden.name, e)),
+	}
+	Ok(())
+}
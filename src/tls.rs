@@ -0,0 +1,78 @@
+use std::io::{Error, ErrorKind, Read, Write};
+use std::sync::Arc;
+use rustls::{ServerSession, Session};
+
+use buffer::*;
+use outbound::FrameQueue;
+use transport::{Transport, TransportIo};
+
+pub use rustls::ServerConfig as TlsConfig;
+
+/* wraps the rustls state machine of a single accepted connection, over whatever Transport it came in on */
+pub struct TlsSession {
+	session:		ServerSession,
+}
+
+impl TlsSession {
+	pub fn new(config: &Arc<TlsConfig>) -> Self {
+		TlsSession {
+			session:		ServerSession::new(config),
+		}
+	}
+
+	/* Ok(None) means the peer closed the connection, otherwise the amount of plaintext appended to `plain` */
+	pub fn read(&mut self, transport: &Transport, plain: &mut Buffer) -> Result<Option<usize>, Error> {
+		let mut stream = TransportIo(transport);
+		if try!(self.session.read_tls(&mut stream)) == 0 {
+			return Ok(None);
+		}
+
+		if let Err(e) = self.session.process_new_packets() {
+			/* try to get the alert out before the socket goes away */
+			let _ = self.session.write_tls(&mut stream);
+			return Err(Error::new(ErrorKind::InvalidData, format!("tls error: {:?}", e)));
+		}
+
+		let mut total = 0;
+		let mut buf = [0; 2048];
+		loop {
+			match self.session.read(&mut buf[..]) {
+				Ok(0)	=> break,
+				Ok(n)	=> {
+					/* a burst of records can decrypt to more than the buffer has free, and whatever */
+					/* stayed in the session would wait for the next socket event, so the buffer grows */
+					plain.extend(&buf[0..n]);
+					total += n;
+				},
+				Err(e)	=> return Err(e),
+			}
+		}
+
+		Ok(Some(total))
+	}
+
+	/* moves pending plaintext into the session and flushes ciphertext, returns bytes written to the socket */
+	pub fn write(&mut self, transport: &Transport, plain: &mut FrameQueue) -> Result<usize, Error> {
+		let mut stream = TransportIo(transport);
+		let accepted = {
+			let chunk = plain.front_chunk();
+			if chunk.is_empty() { 0 } else { try!(self.session.write(chunk)) }
+		};
+		plain.consume(accepted);
+
+		self.session.write_tls(&mut stream)
+	}
+
+	pub fn wants_read(&self) -> bool {
+		self.session.wants_read()
+	}
+
+	/* true while the handshake (or queued ciphertext) still needs the socket to be writable */
+	pub fn wants_write(&self) -> bool {
+		self.session.wants_write()
+	}
+
+	pub fn is_handshaking(&self) -> bool {
+		self.session.is_handshaking()
+	}
+}
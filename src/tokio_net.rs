@@ -0,0 +1,365 @@
+use std::cmp::min;
+use std::future::Future;
+use std::io::{self, Error, ErrorKind};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, RwLock};
+use std::task::{Context, Poll};
+use bytes::BytesMut;
+use futures_core::Stream;
+use futures_util::task::AtomicWaker;
+use mio::Token;
+use tokio::io::AsyncWrite;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio_util::codec::{Decoder, Encoder, FramedRead};
+
+use body::SharedBytes;
+use client::{DisconnectReason, FiestaNetworkClient};
+use packet::FiestaPacket;
+use framing;
+use handle::ClientHandle;
+use limits::FrameLimits;
+use metrics::Metrics;
+use pool::BufferPool;
+use processing::{PacketProcessor, PacketProcessingInfo};
+use protocol::{StateRules, ViolationAction};
+
+/* the Fiesta framing for tokio_util, the same limits apply as on the mio reactor */
+#[derive(Clone)]
+pub struct FiestaCodec {
+	limits:			Arc<FrameLimits>,
+	pool:			BufferPool,
+}
+
+impl FiestaCodec {
+	pub fn new() -> Self {
+		FiestaCodec::with_frame_limits(FrameLimits::default())
+	}
+
+	pub fn with_frame_limits(limits: FrameLimits) -> Self {
+		FiestaCodec {
+			limits:			Arc::new(limits),
+			pool:			BufferPool::default(),
+		}
+	}
+
+	pub fn with_buffer_pool(mut self, pool: BufferPool) -> Self {
+		self.pool = pool;
+		self
+	}
+}
+
+impl Default for FiestaCodec {
+	fn default() -> Self {
+		FiestaCodec::new()
+	}
+}
+
+impl Decoder for FiestaCodec {
+	type Item = FiestaPacket;
+	type Error = Error;
+
+	fn decode(&mut self, src: &mut BytesMut) -> Result<Option<FiestaPacket>, Error> {
+		let available = src.len();
+		/* next_frame_size only looks at the size prefix */
+		let mut prefix = SharedBytes::from_vec(src[0..min(available, 5)].to_vec());
+		let (size, prefix) = match try!(framing::next_frame_size(&mut prefix, available, &self.limits)) {
+			Some(next)	=> next,
+			None		=> return Ok(None),
+		};
+		let total_size = prefix + 2 + size as usize;
+		if available < total_size {
+			src.reserve(total_size - available);
+			return Ok(None);
+		}
+
+		let frame = src.split_to(total_size);
+		let header = ((frame[prefix] as u16) << 8) | frame[prefix + 1] as u16;
+		try!(self.limits.check(header, size as usize));
+		let mut packet = FiestaPacket::from_pool(&self.pool, header, size as usize);
+		packet.data.append(&frame[prefix + 2..]);
+		Ok(Some(packet))
+	}
+}
+
+impl Encoder<FiestaPacket> for FiestaCodec {
+	type Error = Error;
+
+	fn encode(&mut self, packet: FiestaPacket, dst: &mut BytesMut) -> Result<(), Error> {
+		dst.extend_from_slice(&try!(FiestaPacket::encode(packet.header, &packet.data.to_vec()[..]))[..]);
+		Ok(())
+	}
+}
+
+pub type HandlerFuture = Pin<Box<Future<Output = ()> + Send + 'static>>;
+
+/* a packet handler that may wait, e.g. on a database during login. */
+/* a client's packets are handled one after the other, the next one isn't read before the future is done. */
+/* implemented for `async fn(ClientHandle, FiestaPacket)` and closures returning a future */
+pub trait AsyncHandler: Send + Sync + 'static {
+	fn handle(&self, client: ClientHandle, packet: FiestaPacket) -> HandlerFuture;
+}
+
+impl<F, R> AsyncHandler for F
+		where F: Fn(ClientHandle, FiestaPacket) -> R + Send + Sync + 'static,
+			R: Future<Output = ()> + Send + 'static {
+	fn handle(&self, client: ClientHandle, packet: FiestaPacket) -> HandlerFuture {
+		Box::pin(self(client, packet))
+	}
+}
+
+/* runs a synchronous PacketProcessor as an AsyncHandler, the packet is handed over as on the mio */
+/* reactor and the future is ready right away. a processor that blocks blocks the runtime thread, */
+/* so it should be a PacketProcessingThreadPool or something else that queues */
+pub struct ProcessorHandler {
+	processor:		Mutex<Box<PacketProcessor>>,
+}
+
+impl ProcessorHandler {
+	pub fn new(processor: Box<PacketProcessor>) -> Self {
+		ProcessorHandler {
+			processor:		Mutex::new(processor),
+		}
+	}
+}
+
+impl AsyncHandler for ProcessorHandler {
+	fn handle(&self, client: ClientHandle, packet: FiestaPacket) -> HandlerFuture {
+		let client = match client.client() {
+			Some(client) => client,
+			None => return Box::pin(::std::future::ready(())),
+		};
+		/* not counted(), the connection counts the packet out once this future is done */
+		let info = PacketProcessingInfo::new(packet, client);
+		match self.processor.lock() {
+			Ok(mut processor) => processor.process_packet(info),
+			Err(_) => warn!(target: "network", "processor lock poisoned, dropping packet."),
+		}
+		Box::pin(::std::future::ready(()))
+	}
+}
+
+/* accepts clients on a tokio listener and spawns a task per connection, for embedding the server */
+/* in an existing async application. resolves only if accepting fails. */
+/* processors see the same FiestaNetworkClient as on the mio reactor; read backpressure, the byte */
+/* rate limit and tls are not available here. */
+pub struct TokioServer {
+	listener:		TcpListener,
+	handler:		Arc<AsyncHandler>,
+	codec:			FiestaCodec,
+	state_rules:	Option<Arc<StateRules>>,
+	metrics:		Arc<Metrics>,
+	token_count:	usize,
+}
+
+impl TokioServer {
+	/* must be polled from within a tokio runtime */
+	pub fn new(listener: TcpListener, processor: Box<PacketProcessor>) -> Self {
+		TokioServer::with_handler(listener, ProcessorHandler::new(processor))
+	}
+
+	pub fn with_handler<H: AsyncHandler>(listener: TcpListener, handler: H) -> Self {
+		TokioServer {
+			listener:		listener,
+			handler:		Arc::new(handler),
+			codec:			FiestaCodec::new(),
+			state_rules:	None,
+			metrics:		Arc::new(Metrics::new()),
+			token_count:	0,
+		}
+	}
+
+	pub fn with_codec(mut self, codec: FiestaCodec) -> Self {
+		self.codec = codec;
+		self
+	}
+
+	pub fn with_state_rules(mut self, rules: StateRules) -> Self {
+		self.state_rules = Some(Arc::new(rules));
+		self
+	}
+
+	pub fn metrics(&self) -> Arc<Metrics> {
+		self.metrics.clone()
+	}
+
+	fn connect(&mut self, stream: TcpStream) -> io::Result<Connection> {
+		self.token_count += 1;
+		let token = Token(self.token_count);
+
+		/* the client keeps a second handle to the socket, it's only used for disconnect() and the peer address */
+		let stream = try!(stream.into_std());
+		let shadow = try!(stream.try_clone());
+		let stream = try!(TcpStream::from_std(stream));
+		let (reader, writer) = stream.into_split();
+
+		let waker = Arc::new(AtomicWaker::new());
+		let mut client = FiestaNetworkClient::new(::mio::net::TcpStream::from_std(shadow), token)
+			.with_frame_limits(self.codec.limits.clone())
+			.with_buffer_pool(self.codec.pool.clone())
+			.with_metrics(self.metrics.clone())
+			.with_send_waker(waker.clone());
+		if let Some(ref rules) = self.state_rules {
+			client = client.with_state_rules(rules.clone());
+		}
+		info!(target: "network", "accepted client {}", client.describe());
+		self.metrics.connection_accepted();
+
+		Ok(Connection {
+			reader:			FramedRead::new(reader, self.codec.clone()),
+			writer:			writer,
+			client:			Arc::new(RwLock::new(Box::new(client))),
+			handler:		self.handler.clone(),
+			current:		None,
+			waker:			waker,
+			pending:		Vec::new(),
+			written:		0,
+			metrics:		self.metrics.clone(),
+		})
+	}
+}
+
+impl Future for TokioServer {
+	type Output = io::Result<()>;
+
+	fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+		let this = self.get_mut();
+		loop {
+			let stream = match this.listener.poll_accept(cx) {
+				Poll::Ready(Ok((stream, _)))	=> stream,
+				Poll::Ready(Err(e))				=> return Poll::Ready(Err(e)),
+				Poll::Pending					=> return Poll::Pending,
+			};
+			match this.connect(stream) {
+				Ok(connection) => {
+					::tokio::spawn(connection);
+				},
+				Err(e) => {
+					/* only this connection is affected, keep accepting */
+					warn!(target: "network", "failed to set up new client, dropping it: {}", e);
+				}
+			}
+		}
+	}
+}
+
+/* one client: frames are dispatched as they are decoded, whatever the processor sends is written */
+/* out once append_send wakes the task */
+struct Connection {
+	reader:			FramedRead<OwnedReadHalf, FiestaCodec>,
+	writer:			OwnedWriteHalf,
+	client:			Arc<RwLock<Box<FiestaNetworkClient>>>,
+	handler:		Arc<AsyncHandler>,
+	/* the packet being handled, nothing is read until it's done */
+	current:		Option<HandlerFuture>,
+	waker:			Arc<AtomicWaker>,
+	/* taken from the send buffer, `written` bytes of it are out already */
+	pending:		Vec<u8>,
+	written:		usize,
+	metrics:		Arc<Metrics>,
+}
+
+impl Connection {
+	fn check_state(&self, header: u16) -> Result<(), ViolationAction> {
+		match self.client.read() {
+			Ok(client) => client.check_state(header),
+			Err(_) => Err(ViolationAction::Disconnect),
+		}
+	}
+
+	fn dispatch(&mut self, packet: FiestaPacket) -> HandlerFuture {
+		if let Ok(client) = self.client.read() {
+			client.record_inbound(&packet);
+			client.packets_dispatched(1);
+		}
+		self.handler.handle(ClientHandle::new(self.client.clone()), packet)
+	}
+
+	fn handled(&mut self) {
+		if let Ok(client) = self.client.read() {
+			client.packet_processed();
+		}
+	}
+
+	/* Ready(Err) once the socket is unusable, Pending once everything queued is written */
+	fn poll_flush(&mut self, cx: &mut Context) -> Poll<io::Result<()>> {
+		loop {
+			if self.written == self.pending.len() {
+				self.pending = match self.client.read() {
+					Ok(client) => client.take_sent().unwrap_or(Vec::new()),
+					Err(_) => return Poll::Ready(Err(Error::new(ErrorKind::Other, "client lock poisoned"))),
+				};
+				self.written = 0;
+				if self.pending.is_empty() {
+					return Poll::Pending;
+				}
+			}
+			match Pin::new(&mut self.writer).poll_write(cx, &self.pending[self.written..]) {
+				Poll::Ready(Ok(0))		=> return Poll::Ready(Err(Error::new(ErrorKind::WriteZero, "socket closed"))),
+				Poll::Ready(Ok(size))	=> {
+					self.written += size;
+					self.metrics.bytes_written(size);
+				},
+				Poll::Ready(Err(e))		=> return Poll::Ready(Err(e)),
+				Poll::Pending			=> return Poll::Pending,
+			}
+		}
+	}
+
+	fn close(&mut self, reason: DisconnectReason) -> Poll<()> {
+		let reason = match self.client.read() {
+			Ok(client) => {
+				let reason = client.close(reason);
+				info!(target: "network", "client {} disconnected: {}.", client.describe(), reason);
+				reason
+			},
+			Err(_) => reason,
+		};
+		self.metrics.disconnected(&reason);
+		Poll::Ready(())
+	}
+}
+
+impl Future for Connection {
+	type Output = ();
+
+	fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+		let this = self.get_mut();
+		/* before anything is taken from the send buffer, so a send from here on wakes us again */
+		this.waker.register(cx.waker());
+
+		loop {
+			if let Some(mut current) = this.current.take() {
+				match current.as_mut().poll(cx) {
+					Poll::Ready(()) => this.handled(),
+					Poll::Pending => {
+						this.current = Some(current);
+						break;
+					}
+				}
+			}
+
+			match Pin::new(&mut this.reader).poll_next(cx) {
+				Poll::Ready(Some(Ok(packet))) => match this.check_state(packet.header) {
+					Ok(()) => this.current = Some(this.dispatch(packet)),
+					Err(ViolationAction::Drop) => {},
+					Err(ViolationAction::Disconnect) => return this.close(DisconnectReason::ProtocolViolation),
+				},
+				Poll::Ready(Some(Err(e))) => {
+					warn!(target: "network", "dropping client {:?}: {}", this.client.read().map(|client| client.id()).ok(), e);
+					this.metrics.frame_error();
+					return this.close(DisconnectReason::ReadError(e));
+				},
+				/* EOF, or disconnect() shut the socket down */
+				Poll::Ready(None) => return this.close(DisconnectReason::PeerClosed),
+				Poll::Pending => break,
+			}
+		}
+
+		if let Poll::Ready(Err(e)) = this.poll_flush(cx) {
+			debug!(target: "network", "write failed: {}", e);
+			return this.close(DisconnectReason::WriteStall);
+		}
+		Poll::Pending
+	}
+}
@@ -0,0 +1,29 @@
+use std::collections::HashSet;
+
+/* which packets get a full hex dump in the log, on receive and on send */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TraceFilter {
+	Off,
+	All,
+	Opcodes(HashSet<u16>),
+}
+
+impl TraceFilter {
+	pub fn opcodes(opcodes: &[u16]) -> Self {
+		TraceFilter::Opcodes(opcodes.iter().cloned().collect())
+	}
+
+	pub fn matches(&self, header: u16) -> bool {
+		match *self {
+			TraceFilter::Off					=> false,
+			TraceFilter::All					=> true,
+			TraceFilter::Opcodes(ref opcodes)	=> opcodes.contains(&header),
+		}
+	}
+}
+
+impl Default for TraceFilter {
+	fn default() -> Self {
+		TraceFilter::Off
+	}
+}
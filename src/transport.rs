@@ -0,0 +1,285 @@
+use std::cmp::min;
+use std::collections::VecDeque;
+use std::io::{Error, ErrorKind, Read, Write};
+use std::net::{Shutdown, SocketAddr};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::ptr;
+use std::sync::{Arc, Mutex, MutexGuard};
+use libc;
+use mio::{Interest, Registry, Token};
+use mio::net::{TcpStream, UnixStream};
+use mio::unix::SourceFd;
+
+/* iovecs per writev, well below any IOV_MAX */
+pub const MAX_IOVECS: usize = 64;
+
+/* what a client's bytes go through. everything takes &self, the reactor reads and writes while */
+/* any thread may shut the connection down. the framing, tls included, only ever sees this */
+pub trait Transport: Send + Sync + 'static {
+	fn read(&self, buf: &mut [u8]) -> Result<usize, Error>;
+
+	fn write(&self, buf: &[u8]) -> Result<usize, Error>;
+
+	/* as much of `bufs` as goes in one call, in order. the default writes the first non-empty one */
+	fn write_vectored(&self, bufs: &[&[u8]]) -> Result<usize, Error> {
+		match bufs.iter().find(|buf| !buf.is_empty()) {
+			Some(buf) => self.write(buf),
+			None => Ok(0),
+		}
+	}
+
+	/* bytes write() accepted that aren't on the wire yet, the client stays interested in writable */
+	fn wants_write(&self) -> bool {
+		false
+	}
+
+	/* gets them out, Err(WouldBlock) while some are left */
+	fn flush(&self) -> Result<(), Error> {
+		Ok(())
+	}
+
+	/* both directions, the reactor notices on the next event */
+	fn shutdown(&self) -> Result<(), Error>;
+
+	fn register(&self, registry: &Registry, token: Token, interest: Interest) -> Result<(), Error>;
+
+	fn reregister(&self, registry: &Registry, token: Token, interest: Interest) -> Result<(), Error>;
+
+	fn deregister(&self, registry: &Registry) -> Result<(), Error>;
+
+	/* None for transports without an ip address, e.g. unix sockets */
+	fn peer_addr(&self) -> Option<SocketAddr> {
+		None
+	}
+
+	fn local_addr(&self) -> Option<SocketAddr> {
+		None
+	}
+}
+
+/* Read and Write over a shared transport, for code written against std::io like rustls */
+pub struct TransportIo<'a>(pub &'a Transport);
+
+impl<'a> Read for TransportIo<'a> {
+	fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+		self.0.read(buf)
+	}
+}
+
+impl<'a> Write for TransportIo<'a> {
+	fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+		self.0.write(buf)
+	}
+
+	fn flush(&mut self) -> Result<(), Error> {
+		Ok(())
+	}
+}
+
+/* one writev straight from the slices. registering goes by fd too, mio wants the stream mutably */
+fn writev(fd: RawFd, bufs: &[&[u8]]) -> Result<usize, Error> {
+	let mut iov = [libc::iovec { iov_base: ptr::null_mut(), iov_len: 0 }; MAX_IOVECS];
+	let count = min(bufs.len(), MAX_IOVECS);
+	for (iov, buf) in iov.iter_mut().zip(bufs.iter()) {
+		*iov = libc::iovec { iov_base: buf.as_ptr() as *mut libc::c_void, iov_len: buf.len() };
+	}
+	let written = unsafe { libc::writev(fd, iov.as_ptr(), count as libc::c_int) };
+	if written < 0 {
+		return Err(Error::last_os_error());
+	}
+	Ok(written as usize)
+}
+
+impl Transport for TcpStream {
+	fn read(&self, buf: &mut [u8]) -> Result<usize, Error> {
+		let mut stream: &TcpStream = self;
+		Read::read(&mut stream, buf)
+	}
+
+	fn write(&self, buf: &[u8]) -> Result<usize, Error> {
+		let mut stream: &TcpStream = self;
+		Write::write(&mut stream, buf)
+	}
+
+	fn write_vectored(&self, bufs: &[&[u8]]) -> Result<usize, Error> {
+		writev(self.as_raw_fd(), bufs)
+	}
+
+	fn shutdown(&self) -> Result<(), Error> {
+		TcpStream::shutdown(self, Shutdown::Both)
+	}
+
+	fn register(&self, registry: &Registry, token: Token, interest: Interest) -> Result<(), Error> {
+		registry.register(&mut SourceFd(&self.as_raw_fd()), token, interest)
+	}
+
+	fn reregister(&self, registry: &Registry, token: Token, interest: Interest) -> Result<(), Error> {
+		registry.reregister(&mut SourceFd(&self.as_raw_fd()), token, interest)
+	}
+
+	fn deregister(&self, registry: &Registry) -> Result<(), Error> {
+		registry.deregister(&mut SourceFd(&self.as_raw_fd()))
+	}
+
+	fn peer_addr(&self) -> Option<SocketAddr> {
+		TcpStream::peer_addr(self).ok()
+	}
+
+	fn local_addr(&self) -> Option<SocketAddr> {
+		TcpStream::local_addr(self).ok()
+	}
+}
+
+/* e.g. a login server talking to zone servers on the same host */
+impl Transport for UnixStream {
+	fn read(&self, buf: &mut [u8]) -> Result<usize, Error> {
+		let mut stream: &UnixStream = self;
+		Read::read(&mut stream, buf)
+	}
+
+	fn write(&self, buf: &[u8]) -> Result<usize, Error> {
+		let mut stream: &UnixStream = self;
+		Write::write(&mut stream, buf)
+	}
+
+	fn write_vectored(&self, bufs: &[&[u8]]) -> Result<usize, Error> {
+		writev(self.as_raw_fd(), bufs)
+	}
+
+	fn shutdown(&self) -> Result<(), Error> {
+		UnixStream::shutdown(self, Shutdown::Both)
+	}
+
+	fn register(&self, registry: &Registry, token: Token, interest: Interest) -> Result<(), Error> {
+		registry.register(&mut SourceFd(&self.as_raw_fd()), token, interest)
+	}
+
+	fn reregister(&self, registry: &Registry, token: Token, interest: Interest) -> Result<(), Error> {
+		registry.reregister(&mut SourceFd(&self.as_raw_fd()), token, interest)
+	}
+
+	fn deregister(&self, registry: &Registry) -> Result<(), Error> {
+		registry.deregister(&mut SourceFd(&self.as_raw_fd()))
+	}
+}
+
+struct Pipes {
+	/* from the peer to the client */
+	inbound:		VecDeque<u8>,
+	/* from the client to the peer */
+	outbound:		Vec<u8>,
+	closed:			bool,
+}
+
+/* a connection that never leaves the process, for tests that drive a client by hand. there is */
+/* nothing to poll, registering it does nothing and the reactor never hears from it */
+pub struct MemoryTransport {
+	pipes:			Arc<Mutex<Pipes>>,
+}
+
+/* the other end of a MemoryTransport */
+#[derive(Clone)]
+pub struct MemoryPeer {
+	pipes:			Arc<Mutex<Pipes>>,
+}
+
+impl MemoryTransport {
+	pub fn pair() -> (MemoryTransport, MemoryPeer) {
+		let pipes = Arc::new(Mutex::new(Pipes {
+			inbound:		VecDeque::new(),
+			outbound:		Vec::new(),
+			closed:			false,
+		}));
+		(MemoryTransport { pipes: pipes.clone() }, MemoryPeer { pipes: pipes })
+	}
+
+	fn pipes(&self) -> MutexGuard<Pipes> {
+		match self.pipes.lock() {
+			Ok(pipes) => pipes,
+			Err(poisoned) => poisoned.into_inner(),
+		}
+	}
+}
+
+impl Transport for MemoryTransport {
+	/* WouldBlock while the peer has nothing to say, like a nonblocking socket */
+	fn read(&self, buf: &mut [u8]) -> Result<usize, Error> {
+		let mut pipes = self.pipes();
+		if pipes.inbound.is_empty() {
+			if pipes.closed {
+				return Ok(0);
+			}
+			return Err(Error::new(ErrorKind::WouldBlock, "nothing sent yet"));
+		}
+		let size = min(buf.len(), pipes.inbound.len());
+		for (target, byte) in buf.iter_mut().zip(pipes.inbound.drain(..size)) {
+			*target = byte;
+		}
+		Ok(size)
+	}
+
+	fn write(&self, buf: &[u8]) -> Result<usize, Error> {
+		let mut pipes = self.pipes();
+		if pipes.closed {
+			return Err(Error::new(ErrorKind::BrokenPipe, "memory transport closed"));
+		}
+		pipes.outbound.extend_from_slice(buf);
+		Ok(buf.len())
+	}
+
+	fn write_vectored(&self, bufs: &[&[u8]]) -> Result<usize, Error> {
+		let mut total = 0;
+		for buf in bufs.iter() {
+			total += try!(self.write(buf));
+		}
+		Ok(total)
+	}
+
+	fn shutdown(&self) -> Result<(), Error> {
+		self.pipes().closed = true;
+		Ok(())
+	}
+
+	fn register(&self, _registry: &Registry, _token: Token, _interest: Interest) -> Result<(), Error> {
+		Ok(())
+	}
+
+	fn reregister(&self, _registry: &Registry, _token: Token, _interest: Interest) -> Result<(), Error> {
+		Ok(())
+	}
+
+	fn deregister(&self, _registry: &Registry) -> Result<(), Error> {
+		Ok(())
+	}
+}
+
+impl MemoryPeer {
+	fn pipes(&self) -> MutexGuard<Pipes> {
+		match self.pipes.lock() {
+			Ok(pipes) => pipes,
+			Err(poisoned) => poisoned.into_inner(),
+		}
+	}
+
+	/* for the client's next read */
+	pub fn send(&self, bytes: &[u8]) {
+		self.pipes().inbound.extend(bytes.iter().cloned());
+	}
+
+	/* what the client wrote since the last call */
+	pub fn received(&self) -> Vec<u8> {
+		let mut pipes = self.pipes();
+		let received = pipes.outbound.clone();
+		pipes.outbound.clear();
+		received
+	}
+
+	/* the client reads EOF once it has read everything sent before */
+	pub fn close(&self) {
+		self.pipes().closed = true;
+	}
+
+	pub fn is_closed(&self) -> bool {
+		self.pipes().closed
+	}
+}
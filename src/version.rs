@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use client::DisconnectReason;
+use handle::ClientHandle;
+use login::{fixed_string, LoginLayout, LoginOpcodes};
+use processing::{Middleware, Next, PacketProcessor, PacketProcessingInfo, Tick};
+
+/* one client build's opcode numbering. the server is written against canonical opcodes, */
+/* the ones missing from the table are the same on the wire */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProtocolVersion {
+	name:			String,
+	/* wire -> canonical */
+	inbound:		HashMap<u16, u16>,
+	/* canonical -> wire */
+	outbound:		HashMap<u16, u16>,
+}
+
+impl ProtocolVersion {
+	pub fn new<S: Into<String>>(name: S) -> Self {
+		ProtocolVersion {
+			name:			name.into(),
+			inbound:		HashMap::new(),
+			outbound:		HashMap::new(),
+		}
+	}
+
+	/* this build sends and expects `canonical` as `wire` */
+	pub fn map(mut self, canonical: u16, wire: u16) -> Self {
+		self.inbound.insert(wire, canonical);
+		self.outbound.insert(canonical, wire);
+		self
+	}
+
+	pub fn name(&self) -> &str {
+		&self.name
+	}
+
+	pub fn to_canonical(&self, wire: u16) -> u16 {
+		self.inbound.get(&wire).cloned().unwrap_or(wire)
+	}
+
+	pub fn to_wire(&self, canonical: u16) -> u16 {
+		self.outbound.get(&canonical).cloned().unwrap_or(canonical)
+	}
+}
+
+/* which ProtocolVersion a client gets for the version string it sends in the version check */
+#[derive(Debug, Clone, Default)]
+pub struct VersionTable {
+	versions:		HashMap<String, Arc<ProtocolVersion>>,
+	/* for version strings that aren't in the table, None leaves such clients untranslated */
+	fallback:		Option<Arc<ProtocolVersion>>,
+}
+
+impl VersionTable {
+	pub fn new() -> Self {
+		VersionTable::default()
+	}
+
+	pub fn version<S: Into<String>>(mut self, client_version: S, version: ProtocolVersion) -> Self {
+		self.versions.insert(client_version.into(), Arc::new(version));
+		self
+	}
+
+	pub fn fallback(mut self, version: ProtocolVersion) -> Self {
+		self.fallback = Some(Arc::new(version));
+		self
+	}
+
+	pub fn select(&self, client_version: &str) -> Option<Arc<ProtocolVersion>> {
+		self.versions.get(client_version).cloned().or_else(|| self.fallback.clone())
+	}
+}
+
+/* picks the client's ProtocolVersion from its version check and rewrites every later packet's */
+/* header to the canonical opcode. goes in front of the LoginLayer, the version check is passed on */
+pub struct VersionLayer {
+	table:			Arc<VersionTable>,
+	version_req:	u16,
+	version_len:	usize,
+}
+
+impl VersionLayer {
+	pub fn new(table: VersionTable) -> Self {
+		VersionLayer {
+			table:			Arc::new(table),
+			version_req:	LoginOpcodes::default().version_req,
+			version_len:	LoginLayout::default().version_len,
+		}
+	}
+
+	/* where the version check is, the same on every build that should be told apart */
+	pub fn with_version_check(mut self, opcode: u16, version_len: usize) -> Self {
+		self.version_req = opcode;
+		self.version_len = version_len;
+		self
+	}
+}
+
+impl Middleware for VersionLayer {
+	fn handle(&self, mut info: PacketProcessingInfo, next: Next) {
+		{
+			let client_lock = match info.client.client() {
+				Some(client) => client,
+				None => return,
+			};
+			let client = match client_lock.read() {
+				Ok(client) => client,
+				Err(_) => return,
+			};
+			let packet = &mut info.packet;
+			match client.protocol_version() {
+				Some(version) => packet.header = version.to_canonical(packet.header),
+				None if packet.header == self.version_req => {
+					let body = packet.data.to_vec();
+					if let Some(client_version) = fixed_string(&body[..], 0, self.version_len) {
+						match self.table.select(&client_version) {
+							Some(version) => {
+								info!(target: "network", "{:?}: client {} speaks {}.", client.id(), client_version, version.name());
+								client.set_protocol_version(version);
+							},
+							None => debug!(target: "network", "{:?}: no protocol version for client {}.", client.id(), client_version),
+						}
+					}
+				},
+				None => {},
+			}
+		}
+		next.run(info);
+	}
+}
+
+/* hands packets to the processor for the client's protocol version, e.g. where a struct layout */
+/* changed between builds. clients without a version, or one without a processor, go to `fallback` */
+pub struct VersionRouter {
+	processors:		HashMap<String, Box<PacketProcessor>>,
+	fallback:		Box<PacketProcessor>,
+}
+
+impl VersionRouter {
+	pub fn new(fallback: Box<PacketProcessor>) -> Self {
+		VersionRouter {
+			processors:		HashMap::new(),
+			fallback:		fallback,
+		}
+	}
+
+	/* `name` as in ProtocolVersion::name() */
+	pub fn version<S: Into<String>>(mut self, name: S, processor: Box<PacketProcessor>) -> Self {
+		self.processors.insert(name.into(), processor);
+		self
+	}
+}
+
+impl PacketProcessor for VersionRouter {
+	fn process_packet(&mut self, info: PacketProcessingInfo) {
+		let version = match info.client.client() {
+			Some(client) => client.read().ok().and_then(|client| client.protocol_version()),
+			None => return,
+		};
+		let processor = match version {
+			Some(ref version) if self.processors.contains_key(version.name()) => self.processors.get_mut(version.name()).unwrap(),
+			_ => &mut self.fallback,
+		};
+		processor.process_packet(info);
+	}
+
+	/* the world is the same whatever the clients speak, the fallback gets the tick */
+	fn tick(&mut self, tick: Tick) {
+		self.fallback.tick(tick);
+	}
+
+	/* to the processor that had the client's packets */
+	fn on_disconnect(&mut self, client: &ClientHandle, reason: &DisconnectReason) {
+		let version = client.client().and_then(|client| client.read().ok().and_then(|client| client.protocol_version()));
+		let processor = match version {
+			Some(ref version) if self.processors.contains_key(version.name()) => self.processors.get_mut(version.name()).unwrap(),
+			_ => &mut self.fallback,
+		};
+		processor.on_disconnect(client, reason);
+	}
+
+	fn clone(&self) -> Box<PacketProcessor> {
+		Box::new(VersionRouter {
+			processors:		self.processors.iter().map(|(name, processor)| (name.clone(), PacketProcessor::clone(&**processor))).collect(),
+			fallback:		self.fallback.clone(),
+		})
+	}
+}
@@ -0,0 +1,333 @@
+use std::collections::VecDeque;
+use std::io::{Error, ErrorKind};
+use std::net::SocketAddr;
+use std::sync::{Mutex, MutexGuard};
+use base64;
+use mio::{Interest, Registry, Token};
+use sha1::Sha1;
+
+use error::is_transient;
+use transport::Transport;
+
+const ACCEPT_GUID: &'static str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+/* a browser's upgrade request is well below this, anything longer isn't one */
+const MAX_HANDSHAKE_SIZE: usize = 8192;
+/* a message may hold a few frames, not more than a send buffer's worth */
+const MAX_MESSAGE_SIZE: usize = 1 << 20;
+/* encoded messages waiting for the socket, past this writes wait for the backlog to go */
+const MAX_PENDING: usize = 1 << 16;
+
+const OP_CONTINUATION: u8 = 0x0;
+const OP_TEXT: u8 = 0x1;
+const OP_BINARY: u8 = 0x2;
+const OP_CLOSE: u8 = 0x8;
+const OP_PING: u8 = 0x9;
+const OP_PONG: u8 = 0xa;
+
+/* close code 1000, normal closure */
+const NORMAL_CLOSURE: [u8; 2] = [0x03, 0xe8];
+
+struct WebSocketState {
+	handshake_done:	bool,
+	/* read from the socket, not parsed yet */
+	inbound:		Vec<u8>,
+	/* payload of binary messages, what read() hands out */
+	plain:			VecDeque<u8>,
+	/* written, not a complete frame yet */
+	partial:		Vec<u8>,
+	/* encoded messages the socket hasn't taken yet */
+	pending:		Vec<u8>,
+	/* the peer sent a close, read() reports EOF once `plain` is empty */
+	closed:			bool,
+}
+
+/* speaks WebSocket on the wire, e.g. for an admin panel in a browser. every binary message the */
+/* peer sends is part of the Fiesta byte stream, every frame written goes out as one binary message. */
+/* the frames are told apart by their size prefix, so this carries the game's framing, not a Codec's */
+pub struct WebSocketTransport<T: Transport> {
+	inner:			T,
+	state:			Mutex<WebSocketState>,
+}
+
+impl<T: Transport> WebSocketTransport<T> {
+	/* `inner` has just been accepted, the HTTP upgrade request is the first thing read from it */
+	pub fn new(inner: T) -> Self {
+		WebSocketTransport {
+			inner:			inner,
+			state:			Mutex::new(WebSocketState {
+				handshake_done:	false,
+				inbound:		Vec::new(),
+				plain:			VecDeque::new(),
+				partial:		Vec::new(),
+				pending:		Vec::new(),
+				closed:			false,
+			}),
+		}
+	}
+
+	fn state(&self) -> MutexGuard<WebSocketState> {
+		match self.state.lock() {
+			Ok(state) => state,
+			Err(poisoned) => poisoned.into_inner(),
+		}
+	}
+
+	/* Err(WouldBlock) while some of `pending` is left */
+	fn flush_pending(&self, state: &mut WebSocketState) -> Result<(), Error> {
+		while !state.pending.is_empty() {
+			match try!(self.inner.write(&state.pending[..])) {
+				0 => return Err(Error::new(ErrorKind::WriteZero, "websocket peer stopped taking data")),
+				written => { state.pending.drain(..written); },
+			}
+		}
+		Ok(())
+	}
+
+	/* like flush_pending, the rest goes out once the socket is writable again */
+	fn try_flush(&self, state: &mut WebSocketState) -> Result<(), Error> {
+		match self.flush_pending(state) {
+			Err(ref e) if is_transient(e) => Ok(()),
+			result => result,
+		}
+	}
+
+	/* answers the upgrade request once it is complete */
+	fn handshake(&self, state: &mut WebSocketState) -> Result<(), Error> {
+		let end = match state.inbound.windows(4).position(|window| window == b"\r\n\r\n") {
+			Some(end) => end + 4,
+			None if state.inbound.len() > MAX_HANDSHAKE_SIZE => return Err(self.refuse(state, "upgrade request too long")),
+			None => return Ok(()),
+		};
+		let key = upgrade_key(&String::from_utf8_lossy(&state.inbound[..end]));
+		let key = match key {
+			Ok(key) => key,
+			Err(reason) => return Err(self.refuse(state, reason)),
+		};
+		state.inbound.drain(..end);
+
+		let mut digest = Sha1::new();
+		digest.update(key.as_bytes());
+		digest.update(ACCEPT_GUID.as_bytes());
+		let response = format!("HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+			base64::encode(&digest.digest().bytes()[..]));
+		state.pending.extend_from_slice(response.as_bytes());
+		state.handshake_done = true;
+		debug!(target: "network", "websocket handshake done for {:?}", self.inner.peer_addr());
+		/* anything written before the handshake waited for it */
+		encode_frames(state);
+		Ok(())
+	}
+
+	fn refuse(&self, state: &mut WebSocketState, reason: &str) -> Error {
+		state.pending.extend_from_slice(b"HTTP/1.1 400 Bad Request\r\nConnection: close\r\n\r\n");
+		let _ = self.flush_pending(state);
+		Error::new(ErrorKind::InvalidData, format!("websocket handshake failed: {}", reason))
+	}
+
+	/* moves the payload of every complete message into `plain`, control frames are answered */
+	fn read_messages(&self, state: &mut WebSocketState) -> Result<(), Error> {
+		while !state.closed {
+			let (opcode, payload, consumed) = match try!(parse_message(&state.inbound[..])) {
+				Some(message) => message,
+				None => return Ok(()),
+			};
+			state.inbound.drain(..consumed);
+			match opcode {
+				OP_BINARY | OP_CONTINUATION => state.plain.extend(payload),
+				OP_TEXT => return Err(Error::new(ErrorKind::InvalidData, "websocket text message, frames come as binary ones")),
+				OP_PING => encode_message(OP_PONG, &payload[..], &mut state.pending),
+				OP_PONG => {},
+				OP_CLOSE => {
+					encode_message(OP_CLOSE, &NORMAL_CLOSURE[..], &mut state.pending);
+					state.closed = true;
+				},
+				other => return Err(Error::new(ErrorKind::InvalidData, format!("unknown websocket opcode {:#x}", other))),
+			}
+		}
+		Ok(())
+	}
+}
+
+/* the Sec-WebSocket-Key of an upgrade request */
+fn upgrade_key(request: &str) -> Result<String, &'static str> {
+	let mut lines = request.split("\r\n");
+	if !lines.next().map(|line| line.starts_with("GET ")).unwrap_or(false) {
+		return Err("not a GET request");
+	}
+	let mut upgrade = false;
+	let mut key = None;
+	for line in lines {
+		let mut parts = line.splitn(2, ':');
+		let name = parts.next().unwrap_or("").trim().to_lowercase();
+		let value = parts.next().unwrap_or("").trim();
+		match &name[..] {
+			"upgrade" => upgrade = value.to_lowercase().contains("websocket"),
+			"sec-websocket-key" => key = Some(value.to_string()),
+			_ => {},
+		}
+	}
+	match key {
+		Some(key) if upgrade => Ok(key),
+		_ => Err("not a websocket upgrade"),
+	}
+}
+
+/* (opcode, unmasked payload, bytes consumed), Ok(None) while it isn't all there. fragments of a */
+/* message simply continue the byte stream, they aren't put back together */
+fn parse_message(data: &[u8]) -> Result<Option<(u8, Vec<u8>, usize)>, Error> {
+	if data.len() < 2 {
+		return Ok(None);
+	}
+	let opcode = data[0] & 0x0f;
+	if data[1] & 0x80 == 0 {
+		return Err(Error::new(ErrorKind::InvalidData, "unmasked websocket message from a client"));
+	}
+	let (size, mut offset) = match data[1] & 0x7f {
+		126 if data.len() >= 4 => ((((data[2] as u64) << 8) | data[3] as u64), 4),
+		127 if data.len() >= 10 => (data[2..10].iter().fold(0, |size, &byte| (size << 8) | byte as u64), 10),
+		126 | 127 => return Ok(None),
+		size => (size as u64, 2),
+	};
+	if size > MAX_MESSAGE_SIZE as u64 || (opcode & 0x8 != 0 && size > 125) {
+		return Err(Error::new(ErrorKind::InvalidData, format!("websocket message of {} bytes", size)));
+	}
+	let size = size as usize;
+	if data.len() < offset + 4 + size {
+		return Ok(None);
+	}
+	let mask = [data[offset], data[offset + 1], data[offset + 2], data[offset + 3]];
+	offset += 4;
+	let payload = data[offset..offset + size].iter().enumerate().map(|(i, &byte)| byte ^ mask[i % 4]).collect();
+	Ok(Some((opcode, payload, offset + size)))
+}
+
+/* unmasked and unfragmented, as a server sends them */
+fn encode_message(opcode: u8, payload: &[u8], out: &mut Vec<u8>) {
+	out.push(0x80 | opcode);
+	if payload.len() < 126 {
+		out.push(payload.len() as u8);
+	} else if payload.len() <= 0xffff {
+		out.push(126);
+		out.push((payload.len() >> 8) as u8);
+		out.push(payload.len() as u8);
+	} else {
+		out.push(127);
+		for shift in (0..8).rev() {
+			out.push(((payload.len() as u64) >> (shift * 8)) as u8);
+		}
+	}
+	out.extend_from_slice(payload);
+}
+
+/* one binary message per complete frame in `partial` */
+fn encode_frames(state: &mut WebSocketState) {
+	loop {
+		let partial = &state.partial;
+		let (size, prefix) = match partial.first() {
+			Some(&size) if size > 0 => (size as usize, 1),
+			Some(_) if partial.len() >= 3 => ((((partial[1] as usize) << 8) | partial[2] as usize), 3),
+			_ => break,
+		};
+		let total = prefix + 2 + size;
+		if partial.len() < total {
+			break;
+		}
+		encode_message(OP_BINARY, &partial[..total], &mut state.pending);
+		state.partial.drain(..total);
+	}
+}
+
+impl<T: Transport> Transport for WebSocketTransport<T> {
+	fn read(&self, buf: &mut [u8]) -> Result<usize, Error> {
+		let mut state = self.state();
+		loop {
+			if !state.plain.is_empty() {
+				let size = ::std::cmp::min(buf.len(), state.plain.len());
+				for (target, byte) in buf.iter_mut().zip(state.plain.drain(..size)) {
+					*target = byte;
+				}
+				return Ok(size);
+			}
+			if state.closed {
+				return Ok(0);
+			}
+
+			let mut chunk = [0; 4096];
+			match try!(self.inner.read(&mut chunk[..])) {
+				0 => return Ok(0),
+				size => state.inbound.extend_from_slice(&chunk[..size]),
+			}
+			if !state.handshake_done {
+				try!(self.handshake(&mut state));
+			}
+			if state.handshake_done {
+				try!(self.read_messages(&mut state));
+			}
+			/* the handshake response, pongs and the close reply */
+			try!(self.try_flush(&mut state));
+		}
+	}
+
+	fn write(&self, buf: &[u8]) -> Result<usize, Error> {
+		self.write_vectored(&[buf])
+	}
+
+	fn write_vectored(&self, bufs: &[&[u8]]) -> Result<usize, Error> {
+		let mut state = self.state();
+		if state.pending.len() >= MAX_PENDING {
+			try!(self.flush_pending(&mut state));
+		}
+		let mut accepted = 0;
+		for buf in bufs.iter() {
+			state.partial.extend_from_slice(buf);
+			accepted += buf.len();
+		}
+		if state.handshake_done {
+			encode_frames(&mut state);
+		}
+		try!(self.try_flush(&mut state));
+		Ok(accepted)
+	}
+
+	fn wants_write(&self) -> bool {
+		!self.state().pending.is_empty()
+	}
+
+	fn flush(&self) -> Result<(), Error> {
+		let mut state = self.state();
+		self.flush_pending(&mut state)
+	}
+
+	/* says goodbye first if the socket takes it right away */
+	fn shutdown(&self) -> Result<(), Error> {
+		{
+			let mut state = self.state();
+			if state.handshake_done && !state.closed {
+				encode_message(OP_CLOSE, &NORMAL_CLOSURE[..], &mut state.pending);
+				state.closed = true;
+				let _ = self.flush_pending(&mut state);
+			}
+		}
+		self.inner.shutdown()
+	}
+
+	fn register(&self, registry: &Registry, token: Token, interest: Interest) -> Result<(), Error> {
+		self.inner.register(registry, token, interest)
+	}
+
+	fn reregister(&self, registry: &Registry, token: Token, interest: Interest) -> Result<(), Error> {
+		self.inner.reregister(registry, token, interest)
+	}
+
+	fn deregister(&self, registry: &Registry) -> Result<(), Error> {
+		self.inner.deregister(registry)
+	}
+
+	fn peer_addr(&self) -> Option<SocketAddr> {
+		self.inner.peer_addr()
+	}
+
+	fn local_addr(&self) -> Option<SocketAddr> {
+		self.inner.local_addr()
+	}
+}
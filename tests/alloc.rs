@@ -0,0 +1,51 @@
+extern crate fiesta_net;
+extern crate mio;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use mio::Token;
+
+use fiesta_net::{FiestaPacket, INLINE_BODY_SIZE};
+use fiesta_net::testing::MockClient;
+
+/* counts every allocation in the process, this file has a single test so nothing else runs meanwhile */
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+	unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+		ALLOCATIONS.fetch_add(1, Ordering::SeqCst);
+		System.alloc(layout)
+	}
+
+	unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+		System.dealloc(ptr, layout)
+	}
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+const FRAMES: usize = 100;
+
+#[test]
+fn small_packets_decode_without_allocating() {
+	let mut burst = Vec::new();
+	for i in 0..FRAMES {
+		burst.extend_from_slice(&FiestaPacket::encode(0x2000 | i as u16, &[i as u8; INLINE_BODY_SIZE]).unwrap()[..]);
+	}
+	let client = MockClient::new(Token(1)).unwrap();
+	/* the packet queue grows to its size on the first burst */
+	assert_eq!(client.push_bytes(&burst[..]).unwrap().len(), FRAMES);
+
+	let before = ALLOCATIONS.load(Ordering::SeqCst);
+	let packets = client.push_bytes(&burst[..]).unwrap();
+	let allocations = ALLOCATIONS.load(Ordering::SeqCst) - before;
+
+	assert_eq!(packets.len(), FRAMES);
+	assert_eq!(packets[7].header, 0x2007);
+	assert_eq!(packets[7].data.to_vec(), vec![7; INLINE_BODY_SIZE]);
+	/* the Vec push_bytes returns, nothing per packet */
+	assert!(allocations <= 1, "{} allocations for {} packets", allocations, FRAMES);
+}
@@ -0,0 +1,158 @@
+extern crate fiesta_net;
+
+use std::io::ErrorKind;
+
+use fiesta_net::{Buffer, BinaryReadable, BinaryPeekable, BufferError, Endianness};
+
+/* a full 8 byte ring with its read position at 6, so the next append wraps */
+fn wrapped() -> Buffer {
+	let mut buffer = Buffer::with_capacity(8);
+	buffer.append(&[0, 0, 0, 0, 0, 0]).unwrap();
+	buffer.advance_read(6);
+	buffer.append(&[1, 2, 3, 4, 5, 6, 7, 8]).unwrap();
+	buffer
+}
+
+#[test]
+fn appends_and_reads_across_the_end() {
+	let mut buffer = wrapped();
+	assert_eq!(buffer.bytes_remaining(), 8);
+	assert_eq!(buffer.free(), 0);
+	{
+		let (first, second) = buffer.segments();
+		assert_eq!(first, &[1, 2][..]);
+		assert_eq!(second, &[3, 4, 5, 6, 7, 8][..]);
+	}
+	assert_eq!(buffer.to_vec(), vec![1, 2, 3, 4, 5, 6, 7, 8]);
+	assert!(buffer.append(&[9]).is_err());
+
+	assert_eq!(buffer.peek_u32(1).unwrap(), 0x02030405);
+	assert_eq!(buffer.read_bytes(3).unwrap(), vec![1, 2, 3]);
+	assert_eq!(buffer.read_u16_le().unwrap(), 0x0504);
+	buffer.append(&[9, 10, 11, 12, 13]).unwrap();
+	assert_eq!(buffer.read_bytes(8).unwrap(), vec![6, 7, 8, 9, 10, 11, 12, 13]);
+	assert!(buffer.read_u8().is_err());
+}
+
+#[test]
+fn read_into_across_the_end() {
+	let mut buffer = wrapped();
+	let mut target = Buffer::with_capacity(8);
+	buffer.read_into(&mut target, 5).unwrap();
+	assert_eq!(target.to_vec(), vec![1, 2, 3, 4, 5]);
+	assert_eq!(buffer.to_vec(), vec![6, 7, 8]);
+	assert!(buffer.read_into(&mut target, 4).is_err());
+	assert_eq!(buffer.bytes_remaining(), 3);
+}
+
+#[test]
+fn free_segments_wrap_too() {
+	let mut buffer = Buffer::with_capacity(8);
+	buffer.append(&[0, 0, 0, 0, 0, 1, 2]).unwrap();
+	buffer.advance_read(5);
+	{
+		let (first, second) = buffer.free_segments_mut();
+		assert_eq!((first.len(), second.len()), (1, 5));
+		first[0] = 3;
+		second[0] = 4;
+		second[1] = 5;
+	}
+	buffer.commit(3);
+	assert_eq!(buffer.to_vec(), vec![1, 2, 3, 4, 5]);
+
+	let mut reader = &[6, 7, 8, 9][..];
+	assert_eq!(buffer.read_from(&mut reader, 16).unwrap(), 3);
+	assert_eq!(buffer.to_vec(), vec![1, 2, 3, 4, 5, 6, 7, 8]);
+	assert_eq!(buffer.read_from(&mut reader, 16).unwrap(), 0);
+}
+
+#[test]
+fn grows_while_wrapped() {
+	let mut buffer = wrapped();
+	buffer.extend(&[9, 10]);
+	assert_eq!(buffer.capacity(), 16);
+	assert_eq!(buffer.to_vec(), vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10]);
+	{
+		let (first, second) = buffer.segments();
+		assert_eq!(first.len(), 10);
+		assert!(second.is_empty());
+	}
+
+	/* a reserve that already fits leaves the ring alone */
+	buffer.reserve(6);
+	assert_eq!(buffer.capacity(), 16);
+	buffer.reserve(7);
+	assert_eq!(buffer.capacity(), 32);
+	assert_eq!(buffer.read_bytes(10).unwrap(), vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10]);
+}
+
+#[test]
+fn integers_round_trip_in_both_byte_orders() {
+	let mut buffer = Buffer::with_capacity(64);
+	buffer.append_u16(0x0102, Endianness::Big).unwrap();
+	buffer.append_u16(0x0102, Endianness::Little).unwrap();
+	buffer.append_u32(0x01020304, Endianness::Big).unwrap();
+	buffer.append_u32(0x01020304, Endianness::Little).unwrap();
+	buffer.append_u64(0x0102030405060708, Endianness::Big).unwrap();
+	buffer.append_u64(0x0102030405060708, Endianness::Little).unwrap();
+	buffer.append_uint(0x010203, 3, Endianness::Little).unwrap();
+	assert_eq!(&buffer.to_vec()[0..12], &[1, 2, 2, 1, 1, 2, 3, 4, 4, 3, 2, 1][..]);
+
+	/* the plain reads are big endian */
+	assert_eq!(buffer.peek_u16(0).unwrap(), 0x0102);
+	assert_eq!(buffer.read_u16().unwrap(), 0x0102);
+	assert_eq!(buffer.read_u16_le().unwrap(), 0x0102);
+	assert_eq!(buffer.read_u32().unwrap(), 0x01020304);
+	assert_eq!(buffer.peek_u32_le(0).unwrap(), 0x01020304);
+	assert_eq!(buffer.read_u32_le().unwrap(), 0x01020304);
+	assert_eq!(buffer.read_u64().unwrap(), 0x0102030405060708);
+	assert_eq!(buffer.read_u64_le().unwrap(), 0x0102030405060708);
+	assert_eq!(buffer.read_uint(3, Endianness::Little).unwrap(), 0x010203);
+	assert_eq!(buffer.bytes_remaining(), 0);
+
+	buffer.append_u32(-2i32 as u32, Endianness::Little).unwrap();
+	buffer.append_u16(-3i16 as u16, Endianness::Big).unwrap();
+	assert_eq!(buffer.read_i32_le().unwrap(), -2);
+	assert_eq!(buffer.read_i16().unwrap(), -3);
+}
+
+#[test]
+fn integers_wider_than_8_bytes_are_refused() {
+	let mut buffer = Buffer::with_capacity(16);
+	buffer.append(&[0; 16]).unwrap();
+	assert_eq!(buffer.read_uint(9, Endianness::Big), Err(BufferError::TooWide { size: 9 }));
+	assert_eq!(buffer.peek_uint(0, 16, Endianness::Little), Err(BufferError::TooWide { size: 16 }));
+	assert_eq!(buffer.bytes_remaining(), 16);
+
+	let mut buffer = Buffer::with_capacity(16);
+	assert_eq!(buffer.append_uint(1, 9, Endianness::Little), Err(BufferError::TooWide { size: 9 }));
+	assert_eq!(buffer.bytes_remaining(), 0);
+}
+
+#[test]
+fn from_hex_str_ignores_whitespace() {
+	let buffer = Buffer::from_hex_str(" 08 20\n\t01 0aFf\r\n").unwrap();
+	assert_eq!(buffer.to_vec(), vec![0x08, 0x20, 0x01, 0x0a, 0xff]);
+	assert_eq!(Buffer::from_hex_str("").unwrap().bytes_remaining(), 0);
+}
+
+#[test]
+fn from_hex_str_refuses_bad_digits() {
+	assert_eq!(Buffer::from_hex_str("08 2").unwrap_err().kind(), ErrorKind::InvalidData);
+	assert_eq!(Buffer::from_hex_str("08 2g").unwrap_err().kind(), ErrorKind::InvalidData);
+	assert_eq!(Buffer::from_hex_str("0x08").unwrap_err().kind(), ErrorKind::InvalidData);
+	assert_eq!(Buffer::from_hex_str("08,20").unwrap_err().kind(), ErrorKind::InvalidData);
+}
+
+#[test]
+fn hex_dumps_parse_back() {
+	/* more than one line, with a '|' in the ascii column */
+	let bytes: Vec<u8> = b"PROXY | fiesta\x00\x01\x02".iter().cloned().chain(0x70..0x90).collect();
+	let mut buffer = Buffer::with_capacity(bytes.len());
+	buffer.append(&bytes[..]).unwrap();
+
+	let dump = format!("{}", buffer);
+	assert_eq!(dump.lines().count(), 4);
+	assert_eq!(Buffer::from_hex_str(&dump).unwrap().to_vec(), bytes);
+	assert!(format!("{:?}", buffer).contains("50 52 4f 58 59 20 7c"));
+}
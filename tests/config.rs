@@ -0,0 +1,111 @@
+extern crate fiesta_net;
+
+use std::env;
+use std::fs::{self, File};
+use std::io::Write;
+use std::process;
+
+use fiesta_net::{ByteRateLimit, FloodAction, TraceFilter};
+use fiesta_net::config::{self, ConfigError};
+
+const VALID: &'static str = r#"
+name = "zone"
+port = 9210
+address = "127.0.0.1:9210"
+ip_mode = "dual"
+threads = 8
+buffer_size = 131072
+write_buffer_size = 131072
+slow_consumer = "drop_oldest"
+max_clients = 500
+max_frame_size = 4096
+proxy_protocol = true
+banned = ["192.0.2.1", "2001:db8::1"]
+trace = "0x0801, 0x0c01"
+
+[socket]
+nodelay = true
+keepalive_secs = 30
+
+[byte_rate_limit]
+bytes_per_sec = 65536
+action = "throttle"
+"#;
+
+/* the field a config with `line` added to an otherwise empty file is rejected for */
+fn rejected_field(line: &str) -> &'static str {
+	let config = config::from_toml_str(line).unwrap();
+	match config.validate() {
+		Err(ConfigError::Invalid { field, .. })	=> field,
+		other									=> panic!("{}: expected Invalid, got {:?}", line, other),
+	}
+}
+
+#[test]
+fn loads_a_valid_file() {
+	let path = env::temp_dir().join(format!("fiesta-net-config-{}.toml", process::id()));
+	File::create(&path).unwrap().write_all(VALID.as_bytes()).unwrap();
+	let loaded = config::load(&path);
+	fs::remove_file(&path).unwrap();
+
+	let config = loaded.unwrap();
+	assert_eq!(config.name, Some("zone".to_string()));
+	assert_eq!(config.port, Some(9210));
+	assert_eq!(config.threads, Some(8));
+	assert_eq!(config.proxy_protocol, Some(true));
+	assert_eq!(config.socket.as_ref().and_then(|socket| socket.keepalive_secs), Some(30));
+
+	let runtime = config.runtime().unwrap();
+	assert_eq!(runtime.max_clients, Some(500));
+	assert_eq!(runtime.max_frame_size, Some(4096));
+	assert_eq!(runtime.byte_rate_limit, Some(Some(ByteRateLimit { bytes_per_sec: 65536, action: FloodAction::Throttle })));
+	assert_eq!(runtime.banned.map(|banned| banned.len()), Some(2));
+	assert_eq!(runtime.trace, Some(TraceFilter::opcodes(&[0x0801, 0x0c01])));
+}
+
+#[test]
+fn an_empty_file_is_valid() {
+	config::from_toml_str("").unwrap().validate().unwrap();
+}
+
+#[test]
+fn unknown_keys_are_rejected() {
+	match config::from_toml_str("port = 9010\nthread = 4\n") {
+		Err(ConfigError::Parse(e))	=> assert!(e.contains("thread"), "{}", e),
+		other						=> panic!("expected Parse, got {:?}", other),
+	}
+	match config::from_toml_str("[socket]\nno_delay = true\n") {
+		Err(ConfigError::Parse(e))	=> assert!(e.contains("no_delay"), "{}", e),
+		other						=> panic!("expected Parse, got {:?}", other),
+	}
+}
+
+#[test]
+fn out_of_range_values_are_rejected() {
+	assert_eq!(rejected_field("port = 0"), "port");
+	assert_eq!(rejected_field("threads = 0"), "threads");
+	assert_eq!(rejected_field("threads = 257"), "threads");
+	assert_eq!(rejected_field("max_frame_size = 0"), "max_frame_size");
+	assert_eq!(rejected_field("max_frame_size = 65536"), "max_frame_size");
+	assert_eq!(rejected_field("buffer_size = 1024"), "buffer_size");
+	assert_eq!(rejected_field("max_frame_size = 4096\nwrite_buffer_size = 4100"), "write_buffer_size");
+	assert_eq!(rejected_field("max_clients = 0"), "max_clients");
+}
+
+#[test]
+fn bad_names_and_addresses_are_rejected() {
+	assert_eq!(rejected_field("address = \"::1:9010\""), "address");
+	assert_eq!(rejected_field("ip_mode = \"v5\""), "ip_mode");
+	assert_eq!(rejected_field("slow_consumer = \"block\""), "slow_consumer");
+	assert_eq!(rejected_field("[byte_rate_limit]\nbytes_per_sec = 1\naction = \"ban\""), "byte_rate_limit.action");
+	assert_eq!(rejected_field("banned = [\"192.0.2.300\"]"), "banned");
+	assert_eq!(rejected_field("trace = \"0x0801, login\""), "trace");
+}
+
+#[test]
+fn the_edges_of_each_range_are_allowed() {
+	let config = config::from_toml_str("threads = 256\nmax_frame_size = 65535\nbuffer_size = 65540\nmax_clients = 1").unwrap();
+	config.validate().unwrap();
+	let config = config::from_toml_str("threads = 1\nmax_frame_size = 1\nwrite_buffer_size = 6").unwrap();
+	config.validate().unwrap();
+}
@@ -0,0 +1,157 @@
+extern crate fiesta_net;
+extern crate mio;
+
+use std::convert::TryFrom;
+use std::io::ErrorKind;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use mio::Token;
+
+use fiesta_net::{decode_stream, Buffer, Codec, FiestaPacket, Keepalive, Keystream, LengthPrefix};
+use fiesta_net::ProtocolVersion;
+use fiesta_net::{PacketProcessor, PacketProcessingInfo};
+use fiesta_net::packets::{ClientPacket, DecodeError, NcUserLoginfailAck, Packet, ServerPacket};
+use fiesta_net::testing::{builtin_corpus, check_frame, MockClient};
+
+#[test]
+fn builtin_corpus_round_trips() {
+	let corpus = builtin_corpus();
+	assert!(!corpus.is_empty());
+	for golden in corpus.iter() {
+		if let Err(e) = check_frame(golden) {
+			panic!("{}", e);
+		}
+	}
+}
+
+#[test]
+fn corpus_decodes_back_to_back() {
+	/* the frames of one stream must not bleed into each other */
+	let corpus = builtin_corpus();
+	let mut stream = Vec::new();
+	for golden in corpus.iter() {
+		stream.extend_from_slice(&golden.frame[..]);
+	}
+
+	let decoded = decode_stream(&stream[..]);
+	assert_eq!(decoded.len(), corpus.len());
+	for (golden, packet) in corpus.iter().zip(decoded.into_iter()) {
+		let packet = packet.unwrap();
+		assert_eq!(packet.header, golden.header, "{}", golden.name);
+		assert_eq!(packet.data.to_vec(), golden.body, "{}", golden.name);
+	}
+}
+
+#[test]
+fn typed_packets_decode_by_sender() {
+	let frame = NcUserLoginfailAck { error: 0x45 }.encode().unwrap();
+	let packet = decode_stream(&frame[..]).remove(0).unwrap();
+	assert_eq!(ServerPacket::try_from(&packet), Ok(ServerPacket::NcUserLoginfailAck(NcUserLoginfailAck { error: 0x45 })));
+	/* the client never sends it */
+	assert_eq!(ClientPacket::try_from(&packet), Err(DecodeError::UnknownOpcode(NcUserLoginfailAck::OPCODE)));
+}
+
+/* the frame the game client would send, opcode and body xor-ed */
+fn encrypted(keystream: &mut Keystream, header: u16, body: &[u8]) -> Vec<u8> {
+	let mut plain = vec![(header >> 8) as u8, header as u8];
+	plain.extend_from_slice(body);
+	keystream.apply(&mut plain[..]);
+	FiestaPacket::encode(((plain[0] as u16) << 8) | plain[1] as u16, &plain[2..]).unwrap()
+}
+
+#[test]
+fn keystream_rotation_keeps_frames_in_flight() {
+	let table = Arc::new((0..499).map(|i| (i * 31 + 7) as u8).collect::<Vec<u8>>());
+	let mut sender = Keystream::new(table.clone(), 10);
+	let client = MockClient::new(Token(1)).unwrap();
+	client.client().read().unwrap().set_keystream(Keystream::new(table.clone(), 10));
+
+	let packets = client.push_bytes(&encrypted(&mut sender, 0x0804, &[1, 2, 3])[..]).unwrap();
+	assert_eq!((packets[0].header, packets[0].data.to_vec()), (0x0804, vec![1, 2, 3]));
+
+	client.client().read().unwrap().rotate_keystream(99, Duration::from_secs(60)).unwrap();
+	/* sent before the client saw the new seed */
+	let packets = client.push_bytes(&encrypted(&mut sender, 0x0805, &[4])[..]).unwrap();
+	assert_eq!((packets[0].header, packets[0].data.to_vec()), (0x0805, vec![4]));
+
+	let mut sender = sender.reseed(99);
+	let packets = client.push_bytes(&encrypted(&mut sender, 0x0c06, &[5, 6])[..]).unwrap();
+	assert_eq!((packets[0].header, packets[0].data.to_vec()), (0x0c06, vec![5, 6]));
+}
+
+/* opcode and body of every packet the Recorder was given */
+type Seen = Arc<Mutex<Vec<(u16, Vec<u8>)>>>;
+
+/* records what it was given and answers every packet with opcode + 1 */
+struct Recorder {
+	seen:			Seen,
+}
+
+impl PacketProcessor for Recorder {
+	fn process_packet(&mut self, info: PacketProcessingInfo) {
+		self.seen.lock().unwrap().push((info.packet.header, info.packet.data.to_vec()));
+		info.client.send(&FiestaPacket::new(info.packet.header + 1, 0)).unwrap();
+	}
+
+	fn clone(&self) -> Box<PacketProcessor> {
+		Box::new(Recorder { seen: self.seen.clone() })
+	}
+}
+
+#[test]
+fn mock_client_feeds_the_processor() {
+	let seen = Arc::new(Mutex::new(Vec::new()));
+	let mut processor: Box<PacketProcessor> = Box::new(Recorder { seen: seen.clone() });
+	let client = MockClient::new(Token(1)).unwrap();
+
+	let mut bytes = FiestaPacket::encode(0x0c01, &[1, 2]).unwrap();
+	bytes.extend_from_slice(&FiestaPacket::encode(0x0c03, &[]).unwrap()[..]);
+	assert_eq!(client.feed(&mut processor, &bytes[..]).unwrap(), 2);
+
+	assert_eq!(*seen.lock().unwrap(), vec![(0x0c01, vec![1, 2]), (0x0c03, vec![])]);
+	let sent: Vec<u16> = client.sent().unwrap().iter().map(|packet| packet.header).collect();
+	assert_eq!(sent, vec![0x0c02, 0x0c04]);
+	/* every packet was counted in and out again */
+	assert_eq!(client.client().read().unwrap().in_flight(), 0);
+}
+
+#[test]
+fn bodies_past_the_size_prefix_are_refused() {
+	let mut buffer = Buffer::with_capacity(0);
+	let mut packet = FiestaPacket::new(0x2001, 0x10000);
+	packet.data.append(&[0; 0x10000][..]);
+	assert_eq!(LengthPrefix.encode(&packet, &mut buffer).unwrap_err().kind(), ErrorKind::InvalidInput);
+	assert_eq!(buffer.bytes_remaining(), 0);
+	assert_eq!(FiestaPacket::encode(0x2001, &[0; 0x10000][..]).unwrap_err().kind(), ErrorKind::InvalidInput);
+	assert_eq!(FiestaPacket::encode(0x2001, &[0; 0xffff][..]).unwrap().len(), 0xffff + 5);
+
+	/* and the reactor's own send path */
+	let client = MockClient::new(Token(1)).unwrap();
+	let mut packet = FiestaPacket::new(0x2001, 0x10000);
+	packet.data.append(&[0; 0x10000][..]);
+	assert!(client.client().read().unwrap().send(packet).is_err());
+	assert!(client.sent().unwrap().is_empty());
+
+	let mut packet = FiestaPacket::new(0x2001, 0xffff);
+	packet.data.append(&[0; 0xffff][..]);
+	LengthPrefix.encode(&packet, &mut buffer).unwrap();
+	assert_eq!(buffer.bytes_remaining(), 0xffff + 5);
+}
+
+#[test]
+fn heartbeats_follow_the_protocol_version() {
+	let keepalive = Keepalive::default();
+	let client = MockClient::new(Token(1)).unwrap();
+	{
+		let client = client.client();
+		let client = client.read().unwrap();
+		client.set_protocol_version(Arc::new(ProtocolVersion::new("old")
+			.map(keepalive.request, 0x0805)
+			.map(keepalive.response, 0x0806)));
+		/* the client's heartbeat arrives and is answered in the old opcodes */
+		assert!(client.handle_keepalive(&keepalive, 0x0805));
+		assert!(client.send_keepalive(&keepalive));
+	}
+	let sent: Vec<u16> = client.sent().unwrap().iter().map(|packet| packet.header).collect();
+	assert_eq!(sent, vec![0x0806, 0x0805]);
+}
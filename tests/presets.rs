@@ -0,0 +1,67 @@
+extern crate fiesta_net;
+
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use fiesta_net::{ClientHandle, FiestaPacket, Keystream, Outgoing};
+use fiesta_net::packets::{NcMiscSeedAck, NcUserClientRightversionCheckAck, NcUserClientVersionCheckReq, Packet};
+use fiesta_net::presets;
+
+/* the frame the game client would send, opcode and body xor-ed */
+fn encrypted(keystream: &mut Keystream, header: u16, body: &[u8]) -> Vec<u8> {
+	let mut plain = vec![(header >> 8) as u8, header as u8];
+	plain.extend_from_slice(body);
+	keystream.apply(&mut plain[..]);
+	FiestaPacket::encode(((plain[0] as u16) << 8) | plain[1] as u16, &plain[2..]).unwrap()
+}
+
+fn read_frame(stream: &mut TcpStream, len: usize) -> FiestaPacket {
+	let mut frame = vec![0; len];
+	stream.read_exact(&mut frame[..]).unwrap();
+	fiesta_net::decode_stream(&frame[..]).remove(0).unwrap()
+}
+
+#[test]
+fn login_preset_is_a_working_server() {
+	/* the server: a preset, the client's xor table and one route */
+	let table = Arc::new((0..499).map(|i| (i * 31 + 7) as u8).collect::<Vec<u8>>());
+	let builder = presets::login_server()
+		.address("127.0.0.1:0".parse().unwrap())
+		.keystream_table(table.clone());
+	let router = presets::router(&builder)
+		.reply(NcUserClientVersionCheckReq::OPCODE, |_: &FiestaPacket, _: &ClientHandle| {
+			vec![Outgoing::reply(FiestaPacket::new(NcUserClientRightversionCheckAck::OPCODE, 0))]
+		});
+	let metrics = builder.metrics();
+	let server = builder.build(Box::new(router)).unwrap();
+	let (addr, handle) = (server.local_addrs()[0], server.handle());
+	let running = thread::spawn(move || server.run());
+
+	/* a game client: take the seed, send an encrypted version check */
+	let mut stream = TcpStream::connect(addr).unwrap();
+	let seed = read_frame(&mut stream, NcMiscSeedAck { seed: 0 }.encode().unwrap().len());
+	let mut keystream = Keystream::new(table, NcMiscSeedAck::from_packet(&seed).unwrap().seed);
+	let mut body = Vec::new();
+	NcUserClientVersionCheckReq { version: "skeleton".to_string() }.encode_body(&mut body);
+	stream.write_all(&encrypted(&mut keystream, NcUserClientVersionCheckReq::OPCODE, &body[..])[..]).unwrap();
+
+	let reply = read_frame(&mut stream, NcUserClientRightversionCheckAck {}.encode().unwrap().len());
+	assert_eq!(reply.header, NcUserClientRightversionCheckAck::OPCODE);
+
+	/* no route, counted in the server's metrics */
+	stream.write_all(&encrypted(&mut keystream, 0x1234, &[])[..]).unwrap();
+	let unknown = || metrics.snapshot().into_iter().find(|&(name, _)| name == "fiesta_unknown_opcodes_total").unwrap().1;
+	for _ in 0..100 {
+		if unknown() > 0 {
+			break;
+		}
+		thread::sleep(Duration::from_millis(10));
+	}
+	assert_eq!(unknown(), 1);
+
+	handle.shutdown().unwrap();
+	running.join().unwrap().unwrap();
+}
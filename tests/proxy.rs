@@ -0,0 +1,102 @@
+extern crate fiesta_net;
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use fiesta_net::proxy::{parse, ProxyHeader};
+
+const V2_SIGNATURE: [u8; 12] = [0x0d, 0x0a, 0x0d, 0x0a, 0x00, 0x0d, 0x0a, 0x51, 0x55, 0x49, 0x54, 0x0a];
+
+/* a v2 header: version 2, the command, the family and the address block */
+fn v2(command: u8, family: u8, addresses: &[u8]) -> Vec<u8> {
+	let mut header = V2_SIGNATURE.to_vec();
+	header.push(0x20 | command);
+	header.push(family);
+	header.push((addresses.len() >> 8) as u8);
+	header.push(addresses.len() as u8);
+	header.extend_from_slice(addresses);
+	header
+}
+
+#[test]
+fn v1_tcp4() {
+	let data = b"PROXY TCP4 192.0.2.1 198.51.100.1 56324 9010\r\n\x05\x00";
+	assert_eq!(parse(&data[..]), ProxyHeader::Done {
+		consumed: data.len() - 2,
+		source: Some(SocketAddr::new(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)), 56324)),
+	});
+}
+
+#[test]
+fn v1_tcp6() {
+	let data = b"PROXY TCP6 2001:db8::1 2001:db8::2 4242 9010\r\n";
+	assert_eq!(parse(&data[..]), ProxyHeader::Done {
+		consumed: data.len(),
+		source: Some(SocketAddr::new(IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1)), 4242)),
+	});
+}
+
+#[test]
+fn v1_unknown() {
+	let data = b"PROXY UNKNOWN\r\n";
+	assert_eq!(parse(&data[..]), ProxyHeader::Done { consumed: data.len(), source: None });
+}
+
+#[test]
+fn v2_ipv4() {
+	let data = v2(1, 0x11, &[192, 0, 2, 1, 198, 51, 100, 1, 0xdc, 0x04, 0x23, 0x32]);
+	assert_eq!(parse(&data[..]), ProxyHeader::Done {
+		consumed: data.len(),
+		source: Some(SocketAddr::new(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)), 56324)),
+	});
+}
+
+#[test]
+fn v2_ipv6() {
+	let mut addresses = vec![0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1];
+	addresses.extend_from_slice(&[0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2]);
+	addresses.extend_from_slice(&[0x10, 0x92, 0x23, 0x32]);
+	let data = v2(1, 0x21, &addresses[..]);
+	assert_eq!(parse(&data[..]), ProxyHeader::Done {
+		consumed: data.len(),
+		source: Some(SocketAddr::new(IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1)), 4242)),
+	});
+}
+
+#[test]
+fn v2_local() {
+	/* health checks from the proxy itself, whatever address block they carry is skipped */
+	let data = v2(0, 0x11, &[127, 0, 0, 1, 127, 0, 0, 1, 0, 1, 0, 2]);
+	assert_eq!(parse(&data[..]), ProxyHeader::Done { consumed: data.len(), source: None });
+}
+
+#[test]
+fn truncated_headers_need_more() {
+	let v1 = b"PROXY TCP4 192.0.2.1 198.51.100.1 56324 9010\r\n";
+	let v2 = v2(1, 0x11, &[192, 0, 2, 1, 198, 51, 100, 1, 0xdc, 0x04, 0x23, 0x32]);
+	for header in [&v1[..], &v2[..]].iter() {
+		for len in 1..header.len() {
+			assert_eq!(parse(&header[0..len]), ProxyHeader::Incomplete, "{} of {} bytes", len, header.len());
+		}
+	}
+}
+
+#[test]
+fn bad_signature() {
+	let mut data = v2(1, 0x11, &[192, 0, 2, 1, 198, 51, 100, 1, 0xdc, 0x04, 0x23, 0x32]);
+	data[11] = 0x0b;
+	assert_eq!(parse(&data[..]), ProxyHeader::Invalid);
+	assert_eq!(parse(b"PROXZ TCP4 192.0.2.1 198.51.100.1 56324 9010\r\n"), ProxyHeader::Invalid);
+	/* a plain Fiesta frame from a client that skipped the proxy */
+	assert_eq!(parse(&[0x03, 0x65, 0x0c, 0x01][..]), ProxyHeader::Invalid);
+}
+
+#[test]
+fn oversized_v1_line() {
+	/* 107 bytes is the longest line the spec allows, more without a CRLF can't be a header */
+	let mut data = b"PROXY TCP4 ".to_vec();
+	while data.len() < 107 {
+		data.push(b'1');
+	}
+	assert_eq!(parse(&data[0..106]), ProxyHeader::Incomplete);
+	assert_eq!(parse(&data[..]), ProxyHeader::Invalid);
+}
@@ -0,0 +1,56 @@
+extern crate fiesta_net;
+extern crate mio;
+
+use std::env;
+use std::fs;
+use std::process;
+use std::sync::{Arc, Mutex};
+use mio::Token;
+
+use fiesta_net::{Direction, PacketCapture, PacketProcessor, PacketProcessingInfo};
+use fiesta_net::replay::{read_capture, replay_into, Timing};
+
+/* the token, opcode and body of every packet the Recorder was given */
+type Seen = Arc<Mutex<Vec<(Token, u16, Vec<u8>)>>>;
+
+struct Recorder {
+	seen:			Seen,
+}
+
+impl PacketProcessor for Recorder {
+	fn process_packet(&mut self, info: PacketProcessingInfo) {
+		self.seen.lock().unwrap().push((info.token, info.packet.header, info.packet.data.to_vec()));
+	}
+
+	fn clone(&self) -> Box<PacketProcessor> {
+		Box::new(Recorder { seen: self.seen.clone() })
+	}
+}
+
+#[test]
+fn capture_replays_inbound_packets() {
+	let path = env::temp_dir().join(format!("fiesta-net-replay-{}.pcapng", process::id()));
+	{
+		let capture = PacketCapture::create(&path).unwrap();
+		capture.record(Direction::Inbound, Token(1), &[0x0c, 0x01, 0xaa]);
+		capture.record(Direction::Outbound, Token(1), &[0x0c, 0x02]);
+		capture.record(Direction::Inbound, Token(2), &[0x08, 0x04]);
+		capture.record(Direction::Inbound, Token(1), &[0x0c, 0x03, 0xbb, 0xcc]);
+	}
+
+	let captured = read_capture(&path).unwrap();
+	assert_eq!(captured.len(), 4);
+	assert_eq!(captured[1].direction, Direction::Outbound);
+
+	let seen = Arc::new(Mutex::new(Vec::new()));
+	let mut processor: Box<PacketProcessor> = Box::new(Recorder { seen: seen.clone() });
+	let replayed = replay_into(&path, &mut processor, Timing::AsFastAsPossible);
+	let _ = fs::remove_file(&path);
+
+	assert_eq!(replayed.unwrap(), 3);
+	assert_eq!(*seen.lock().unwrap(), vec![
+		(Token(1), 0x0c01, vec![0xaa]),
+		(Token(2), 0x0804, vec![]),
+		(Token(1), 0x0c03, vec![0xbb, 0xcc]),
+	]);
+}